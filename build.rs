@@ -0,0 +1,57 @@
+use std::{path::Path, process::Command};
+
+/// Trimmed stdout of a successful git command.
+fn git(args: &[&str]) -> Option<String> {
+    Command::new("git")
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|stdout| stdout.trim().to_string())
+        .filter(|stdout| !stdout.is_empty())
+}
+
+fn main() {
+    let commit: String =
+        git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    let dirty: bool = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .output()
+        .map(|output| output.status.success() && !output.stdout.is_empty())
+        .unwrap_or(false);
+
+    // PROFILE only distinguishes debug from release, the profile directory also names custom profiles
+    let out_dir: String = std::env::var("OUT_DIR").unwrap_or_default();
+    let profile: String = Path::new(&out_dir)
+        .ancestors()
+        .nth(3)
+        .and_then(|dir| dir.file_name())
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .or_else(|| std::env::var("PROFILE").ok())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!(
+        "cargo:rustc-env=BUILD_GIT_COMMIT={}{}",
+        commit,
+        if dirty { "-dirty" } else { "" }
+    );
+    println!("cargo:rustc-env=BUILD_PROFILE={}", profile);
+
+    // A commit moves the branch HEAD points to rather than HEAD itself, and the branch may only be in
+    // packed-refs. Paths that do not exist are skipped, cargo would rerun the script on every build.
+    let mut watched: Vec<String> = vec![
+        "HEAD".to_string(),
+        "index".to_string(),
+        "packed-refs".to_string(),
+    ];
+    watched.extend(git(&["symbolic-ref", "-q", "HEAD"]));
+    for name in watched {
+        if let Some(path) = git(&["rev-parse", "--git-path", &name]) {
+            if Path::new(&path).exists() {
+                println!("cargo:rerun-if-changed={}", path);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use crate::error::AppError;
+use std::{collections::BTreeSet, str::FromStr};
+
+/// A set of logical CPU ids, written as a list of ids and ranges such as `0-3,6`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreSet {
+    pub cores: BTreeSet<usize>,
+}
+
+impl FromStr for CoreSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |id: &str| {
+            id.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("invalid core id '{}' in '{}'", id, s))
+        };
+
+        let mut cores: BTreeSet<usize> = BTreeSet::new();
+        for part in s.split(',').filter(|part| !part.trim().is_empty()) {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse(first)?, parse(last)?);
+                    if first > last {
+                        return Err(format!("core range '{}' is reversed", part));
+                    }
+                    cores.extend(first..=last);
+                }
+                None => {
+                    cores.insert(parse(part)?);
+                }
+            }
+        }
+
+        if cores.is_empty() {
+            return Err("expected at least one core, e.g. 0-3".to_string());
+        }
+        Ok(Self { cores })
+    }
+}
+
+impl std::fmt::Display for CoreSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Collapse runs of consecutive ids back into ranges
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for &core in &self.cores {
+            match ranges.last_mut() {
+                Some((_, last)) if *last + 1 == core => *last = core,
+                _ => ranges.push((core, core)),
+            }
+        }
+
+        let parts: Vec<String> = ranges
+            .into_iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{}-{}", first, last)
+                }
+            })
+            .collect();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+impl CoreSet {
+    pub fn len(&self) -> usize {
+        self.cores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cores.is_empty()
+    }
+
+    /// ONNX Runtime intra-op affinity string: one entry per worker thread, using 1-based processor ids.
+    /// The calling thread runs the first share of the work itself, so it keeps the first core.
+    pub fn ort_intra_op_affinities(&self) -> String {
+        self.cores
+            .iter()
+            .skip(1)
+            .map(|core| (core + 1).to_string())
+            .collect::<Vec<String>>()
+            .join(";")
+    }
+}
+
+/// Restricts the process to the given cores: the calling thread and every thread already running,
+/// e.g. for tracing, sinks, the `serve` worker or the prefetch pool. Threads spawned afterwards,
+/// including the ONNX Runtime thread pools, inherit the mask, so this has to run before ORT is
+/// initialized.
+pub fn pin_process(cores: &CoreSet) -> Result<(), AppError> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &core in &cores.cores {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(AppError::SystemError(format!(
+                "core {} is out of range",
+                core
+            )));
+        }
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+
+    let pin = |tid: libc::pid_t| -> Result<(), std::io::Error> {
+        let result: libc::c_int =
+            unsafe { libc::sched_setaffinity(tid, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    let pin_error = |e: std::io::Error| {
+        AppError::SystemError(format!("failed to pin to cores {}: {}", cores, e))
+    };
+
+    // The calling thread first, so the threads it spawns from now on inherit the mask
+    pin(0).map_err(pin_error)?;
+
+    // Until a pass finds no new thread, as the threads pinned last may have spawned others meanwhile
+    let mut pinned: BTreeSet<libc::pid_t> = BTreeSet::new();
+    loop {
+        let tasks: Vec<libc::pid_t> = std::fs::read_dir("/proc/self/task")
+            .map_err(|e| AppError::SystemError(format!("failed to list threads: {}", e)))?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::pid_t>().ok())
+            .filter(|tid| !pinned.contains(tid))
+            .collect();
+        if tasks.is_empty() {
+            return Ok(());
+        }
+        for tid in tasks {
+            match pin(tid) {
+                // The thread exited since the directory was read
+                Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+                result => result.map_err(pin_error)?,
+            }
+            pinned.insert(tid);
+        }
+    }
+}
+
+/// Cores the calling thread is currently allowed to run on.
+pub fn current_affinity() -> Option<CoreSet> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let result: libc::c_int =
+        unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) };
+    if result != 0 {
+        return None;
+    }
+
+    let cores: BTreeSet<usize> = (0..libc::CPU_SETSIZE as usize)
+        .filter(|&core| unsafe { libc::CPU_ISSET(core, &set) })
+        .collect();
+    (!cores.is_empty()).then_some(CoreSet { cores })
+}
@@ -0,0 +1,237 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ffi::CStr,
+    str::FromStr,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+};
+
+/// Environment variable the allocator is picked from, before the first allocation.
+pub const ALLOCATOR_ENV: &str = "RUST_ML_BENCHMARK_ALLOCATOR";
+const ALLOCATOR_ENV_C: &CStr = c"RUST_ML_BENCHMARK_ALLOCATOR";
+/// Environment variable turning on the allocation counters when set to `1`, read with the allocator
+/// so every block freed was counted when it was allocated.
+pub const COUNT_ALLOCATIONS_ENV: &str = "RUST_ML_BENCHMARK_COUNT_ALLOCATIONS";
+const COUNT_ALLOCATIONS_ENV_C: &CStr = c"RUST_ML_BENCHMARK_COUNT_ALLOCATIONS";
+
+const UNSELECTED: u8 = u8::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocatorKind {
+    System,
+    Jemalloc,
+    Mimalloc,
+}
+
+impl FromStr for AllocatorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AllocatorKind::from_name(s).ok_or_else(|| {
+            format!(
+                "unknown allocator '{}', expected system, jemalloc or mimalloc",
+                s
+            )
+        })
+    }
+}
+
+impl std::fmt::Display for AllocatorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl AllocatorKind {
+    /// Whether the allocator was compiled in, through the feature of the same name.
+    pub fn is_available(self) -> bool {
+        match self {
+            AllocatorKind::System => true,
+            AllocatorKind::Jemalloc => cfg!(feature = "jemalloc"),
+            AllocatorKind::Mimalloc => cfg!(feature = "mimalloc"),
+        }
+    }
+
+    /// Parses without allocating, as it runs inside the first allocation.
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            AllocatorKind::System,
+            AllocatorKind::Jemalloc,
+            AllocatorKind::Mimalloc,
+        ]
+        .into_iter()
+        .find(|kind| name.eq_ignore_ascii_case(kind.name()))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            AllocatorKind::System => "system",
+            AllocatorKind::Jemalloc => "jemalloc",
+            AllocatorKind::Mimalloc => "mimalloc",
+        }
+    }
+
+    fn from_index(index: u8) -> Self {
+        match index {
+            1 => AllocatorKind::Jemalloc,
+            2 => AllocatorKind::Mimalloc,
+            _ => AllocatorKind::System,
+        }
+    }
+
+    fn index(self) -> u8 {
+        match self {
+            AllocatorKind::System => 0,
+            AllocatorKind::Jemalloc => 1,
+            AllocatorKind::Mimalloc => 2,
+        }
+    }
+}
+
+static SELECTED: AtomicU8 = AtomicU8::new(UNSELECTED);
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static IN_USE: AtomicU64 = AtomicU64::new(0);
+static PEAK: AtomicU64 = AtomicU64::new(0);
+
+/// The allocator serving this process. It is picked from `ALLOCATOR_ENV` on the very first
+/// allocation and never changes afterwards, so every block is freed by the allocator it came from.
+pub fn active() -> AllocatorKind {
+    let selected: u8 = SELECTED.load(Ordering::Acquire);
+    if selected != UNSELECTED {
+        return AllocatorKind::from_index(selected);
+    }
+
+    let requested: AllocatorKind = env_value(ALLOCATOR_ENV_C)
+        .and_then(AllocatorKind::from_name)
+        .filter(|kind| kind.is_available())
+        .unwrap_or(AllocatorKind::System);
+    // Threads racing for the first allocation all end up with the winner's choice, the counters are
+    // switched on before it is published so no allocation through it goes uncounted
+    if env_value(COUNT_ALLOCATIONS_ENV_C) == Some("1") {
+        COUNTING.store(true, Ordering::Release);
+    }
+    let _ = SELECTED.compare_exchange(
+        UNSELECTED,
+        requested.index(),
+        Ordering::AcqRel,
+        Ordering::Acquire,
+    );
+    AllocatorKind::from_index(SELECTED.load(Ordering::Acquire))
+}
+
+/// Reads an environment variable without allocating, unlike std::env::var.
+fn env_value(name: &CStr) -> Option<&'static str> {
+    let value: *const libc::c_char = unsafe { libc::getenv(name.as_ptr()) };
+    if value.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(value) }.to_str().ok()
+    }
+}
+
+/// Whether the allocations are counted, decided with the allocator from `COUNT_ALLOCATIONS_ENV`.
+/// Off by default, as counting adds atomic updates to every allocation and free. Always false when
+/// [`CountingAllocator`] is not the global allocator of the binary.
+pub fn counting() -> bool {
+    COUNTING.load(Ordering::Acquire)
+}
+
+/// Heap counters of the whole process since it started, all zero unless [`counting`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// Calls to `alloc` and `realloc`.
+    pub allocations: u64,
+    /// Bytes requested by all allocations.
+    pub allocated_bytes: u64,
+    /// Bytes allocated and not freed yet.
+    pub in_use: u64,
+    /// Highest `in_use` of the process so far, never reset.
+    pub peak: u64,
+}
+
+pub fn stats() -> AllocationStats {
+    AllocationStats {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        in_use: IN_USE.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+    }
+}
+
+fn record_allocation(size: usize) {
+    if !counting() {
+        return;
+    }
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+    let in_use: u64 = IN_USE.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+    PEAK.fetch_max(in_use, Ordering::Relaxed);
+}
+
+fn record_free(size: usize) {
+    if !counting() {
+        return;
+    }
+    IN_USE.fetch_sub(size as u64, Ordering::Relaxed);
+}
+
+/// Hands every allocation to the allocator selected by [`active`], counting it when [`counting`].
+/// Binaries opt in by making it their `#[global_allocator]`.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr: *mut u8 = match active() {
+            #[cfg(feature = "jemalloc")]
+            AllocatorKind::Jemalloc => tikv_jemallocator::Jemalloc.alloc(layout),
+            #[cfg(feature = "mimalloc")]
+            AllocatorKind::Mimalloc => mimalloc::MiMalloc.alloc(layout),
+            _ => System.alloc(layout),
+        };
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr: *mut u8 = match active() {
+            #[cfg(feature = "jemalloc")]
+            AllocatorKind::Jemalloc => tikv_jemallocator::Jemalloc.alloc_zeroed(layout),
+            #[cfg(feature = "mimalloc")]
+            AllocatorKind::Mimalloc => mimalloc::MiMalloc.alloc_zeroed(layout),
+            _ => System.alloc_zeroed(layout),
+        };
+        if !ptr.is_null() {
+            record_allocation(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match active() {
+            #[cfg(feature = "jemalloc")]
+            AllocatorKind::Jemalloc => tikv_jemallocator::Jemalloc.dealloc(ptr, layout),
+            #[cfg(feature = "mimalloc")]
+            AllocatorKind::Mimalloc => mimalloc::MiMalloc.dealloc(ptr, layout),
+            _ => System.dealloc(ptr, layout),
+        }
+        record_free(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr: *mut u8 = match active() {
+            #[cfg(feature = "jemalloc")]
+            AllocatorKind::Jemalloc => tikv_jemallocator::Jemalloc.realloc(ptr, layout, new_size),
+            #[cfg(feature = "mimalloc")]
+            AllocatorKind::Mimalloc => mimalloc::MiMalloc.realloc(ptr, layout, new_size),
+            _ => System.realloc(ptr, layout, new_size),
+        };
+        if !new_ptr.is_null() {
+            record_free(layout.size());
+            record_allocation(new_size);
+        }
+        new_ptr
+    }
+}
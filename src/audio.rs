@@ -0,0 +1,257 @@
+use crate::error::AppError;
+use hound::{SampleFormat, WavReader};
+use ndarray::{Array2, ArrayD, IxDyn};
+use ort::{
+    session::Input,
+    value::{DynValue, Tensor, ValueType},
+};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::{f32::consts::PI, fs::File, io::BufReader, sync::Arc};
+
+/// Mono samples in `[-1, 1]`.
+#[derive(Debug, Clone)]
+pub struct AudioClip {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+pub fn load_wav(wav_path: &str) -> Result<AudioClip, AppError> {
+    let reader: WavReader<BufReader<File>> = WavReader::open(wav_path)?;
+    let spec: hound::WavSpec = reader.spec();
+    let channels: usize = spec.channels.max(1) as usize;
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        SampleFormat::Float => reader.into_samples::<f32>().collect::<Result<_, _>>()?,
+        SampleFormat::Int => {
+            let max: f32 = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / max))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let samples: Vec<f32> = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(AudioClip {
+        samples,
+        sample_rate: spec.sample_rate,
+    })
+}
+
+impl AudioClip {
+    /// Linear resampling, good enough to feed a model trained at a different rate.
+    pub fn resampled(&self, sample_rate: u32) -> AudioClip {
+        if sample_rate == self.sample_rate || self.samples.is_empty() {
+            return self.clone();
+        }
+
+        let ratio: f64 = self.sample_rate as f64 / sample_rate as f64;
+        let length: usize = (self.samples.len() as f64 / ratio) as usize;
+        let samples: Vec<f32> = (0..length)
+            .map(|i| {
+                let position: f64 = i as f64 * ratio;
+                let index: usize = position as usize;
+                let fraction: f32 = (position - index as f64) as f32;
+                let current: f32 = self.samples[index];
+                let next: f32 = *self.samples.get(index + 1).unwrap_or(&current);
+                current + (next - current) * fraction
+            })
+            .collect();
+
+        AudioClip {
+            samples,
+            sample_rate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MelConfig {
+    pub sample_rate: u32,
+    pub n_fft: usize,
+    pub hop_length: usize,
+    pub n_mels: usize,
+    pub f_min: f32,
+    pub f_max: Option<f32>,
+}
+
+impl Default for MelConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 16000,
+            n_fft: 512,
+            hop_length: 160,
+            n_mels: 64,
+            f_min: 0.,
+            f_max: None,
+        }
+    }
+}
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595. * (1. + hz / 700.).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700. * (10f32.powf(mel / 2595.) - 1.)
+}
+
+/// Triangular HTK mel filters, `[n_mels, n_fft / 2 + 1]`.
+fn mel_filterbank(config: &MelConfig) -> Array2<f32> {
+    let bins: usize = config.n_fft / 2 + 1;
+    let f_max: f32 = config.f_max.unwrap_or(config.sample_rate as f32 / 2.);
+    let (mel_min, mel_max) = (hz_to_mel(config.f_min), hz_to_mel(f_max));
+    let edges: Vec<f32> = (0..config.n_mels + 2)
+        .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f32 / (config.n_mels + 1) as f32))
+        .collect();
+    let bin_hz: f32 = config.sample_rate as f32 / config.n_fft as f32;
+
+    let mut filters: Array2<f32> = Array2::zeros((config.n_mels, bins));
+    for mel in 0..config.n_mels {
+        let (left, center, right) = (edges[mel], edges[mel + 1], edges[mel + 2]);
+        for bin in 0..bins {
+            let hz: f32 = bin as f32 * bin_hz;
+            let weight: f32 = if hz >= left && hz <= center && center > left {
+                (hz - left) / (center - left)
+            } else if hz > center && hz <= right && right > center {
+                (right - hz) / (right - center)
+            } else {
+                0.
+            };
+            filters[[mel, bin]] = weight;
+        }
+    }
+    filters
+}
+
+/// Log-mel spectrogram, `[n_mels, frames]`, of a clip resampled to the configured rate.
+pub fn log_mel_spectrogram(clip: &AudioClip, config: &MelConfig) -> Array2<f32> {
+    let clip: AudioClip = clip.resampled(config.sample_rate);
+    let frames: usize = if clip.samples.len() >= config.n_fft {
+        1 + (clip.samples.len() - config.n_fft) / config.hop_length
+    } else {
+        1
+    };
+
+    let window: Vec<f32> = (0..config.n_fft)
+        .map(|i| 0.5 - 0.5 * (2. * PI * i as f32 / config.n_fft as f32).cos())
+        .collect();
+    let filters: Array2<f32> = mel_filterbank(config);
+    let fft: Arc<dyn Fft<f32>> = FftPlanner::new().plan_fft_forward(config.n_fft);
+    let bins: usize = config.n_fft / 2 + 1;
+
+    let mut spectrogram: Array2<f32> = Array2::zeros((config.n_mels, frames));
+    let mut buffer: Vec<Complex<f32>> = vec![Complex::default(); config.n_fft];
+    let mut power: Vec<f32> = vec![0.; bins];
+    for frame in 0..frames {
+        let offset: usize = frame * config.hop_length;
+        for (i, value) in buffer.iter_mut().enumerate() {
+            let sample: f32 = clip.samples.get(offset + i).copied().unwrap_or(0.);
+            *value = Complex::new(sample * window[i], 0.);
+        }
+        fft.process(&mut buffer);
+        for (bin, value) in power.iter_mut().enumerate() {
+            *value = buffer[bin].norm_sqr();
+        }
+        for mel in 0..config.n_mels {
+            let energy: f32 = filters
+                .row(mel)
+                .iter()
+                .zip(&power)
+                .map(|(weight, power)| weight * power)
+                .sum();
+            spectrogram[[mel, frame]] = (energy + 1e-6).ln();
+        }
+    }
+    spectrogram
+}
+
+/// How an audio model wants its input, derived from the input shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioInputKind {
+    /// `[1, samples]` raw waveform.
+    Waveform { length: Option<usize> },
+    /// Spectrogram with `mels_first` telling whether the mel axis comes before the time axis.
+    Spectrogram {
+        rank: usize,
+        mels_first: bool,
+        n_mels: Option<usize>,
+        frames: Option<usize>,
+    },
+}
+
+impl AudioInputKind {
+    pub fn for_input(input: &Input, n_mels: usize) -> Self {
+        let ValueType::Tensor { shape, .. } = &input.input_type else {
+            return AudioInputKind::Waveform { length: None };
+        };
+        let static_dim = |dim: i64| if dim > 0 { Some(dim as usize) } else { None };
+
+        if shape.len() <= 2 {
+            return AudioInputKind::Waveform {
+                length: shape.last().copied().and_then(static_dim),
+            };
+        }
+
+        let (a, b) = (shape[shape.len() - 2], shape[shape.len() - 1]);
+        let mels_first: bool = b != n_mels as i64 || a == n_mels as i64;
+        let (mels, frames) = if mels_first { (a, b) } else { (b, a) };
+        AudioInputKind::Spectrogram {
+            rank: shape.len(),
+            mels_first,
+            n_mels: static_dim(mels),
+            frames: static_dim(frames),
+        }
+    }
+}
+
+/// Converts a clip into the session value the model input expects, padding or truncating the time axis
+/// to static model dimensions.
+pub fn audio_input(
+    clip: &AudioClip,
+    config: &MelConfig,
+    kind: AudioInputKind,
+) -> Result<DynValue, AppError> {
+    match kind {
+        AudioInputKind::Waveform { length } => {
+            let mut samples: Vec<f32> = clip.resampled(config.sample_rate).samples;
+            if let Some(length) = length {
+                samples.resize(length, 0.);
+            }
+            Ok(Tensor::from_array(([1usize, samples.len()], samples))?.into_dyn())
+        }
+        AudioInputKind::Spectrogram {
+            rank,
+            mels_first,
+            n_mels,
+            frames,
+        } => {
+            let mut config: MelConfig = config.clone();
+            if let Some(n_mels) = n_mels {
+                config.n_mels = n_mels;
+            }
+            let spectrogram: Array2<f32> = log_mel_spectrogram(clip, &config);
+            let frames: usize = frames.unwrap_or(spectrogram.ncols());
+
+            let mut features: Array2<f32> = Array2::zeros((config.n_mels, frames));
+            let copied: usize = frames.min(spectrogram.ncols());
+            features
+                .slice_mut(ndarray::s![.., ..copied])
+                .assign(&spectrogram.slice(ndarray::s![.., ..copied]));
+            if !mels_first {
+                features = features.reversed_axes().as_standard_layout().to_owned();
+            }
+
+            let mut shape: Vec<usize> = vec![1; rank - 2];
+            shape.extend_from_slice(features.shape());
+            let tensor: ArrayD<f32> = features
+                .into_shape_with_order(IxDyn(&shape))
+                .map_err(|e| AppError::Other(format!("Shape error: {}", e)))?;
+            Ok(Tensor::from_array(tensor)?.into_dyn())
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! The benchmark harness for `wasm32-wasip1`, with a wasi-nn host as the inference backend. Image
+//! decoding and preprocessing are the pure-Rust ones of the native build, and the tracker records
+//! the same operations and phases, so both deployments report comparable metrics.
+
+#[cfg(target_family = "wasm")]
+use {
+    clap::{Parser, ValueEnum},
+    image::DynamicImage,
+    ndarray::Array4,
+    rust_ml_benchmark::{
+        allocator::CountingAllocator,
+        bench_op,
+        error::AppError,
+        logging::{init_logging, Verbosity},
+        preprocess::{image_tensor, PreprocessConfig},
+        tracker::BenchmarkTracker,
+        wasi::{infer, load_graph, Encoding, Target, WasiPrediction, WasiReport},
+    },
+    wasi_nn::{Graph, GraphExecutionContext},
+};
+
+#[cfg(target_family = "wasm")]
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[cfg(target_family = "wasm")]
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Benchmark ML inference in WebAssembly through wasi-nn"
+)]
+struct Cli {
+    /// Path of the model, as seen by the Wasm runtime (e.g. a directory mapped with --dir)
+    model: String,
+
+    /// Path of the image to classify
+    image: String,
+
+    /// Number of inference runs
+    #[arg(long, default_value_t = 1)]
+    iterations: u32,
+
+    /// Graph encoding the host loads the model with: onnx, openvino, tensorflow, pytorch, tflite or autodetect
+    #[arg(long, default_value = "onnx")]
+    encoding: Encoding,
+
+    /// Device the host runs the model on: cpu, gpu, tpu or auto
+    #[arg(long, default_value = "cpu")]
+    target: Target,
+
+    /// Override the image preprocessing (size=WxH, batch, layout, scale, mean, std), as the native build
+    #[arg(long, value_name = "KEY=VALUE")]
+    preprocess: Vec<String>,
+
+    /// Number of f32 elements of the first output, which wasi-nn cannot query
+    #[arg(long, default_value_t = 1000)]
+    output_len: usize,
+
+    /// Format of the printed results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Log more details to stderr
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[cfg(target_family = "wasm")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[cfg(target_family = "wasm")]
+fn main() -> Result<(), AppError> {
+    let cli: Cli = Cli::parse();
+    init_logging(Verbosity::from_flags(cli.verbose, cli.quiet), false);
+
+    let mut config: PreprocessConfig = PreprocessConfig::default();
+    for option in &cli.preprocess {
+        let (key, value) = option
+            .split_once('=')
+            .ok_or_else(|| AppError::InputError(format!("expected KEY=VALUE, got '{}'", option)))?;
+        config.apply(key, value)?;
+    }
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+
+    // RED BOX: model and image loading
+    tracker.start_phase("RED BOX Phase");
+    let graph: Graph = bench_op!(
+        tracker,
+        "loadmodel",
+        load_graph(&cli.model, cli.encoding, cli.target)?
+    );
+    let mut context: GraphExecutionContext<'_> = graph.init_execution_context()?;
+    let image: DynamicImage = bench_op!(tracker, "readimg", image::open(&cli.image)?);
+    tracker.end_phase("RED BOX Phase");
+
+    // GREEN BOX: preprocessing, inference and post-processing
+    tracker.start_phase("GREEN BOX Phase");
+    let input: Array4<f32> = bench_op!(tracker, "Pre-processing", image_tensor(image, &config));
+    let mut output: Vec<f32> = vec![0.; cli.output_len];
+    let mut written: usize = 0;
+    for _ in 0..cli.iterations.max(1) {
+        written = bench_op!(
+            tracker,
+            "Inference",
+            infer(&mut context, &input, &mut output)?
+        );
+    }
+    output.truncate(written);
+    let prediction: Option<WasiPrediction> =
+        bench_op!(tracker, "Post-processing", WasiPrediction::new(&output));
+    tracker.end_phase("GREEN BOX Phase");
+
+    let report: WasiReport = WasiReport::new(
+        &cli.model,
+        cli.iterations,
+        (cli.encoding, cli.target),
+        &tracker,
+        prediction,
+    );
+    match cli.output {
+        OutputFormat::Text => {
+            tracker.print_all_metrics();
+            if let Some(prediction) = &report.output {
+                print!("{}", prediction);
+            }
+            if let Some(latency) = &report.latency {
+                print!("{}", latency);
+            }
+            if let Some(outliers) = &report.outliers {
+                print!("{}", outliers);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+    Ok(())
+}
+
+/// The wasi-nn host functions only exist inside a Wasm runtime.
+#[cfg(not(target_family = "wasm"))]
+fn main() {
+    eprintln!(
+        "rust-ml-benchmark-wasi runs in a wasi-nn runtime, build it with --target wasm32-wasip1"
+    );
+    std::process::exit(1);
+}
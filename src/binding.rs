@@ -0,0 +1,159 @@
+use crate::{
+    error::AppError,
+    stats::{mean, millis},
+    tracker::BenchmarkTracker,
+};
+use ort::{
+    io_binding::IoBinding,
+    memory::{AllocationDevice, Allocator, AllocatorType, MemoryInfo, MemoryType},
+    session::Session,
+    value::{DynTensor, DynTensorValueType, DynValue, ValueType},
+};
+use serde::{Deserialize, Serialize};
+use std::{str::FromStr, time::Duration};
+
+/// Operation name of the inference runs through the IOBinding.
+pub const BOUND_INFERENCE: &str = "Inference (IOBinding)";
+
+/// Device the bound inputs are copied to and the output buffers are allocated on. With `Cuda` the
+/// inputs are copied to device memory once when binding, so the bound runs read them from the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BindingDevice {
+    Cpu,
+    Cuda,
+}
+
+impl FromStr for BindingDevice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cpu" => Ok(BindingDevice::Cpu),
+            "cuda" => Ok(BindingDevice::Cuda),
+            other => Err(format!(
+                "unknown binding device '{}', expected cpu or cuda",
+                other
+            )),
+        }
+    }
+}
+
+impl BindingDevice {
+    fn allocation_device(self) -> AllocationDevice {
+        match self {
+            BindingDevice::Cpu => AllocationDevice::CPU,
+            BindingDevice::Cuda => AllocationDevice::CUDA,
+        }
+    }
+
+    fn memory_info(self, device_id: i32) -> Result<MemoryInfo, AppError> {
+        Ok(MemoryInfo::new(
+            self.allocation_device(),
+            device_id,
+            AllocatorType::Device,
+            MemoryType::Default,
+        )?)
+    }
+}
+
+/// Binds the inputs once and pre-allocates every output with a static shape on `device`, so the
+/// measured runs neither copy inputs nor allocate outputs. On a GPU the inputs are copied to the
+/// memory of device `device_id` first. Outputs with dynamic dimensions are left to ORT, which
+/// allocates them on the same device.
+pub fn bind_session(
+    model: &Session,
+    inputs: &[(String, DynValue)],
+    device: BindingDevice,
+    device_id: i32,
+) -> Result<IoBinding, AppError> {
+    let memory_info: MemoryInfo = device.memory_info(device_id)?;
+    let allocator: Allocator = Allocator::new(model, device.memory_info(device_id)?)?;
+    let mut binding: IoBinding = model.create_binding()?;
+
+    for (name, value) in inputs {
+        match device {
+            BindingDevice::Cpu => binding.bind_input(name.as_str(), value)?,
+            // The binding holds on to the device copy, so it lives as long as the binding
+            BindingDevice::Cuda => {
+                let tensor: DynTensor = value
+                    .downcast_ref::<DynTensorValueType>()?
+                    .to(device.allocation_device(), device_id)?;
+                binding.bind_input(name.as_str(), &tensor)?;
+            }
+        }
+    }
+
+    for output in &model.outputs {
+        match &output.output_type {
+            ValueType::Tensor { ty, shape, .. } if shape.iter().all(|&dim| dim > 0) => {
+                let buffer: DynTensor = DynTensor::new(&allocator, *ty, shape.clone())?;
+                binding.bind_output(output.name.as_str(), buffer)?;
+            }
+            _ => binding.bind_output_to_device(output.name.as_str(), &memory_info)?,
+        }
+    }
+
+    binding.synchronize_inputs()?;
+    Ok(binding)
+}
+
+/// One inference run through the binding, waiting for the outputs so device runs are fully timed.
+pub fn run_bound(model: &mut Session, binding: &IoBinding) -> Result<(), AppError> {
+    model.run_binding(binding)?;
+    binding.synchronize_outputs()?;
+    Ok(())
+}
+
+/// Mean latency of the bound runs against the plain `Session::run` inference runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct BindingComparison {
+    pub run_mean_ms: f64,
+    pub bound_mean_ms: f64,
+    pub run_count: usize,
+    pub bound_count: usize,
+}
+
+impl BindingComparison {
+    pub fn from_tracker(tracker: &BenchmarkTracker) -> Option<Self> {
+        let mean_ms = |name: &str| {
+            let times: Vec<Duration> = tracker.operation_times(name);
+            (!times.is_empty()).then(|| (millis(mean(&times)), times.len()))
+        };
+        let (run_mean_ms, run_count) = mean_ms("Inference")?;
+        let (bound_mean_ms, bound_count) = mean_ms(BOUND_INFERENCE)?;
+
+        Some(Self {
+            run_mean_ms,
+            bound_mean_ms,
+            run_count,
+            bound_count,
+        })
+    }
+
+    pub fn speedup_percent(&self) -> f64 {
+        if self.run_mean_ms > 0. {
+            (1. - self.bound_mean_ms / self.run_mean_ms) * 100.
+        } else {
+            0.
+        }
+    }
+}
+
+impl std::fmt::Display for BindingComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= IOBinding Metrics =============")?;
+        writeln!(
+            f,
+            "run(): {:.3} ms mean over {} runs",
+            self.run_mean_ms, self.run_count
+        )?;
+        writeln!(
+            f,
+            "IOBinding: {:.3} ms mean over {} runs",
+            self.bound_mean_ms, self.bound_count
+        )?;
+        writeln!(f, "Saved: {:.2}%", self.speedup_percent())?;
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,552 @@
+use crate::{
+    binding::BindingDevice, cuda::CudaOptions, environment::EnvironmentInfo, error::AppError,
+    metrics::format_bytes, model::ModelOptions, tensorrt::TensorRtOptions,
+};
+use half::f16;
+use ort::{
+    tensor::TensorElementType,
+    value::{DynValue, Tensor},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Version of the bundle layout, bumped when bundles written before can no longer be replayed.
+pub const BUNDLE_VERSION: u32 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const MODEL_FILE: &str = "model.onnx";
+
+/// Element types a bundle can hold, stored under their ONNX Runtime names.
+const ELEMENT_TYPES: [TensorElementType; 12] = [
+    TensorElementType::Float32,
+    TensorElementType::Float16,
+    TensorElementType::Float64,
+    TensorElementType::Uint8,
+    TensorElementType::Int8,
+    TensorElementType::Uint16,
+    TensorElementType::Int16,
+    TensorElementType::Uint32,
+    TensorElementType::Int32,
+    TensorElementType::Uint64,
+    TensorElementType::Int64,
+    TensorElementType::Bool,
+];
+
+/// A preprocessed input of the recorded run, saved as its raw little-endian elements.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleTensor {
+    pub name: String,
+    pub element_type: String,
+    pub shape: Vec<i64>,
+    /// File of the elements, relative to the bundle.
+    pub file: String,
+}
+
+/// What a bundle was recorded from: the model, the command line and the machine it ran on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub version: u32,
+    /// Run id of the recording, which replays carry so their results can be joined with it.
+    pub recorded_run: String,
+    /// Model path or URL as given to `record`, a copy of the file is in the bundle.
+    pub model: String,
+    pub model_size: u64,
+    pub task: String,
+    /// Arguments `record` was called with, preprocessing included.
+    pub command: Vec<String>,
+    /// `EnvironmentInfo` of the recording machine.
+    pub environment: Value,
+    /// Settings the recorded run resolved, missing from bundles recorded before they were saved.
+    #[serde(default)]
+    pub options: RecordedOptions,
+    pub inputs: Vec<BundleTensor>,
+}
+
+/// Session and execution provider settings of the recorded run, which `replay` applies again so the
+/// replay measures the model the way it was recorded.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordedOptions {
+    /// Run on the CPU only, without registering the CUDA execution provider.
+    pub cpu: bool,
+    pub cuda: CudaOptions,
+    /// TensorRT in front of CUDA, None when it was not used.
+    pub tensorrt: Option<TensorRtOptions>,
+    /// Intra-op threads and their affinities.
+    pub session: ModelOptions,
+    /// Cores the process was pinned to, e.g. `0-3`.
+    pub pin_cores: Option<String>,
+    pub io_binding: Option<BindingDevice>,
+    /// Directory of the optimized models, used by the replay too.
+    pub optimized_cache: Option<String>,
+}
+
+impl RecordedOptions {
+    /// Execution providers in order of preference, e.g. `TensorRT (fp16), CUDA`.
+    pub fn providers(&self) -> String {
+        let mut providers: Vec<String> = Vec::new();
+        if let Some(tensorrt) = &self.tensorrt {
+            let precisions: Vec<&str> = [(tensorrt.fp16, "fp16"), (tensorrt.int8, "int8")]
+                .into_iter()
+                .filter_map(|(enabled, precision)| enabled.then_some(precision))
+                .collect();
+            if precisions.is_empty() {
+                providers.push("TensorRT".to_string());
+            } else {
+                providers.push(format!("TensorRT ({})", precisions.join(", ")));
+            }
+        }
+        if !self.cpu {
+            providers.push("CUDA".to_string());
+        }
+        providers.push("CPU".to_string());
+        providers.join(", ")
+    }
+}
+
+/// What a bundle is recorded from, besides its inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct BundleSource<'a> {
+    pub run_id: &'a str,
+    pub model: &'a str,
+    /// Local copy of the model, the cached file for remote models.
+    pub model_file: &'a Path,
+    pub task: &'a str,
+    pub environment: &'a EnvironmentInfo,
+    pub options: &'a RecordedOptions,
+}
+
+/// A directory holding the model, its preprocessed inputs and a manifest, so the exact same workload
+/// can be measured again later or on another device, without decoding or preprocessing anything.
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    pub dir: PathBuf,
+    pub manifest: BundleManifest,
+}
+
+impl Bundle {
+    /// Writes a new bundle to `dir`, which must not exist yet or be empty.
+    pub fn record(
+        dir: &Path,
+        source: &BundleSource<'_>,
+        inputs: &[(String, DynValue)],
+    ) -> Result<Self, AppError> {
+        let write_error = |path: &Path, e: std::io::Error| {
+            AppError::Other(format!("Failed to write {}: {}", path.display(), e))
+        };
+        if fs::read_dir(dir).is_ok_and(|mut entries| entries.next().is_some()) {
+            return Err(AppError::InputError(format!(
+                "bundle directory {} is not empty",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(dir).map_err(|e| write_error(dir, e))?;
+
+        let model_path: PathBuf = dir.join(MODEL_FILE);
+        let model_size: u64 =
+            fs::copy(source.model_file, &model_path).map_err(|e| write_error(&model_path, e))?;
+
+        let mut tensors: Vec<BundleTensor> = Vec::new();
+        for (index, (name, value)) in inputs.iter().enumerate() {
+            let (element_type, shape, bytes) = tensor_bytes(name, value)?;
+            let file: String = format!("input{}.bin", index);
+            let path: PathBuf = dir.join(&file);
+            fs::write(&path, bytes).map_err(|e| write_error(&path, e))?;
+            tensors.push(BundleTensor {
+                name: name.clone(),
+                element_type: element_type.to_string(),
+                shape,
+                file,
+            });
+        }
+
+        let manifest: BundleManifest = BundleManifest {
+            version: BUNDLE_VERSION,
+            recorded_run: source.run_id.to_string(),
+            model: source.model.to_string(),
+            model_size,
+            task: source.task.to_string(),
+            command: std::env::args().skip(1).collect(),
+            environment: serde_json::to_value(source.environment)?,
+            options: source.options.clone(),
+            inputs: tensors,
+        };
+        let manifest_path: PathBuf = dir.join(MANIFEST_FILE);
+        fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)
+            .map_err(|e| write_error(&manifest_path, e))?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            manifest,
+        })
+    }
+
+    pub fn open(dir: &Path) -> Result<Self, AppError> {
+        let manifest_path: PathBuf = dir.join(MANIFEST_FILE);
+        let manifest: String = fs::read_to_string(&manifest_path).map_err(|e| {
+            AppError::InputError(format!("Failed to read {}: {}", manifest_path.display(), e))
+        })?;
+        let manifest: BundleManifest = serde_json::from_str(&manifest)?;
+        if manifest.version != BUNDLE_VERSION {
+            return Err(AppError::InputError(format!(
+                "bundle {} has version {}, this build replays version {}",
+                dir.display(),
+                manifest.version,
+                BUNDLE_VERSION
+            )));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            manifest,
+        })
+    }
+
+    pub fn model_path(&self) -> PathBuf {
+        self.dir.join(MODEL_FILE)
+    }
+
+    /// Reads the recorded inputs back into values ready to be fed to the model.
+    pub fn load_inputs(&self) -> Result<Vec<(String, DynValue)>, AppError> {
+        self.manifest
+            .inputs
+            .iter()
+            .map(|tensor| {
+                let path: PathBuf = self.dir.join(&tensor.file);
+                let bytes: Vec<u8> = fs::read(&path).map_err(|e| {
+                    AppError::InputError(format!("Failed to read {}: {}", path.display(), e))
+                })?;
+                Ok((tensor.name.clone(), tensor_value(tensor, &bytes)?))
+            })
+            .collect()
+    }
+
+    /// Fields of the environment that differ between the recording and `current`. The warnings are
+    /// left out, they are expected to change between runs.
+    pub fn environment_changes(
+        &self,
+        current: &EnvironmentInfo,
+    ) -> Result<Vec<EnvironmentChange>, AppError> {
+        let Value::Object(current) = serde_json::to_value(current)? else {
+            return Ok(Vec::new());
+        };
+        let recorded = |field: &str| self.manifest.environment.get(field).unwrap_or(&Value::Null);
+        Ok(current
+            .iter()
+            .filter(|(field, value)| *field != "warnings" && recorded(field) != *value)
+            .map(|(field, value)| EnvironmentChange {
+                field: field.clone(),
+                recorded: display_value(recorded(field)),
+                current: display_value(value),
+            })
+            .collect())
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Element types whose values are stored as their little-endian bytes, booleans as one byte.
+trait LeBytes: Sized {
+    const SIZE: usize;
+
+    fn append_to(&self, bytes: &mut Vec<u8>);
+
+    /// Reads an element from exactly `SIZE` bytes.
+    fn from_chunk(chunk: &[u8]) -> Self;
+}
+
+macro_rules! le_bytes {
+    ($($type:ty),*) => {$(
+        impl LeBytes for $type {
+            const SIZE: usize = std::mem::size_of::<$type>();
+
+            fn append_to(&self, bytes: &mut Vec<u8>) {
+                bytes.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn from_chunk(chunk: &[u8]) -> Self {
+                <$type>::from_le_bytes(chunk.try_into().expect("chunks of the element size"))
+            }
+        }
+    )*};
+}
+
+le_bytes!(f32, f16, f64, u8, i8, u16, i16, u32, i32, u64, i64);
+
+impl LeBytes for bool {
+    const SIZE: usize = 1;
+
+    fn append_to(&self, bytes: &mut Vec<u8>) {
+        bytes.push(*self as u8);
+    }
+
+    fn from_chunk(chunk: &[u8]) -> Self {
+        chunk[0] != 0
+    }
+}
+
+fn encode<T: LeBytes>(data: &[T]) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(data.len() * T::SIZE);
+    for element in data {
+        element.append_to(&mut bytes);
+    }
+    bytes
+}
+
+/// Elements of `tensor` stored in `bytes`, which must hold exactly as many as its shape.
+fn decode<T: LeBytes>(tensor: &BundleTensor, bytes: &[u8]) -> Result<Vec<T>, AppError> {
+    let elements: usize = tensor
+        .shape
+        .iter()
+        .map(|&dim| dim.max(0) as usize)
+        .product();
+    check_size(tensor, bytes, elements * T::SIZE)?;
+    Ok(bytes.chunks_exact(T::SIZE).map(T::from_chunk).collect())
+}
+
+/// Element type stored under its ONNX Runtime `name`.
+fn element_type_named(name: &str) -> Option<TensorElementType> {
+    ELEMENT_TYPES
+        .into_iter()
+        .find(|element_type| element_type.to_string() == name)
+}
+
+/// Element type, shape and little-endian elements of a tensor, as recorded in a bundle and returned
+/// in the `raw_output_contents` of the KServe server.
+pub fn tensor_bytes(
+    name: &str,
+    value: &DynValue,
+) -> Result<(TensorElementType, Vec<i64>, Vec<u8>), AppError> {
+    let element_type: TensorElementType = value
+        .dtype()
+        .tensor_type()
+        .filter(|element_type| ELEMENT_TYPES.contains(element_type))
+        .ok_or_else(|| {
+            AppError::InputError(format!(
+                "'{}' is {}, which has no raw element encoding",
+                name,
+                value.dtype()
+            ))
+        })?;
+    macro_rules! elements {
+        ($type:ty) => {{
+            let (shape, data) = value.try_extract_tensor::<$type>()?;
+            (shape.to_vec(), encode(data))
+        }};
+    }
+    let (shape, bytes) = match element_type {
+        TensorElementType::Float32 => elements!(f32),
+        TensorElementType::Float16 => elements!(f16),
+        TensorElementType::Float64 => elements!(f64),
+        TensorElementType::Uint8 => elements!(u8),
+        TensorElementType::Int8 => elements!(i8),
+        TensorElementType::Uint16 => elements!(u16),
+        TensorElementType::Int16 => elements!(i16),
+        TensorElementType::Uint32 => elements!(u32),
+        TensorElementType::Int32 => elements!(i32),
+        TensorElementType::Uint64 => elements!(u64),
+        TensorElementType::Int64 => elements!(i64),
+        _ => elements!(bool),
+    };
+    Ok((element_type, shape, bytes))
+}
+
+fn tensor_value(tensor: &BundleTensor, bytes: &[u8]) -> Result<DynValue, AppError> {
+    let element_type: TensorElementType =
+        element_type_named(&tensor.element_type).ok_or_else(|| {
+            AppError::InputError(format!(
+                "input '{}' has unknown element type {}",
+                tensor.name, tensor.element_type
+            ))
+        })?;
+    macro_rules! value {
+        ($type:ty) => {{
+            let data: Vec<$type> = decode(tensor, bytes)?;
+            Tensor::from_array((tensor.shape.clone(), data))?.into_dyn()
+        }};
+    }
+    let value: DynValue = match element_type {
+        TensorElementType::Float32 => value!(f32),
+        TensorElementType::Float16 => value!(f16),
+        TensorElementType::Float64 => value!(f64),
+        TensorElementType::Uint8 => value!(u8),
+        TensorElementType::Int8 => value!(i8),
+        TensorElementType::Uint16 => value!(u16),
+        TensorElementType::Int16 => value!(i16),
+        TensorElementType::Uint32 => value!(u32),
+        TensorElementType::Int32 => value!(i32),
+        TensorElementType::Uint64 => value!(u64),
+        TensorElementType::Int64 => value!(i64),
+        _ => value!(bool),
+    };
+    Ok(value)
+}
+
+fn check_size(tensor: &BundleTensor, bytes: &[u8], expected: usize) -> Result<(), AppError> {
+    if bytes.len() != expected {
+        return Err(AppError::InputShapeError(format!(
+            "input '{}' of shape {:?} needs {} bytes, {} has {}",
+            tensor.name,
+            tensor.shape,
+            expected,
+            tensor.file,
+            bytes.len()
+        )));
+    }
+    Ok(())
+}
+
+/// An environment field that differs between the recording and the replay, e.g. the ONNX Runtime
+/// build after an upgrade.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentChange {
+    pub field: String,
+    pub recorded: String,
+    pub current: String,
+}
+
+/// Where the inputs of a replayed run came from and what changed since they were recorded, so a
+/// difference in latency can be put down to the runtime or the machine rather than the inputs.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayReport {
+    pub bundle: String,
+    pub recorded_run: String,
+    pub model: String,
+    pub inputs: usize,
+    pub changes: Vec<EnvironmentChange>,
+}
+
+impl ReplayReport {
+    pub fn new(bundle: &Bundle, current: &EnvironmentInfo) -> Result<Self, AppError> {
+        Ok(Self {
+            bundle: bundle.dir.display().to_string(),
+            recorded_run: bundle.manifest.recorded_run.clone(),
+            model: bundle.manifest.model.clone(),
+            inputs: bundle.manifest.inputs.len(),
+            changes: bundle.environment_changes(current)?,
+        })
+    }
+}
+
+impl std::fmt::Display for BundleManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Bundle =============")?;
+        writeln!(f, "Recorded run: {}", self.recorded_run)?;
+        writeln!(
+            f,
+            "Model: {} ({})",
+            self.model,
+            format_bytes(self.model_size)
+        )?;
+        writeln!(f, "Task: {}", self.task)?;
+        writeln!(f, "Execution providers: {}", self.options.providers())?;
+        if let Some(device) = self.options.io_binding {
+            writeln!(f, "IOBinding: {:?}", device)?;
+        }
+        for input in &self.inputs {
+            writeln!(
+                f,
+                "Input {}: {}{:?} in {}",
+                input.name, input.element_type, input.shape, input.file
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
+
+impl std::fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Replay Metrics =============")?;
+        writeln!(f, "Bundle: {}", self.bundle)?;
+        writeln!(f, "Recorded run: {}", self.recorded_run)?;
+        writeln!(f, "Model: {}, {} recorded inputs", self.model, self.inputs)?;
+        if self.changes.is_empty() {
+            writeln!(f, "Environment unchanged since the recording")?;
+        }
+        for change in &self.changes {
+            writeln!(
+                f,
+                "{}: {} -> {}",
+                change.field, change.recorded, change.current
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(element_type: TensorElementType, shape: Vec<i64>) -> BundleTensor {
+        BundleTensor {
+            name: "input".to_string(),
+            element_type: element_type.to_string(),
+            shape,
+            file: "input0.bin".to_string(),
+        }
+    }
+
+    fn round_trip<T: LeBytes + PartialEq + std::fmt::Debug>(
+        element_type: TensorElementType,
+        data: Vec<T>,
+    ) {
+        let tensor: BundleTensor = tensor(element_type, vec![1, data.len() as i64]);
+        let bytes: Vec<u8> = encode(&data);
+        assert_eq!(bytes.len(), data.len() * T::SIZE);
+        assert_eq!(decode::<T>(&tensor, &bytes).unwrap(), data);
+        assert_eq!(element_type_named(&tensor.element_type), Some(element_type));
+    }
+
+    #[test]
+    fn elements_survive_a_round_trip() {
+        round_trip(
+            TensorElementType::Float32,
+            vec![0., -1.5, f32::MAX, f32::MIN_POSITIVE],
+        );
+        round_trip(
+            TensorElementType::Float16,
+            vec![f16::from_f32(0.5), f16::from_f32(-2.), f16::MAX],
+        );
+        round_trip(TensorElementType::Float64, vec![std::f64::consts::PI, -0.]);
+        round_trip(TensorElementType::Uint8, vec![0u8, 127, 255]);
+        round_trip(TensorElementType::Int8, vec![i8::MIN, -1, i8::MAX]);
+        round_trip(TensorElementType::Uint16, vec![0u16, 256, u16::MAX]);
+        round_trip(TensorElementType::Int16, vec![i16::MIN, 1, i16::MAX]);
+        round_trip(TensorElementType::Uint32, vec![0u32, 1 << 20, u32::MAX]);
+        round_trip(TensorElementType::Int32, vec![i32::MIN, -7, i32::MAX]);
+        round_trip(TensorElementType::Uint64, vec![0u64, 1 << 40, u64::MAX]);
+        round_trip(TensorElementType::Int64, vec![i64::MIN, 42, i64::MAX]);
+        round_trip(TensorElementType::Bool, vec![true, false, true]);
+    }
+
+    #[test]
+    fn elements_are_little_endian() {
+        assert_eq!(encode(&[1u32, 0x0102_0304]), [1, 0, 0, 0, 4, 3, 2, 1]);
+        assert_eq!(encode(&[true, false]), [1, 0]);
+    }
+
+    #[test]
+    fn a_file_of_the_wrong_size_is_refused() {
+        let tensor: BundleTensor = tensor(TensorElementType::Float32, vec![2, 3]);
+        assert!(decode::<f32>(&tensor, &[0; 6 * 4]).is_ok());
+        assert!(matches!(
+            decode::<f32>(&tensor, &[0; 5 * 4]),
+            Err(AppError::InputShapeError(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_element_types_are_not_found() {
+        assert_eq!(element_type_named("String"), None);
+        assert_eq!(element_type_named("complex64"), None);
+    }
+}
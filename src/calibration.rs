@@ -0,0 +1,326 @@
+#[cfg(feature = "flamegraph")]
+use crate::flamegraph::FlamegraphProfiler;
+use crate::{
+    allocator,
+    cuda::{GpuMemoryMonitor, SAMPLING_PERIOD_MS},
+    error::AppError,
+    metrics::{current_rss, Metrics},
+    stats::{mean, percentile},
+    thermal::ThermalMonitor,
+    tracker::{BenchmarkTracker, SpanKind},
+};
+use serde::Serialize;
+#[cfg(feature = "flamegraph")]
+use std::sync::{Arc, Mutex};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Phases open while measuring the cost of an operation inside nested phases, as in a run.
+const NESTED_PHASES: [&str; 3] = ["Calibration", "RED BOX Phase", "GREEN BOX Phase"];
+/// Spin iterations of the probe workload timed with and without a sampler running.
+const PROBE_SPINS: u64 = 100_000;
+/// Sampling periods a sampler runs for while the probe is timed, so it is caught sampling.
+const SAMPLED_PERIODS: u32 = 4;
+/// How long the probe is timed under the `--flamegraph` profiler, about a thousand samples.
+#[cfg(feature = "flamegraph")]
+const PROFILED_WINDOW: Duration = Duration::from_secs(1);
+/// Bytes of the heap allocation timed, small enough for the allocator's thread cache.
+const ALLOCATION_SIZE: usize = 64;
+
+/// Cost of one call of a piece of the harness, from timing it `calls` times in a row.
+#[derive(Debug, Clone, Serialize)]
+pub struct OverheadStat {
+    pub name: String,
+    pub calls: usize,
+    pub mean_ns: f64,
+    pub p50_ns: f64,
+    pub p99_ns: f64,
+}
+
+impl OverheadStat {
+    fn measure(name: &str, calls: usize, mut call: impl FnMut()) -> Self {
+        let times: Vec<Duration> = (0..calls)
+            .map(|_| {
+                let start: Instant = Instant::now();
+                call();
+                start.elapsed()
+            })
+            .collect();
+        Self::from_times(name, times)
+    }
+
+    /// Like `measure`, but keeps calling until `window` has passed as well.
+    fn measure_for(name: &str, calls: usize, window: Duration, mut call: impl FnMut()) -> Self {
+        let start: Instant = Instant::now();
+        let mut times: Vec<Duration> = Vec::with_capacity(calls);
+        while times.len() < calls || start.elapsed() < window {
+            let call_start: Instant = Instant::now();
+            call();
+            times.push(call_start.elapsed());
+        }
+        Self::from_times(name, times)
+    }
+
+    fn from_times(name: &str, mut times: Vec<Duration>) -> Self {
+        times.sort();
+        let nanos = |time: Duration| time.as_nanos() as f64;
+        Self {
+            name: name.to_string(),
+            calls: times.len(),
+            mean_ns: nanos(mean(&times)),
+            p50_ns: nanos(percentile(&times, 0.5)),
+            p99_ns: nanos(percentile(&times, 0.99)),
+        }
+    }
+}
+
+/// How much a background sampler slows down a fixed CPU-bound probe workload.
+#[derive(Debug, Clone, Serialize)]
+pub struct SamplerOverhead {
+    pub name: String,
+    pub baseline_mean_ns: f64,
+    pub sampled_mean_ns: f64,
+    pub overhead_percent: f64,
+    /// Why the sampler could not be measured, e.g. no `nvidia-smi`.
+    pub error: Option<String>,
+}
+
+/// What the harness itself costs, so operations close to it can be told apart from noise.
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationReport {
+    pub iterations: usize,
+    /// Time of every measured call, outside of the tracker.
+    pub calls: Vec<OverheadStat>,
+    /// Wall-clock time the tracker records for an operation that does nothing: the floor of every
+    /// operation, which can be subtracted from sub-millisecond ones.
+    pub operation_floor: OverheadStat,
+    pub samplers: Vec<SamplerOverhead>,
+}
+
+/// Which background samplers to measure on top of the tracker itself.
+#[derive(Debug, Clone, Copy)]
+pub struct CalibrationConfig {
+    pub iterations: usize,
+    /// Sampling period of the thermal monitor, as given to `--thermal-interval`.
+    pub thermal_interval: Duration,
+    /// Also measure the `nvidia-smi` GPU memory sampler of this device.
+    pub gpu_device: Option<i32>,
+}
+
+pub fn calibrate(config: &CalibrationConfig) -> CalibrationReport {
+    let iterations: usize = config.iterations.max(1);
+    let mut calls: Vec<OverheadStat> = Vec::new();
+
+    calls.push(OverheadStat::measure(
+        "Metrics::current",
+        iterations,
+        || {
+            black_box(Metrics::current(String::new()));
+        },
+    ));
+    calls.push(OverheadStat::measure(
+        "RSS read (statm)",
+        iterations,
+        || {
+            black_box(current_rss());
+        },
+    ));
+    // Counting is fixed when the process starts, `--count-allocations` measures the counted path
+    calls.push(OverheadStat::measure(
+        if allocator::counting() {
+            "allocation + free (counted)"
+        } else {
+            "allocation + free (uncounted)"
+        },
+        iterations,
+        || {
+            black_box(Vec::<u8>::with_capacity(ALLOCATION_SIZE));
+        },
+    ));
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    calls.push(OverheadStat::measure(
+        "operation (start + finish)",
+        iterations,
+        || {
+            tracker.start_operation("calibration");
+            tracker.finish_operation();
+        },
+    ));
+    let operation_floor: OverheadStat = OverheadStat::from_times(
+        "recorded empty operation",
+        tracker
+            .spans()
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation)
+            .map(|span| span.metrics.wall_clock_time)
+            .collect(),
+    );
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    for phase in NESTED_PHASES {
+        tracker.start_phase(phase);
+    }
+    calls.push(OverheadStat::measure(
+        "operation in 3 nested phases",
+        iterations,
+        || {
+            tracker.start_operation("calibration");
+            tracker.finish_operation();
+        },
+    ));
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    calls.push(OverheadStat::measure(
+        "phase (start + end)",
+        iterations,
+        || {
+            tracker.start_phase("calibration");
+            tracker.end_phase("calibration");
+        },
+    ));
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    tracker.set_thread_sampling(true);
+    calls.push(OverheadStat::measure(
+        "operation with --thread-cpu",
+        iterations,
+        || {
+            tracker.start_operation("calibration");
+            tracker.finish_operation();
+        },
+    ));
+
+    let probes: usize = (iterations / 10).max(1);
+    let probe_window = |window: Duration| OverheadStat::measure_for("probe", probes, window, probe);
+    let mut samplers: Vec<SamplerOverhead> = Vec::new();
+
+    // The first probes also ramp up the clock frequency, which would pass for a negative overhead
+    let window: Duration = config.thermal_interval * SAMPLED_PERIODS;
+    probe_window(window);
+    let baseline: OverheadStat = probe_window(window);
+    let thermal: ThermalMonitor = ThermalMonitor::start(config.thermal_interval);
+    let sampled: OverheadStat = probe_window(window);
+    thermal.finish(&BenchmarkTracker::new());
+    samplers.push(SamplerOverhead::new(
+        &format!("--thermal every {:?}", config.thermal_interval),
+        &baseline,
+        Ok(sampled),
+    ));
+
+    if let Some(device_id) = config.gpu_device {
+        let window: Duration = Duration::from_millis(SAMPLING_PERIOD_MS.into()) * SAMPLED_PERIODS;
+        let baseline: OverheadStat = probe_window(window);
+        let sampled: Result<OverheadStat, AppError> =
+            GpuMemoryMonitor::start(device_id).map(|monitor| {
+                let sampled: OverheadStat = probe_window(window);
+                monitor.finish();
+                sampled
+            });
+        samplers.push(SamplerOverhead::new("--gpu-memory", &baseline, sampled));
+    }
+
+    // The profiler samples while an operation it was given is open, as with --flamegraph-operation
+    #[cfg(feature = "flamegraph")]
+    {
+        let baseline: OverheadStat = probe_window(PROFILED_WINDOW);
+        let profiler: Arc<Mutex<FlamegraphProfiler>> = Arc::new(Mutex::new(
+            FlamegraphProfiler::new(Some("calibration".to_string())),
+        ));
+        let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+        tracker.add_hook(profiler.clone());
+        tracker.start_operation("calibration");
+        let sampled: OverheadStat = probe_window(PROFILED_WINDOW);
+        tracker.finish_operation();
+        let sampled: Result<OverheadStat, AppError> = match profiler.lock().unwrap().error() {
+            Some(error) => Err(AppError::SystemError(format!("CPU profiler: {}", error))),
+            None => Ok(sampled),
+        };
+        samplers.push(SamplerOverhead::new("--flamegraph", &baseline, sampled));
+    }
+
+    CalibrationReport {
+        iterations,
+        calls,
+        operation_floor,
+        samplers,
+    }
+}
+
+/// Fixed CPU-bound work standing in for an operation.
+fn probe() {
+    let mut value: u64 = 0;
+    for i in 0..PROBE_SPINS {
+        value = black_box(value.wrapping_mul(31).wrapping_add(i));
+    }
+    black_box(value);
+}
+
+impl SamplerOverhead {
+    fn new(name: &str, baseline: &OverheadStat, sampled: Result<OverheadStat, AppError>) -> Self {
+        match sampled {
+            Ok(sampled) => Self {
+                name: name.to_string(),
+                baseline_mean_ns: baseline.mean_ns,
+                sampled_mean_ns: sampled.mean_ns,
+                overhead_percent: if baseline.mean_ns > 0. {
+                    (sampled.mean_ns / baseline.mean_ns - 1.) * 100.
+                } else {
+                    0.
+                },
+                error: None,
+            },
+            Err(e) => Self {
+                name: name.to_string(),
+                baseline_mean_ns: baseline.mean_ns,
+                sampled_mean_ns: 0.,
+                overhead_percent: 0.,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for CalibrationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let micros = |nanos: f64| format!("{:.3} µs", nanos / 1000.);
+
+        writeln!(f, "============= Calibration Metrics =============")?;
+        writeln!(f, "Calls per measurement: {}", self.iterations)?;
+        for stat in self
+            .calls
+            .iter()
+            .chain(std::iter::once(&self.operation_floor))
+        {
+            writeln!(
+                f,
+                "{:<30} mean {:>12}  p50 {:>12}  p99 {:>12}",
+                stat.name,
+                micros(stat.mean_ns),
+                micros(stat.p50_ns),
+                micros(stat.p99_ns)
+            )?;
+        }
+        for sampler in &self.samplers {
+            match &sampler.error {
+                Some(error) => writeln!(f, "{:<30} not measured: {}", sampler.name, error)?,
+                None => writeln!(
+                    f,
+                    "{:<30} probe {} -> {} ({:+.2}%)",
+                    sampler.name,
+                    micros(sampler.baseline_mean_ns),
+                    micros(sampler.sampled_mean_ns),
+                    sampler.overhead_percent
+                )?,
+            }
+        }
+        writeln!(
+            f,
+            "Operations under {} are within 10x of the harness overhead",
+            micros(self.operation_floor.p99_ns * 10.)
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
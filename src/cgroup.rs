@@ -0,0 +1,217 @@
+use crate::{environment::read_trimmed, metrics::format_bytes};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// cgroup v1 reports "no limit" as a page-aligned i64::MAX instead of a keyword.
+const V1_UNLIMITED: u64 = 1 << 60;
+
+/// CPU bandwidth counters of the cgroup, from `cpu.stat`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CpuStat {
+    pub nr_periods: u64,
+    pub nr_throttled: u64,
+    pub throttled_time: Duration,
+}
+
+impl CpuStat {
+    pub fn diff(&self, prev: &Self) -> Self {
+        Self {
+            nr_periods: self.nr_periods.saturating_sub(prev.nr_periods),
+            nr_throttled: self.nr_throttled.saturating_sub(prev.nr_throttled),
+            throttled_time: self.throttled_time.saturating_sub(prev.throttled_time),
+        }
+    }
+}
+
+/// Resource limits of the cgroup the process runs in.
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupInfo {
+    pub version: u8,
+    pub path: String,
+    pub memory_limit: Option<u64>,
+    /// CPU bandwidth quota in cores, e.g. 1.5 for `--cpus=1.5`.
+    pub cpu_quota: Option<f64>,
+    #[serde(skip)]
+    memory_dir: PathBuf,
+    #[serde(skip)]
+    cpu_dir: PathBuf,
+}
+
+impl CgroupInfo {
+    /// Finds the cgroup of the current process, or `None` when cgroups are not mounted.
+    pub fn detect() -> Option<Self> {
+        let membership: String = fs::read_to_string("/proc/self/cgroup").ok()?;
+
+        // v2 has a single line for the unified hierarchy: "0::/path"
+        if let Some(path) = membership
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .filter(|_| Path::new(CGROUP_ROOT).join("cgroup.controllers").exists())
+        {
+            let dir: PathBuf = controller_dir(Path::new(CGROUP_ROOT), path);
+            return Some(Self {
+                version: 2,
+                path: path.to_string(),
+                memory_limit: read_trimmed(&dir.join("memory.max").to_string_lossy())
+                    .and_then(|max| max.parse::<u64>().ok()),
+                cpu_quota: read_trimmed(&dir.join("cpu.max").to_string_lossy()).and_then(|max| {
+                    let (quota, period) = max.split_once(' ')?;
+                    Some(quota.parse::<f64>().ok()? / period.parse::<f64>().ok()?)
+                }),
+                memory_dir: dir.clone(),
+                cpu_dir: dir,
+            });
+        }
+
+        // v1 has one line per hierarchy: "4:memory:/path", "3:cpu,cpuacct:/path"
+        let hierarchy = |controller: &str| {
+            membership.lines().find_map(|line| {
+                let mut fields = line.splitn(3, ':');
+                let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+                controllers
+                    .split(',')
+                    .any(|name| name == controller)
+                    .then(|| (controllers.to_string(), path.to_string()))
+            })
+        };
+        let (memory_mount, memory_path) = hierarchy("memory")?;
+        let memory_dir: PathBuf =
+            controller_dir(&Path::new(CGROUP_ROOT).join(memory_mount), &memory_path);
+        let cpu_dir: PathBuf = match hierarchy("cpu") {
+            Some((cpu_mount, cpu_path)) => {
+                controller_dir(&Path::new(CGROUP_ROOT).join(cpu_mount), &cpu_path)
+            }
+            None => memory_dir.clone(),
+        };
+        let read_number = |dir: &Path, file: &str| {
+            read_trimmed(&dir.join(file).to_string_lossy()).and_then(|n| n.parse::<i64>().ok())
+        };
+
+        Some(Self {
+            version: 1,
+            path: memory_path,
+            memory_limit: read_number(&memory_dir, "memory.limit_in_bytes")
+                .map(|limit| limit as u64)
+                .filter(|&limit| limit < V1_UNLIMITED),
+            cpu_quota: match (
+                read_number(&cpu_dir, "cpu.cfs_quota_us"),
+                read_number(&cpu_dir, "cpu.cfs_period_us"),
+            ) {
+                (Some(quota), Some(period)) if quota > 0 && period > 0 => {
+                    Some(quota as f64 / period as f64)
+                }
+                _ => None,
+            },
+            memory_dir,
+            cpu_dir,
+        })
+    }
+
+    /// Whether the cgroup restricts memory or CPU, i.e. the host-wide numbers do not apply.
+    pub fn is_limited(&self) -> bool {
+        self.memory_limit.is_some() || self.cpu_quota.is_some()
+    }
+
+    pub fn cpu_stat(&self) -> CpuStat {
+        let content: String = fs::read_to_string(self.cpu_dir.join("cpu.stat")).unwrap_or_default();
+        let mut stat: CpuStat = CpuStat::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(value) = value.trim().parse::<u64>() else {
+                continue;
+            };
+            match key {
+                "nr_periods" => stat.nr_periods = value,
+                "nr_throttled" => stat.nr_throttled = value,
+                "throttled_usec" => stat.throttled_time = Duration::from_micros(value),
+                "throttled_time" => stat.throttled_time = Duration::from_nanos(value),
+                _ => {}
+            }
+        }
+        stat
+    }
+
+    /// Peak memory usage of the whole cgroup, when the kernel tracks it.
+    pub fn memory_peak(&self) -> Option<u64> {
+        let file: &str = if self.version == 2 {
+            "memory.peak"
+        } else {
+            "memory.max_usage_in_bytes"
+        };
+        read_trimmed(&self.memory_dir.join(file).to_string_lossy())
+            .and_then(|peak| peak.parse::<u64>().ok())
+    }
+}
+
+/// Joins the cgroup path onto the controller mount. Inside a cgroup namespace the mount already is the
+/// process' own cgroup and the joined path does not exist.
+fn controller_dir(mount: &Path, path: &str) -> PathBuf {
+    let joined: PathBuf = mount.join(path.trim_start_matches('/'));
+    if joined.exists() {
+        joined
+    } else {
+        mount.to_path_buf()
+    }
+}
+
+/// Container resource usage over the measured run.
+#[derive(Debug, Clone, Serialize)]
+pub struct CgroupReport {
+    pub cgroup: CgroupInfo,
+    /// Peak RSS of the process, in bytes.
+    pub peak_rss: u64,
+    pub memory_peak: Option<u64>,
+    pub throttling: CpuStat,
+}
+
+impl CgroupReport {
+    pub fn rss_percent_of_limit(&self) -> Option<f64> {
+        self.cgroup
+            .memory_limit
+            .filter(|&limit| limit > 0)
+            .map(|limit| self.peak_rss as f64 / limit as f64 * 100.)
+    }
+}
+
+impl std::fmt::Display for CgroupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Container Metrics =============")?;
+        writeln!(f, "Cgroup: v{} {}", self.cgroup.version, self.cgroup.path)?;
+        match self.cgroup.memory_limit {
+            Some(limit) => writeln!(f, "Memory Limit: {}", format_bytes(limit))?,
+            None => writeln!(f, "Memory Limit: -")?,
+        }
+        match self.cgroup.cpu_quota {
+            Some(quota) => writeln!(f, "CPU Quota: {:.2} cores", quota)?,
+            None => writeln!(f, "CPU Quota: -")?,
+        }
+        match self.rss_percent_of_limit() {
+            Some(percent) => writeln!(
+                f,
+                "Peak RSS: {} ({:.2}% of limit)",
+                format_bytes(self.peak_rss),
+                percent
+            )?,
+            None => writeln!(f, "Peak RSS: {}", format_bytes(self.peak_rss))?,
+        }
+        if let Some(peak) = self.memory_peak {
+            writeln!(f, "Cgroup Memory Peak: {}", format_bytes(peak))?;
+        }
+        writeln!(
+            f,
+            "Throttled: {} of {} periods ({:?})",
+            self.throttling.nr_throttled,
+            self.throttling.nr_periods,
+            self.throttling.throttled_time
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,320 @@
+use crate::error::AppError;
+use ort::execution_providers::{
+    cuda::CuDNNConvAlgorithmSearch, ArenaExtendStrategy, CUDAExecutionProvider,
+    ExecutionProviderDispatch,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    str::FromStr,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// Period `nvidia-smi` reports the memory used on the device at.
+pub const SAMPLING_PERIOD_MS: u32 = 100;
+/// Longest wait for the first sample, which is the baseline the run is compared to.
+const FIRST_SAMPLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConvAlgorithmSearch {
+    Exhaustive,
+    Heuristic,
+    Default,
+}
+
+impl FromStr for ConvAlgorithmSearch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "exhaustive" => Ok(ConvAlgorithmSearch::Exhaustive),
+            "heuristic" => Ok(ConvAlgorithmSearch::Heuristic),
+            "default" => Ok(ConvAlgorithmSearch::Default),
+            other => Err(format!(
+                "unknown cuDNN convolution algorithm search '{}', expected exhaustive, heuristic or default",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ConvAlgorithmSearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConvAlgorithmSearch::Exhaustive => "exhaustive",
+            ConvAlgorithmSearch::Heuristic => "heuristic",
+            ConvAlgorithmSearch::Default => "default",
+        })
+    }
+}
+
+impl From<ConvAlgorithmSearch> for CuDNNConvAlgorithmSearch {
+    fn from(search: ConvAlgorithmSearch) -> Self {
+        match search {
+            ConvAlgorithmSearch::Exhaustive => CuDNNConvAlgorithmSearch::Exhaustive,
+            ConvAlgorithmSearch::Heuristic => CuDNNConvAlgorithmSearch::Heuristic,
+            ConvAlgorithmSearch::Default => CuDNNConvAlgorithmSearch::Default,
+        }
+    }
+}
+
+/// How the CUDA memory arena grows when it runs out of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArenaStrategy {
+    NextPowerOfTwo,
+    SameAsRequested,
+}
+
+impl FromStr for ArenaStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "next-power-of-two" => Ok(ArenaStrategy::NextPowerOfTwo),
+            "same-as-requested" => Ok(ArenaStrategy::SameAsRequested),
+            other => Err(format!(
+                "unknown arena extend strategy '{}', expected next-power-of-two or same-as-requested",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ArenaStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArenaStrategy::NextPowerOfTwo => "next-power-of-two",
+            ArenaStrategy::SameAsRequested => "same-as-requested",
+        })
+    }
+}
+
+impl From<ArenaStrategy> for ArenaExtendStrategy {
+    fn from(strategy: ArenaStrategy) -> Self {
+        match strategy {
+            ArenaStrategy::NextPowerOfTwo => ArenaExtendStrategy::NextPowerOfTwo,
+            ArenaStrategy::SameAsRequested => ArenaExtendStrategy::SameAsRequested,
+        }
+    }
+}
+
+/// CUDA execution provider settings that can be changed from the command line. Unset settings keep
+/// the defaults of ONNX Runtime.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CudaOptions {
+    pub device_id: Option<i32>,
+    /// Largest size of the CUDA memory arena, in bytes.
+    pub memory_limit: Option<usize>,
+    pub conv_algorithm_search: Option<ConvAlgorithmSearch>,
+    pub arena_extend_strategy: Option<ArenaStrategy>,
+    /// Captures the kernels of the first run in a CUDA graph and replays it afterwards. The inputs
+    /// and outputs have to stay at the same addresses, as with `--io-binding cuda`.
+    pub cuda_graph: bool,
+}
+
+impl CudaOptions {
+    pub fn execution_provider(&self) -> ExecutionProviderDispatch {
+        let mut provider: CUDAExecutionProvider = CUDAExecutionProvider::default();
+        if let Some(device_id) = self.device_id {
+            provider = provider.with_device_id(device_id);
+        }
+        if let Some(limit) = self.memory_limit {
+            provider = provider.with_memory_limit(limit);
+        }
+        if let Some(search) = self.conv_algorithm_search {
+            provider = provider.with_conv_algorithm_search(search.into());
+        }
+        if let Some(strategy) = self.arena_extend_strategy {
+            provider = provider.with_arena_extend_strategy(strategy.into());
+        }
+        if self.cuda_graph {
+            provider = provider.with_cuda_graph(true);
+        }
+        provider.build()
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.device_id.is_none()
+            && self.memory_limit.is_none()
+            && self.conv_algorithm_search.is_none()
+            && self.arena_extend_strategy.is_none()
+            && !self.cuda_graph
+    }
+}
+
+/// Samples the memory used on one GPU through `nvidia-smi` until it is finished. The memory of the
+/// device is counted rather than that of the process, as containers hide the host PIDs
+/// `nvidia-smi` reports per process, so other processes on the GPU count too. Allocations shorter
+/// than the sampling period can be missed. Jetson boards have no `nvidia-smi`, the monitor fails to
+/// start there.
+pub struct GpuMemoryMonitor {
+    device_id: i32,
+    child: Child,
+    baseline_mib: u64,
+    total_mib: u64,
+    sampler: JoinHandle<(u64, usize)>,
+}
+
+impl GpuMemoryMonitor {
+    /// Waits for the first sample, taken before the model allocates anything, as the baseline.
+    pub fn start(device_id: i32) -> Result<Self, AppError> {
+        let unavailable =
+            |reason: String| AppError::SystemError(format!("GPU memory sampling: {}", reason));
+
+        let mut child: Child = Command::new("nvidia-smi")
+            .args([
+                format!("--id={}", device_id),
+                "--query-gpu=memory.used,memory.total".to_string(),
+                "--format=csv,noheader,nounits".to_string(),
+                format!("--loop-ms={}", SAMPLING_PERIOD_MS),
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| {
+                unavailable(format!(
+                    "failed to run nvidia-smi, which Jetson boards do not have: {}",
+                    e
+                ))
+            })?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (first, first_sample) = mpsc::channel::<(u64, u64)>();
+        let sampler: JoinHandle<(u64, usize)> = thread::spawn(move || {
+            let (mut peak, mut samples): (u64, usize) = (0, 0);
+            // nvidia-smi prints until it is killed, which closes its output and ends the loop
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let Some((used, total)) = line.split_once(',') else {
+                    continue;
+                };
+                let (Ok(used), Ok(total)) = (used.trim().parse::<u64>(), total.trim().parse())
+                else {
+                    continue;
+                };
+                if samples == 0 {
+                    let _ = first.send((used, total));
+                }
+                peak = peak.max(used);
+                samples += 1;
+            }
+            (peak, samples)
+        });
+
+        match first_sample.recv_timeout(FIRST_SAMPLE_TIMEOUT) {
+            Ok((baseline_mib, total_mib)) => Ok(Self {
+                device_id,
+                child,
+                baseline_mib,
+                total_mib,
+                sampler,
+            }),
+            Err(reason) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(unavailable(match reason {
+                    RecvTimeoutError::Timeout => "nvidia-smi reported nothing".to_string(),
+                    RecvTimeoutError::Disconnected => {
+                        format!("nvidia-smi found no GPU {}", device_id)
+                    }
+                }))
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> GpuMemoryReport {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let (peak_mib, samples) = self.sampler.join().unwrap_or_default();
+        let peak_mib: u64 = peak_mib.max(self.baseline_mib);
+        GpuMemoryReport {
+            device_id: self.device_id,
+            baseline_mib: self.baseline_mib,
+            peak_mib,
+            peak_increase_mib: peak_mib - self.baseline_mib,
+            total_mib: self.total_mib,
+            samples,
+            sampling_period_ms: SAMPLING_PERIOD_MS,
+        }
+    }
+}
+
+/// GPU memory high-water mark of a run, for `--gpu-memory`. It is the memory used on the whole
+/// device by every process, sampled every `sampling_period_ms`, so it is not the run's own usage and
+/// misses spikes between two samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct GpuMemoryReport {
+    pub device_id: i32,
+    /// Memory used on the device before ONNX Runtime was initialized.
+    pub baseline_mib: u64,
+    pub peak_mib: u64,
+    /// Peak above the baseline: what the run allocated at most, with the CUDA context.
+    pub peak_increase_mib: u64,
+    pub total_mib: u64,
+    pub samples: usize,
+    pub sampling_period_ms: u32,
+}
+
+/// CUDA settings of a run and the GPU memory it used.
+#[derive(Debug, Clone, Serialize)]
+pub struct CudaReport {
+    pub options: CudaOptions,
+    pub memory: Option<GpuMemoryReport>,
+}
+
+impl std::fmt::Display for CudaReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_default =
+            |value: Option<String>| value.unwrap_or_else(|| "ONNX Runtime default".to_string());
+        let options: &CudaOptions = &self.options;
+
+        writeln!(f, "============= CUDA Metrics =============")?;
+        writeln!(f, "Device: {}", options.device_id.unwrap_or(0))?;
+        writeln!(
+            f,
+            "Memory limit: {}",
+            or_default(options.memory_limit.map(|limit| format!("{} bytes", limit)))
+        )?;
+        writeln!(
+            f,
+            "cuDNN conv algorithm search: {}",
+            or_default(
+                options
+                    .conv_algorithm_search
+                    .map(|search| search.to_string())
+            )
+        )?;
+        writeln!(
+            f,
+            "Arena extend strategy: {}",
+            or_default(
+                options
+                    .arena_extend_strategy
+                    .map(|strategy| strategy.to_string())
+            )
+        )?;
+        writeln!(
+            f,
+            "CUDA graph: {}",
+            if options.cuda_graph { "on" } else { "off" }
+        )?;
+        if let Some(memory) = &self.memory {
+            writeln!(
+                f,
+                "GPU memory (whole device, all processes): peak {} MiB of {} MiB, {} MiB above the {} MiB in use before the run ({} samples every {} ms, shorter spikes are missed)",
+                memory.peak_mib,
+                memory.total_mib,
+                memory.peak_increase_mib,
+                memory.baseline_mib,
+                memory.samples,
+                memory.sampling_period_ms
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
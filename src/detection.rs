@@ -0,0 +1,320 @@
+use crate::{error::AppError, postprocess::OutputTensor};
+use ort::tensor::TensorElementType;
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionFormat {
+    /// Picks YOLOv5, YOLOv8 or SSD from the number and shape of the outputs.
+    Auto,
+    /// `[1, N, 5 + C]` rows of cx, cy, w, h, objectness and class scores.
+    YoloV5,
+    /// `[1, 4 + C, N]` columns of cx, cy, w, h and class scores.
+    YoloV8,
+    /// Separate box `[1, N, 4]`, score `[1, N]` and label `[1, N]` outputs.
+    Ssd,
+}
+
+impl FromStr for DetectionFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(DetectionFormat::Auto),
+            "yolov5" => Ok(DetectionFormat::YoloV5),
+            "yolov8" => Ok(DetectionFormat::YoloV8),
+            "ssd" => Ok(DetectionFormat::Ssd),
+            other => Err(format!(
+                "unknown detection format '{}', expected auto, yolov5, yolov8 or ssd",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionConfig {
+    pub format: DetectionFormat,
+    pub conf_threshold: f32,
+    pub iou_threshold: f32,
+    pub max_detections: usize,
+    /// Size of the model input, used to scale normalized SSD boxes to pixels.
+    pub input_size: (u32, u32),
+    /// Factors mapping model input coordinates back onto the original image.
+    pub scale: (f32, f32),
+    /// Position of the resized image in the model input, removed before scaling. Non-zero for
+    /// letterboxed and center-cropped inputs.
+    pub offset: (f32, f32),
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            format: DetectionFormat::Auto,
+            conf_threshold: 0.25,
+            iou_threshold: 0.45,
+            max_detections: 300,
+            input_size: (640, 640),
+            scale: (1., 1.),
+            offset: (0., 0.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Detection {
+    pub class_id: usize,
+    pub score: f32,
+    /// Corners as x1, y1, x2, y2.
+    pub bbox: [f32; 4],
+}
+
+impl Detection {
+    fn area(&self) -> f32 {
+        (self.bbox[2] - self.bbox[0]).max(0.) * (self.bbox[3] - self.bbox[1]).max(0.)
+    }
+
+    pub fn iou(&self, other: &Detection) -> f32 {
+        let x1: f32 = self.bbox[0].max(other.bbox[0]);
+        let y1: f32 = self.bbox[1].max(other.bbox[1]);
+        let x2: f32 = self.bbox[2].min(other.bbox[2]);
+        let y2: f32 = self.bbox[3].min(other.bbox[3]);
+        let intersection: f32 = (x2 - x1).max(0.) * (y2 - y1).max(0.);
+        let union: f32 = self.area() + other.area() - intersection;
+        if union > 0. {
+            intersection / union
+        } else {
+            0.
+        }
+    }
+
+    fn scaled(mut self, scale: (f32, f32), offset: (f32, f32)) -> Self {
+        self.bbox[0] = (self.bbox[0] - offset.0) * scale.0;
+        self.bbox[1] = (self.bbox[1] - offset.1) * scale.1;
+        self.bbox[2] = (self.bbox[2] - offset.0) * scale.0;
+        self.bbox[3] = (self.bbox[3] - offset.1) * scale.1;
+        self
+    }
+}
+
+impl std::fmt::Display for Detection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "class {} score {:.4} box [{:.1}, {:.1}, {:.1}, {:.1}]",
+            self.class_id, self.score, self.bbox[0], self.bbox[1], self.bbox[2], self.bbox[3]
+        )
+    }
+}
+
+/// Decodes raw detector outputs into candidate boxes above the confidence threshold, before NMS.
+pub fn decode_detections(
+    tensors: &[OutputTensor],
+    config: &DetectionConfig,
+) -> Result<Vec<Detection>, AppError> {
+    if tensors.is_empty() {
+        return Err(AppError::OutputError(
+            "model has no numeric output to decode".to_string(),
+        ));
+    }
+
+    let format: DetectionFormat = match config.format {
+        DetectionFormat::Auto => detect_format(tensors)?,
+        format => format,
+    };
+
+    let candidates: Vec<Detection> = match format {
+        DetectionFormat::YoloV5 => decode_yolo(&tensors[0], true, config)?,
+        DetectionFormat::YoloV8 => decode_yolo(&tensors[0], false, config)?,
+        DetectionFormat::Ssd => decode_ssd(tensors, config)?,
+        DetectionFormat::Auto => unreachable!("format was resolved above"),
+    };
+
+    Ok(candidates
+        .into_iter()
+        .map(|detection| detection.scaled(config.scale, config.offset))
+        .collect())
+}
+
+fn detect_format(tensors: &[OutputTensor]) -> Result<DetectionFormat, AppError> {
+    match tensors {
+        [single] if single.shape.len() == 3 => {
+            if single.shape[1] < single.shape[2] {
+                Ok(DetectionFormat::YoloV8)
+            } else {
+                Ok(DetectionFormat::YoloV5)
+            }
+        }
+        [_, _, ..] => Ok(DetectionFormat::Ssd),
+        _ => Err(AppError::OutputError(
+            "cannot infer the detection format, pass --detection-format".to_string(),
+        )),
+    }
+}
+
+fn decode_yolo(
+    tensor: &OutputTensor,
+    has_objectness: bool,
+    config: &DetectionConfig,
+) -> Result<Vec<Detection>, AppError> {
+    if tensor.shape.len() != 3 {
+        return Err(AppError::OutputError(format!(
+            "YOLO output '{}' must have rank 3, got {:?}",
+            tensor.name, tensor.shape
+        )));
+    }
+
+    // YOLOv5 stores one row per anchor, YOLOv8 one column per anchor
+    let (anchors, attributes) = if has_objectness {
+        (tensor.shape[1], tensor.shape[2])
+    } else {
+        (tensor.shape[2], tensor.shape[1])
+    };
+    let class_offset: usize = if has_objectness { 5 } else { 4 };
+    if attributes <= class_offset {
+        return Err(AppError::OutputError(format!(
+            "YOLO output '{}' has no class scores: {:?}",
+            tensor.name, tensor.shape
+        )));
+    }
+
+    let at = |anchor: usize, attribute: usize| -> f32 {
+        if has_objectness {
+            tensor.data[anchor * attributes + attribute]
+        } else {
+            tensor.data[attribute * anchors + anchor]
+        }
+    };
+
+    let mut candidates: Vec<Detection> = Vec::new();
+    for anchor in 0..anchors {
+        let objectness: f32 = if has_objectness { at(anchor, 4) } else { 1. };
+        let (class_id, class_score) = (class_offset..attributes)
+            .map(|attribute| (attribute - class_offset, at(anchor, attribute)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("at least one class score");
+        let score: f32 = objectness * class_score;
+        if score < config.conf_threshold {
+            continue;
+        }
+
+        let (cx, cy, w, h) = (at(anchor, 0), at(anchor, 1), at(anchor, 2), at(anchor, 3));
+        candidates.push(Detection {
+            class_id,
+            score,
+            bbox: [cx - w / 2., cy - h / 2., cx + w / 2., cy + h / 2.],
+        });
+    }
+    Ok(candidates)
+}
+
+fn decode_ssd(
+    tensors: &[OutputTensor],
+    config: &DetectionConfig,
+) -> Result<Vec<Detection>, AppError> {
+    let is_float = |tensor: &OutputTensor| {
+        matches!(
+            tensor.element_type,
+            TensorElementType::Float32
+                | TensorElementType::Float16
+                | TensorElementType::Bfloat16
+                | TensorElementType::Float64
+        )
+    };
+    let named = |tensor: &OutputTensor, keys: &[&str]| {
+        let name: String = tensor.name.to_ascii_lowercase();
+        keys.iter().any(|key| name.contains(key))
+    };
+
+    let boxes: &OutputTensor = tensors
+        .iter()
+        .find(|tensor| tensor.shape.len() == 3 && tensor.shape[2] == 4)
+        .ok_or_else(|| {
+            AppError::OutputError("SSD output has no [1, N, 4] box tensor".to_string())
+        })?;
+    let rest: Vec<&OutputTensor> = tensors
+        .iter()
+        .filter(|tensor| tensor.name != boxes.name && tensor.shape.len() >= 2)
+        .collect();
+
+    let scores: &OutputTensor = rest
+        .iter()
+        .find(|tensor| named(tensor, &["score", "conf"]))
+        .or_else(|| rest.iter().find(|tensor| is_float(tensor)))
+        .copied()
+        .ok_or_else(|| AppError::OutputError("SSD output has no score tensor".to_string()))?;
+    let labels: Option<&OutputTensor> = rest
+        .iter()
+        .find(|tensor| tensor.name != scores.name && named(tensor, &["label", "class"]))
+        .or_else(|| {
+            rest.iter()
+                .find(|tensor| tensor.name != scores.name && !is_float(tensor))
+        })
+        .copied();
+
+    let count: usize = boxes.shape[1];
+    let normalized: bool = boxes.data.iter().all(|&v| v <= 1.5);
+    let (width, height) = (config.input_size.0 as f32, config.input_size.1 as f32);
+
+    let mut candidates: Vec<Detection> = Vec::new();
+    for i in 0..count {
+        // Scores are either one per box, or one per box and class
+        let (class_id, score) = if scores.shape.len() == 3 {
+            let classes: usize = scores.shape[2];
+            (0..classes)
+                .map(|c| (c, scores.data[i * classes + c]))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or((0, 0.))
+        } else {
+            let class_id: usize = labels.map_or(0, |labels| labels.data[i] as usize);
+            (class_id, scores.data[i])
+        };
+        if score < config.conf_threshold {
+            continue;
+        }
+
+        let mut bbox: [f32; 4] = [
+            boxes.data[i * 4],
+            boxes.data[i * 4 + 1],
+            boxes.data[i * 4 + 2],
+            boxes.data[i * 4 + 3],
+        ];
+        if normalized {
+            bbox = [
+                bbox[0] * width,
+                bbox[1] * height,
+                bbox[2] * width,
+                bbox[3] * height,
+            ];
+        }
+        candidates.push(Detection {
+            class_id,
+            score,
+            bbox,
+        });
+    }
+    Ok(candidates)
+}
+
+/// Class-aware greedy non-maximum suppression, keeping at most `max_detections` boxes.
+pub fn non_max_suppression(
+    mut candidates: Vec<Detection>,
+    iou_threshold: f32,
+    max_detections: usize,
+) -> Vec<Detection> {
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut kept: Vec<Detection> = Vec::new();
+    for candidate in candidates {
+        if kept.len() >= max_detections {
+            break;
+        }
+        let suppressed: bool = kept.iter().any(|detection| {
+            detection.class_id == candidate.class_id && detection.iou(&candidate) > iou_threshold
+        });
+        if !suppressed {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
@@ -0,0 +1,227 @@
+use crate::error::AppError;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const HF_ENDPOINT: &str = "https://huggingface.co";
+
+/// A model given as a remote reference instead of a local path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteModel {
+    /// `https://` or `http://` URL of the model file.
+    Url(String),
+    /// `hf://org/repo[@revision]/path/model.onnx` on the Hugging Face Hub.
+    HuggingFace {
+        repo: String,
+        revision: String,
+        file: String,
+    },
+}
+
+impl RemoteModel {
+    /// `None` for anything that looks like a local path.
+    pub fn parse(reference: &str) -> Result<Option<Self>, AppError> {
+        if reference.starts_with("https://") || reference.starts_with("http://") {
+            return Ok(Some(RemoteModel::Url(reference.to_string())));
+        }
+        let Some(hub_path) = reference.strip_prefix("hf://") else {
+            return Ok(None);
+        };
+
+        let mut parts = hub_path.splitn(3, '/');
+        let (Some(org), Some(repo), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(AppError::DownloadError(format!(
+                "expected hf://org/repo/path/model.onnx, got '{}'",
+                reference
+            )));
+        };
+        let (repo, revision) = repo.split_once('@').unwrap_or((repo, "main"));
+        if org.is_empty() || repo.is_empty() || file.is_empty() {
+            return Err(AppError::DownloadError(format!(
+                "expected hf://org/repo/path/model.onnx, got '{}'",
+                reference
+            )));
+        }
+
+        Ok(Some(RemoteModel::HuggingFace {
+            repo: format!("{}/{}", org, repo),
+            revision: revision.to_string(),
+            file: file.to_string(),
+        }))
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            RemoteModel::Url(url) => url.clone(),
+            RemoteModel::HuggingFace {
+                repo,
+                revision,
+                file,
+            } => format!("{}/{}/resolve/{}/{}", HF_ENDPOINT, repo, revision, file),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        let url: String = self.url();
+        let path: &str = url.split(['?', '#']).next().unwrap_or_default();
+        path.rsplit('/')
+            .find(|segment| !segment.is_empty())
+            .unwrap_or("model.onnx")
+            .to_string()
+    }
+}
+
+/// A model in the local cache.
+#[derive(Debug, Clone)]
+pub struct CachedModel {
+    pub path: PathBuf,
+    pub sha256: String,
+    /// Whether the file was fetched now rather than found in the cache.
+    pub downloaded: bool,
+    pub size: u64,
+}
+
+/// Directory of downloaded models. Every file sits next to a `.sha256` file holding the checksum it
+/// was downloaded with, and a cache hit hashes the file again to check it was not truncated or
+/// modified since.
+#[derive(Debug, Clone)]
+pub struct ModelCache {
+    dir: PathBuf,
+}
+
+impl ModelCache {
+    /// `dir`, or `$XDG_CACHE_HOME/rust-ml-benchmark/models`, falling back to `~/.cache`.
+    pub fn new(dir: Option<&str>) -> Self {
+        let dir: PathBuf = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+                .unwrap_or_else(std::env::temp_dir)
+                .join(env!("CARGO_PKG_NAME"))
+                .join("models"),
+        };
+        Self { dir }
+    }
+
+    fn entry(&self, model: &RemoteModel) -> PathBuf {
+        // The URL hash keeps files with the same name from different sources apart
+        let key: String = hex(&Sha256::digest(model.url().as_bytes()))[..16].to_string();
+        self.dir.join(format!("{}-{}", key, model.file_name()))
+    }
+
+    /// Returns the cached model, downloading it first when it is missing or does not match `expected`.
+    pub fn fetch(
+        &self,
+        model: &RemoteModel,
+        expected_sha256: Option<&str>,
+    ) -> Result<CachedModel, AppError> {
+        let path: PathBuf = self.entry(model);
+        let checksum_path: PathBuf = path.with_extension("sha256");
+        let expected: Option<String> = expected_sha256.map(|hash| hash.trim().to_ascii_lowercase());
+
+        if let (Ok(metadata), Ok(cached)) =
+            (fs::metadata(&path), fs::read_to_string(&checksum_path))
+        {
+            let cached: String = cached.trim().to_string();
+            if expected
+                .as_ref()
+                .is_some_and(|expected| *expected != cached)
+            {
+                tracing::info!(path = %path.display(), "cached model does not match --model-sha256");
+            } else if hash_file(&path)? != cached {
+                tracing::warn!(path = %path.display(), "cached model changed since it was downloaded");
+            } else {
+                tracing::debug!(path = %path.display(), "model cache hit");
+                return Ok(CachedModel {
+                    path,
+                    sha256: cached,
+                    downloaded: false,
+                    size: metadata.len(),
+                });
+            }
+        }
+
+        fs::create_dir_all(&self.dir).map_err(|e| {
+            AppError::DownloadError(format!("failed to create {}: {}", self.dir.display(), e))
+        })?;
+        let url: String = model.url();
+        tracing::info!(url = %url, "downloading model");
+
+        let mut request = ureq::get(&url);
+        if let (RemoteModel::HuggingFace { .. }, Ok(token)) = (model, std::env::var("HF_TOKEN")) {
+            request = request.header("Authorization", &format!("Bearer {}", token));
+        }
+        let response = request
+            .call()
+            .map_err(|e| AppError::DownloadError(format!("{}: {}", url, e)))?;
+
+        // Written under a temporary name so an interrupted download never looks like a cache hit
+        let partial_path: PathBuf = path.with_extension("partial");
+        let write_error = |e: std::io::Error| {
+            AppError::DownloadError(format!("{}: {}", partial_path.display(), e))
+        };
+        let mut file: File = File::create(&partial_path).map_err(write_error)?;
+        let mut reader = BufReader::new(response.into_body().into_reader());
+        let mut hasher: Sha256 = Sha256::new();
+        let mut buffer: Vec<u8> = vec![0; 1 << 16];
+        let mut size: u64 = 0;
+        loop {
+            let read: usize = reader
+                .read(&mut buffer)
+                .map_err(|e| AppError::DownloadError(format!("{}: {}", url, e)))?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            file.write_all(&buffer[..read]).map_err(write_error)?;
+            size += read as u64;
+        }
+        file.sync_all().map_err(write_error)?;
+
+        let sha256: String = hex(&hasher.finalize());
+        if let Some(expected) = &expected {
+            if *expected != sha256 {
+                let _ = fs::remove_file(&partial_path);
+                return Err(AppError::DownloadError(format!(
+                    "checksum mismatch for {}: expected {}, got {}",
+                    url, expected, sha256
+                )));
+            }
+        }
+
+        fs::rename(&partial_path, &path).map_err(write_error)?;
+        fs::write(&checksum_path, format!("{}\n", sha256)).map_err(write_error)?;
+        tracing::info!(path = %path.display(), size, sha256 = %sha256, "downloaded model");
+
+        Ok(CachedModel {
+            path,
+            sha256,
+            downloaded: true,
+            size,
+        })
+    }
+}
+
+fn hash_file(path: &Path) -> Result<String, AppError> {
+    let read_error =
+        |e: std::io::Error| AppError::DownloadError(format!("{}: {}", path.display(), e));
+    let mut reader: BufReader<File> = BufReader::new(File::open(path).map_err(read_error)?);
+    let mut hasher: Sha256 = Sha256::new();
+    let mut buffer: Vec<u8> = vec![0; 1 << 16];
+    loop {
+        let read: usize = reader.read(&mut buffer).map_err(read_error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
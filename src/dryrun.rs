@@ -0,0 +1,272 @@
+use crate::model::{load_model_on, ModelOptions};
+use ort::{
+    execution_providers::{
+        CUDAExecutionProvider, ExecutionProvider, ExecutionProviderDispatch,
+        TensorRTExecutionProvider,
+    },
+    session::Input,
+    value::{DynValue, ValueType},
+    Error as OrtError,
+};
+use serde::Serialize;
+use std::path::Path;
+
+/// Exit code of a dry run that found the run would not measure what was asked, apart from the 1 of a
+/// failed run and the 3 of a missed SLA.
+pub const DRY_RUN_FAILED_EXIT_CODE: i32 = 4;
+
+/// Whether an execution provider would run the model, or ONNX Runtime would quietly use the CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderStatus {
+    /// Every node of the model runs on the provider.
+    Active,
+    /// The provider is registered, but the nodes it does not support run on the CPU.
+    PartialFallback,
+    /// ONNX Runtime was built without the provider.
+    NotBuilt,
+    /// The provider is built but could not be registered, e.g. no driver or no device.
+    Unusable,
+}
+
+impl ProviderStatus {
+    fn label(self) -> &'static str {
+        match self {
+            ProviderStatus::Active => "active",
+            ProviderStatus::PartialFallback => "partial CPU fallback",
+            ProviderStatus::NotBuilt => "not built into ONNX Runtime",
+            ProviderStatus::Unusable => "unusable",
+        }
+    }
+}
+
+impl std::fmt::Display for ProviderStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// An execution provider a run registers, with whether ONNX Runtime was built with it.
+#[derive(Debug)]
+pub struct RequestedProvider {
+    name: &'static str,
+    built: Result<bool, OrtError>,
+    provider: ExecutionProviderDispatch,
+}
+
+impl RequestedProvider {
+    pub fn cuda(provider: ExecutionProviderDispatch) -> Self {
+        Self {
+            name: "CUDA",
+            built: CUDAExecutionProvider::default().is_available(),
+            provider,
+        }
+    }
+
+    pub fn tensorrt(provider: ExecutionProviderDispatch) -> Self {
+        Self {
+            name: "TensorRT",
+            built: TensorRTExecutionProvider::default().is_available(),
+            provider,
+        }
+    }
+}
+
+/// Whether the requested execution providers, registered together as the run registers them, run
+/// every node of the model.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderCheck {
+    /// The providers in the order they take nodes, e.g. `TensorRT -> CUDA`.
+    pub provider: String,
+    pub status: ProviderStatus,
+    /// Error ONNX Runtime gave for the check that failed, or the provider it was built without.
+    pub detail: Option<String>,
+}
+
+impl ProviderCheck {
+    /// Builds a session with the providers chained, first without the CPU to run unsupported nodes
+    /// on and then with it, so a provider that fails to register is an error instead of a CPU run.
+    /// Nodes TensorRT does not support may run on CUDA, only the nodes left for the CPU fail the check.
+    pub fn run(
+        providers: Vec<RequestedProvider>,
+        model_path: &Path,
+        options: &ModelOptions,
+    ) -> Self {
+        let name: String = providers
+            .iter()
+            .map(|requested| requested.name)
+            .collect::<Vec<&str>>()
+            .join(" -> ");
+        let check = |status: ProviderStatus, detail: Option<String>| Self {
+            provider: name.clone(),
+            status,
+            detail,
+        };
+        let mut dispatches: Vec<ExecutionProviderDispatch> = Vec::new();
+        for requested in providers {
+            match requested.built {
+                Ok(true) => dispatches.push(requested.provider),
+                Ok(false) => {
+                    return check(ProviderStatus::NotBuilt, Some(requested.name.to_string()))
+                }
+                Err(e) => {
+                    return check(
+                        ProviderStatus::Unusable,
+                        Some(format!("{}: {}", requested.name, e)),
+                    )
+                }
+            }
+        }
+
+        let strict: OrtError = match load_model_on(model_path, options, dispatches.clone(), false) {
+            Ok(_) => return check(ProviderStatus::Active, None),
+            Err(e) => e,
+        };
+        match load_model_on(model_path, options, dispatches, true) {
+            Ok(_) => check(ProviderStatus::PartialFallback, Some(strict.to_string())),
+            Err(e) => check(ProviderStatus::Unusable, Some(e.to_string())),
+        }
+    }
+
+    /// Only a run whose nodes all stay on the requested providers measures them, a partial CPU
+    /// fallback fails the dry run too.
+    pub fn passed(&self) -> bool {
+        self.status == ProviderStatus::Active
+    }
+}
+
+/// A model input against the value preprocessing made for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputCheck {
+    pub name: String,
+    pub expected: String,
+    /// None when preprocessing made no value for the input.
+    pub actual: Option<String>,
+    pub compatible: bool,
+}
+
+/// Checks the type and shape of every preprocessed value against the model inputs, dynamic dimensions
+/// match any size.
+pub fn check_inputs(model_inputs: &[Input], inputs: &[(String, DynValue)]) -> Vec<InputCheck> {
+    model_inputs
+        .iter()
+        .map(|input| {
+            let actual: Option<&ValueType> = inputs
+                .iter()
+                .find(|(name, _)| *name == input.name)
+                .map(|(_, value)| value.dtype());
+            InputCheck {
+                name: input.name.clone(),
+                expected: input.input_type.to_string(),
+                actual: actual.map(ToString::to_string),
+                compatible: actual.is_some_and(|actual| compatible(&input.input_type, actual)),
+            }
+        })
+        .collect()
+}
+
+fn compatible(expected: &ValueType, actual: &ValueType) -> bool {
+    match (expected, actual) {
+        (
+            ValueType::Tensor { ty, shape, .. },
+            ValueType::Tensor {
+                ty: actual_ty,
+                shape: actual_shape,
+                ..
+            },
+        ) => {
+            ty == actual_ty
+                && shape.len() == actual_shape.len()
+                && shape
+                    .iter()
+                    .zip(actual_shape.iter())
+                    .all(|(&dim, &actual_dim)| dim < 0 || dim == actual_dim)
+        }
+        _ => expected == actual,
+    }
+}
+
+/// What `--dry-run` found: whether the run would measure the model on the requested providers with
+/// the inputs it was given, and the operations it would time.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    pub model: String,
+    /// The requested execution providers, None when the run only uses the CPU.
+    pub providers: Option<ProviderCheck>,
+    pub inputs: Vec<InputCheck>,
+    /// Why the inputs could not be prepared, in which case there is nothing to check.
+    pub preprocess_error: Option<String>,
+    pub plan: Vec<String>,
+    pub passed: bool,
+}
+
+impl DryRunReport {
+    pub fn new(
+        model: &str,
+        providers: Option<ProviderCheck>,
+        inputs: Vec<InputCheck>,
+        preprocess_error: Option<String>,
+        plan: Vec<String>,
+    ) -> Self {
+        let passed: bool = providers.as_ref().is_none_or(ProviderCheck::passed)
+            && inputs.iter().all(|input| input.compatible)
+            && preprocess_error.is_none();
+        Self {
+            model: model.to_string(),
+            providers,
+            inputs,
+            preprocess_error,
+            plan,
+            passed,
+        }
+    }
+}
+
+impl std::fmt::Display for DryRunReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Dry Run =============")?;
+        writeln!(f, "Model: {}", self.model)?;
+        match &self.providers {
+            None => writeln!(f, "Execution provider: CPU")?,
+            Some(check) => match &check.detail {
+                Some(detail) => writeln!(
+                    f,
+                    "Execution providers {}: {} ({})",
+                    check.provider, check.status, detail
+                )?,
+                None => writeln!(
+                    f,
+                    "Execution providers {}: {}",
+                    check.provider, check.status
+                )?,
+            },
+        }
+        if let Some(error) = &self.preprocess_error {
+            writeln!(f, "Preprocessing failed: {}", error)?;
+        }
+        for input in &self.inputs {
+            writeln!(
+                f,
+                "Input {}: expects {}, gets {}{}",
+                input.name,
+                input.expected,
+                input.actual.as_deref().unwrap_or("nothing"),
+                if input.compatible { "" } else { " (mismatch)" }
+            )?;
+        }
+        writeln!(f, "Would measure:")?;
+        for step in &self.plan {
+            writeln!(f, "  {}", step)?;
+        }
+        writeln!(
+            f,
+            "Result: {}",
+            if self.passed {
+                "ready to run"
+            } else {
+                "the run would not measure what was asked"
+            }
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
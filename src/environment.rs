@@ -0,0 +1,224 @@
+use crate::affinity::current_affinity;
+use crate::allocator;
+use serde::Serialize;
+use std::{collections::HashSet, ffi::CStr, fs, process::Command};
+
+/// Machine and build context of a benchmark run, so results from different devices can be told apart.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentInfo {
+    pub hostname: String,
+    pub cpu_model: String,
+    pub logical_cores: usize,
+    pub physical_cores: Option<usize>,
+    pub cpu_affinity: Option<String>,
+    pub cpu_governor: Option<String>,
+    pub cpu_max_frequency_mhz: Option<u64>,
+    pub total_memory: Option<u64>,
+    pub os: String,
+    pub kernel: String,
+    pub architecture: String,
+    pub gpu: Option<String>,
+    pub gpu_driver: Option<String>,
+    pub ort_api_version: String,
+    pub ort_build_info: String,
+    pub git_commit: String,
+    pub build_profile: String,
+    pub allocator: String,
+    /// Conditions found before measuring that may make the numbers noisy.
+    pub warnings: Vec<String>,
+}
+
+impl EnvironmentInfo {
+    pub fn capture() -> Self {
+        let cpuinfo: String = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+        let (gpu, gpu_driver) = gpu_info();
+        let (hostname, kernel) = uname();
+
+        Self {
+            hostname,
+            cpu_model: cpu_model(&cpuinfo),
+            logical_cores: logical_cores(),
+            physical_cores: physical_cores(&cpuinfo),
+            cpu_affinity: current_affinity().map(|cores| cores.to_string()),
+            cpu_governor: read_trimmed("/sys/devices/system/cpu/cpu0/cpufreq/scaling_governor"),
+            cpu_max_frequency_mhz: read_trimmed(
+                "/sys/devices/system/cpu/cpu0/cpufreq/cpuinfo_max_freq",
+            )
+            .and_then(|khz| khz.parse::<u64>().ok())
+            .map(|khz| khz / 1000),
+            total_memory: total_memory(),
+            os: os_name(),
+            kernel,
+            architecture: std::env::consts::ARCH.to_string(),
+            gpu,
+            gpu_driver,
+            ort_api_version: format!("1.{}", ort::MINOR_VERSION),
+            ort_build_info: ort::info().to_string(),
+            git_commit: env!("BUILD_GIT_COMMIT").to_string(),
+            build_profile: env!("BUILD_PROFILE").to_string(),
+            allocator: allocator::active().to_string(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+pub(crate) fn read_trimmed(path: &str) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+}
+
+fn cpuinfo_field<'a>(cpuinfo: &'a str, key: &str) -> impl Iterator<Item = &'a str> + 'a {
+    let key: String = key.to_string();
+    cpuinfo.lines().filter_map(move |line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim())
+    })
+}
+
+fn cpu_model(cpuinfo: &str) -> String {
+    // ARM kernels have no "model name", the board model from the device tree is the next best thing
+    cpuinfo_field(cpuinfo, "model name")
+        .next()
+        .or_else(|| cpuinfo_field(cpuinfo, "Hardware").next())
+        .map(str::to_string)
+        .or_else(|| {
+            read_trimmed("/sys/firmware/devicetree/base/model")
+                .map(|model| model.trim_end_matches('\0').to_string())
+        })
+        .unwrap_or_else(|| std::env::consts::ARCH.to_string())
+}
+
+fn logical_cores() -> usize {
+    let online: libc::c_long = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if online > 0 {
+        online as usize
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    }
+}
+
+fn physical_cores(cpuinfo: &str) -> Option<usize> {
+    let mut cores: HashSet<(&str, &str)> = HashSet::new();
+    let mut package: &str = "0";
+    for line in cpuinfo.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim() {
+            "physical id" => package = value.trim(),
+            "core id" => {
+                cores.insert((package, value.trim()));
+            }
+            _ => {}
+        }
+    }
+    (!cores.is_empty()).then_some(cores.len())
+}
+
+fn total_memory() -> Option<u64> {
+    let meminfo: String = fs::read_to_string("/proc/meminfo").ok()?;
+    let kilobytes: u64 = cpuinfo_field(&meminfo, "MemTotal")
+        .next()?
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+fn os_name() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|release| {
+            release.lines().find_map(|line| {
+                line.strip_prefix("PRETTY_NAME=")
+                    .map(|name| name.trim_matches('"').to_string())
+            })
+        })
+        .unwrap_or_else(|| std::env::consts::OS.to_string())
+}
+
+/// Host name and kernel version.
+fn uname() -> (String, String) {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return ("unknown".to_string(), "unknown".to_string());
+    }
+    let field = |field: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(field.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    (
+        field(&name.nodename),
+        format!("{} {}", field(&name.sysname), field(&name.release)),
+    )
+}
+
+fn gpu_info() -> (Option<String>, Option<String>) {
+    let query: Option<String> = Command::new("nvidia-smi")
+        .args(["--query-gpu=name,driver_version", "--format=csv,noheader"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+
+    if let Some((name, driver)) = query
+        .as_deref()
+        .and_then(|query| query.lines().next())
+        .and_then(|line| line.split_once(','))
+    {
+        return (
+            Some(name.trim().to_string()),
+            Some(driver.trim().to_string()),
+        );
+    }
+
+    // Without nvidia-smi (e.g. Jetson boards) the kernel module still reports its version
+    let driver: Option<String> = read_trimmed("/proc/driver/nvidia/version")
+        .and_then(|version| version.lines().next().map(str::to_string));
+    (None, driver)
+}
+
+impl std::fmt::Display for EnvironmentInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+
+        writeln!(f, "============= Environment =============")?;
+        writeln!(f, "Host: {}", self.hostname)?;
+        writeln!(f, "CPU: {}", self.cpu_model)?;
+        match self.physical_cores {
+            Some(physical) => writeln!(
+                f,
+                "Cores: {} logical, {} physical",
+                self.logical_cores, physical
+            )?,
+            None => writeln!(f, "Cores: {} logical", self.logical_cores)?,
+        }
+        writeln!(f, "CPU Affinity: {}", or_unknown(&self.cpu_affinity))?;
+        writeln!(f, "Governor: {}", or_unknown(&self.cpu_governor))?;
+        match self.cpu_max_frequency_mhz {
+            Some(mhz) => writeln!(f, "Max Frequency: {} MHz", mhz)?,
+            None => writeln!(f, "Max Frequency: -")?,
+        }
+        match self.total_memory {
+            Some(bytes) => writeln!(f, "Total Memory: {} bytes", bytes)?,
+            None => writeln!(f, "Total Memory: -")?,
+        }
+        writeln!(f, "OS: {}", self.os)?;
+        writeln!(f, "Kernel: {} ({})", self.kernel, self.architecture)?;
+        writeln!(f, "GPU: {}", or_unknown(&self.gpu))?;
+        writeln!(f, "GPU Driver: {}", or_unknown(&self.gpu_driver))?;
+        writeln!(f, "ONNX Runtime API: {}", self.ort_api_version)?;
+        writeln!(f, "ONNX Runtime Build: {}", self.ort_build_info)?;
+        writeln!(f, "Git Commit: {}", self.git_commit)?;
+        writeln!(f, "Build Profile: {}", self.build_profile)?;
+        writeln!(f, "Allocator: {}", self.allocator)?;
+        for warning in &self.warnings {
+            writeln!(f, "Warning: {}", warning)?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(not(target_family = "wasm"))]
+use ort::Error as OrtError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("Failed to load image: {0}")]
+    ImageLoadError(#[from] image::ImageError),
+    #[cfg(feature = "audio")]
+    #[error("Failed to load audio: {0}")]
+    AudioLoadError(#[from] hound::Error),
+    #[error("Failed to load input tensor: {0}")]
+    TensorLoadError(#[from] ndarray_npy::ReadNpyError),
+    #[error("Invalid input: {0}")]
+    InputError(String),
+    #[error("Input shape mismatch: {0}")]
+    InputShapeError(String),
+    #[error("Video error: {0}")]
+    VideoError(String),
+    #[error("Unsupported output: {0}")]
+    OutputError(String),
+    #[error("Tokenizer error: {0}")]
+    TokenizerError(String),
+    #[cfg(not(target_family = "wasm"))]
+    #[error("ORT error: {0}")]
+    OrtError(#[from] OrtError),
+    #[cfg(feature = "wasi-nn")]
+    #[error("WASI-NN error: {0}")]
+    WasiNnError(#[from] wasi_nn::Error),
+    #[error("System error: {0}")]
+    SystemError(String),
+    #[error("Noisy environment: {0}")]
+    EnvironmentError(String),
+    #[error("Export error: {0}")]
+    ExportError(String),
+    #[error("Download error: {0}")]
+    DownloadError(String),
+    #[error("Server error: {0}")]
+    ServerError(String),
+    #[cfg(feature = "sqlite")]
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] rusqlite::Error),
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+    #[error("Other error: {0}")]
+    Other(String),
+}
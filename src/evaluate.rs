@@ -0,0 +1,289 @@
+use crate::{
+    error::AppError,
+    input::{image_config, InputData, PreparedInput, PreprocessOverride},
+    metadata::RunMetadata,
+    postprocess::{extract_outputs, ExtractedOutputs, OutputSchema},
+    prefetch::{PrefetchConfig, PrefetchReport, Prefetcher},
+    preprocess::PreprocessConfig,
+    tensor::{convert_value, session_inputs},
+};
+use ort::{
+    session::{Session, SessionOutputs},
+    tensor::TensorElementType,
+    value::DynValue,
+};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "bmp", "webp"];
+
+/// One labelled image of an evaluation dataset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub path: PathBuf,
+    pub label: usize,
+}
+
+/// Loads an ImageNet-style directory with one sub-directory of images per class, or a CSV file of
+/// `path,label` rows with paths relative to the file. Labels are class indices, or class names
+/// looked up in `labels` (one name per line, in the order of the model's classes). Without
+/// `labels`, the sorted sub-directory names are the classes of a directory.
+pub fn load_dataset(path: &str, labels: Option<&str>) -> Result<Vec<Sample>, AppError> {
+    let class_names: Option<Vec<String>> = labels
+        .map(|labels| {
+            fs::read_to_string(labels)
+                .map(|text| text.lines().map(|line| line.trim().to_string()).collect())
+                .map_err(|e| AppError::InputError(format!("failed to read {}: {}", labels, e)))
+        })
+        .transpose()?;
+    let resolve = |label: &str, classes: &[String]| -> Result<usize, AppError> {
+        if let Ok(index) = label.parse::<usize>() {
+            return Ok(index);
+        }
+        classes
+            .iter()
+            .position(|class| class == label)
+            .ok_or_else(|| AppError::InputError(format!("unknown class '{}'", label)))
+    };
+
+    let dataset: &Path = Path::new(path);
+    let mut samples: Vec<Sample> = Vec::new();
+    if dataset.is_dir() {
+        let mut classes: Vec<PathBuf> = read_dir(dataset)?
+            .into_iter()
+            .filter(|entry| entry.is_dir())
+            .collect();
+        classes.sort();
+        let names: Vec<String> = classes.iter().map(|class| file_name(class)).collect();
+        let classes_by_name: &[String] = class_names.as_deref().unwrap_or(&names);
+
+        for (class, name) in classes.iter().zip(&names) {
+            let label: usize = resolve(name, classes_by_name)?;
+            let mut images: Vec<PathBuf> = read_dir(class)?
+                .into_iter()
+                .filter(|image| is_image(image))
+                .collect();
+            images.sort();
+            samples.extend(images.into_iter().map(|path| Sample { path, label }));
+        }
+    } else {
+        let text: String = fs::read_to_string(dataset)
+            .map_err(|e| AppError::InputError(format!("failed to read {}: {}", path, e)))?;
+        let base: &Path = dataset.parent().unwrap_or(Path::new("."));
+        let classes_by_name: &[String] = class_names.as_deref().unwrap_or_default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((image, label)) = line.rsplit_once(',') else {
+                return Err(AppError::InputError(format!(
+                    "{}:{}: expected path,label",
+                    path,
+                    number + 1
+                )));
+            };
+            let label: usize = match resolve(label.trim(), classes_by_name) {
+                Ok(label) => label,
+                // A header row
+                Err(_) if number == 0 && !is_image(Path::new(image.trim())) => continue,
+                Err(e) => return Err(e),
+            };
+            samples.push(Sample {
+                path: base.join(image.trim()),
+                label,
+            });
+        }
+    }
+
+    if samples.is_empty() {
+        return Err(AppError::InputError(format!("{} has no images", path)));
+    }
+    Ok(samples)
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|e| AppError::InputError(format!("failed to read {}: {}", dir.display(), e)))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect())
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        IMAGE_EXTENSIONS
+            .iter()
+            .any(|image| extension.eq_ignore_ascii_case(image))
+    })
+}
+
+/// Indices of the `k` highest scores, highest first.
+pub fn top_k(scores: &[f32], k: usize) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..scores.len()).collect();
+    indices.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+    indices.truncate(k);
+    indices
+}
+
+/// Accuracy and throughput of a model over a dataset.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvaluationReport {
+    pub metadata: RunMetadata,
+    pub model: String,
+    pub samples: usize,
+    pub top1_accuracy: f64,
+    pub top5_accuracy: f64,
+    /// Images per second, including decoding and pre-processing.
+    pub throughput: f64,
+    pub mean_preprocess_ms: f64,
+    pub mean_inference_ms: f64,
+    /// Stage utilization when images were decoded and pre-processed on worker threads.
+    pub prefetch: Option<PrefetchReport>,
+}
+
+impl std::fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Evaluation Metrics =============")?;
+        writeln!(f, "Model: {}", self.model)?;
+        writeln!(f, "Samples: {}", self.samples)?;
+        writeln!(f, "Top-1 accuracy: {:.2}%", self.top1_accuracy * 100.)?;
+        writeln!(f, "Top-5 accuracy: {:.2}%", self.top5_accuracy * 100.)?;
+        writeln!(f, "Throughput: {:.2} images/s", self.throughput)?;
+        writeln!(
+            f,
+            "Mean pre-processing time: {:.3} ms",
+            self.mean_preprocess_ms
+        )?;
+        writeln!(f, "Mean inference time: {:.3} ms", self.mean_inference_ms)?;
+        writeln!(f, "=======================================")?;
+        if let Some(prefetch) = &self.prefetch {
+            write!(f, "{}", prefetch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes and pre-processes one image into the value fed to the model input.
+fn prepare_sample(
+    path: &Path,
+    (name, config): (&str, &PreprocessConfig),
+    target: Option<TensorElementType>,
+) -> Result<(String, DynValue), AppError> {
+    let image = image::open(path)
+        .map_err(|e| AppError::InputError(format!("failed to load {}: {}", path.display(), e)))?;
+    let prepared: PreparedInput = PreparedInput {
+        name: name.to_string(),
+        data: InputData::Image(image),
+        config: config.clone(),
+    };
+    let (name, value) = prepared.into_value()?;
+    match target {
+        Some(target) => Ok((name, convert_value(value, target)?)),
+        None => Ok((name, value)),
+    }
+}
+
+/// Classifies every sample and compares the top-5 classes of the first output with its label.
+/// Models with a batch dimension are scored on the first row. With `prefetch`, the images are
+/// decoded and pre-processed on worker threads while the model runs.
+pub fn evaluate(
+    metadata: RunMetadata,
+    model_path: &str,
+    model: &mut Session,
+    samples: &[Sample],
+    overrides: &[PreprocessOverride],
+    prefetch: Option<PrefetchConfig>,
+) -> Result<EvaluationReport, AppError> {
+    let [model_input] = model.inputs.as_slice() else {
+        return Err(AppError::InputError(format!(
+            "evaluation feeds a single image input, the model has {} inputs",
+            model.inputs.len()
+        )));
+    };
+    let name: String = model_input.name.clone();
+    let config: PreprocessConfig = image_config(model_input, overrides)?;
+    let target: Option<TensorElementType> = model_input.input_type.tensor_type();
+    let schema: OutputSchema = OutputSchema::new(&model.outputs);
+
+    let (mut top1, mut top5) = (0usize, 0usize);
+    let mut preprocess_time: Duration = Duration::default();
+    let mut inference_time: Duration = Duration::default();
+    let start: Instant = Instant::now();
+    let mut prefetcher: Option<Prefetcher<(String, DynValue)>> = prefetch.map(|prefetch| {
+        let mut paths = samples
+            .iter()
+            .map(|sample| sample.path.clone())
+            .collect::<Vec<PathBuf>>()
+            .into_iter();
+        let (name, config): (String, PreprocessConfig) = (name.clone(), config.clone());
+        Prefetcher::start(
+            prefetch,
+            move || Ok(paths.next()),
+            move |path: PathBuf| prepare_sample(&path, (&name, &config), target),
+        )
+    });
+
+    for (index, sample) in samples.iter().enumerate() {
+        let input: (String, DynValue) = match &mut prefetcher {
+            Some(prefetcher) => prefetcher.next_item().ok_or_else(|| {
+                AppError::Other("prefetch workers stopped before the last image".to_string())
+            })??,
+            None => {
+                let preprocess_start: Instant = Instant::now();
+                let input = prepare_sample(&sample.path, (&name, &config), target)?;
+                preprocess_time += preprocess_start.elapsed();
+                input
+            }
+        };
+        let inputs: Vec<(String, DynValue)> = vec![input];
+
+        let inference_start: Instant = Instant::now();
+        let outputs: SessionOutputs<'_> = model.run(session_inputs(&inputs))?;
+        inference_time += inference_start.elapsed();
+
+        let extracted: ExtractedOutputs = extract_outputs(&outputs, &schema);
+        let Some(scores) = extracted.tensors.first() else {
+            return Err(AppError::OutputError(
+                "model has no numeric output to score".to_string(),
+            ));
+        };
+        let classes: usize = scores.shape.last().copied().unwrap_or(scores.data.len());
+        let predicted: Vec<usize> = top_k(&scores.data[..classes.min(scores.data.len())], 5);
+        top1 += usize::from(predicted.first() == Some(&sample.label));
+        top5 += usize::from(predicted.contains(&sample.label));
+
+        if (index + 1) % 100 == 0 {
+            tracing::info!(done = index + 1, total = samples.len(), "evaluating");
+        }
+    }
+
+    let count: f64 = samples.len() as f64;
+    let elapsed: f64 = start.elapsed().as_secs_f64();
+    let prefetch: Option<PrefetchReport> = prefetcher.map(|prefetcher| prefetcher.report());
+    Ok(EvaluationReport {
+        metadata,
+        model: model_path.to_string(),
+        samples: samples.len(),
+        top1_accuracy: top1 as f64 / count,
+        top5_accuracy: top5 as f64 / count,
+        throughput: if elapsed > 0. { count / elapsed } else { 0. },
+        mean_preprocess_ms: prefetch.as_ref().map_or_else(
+            || preprocess_time.as_secs_f64() * 1000. / count,
+            |prefetch| prefetch.mean_prepare_ms,
+        ),
+        mean_inference_ms: inference_time.as_secs_f64() * 1000. / count,
+        prefetch,
+    })
+}
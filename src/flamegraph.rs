@@ -0,0 +1,174 @@
+use crate::{
+    error::AppError,
+    tracker::{SpanHook, SpanKind},
+};
+use pprof::{Frames, ProfilerGuard, ProfilerGuardBuilder, Report, UnresolvedReport};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    time::{Duration, Instant},
+};
+
+/// Samples per second of CPU time, off a round rate so sampling does not run in lockstep with timers.
+const SAMPLING_FREQUENCY: i32 = 997;
+/// Libraries whose frames cannot be unwound safely from a signal handler.
+const BLOCKLIST: [&str; 4] = ["libc", "libgcc", "pthread", "vdso"];
+/// Phase profiled unless the profile is restricted to an operation, as it holds the measured runs.
+pub const MEASURED_PHASE: &str = "GREEN BOX Phase";
+
+/// Sampling CPU profiler of the process, running while the measured phase, or every run of one
+/// operation, is open. The samples of all windows are merged into one flamegraph.
+pub struct FlamegraphProfiler {
+    operation: Option<String>,
+    guard: Option<(ProfilerGuard<'static>, Instant)>,
+    /// Raw stacks of every window so far, merged into those of the first one. They are only
+    /// symbolized when the flamegraph is written, after the measured phases.
+    samples: Option<UnresolvedReport>,
+    duration: Duration,
+    windows: usize,
+    error: Option<String>,
+}
+
+impl std::fmt::Debug for FlamegraphProfiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlamegraphProfiler")
+            .field("operation", &self.operation)
+            .field("running", &self.guard.is_some())
+            .field("windows", &self.windows)
+            .finish()
+    }
+}
+
+impl FlamegraphProfiler {
+    /// Profiles every run of `operation`, or the measured phase without one.
+    pub fn new(operation: Option<String>) -> Self {
+        Self {
+            operation,
+            guard: None,
+            samples: None,
+            duration: Duration::default(),
+            windows: 0,
+            error: None,
+        }
+    }
+
+    fn profiles(&self, kind: SpanKind, name: &str) -> bool {
+        match &self.operation {
+            Some(operation) => kind == SpanKind::Operation && name == operation,
+            None => kind == SpanKind::Phase && name == MEASURED_PHASE,
+        }
+    }
+
+    /// Why the profiler could not start or keep its samples, in which case nothing was profiled.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Writes the flamegraph of every sample taken so far as an SVG.
+    pub fn write(&self, path: &str) -> Result<FlamegraphReport, AppError> {
+        let profiler_error =
+            |e: &dyn std::fmt::Display| AppError::SystemError(format!("CPU profiler: {}", e));
+        if let Some(error) = &self.error {
+            return Err(profiler_error(error));
+        }
+        let Some(samples) = &self.samples else {
+            return Err(profiler_error(&format!(
+                "{} never ran",
+                self.operation.as_deref().unwrap_or(MEASURED_PHASE)
+            )));
+        };
+        // Stacks that differed only in addresses can resolve to the same frames
+        let mut data: HashMap<Frames, isize> = HashMap::new();
+        for (frames, &count) in &samples.data {
+            *data.entry(Frames::from(frames.clone())).or_default() += count;
+        }
+        let profile: Report = Report {
+            data,
+            timing: samples.timing.clone(),
+        };
+
+        let file: File = File::create(path)
+            .map_err(|e| profiler_error(&format!("failed to create {}: {}", path, e)))?;
+        profile
+            .flamegraph(file)
+            .map_err(|e| profiler_error(&format!("failed to write {}: {}", path, e)))?;
+
+        Ok(FlamegraphReport {
+            path: path.to_string(),
+            window: self
+                .operation
+                .clone()
+                .unwrap_or_else(|| MEASURED_PHASE.to_string()),
+            windows: self.windows,
+            samples: profile.data.values().sum::<isize>().max(0) as u64,
+            profiled_ms: self.duration.as_secs_f64() * 1000.,
+        })
+    }
+}
+
+impl SpanHook for FlamegraphProfiler {
+    fn started(&mut self, kind: SpanKind, name: &str) {
+        if self.guard.is_some() || self.error.is_some() || !self.profiles(kind, name) {
+            return;
+        }
+        match ProfilerGuardBuilder::default()
+            .frequency(SAMPLING_FREQUENCY)
+            .blocklist(&BLOCKLIST)
+            .build()
+        {
+            Ok(guard) => self.guard = Some((guard, Instant::now())),
+            Err(e) => self.error = Some(e.to_string()),
+        }
+    }
+
+    /// Stops the profiler. Dropping the guard discards its samples, so only their raw stacks are
+    /// copied out first; symbolizing them takes a while and waits for [`FlamegraphProfiler::write`].
+    fn finished(&mut self, kind: SpanKind, name: &str) {
+        if !self.profiles(kind, name) {
+            return;
+        }
+        let Some((guard, started)) = self.guard.take() else {
+            return;
+        };
+        self.duration += started.elapsed();
+        self.windows += 1;
+        match (guard.report().build_unresolved(), &mut self.samples) {
+            (Ok(report), None) => self.samples = Some(report),
+            (Ok(report), Some(samples)) => {
+                for (frames, count) in report.data {
+                    *samples.data.entry(frames).or_default() += count;
+                }
+                samples.timing.duration += report.timing.duration;
+            }
+            (Err(e), _) => self.error = Some(e.to_string()),
+        }
+        drop(guard);
+    }
+}
+
+/// Where the flamegraph of a `--flamegraph` run was written and what it covers.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlamegraphReport {
+    pub path: String,
+    /// Operation or phase the profiler ran during.
+    pub window: String,
+    /// Runs of the operation or phase that were profiled.
+    pub windows: usize,
+    pub samples: u64,
+    pub profiled_ms: f64,
+}
+
+impl std::fmt::Display for FlamegraphReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Flamegraph Metrics =============")?;
+        writeln!(f, "Flamegraph: {}", self.path)?;
+        writeln!(
+            f,
+            "Profiled: {} ({} runs, {:.3} ms)",
+            self.window, self.windows, self.profiled_ms
+        )?;
+        writeln!(f, "Samples: {} at {} Hz", self.samples, SAMPLING_FREQUENCY)?;
+        writeln!(f, "=======================================")
+    }
+}
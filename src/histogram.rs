@@ -0,0 +1,134 @@
+use crate::tracker::BenchmarkTracker;
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Latencies are recorded in microseconds, up to an hour, with three significant digits.
+const HIGHEST_LATENCY_US: u64 = 3_600_000_000;
+const SIGNIFICANT_DIGITS: u8 = 3;
+const ASCII_BINS: usize = 12;
+const ASCII_WIDTH: usize = 40;
+
+/// Number of latencies of one histogram bucket, for `--output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyBucket {
+    /// Highest latency counted in this bucket.
+    pub upper_us: u64,
+    pub count: u64,
+}
+
+/// HDR histogram of the latencies of one operation across iterations.
+#[derive(Debug, Clone, Serialize)]
+pub struct LatencyHistogram {
+    pub operation: String,
+    pub count: u64,
+    pub min_ms: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub p999_ms: f64,
+    pub max_ms: f64,
+    pub buckets: Vec<LatencyBucket>,
+}
+
+impl LatencyHistogram {
+    pub fn from_latencies(operation: &str, latencies: &[Duration]) -> Option<Self> {
+        let mut histogram: Histogram<u64> =
+            Histogram::new_with_bounds(1, HIGHEST_LATENCY_US, SIGNIFICANT_DIGITS)
+                .expect("static histogram bounds are valid");
+        for latency in latencies {
+            histogram.saturating_record((latency.as_micros() as u64).max(1));
+        }
+        if histogram.is_empty() {
+            return None;
+        }
+
+        let millis = |us: u64| us as f64 / 1000.;
+        let quantile = |quantile: f64| millis(histogram.value_at_quantile(quantile));
+        Some(Self {
+            operation: operation.to_string(),
+            count: histogram.len(),
+            min_ms: millis(histogram.min()),
+            mean_ms: histogram.mean() / 1000.,
+            p50_ms: quantile(0.5),
+            p90_ms: quantile(0.9),
+            p95_ms: quantile(0.95),
+            p99_ms: quantile(0.99),
+            p999_ms: quantile(0.999),
+            max_ms: millis(histogram.max()),
+            buckets: histogram
+                .iter_recorded()
+                .map(|bucket| LatencyBucket {
+                    upper_us: bucket.value_iterated_to(),
+                    count: bucket.count_at_value(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Histogram of every `operation` recorded by the tracker, when it ran more than once.
+    pub fn from_tracker(tracker: &BenchmarkTracker, operation: &str) -> Option<Self> {
+        let latencies: Vec<Duration> = tracker.operation_times(operation);
+        if latencies.len() < 2 {
+            return None;
+        }
+        Self::from_latencies(operation, &latencies)
+    }
+
+    /// The buckets merged into `ASCII_BINS` equally wide bins between the lowest and highest latency.
+    fn bins(&self) -> Vec<(f64, u64)> {
+        let (min, max) = (
+            self.buckets[0].upper_us,
+            self.buckets[self.buckets.len() - 1].upper_us,
+        );
+        let width: f64 = ((max - min) as f64 / ASCII_BINS as f64).max(1.);
+        let mut bins: Vec<(f64, u64)> = (0..ASCII_BINS)
+            .map(|bin| ((min as f64 + width * (bin + 1) as f64) / 1000., 0))
+            .collect();
+        for bucket in &self.buckets {
+            let bin: usize =
+                (((bucket.upper_us - min) as f64 / width) as usize).min(ASCII_BINS - 1);
+            bins[bin].1 += bucket.count;
+        }
+        // Spread of a few microseconds fits in fewer bins
+        while bins.len() > 1 && bins.last().is_some_and(|bin| bin.1 == 0) {
+            bins.pop();
+        }
+        bins
+    }
+}
+
+impl std::fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= {} Latency =============", self.operation)?;
+        writeln!(f, "Samples: {}", self.count)?;
+        writeln!(
+            f,
+            "Min: {:.3} ms, Mean: {:.3} ms",
+            self.min_ms, self.mean_ms
+        )?;
+        writeln!(
+            f,
+            "p50: {:.3} ms, p90: {:.3} ms, p95: {:.3} ms, p99: {:.3} ms, p99.9: {:.3} ms",
+            self.p50_ms, self.p90_ms, self.p95_ms, self.p99_ms, self.p999_ms
+        )?;
+        writeln!(f, "Max: {:.3} ms", self.max_ms)?;
+
+        let bins: Vec<(f64, u64)> = self.bins();
+        let highest: u64 = bins.iter().map(|bin| bin.1).max().unwrap_or(1).max(1);
+        for (upper_ms, count) in bins {
+            let bar: usize = (count as usize * ASCII_WIDTH).div_ceil(highest as usize);
+            writeln!(
+                f,
+                "<= {:>10.3} ms | {:<width$} {}",
+                upper_ms,
+                "#".repeat(bar),
+                count,
+                width = ASCII_WIDTH
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    environment::EnvironmentInfo,
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::{format_bytes, Metrics},
+    tracker::{BenchmarkTracker, SpanKind},
+};
+use rusqlite::{params, Connection, OptionalExtension, Statement, Transaction};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp INTEGER NOT NULL,
+    model TEXT NOT NULL,
+    task TEXT NOT NULL,
+    device TEXT NOT NULL,
+    git_commit TEXT NOT NULL,
+    environment TEXT NOT NULL,
+    interrupted INTEGER NOT NULL,
+    run_uuid TEXT NOT NULL,
+    tags TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS operations (
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    wall_clock_ns INTEGER NOT NULL,
+    user_ns INTEGER NOT NULL,
+    system_ns INTEGER NOT NULL,
+    cpu_usage REAL NOT NULL,
+    rss_before INTEGER NOT NULL,
+    rss_after INTEGER NOT NULL,
+    peak_rss INTEGER NOT NULL,
+    peak_rss_growth INTEGER NOT NULL,
+    PRIMARY KEY (run_id, position)
+);
+CREATE INDEX IF NOT EXISTS runs_model_device ON runs (model, device);
+";
+
+const SUMMARY_QUERY: &str = "
+    SELECT r.id, r.timestamp, r.model, r.task, r.device, r.git_commit, t.wall_clock_ns, t.peak_rss,
+           (SELECT wall_clock_ns FROM operations
+            WHERE run_id = r.id AND kind = 'operation' AND name = 'Inference'
+            ORDER BY position LIMIT 1),
+           r.interrupted, r.run_uuid, r.tags
+    FROM runs r JOIN operations t ON t.run_id = r.id AND t.kind = 'total'";
+
+/// Local SQLite database holding every recorded run, for tracking performance over time.
+pub struct ResultsDb {
+    connection: Connection,
+}
+
+/// One row of the `history` listing.
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub id: i64,
+    pub timestamp: u64,
+    pub model: String,
+    pub task: String,
+    pub device: String,
+    pub git_commit: String,
+    pub total: Duration,
+    pub inference: Option<Duration>,
+    /// Peak RSS of the process in bytes.
+    pub peak_rss: u64,
+    /// The run stopped early on SIGINT or SIGTERM.
+    pub interrupted: bool,
+    pub run_uuid: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OperationRow {
+    pub kind: String,
+    pub name: String,
+    pub wall_clock_time: Duration,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// In bytes.
+    pub rss_before: u64,
+    pub rss_after: u64,
+    pub peak_rss: u64,
+    pub peak_rss_growth: u64,
+    pub cpu_usage: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    /// Substring of the model path.
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub limit: usize,
+}
+
+impl ResultsDb {
+    pub fn open(path: &str) -> Result<Self, AppError> {
+        let connection: Connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Stores the run with its environment and the metrics of every operation, phase and the total.
+    /// Returns the id of the new run.
+    pub fn record_run(
+        &mut self,
+        model: &str,
+        task: &str,
+        metadata: &RunMetadata,
+        environment: &EnvironmentInfo,
+        tracker: &BenchmarkTracker,
+    ) -> Result<i64, AppError> {
+        let timestamp: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        let total: Metrics = tracker.get_total_metrics();
+
+        let transaction: Transaction<'_> = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs
+             (timestamp, model, task, device, git_commit, environment, interrupted, run_uuid, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                timestamp as i64,
+                model,
+                task,
+                environment.hostname,
+                environment.git_commit,
+                serde_json::to_string(environment)?,
+                tracker.is_interrupted(),
+                metadata.run_id,
+                serde_json::to_string(&metadata.tags)?,
+            ],
+        )?;
+        let run_id: i64 = transaction.last_insert_rowid();
+
+        let rows = tracker
+            .spans()
+            .iter()
+            .map(|span| {
+                let kind: &str = match span.kind {
+                    SpanKind::Operation => "operation",
+                    SpanKind::Phase => "phase",
+                };
+                (kind, &span.metrics)
+            })
+            .chain(std::iter::once(("total", &total)));
+        for (position, (kind, metrics)) in rows.enumerate() {
+            transaction.execute(
+                "INSERT INTO operations
+                 (run_id, position, kind, name, wall_clock_ns, user_ns, system_ns, cpu_usage,
+                  rss_before, rss_after, peak_rss, peak_rss_growth)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    run_id,
+                    position as i64,
+                    kind,
+                    metrics.name,
+                    metrics.wall_clock_time.as_nanos() as i64,
+                    metrics.user_time.as_nanos() as i64,
+                    metrics.system_time.as_nanos() as i64,
+                    metrics.cpu_usage as f64,
+                    metrics.rss_before as i64,
+                    metrics.rss_after as i64,
+                    metrics.peak_rss as i64,
+                    metrics.peak_rss_growth as i64,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(run_id)
+    }
+
+    /// Most recent runs first.
+    pub fn runs(&self, filter: &HistoryFilter) -> Result<Vec<RunSummary>, AppError> {
+        let mut statement: Statement<'_> = self.connection.prepare(&format!(
+            "{} WHERE (?1 IS NULL OR instr(r.model, ?1) > 0) AND (?2 IS NULL OR r.device = ?2)
+             ORDER BY r.id DESC LIMIT ?3",
+            SUMMARY_QUERY
+        ))?;
+        let runs = statement
+            .query_map(
+                params![filter.model, filter.device, filter.limit as i64],
+                summary_from_row,
+            )?
+            .collect::<Result<Vec<RunSummary>, rusqlite::Error>>()?;
+        Ok(runs)
+    }
+
+    pub fn run(&self, run_id: i64) -> Result<Option<RunSummary>, AppError> {
+        let summary: Option<RunSummary> = self
+            .connection
+            .query_row(
+                &format!("{} WHERE r.id = ?1", SUMMARY_QUERY),
+                [run_id],
+                summary_from_row,
+            )
+            .optional()?;
+        Ok(summary)
+    }
+
+    pub fn operations(&self, run_id: i64) -> Result<Vec<OperationRow>, AppError> {
+        let mut statement: Statement<'_> = self.connection.prepare(
+            "SELECT kind, name, wall_clock_ns, user_ns, system_ns, cpu_usage,
+                    rss_before, rss_after, peak_rss, peak_rss_growth
+             FROM operations WHERE run_id = ?1 ORDER BY position",
+        )?;
+        let operations = statement
+            .query_map([run_id], |row| {
+                Ok(OperationRow {
+                    kind: row.get(0)?,
+                    name: row.get(1)?,
+                    wall_clock_time: nanos(row.get(2)?),
+                    user_time: nanos(row.get(3)?),
+                    system_time: nanos(row.get(4)?),
+                    cpu_usage: row.get::<_, f64>(5)? as f32,
+                    rss_before: bytes(row.get(6)?),
+                    rss_after: bytes(row.get(7)?),
+                    peak_rss: bytes(row.get(8)?),
+                    peak_rss_growth: bytes(row.get(9)?),
+                })
+            })?
+            .collect::<Result<Vec<OperationRow>, rusqlite::Error>>()?;
+        Ok(operations)
+    }
+}
+
+fn summary_from_row(row: &rusqlite::Row<'_>) -> Result<RunSummary, rusqlite::Error> {
+    Ok(RunSummary {
+        id: row.get(0)?,
+        timestamp: row.get::<_, i64>(1)? as u64,
+        model: row.get(2)?,
+        task: row.get(3)?,
+        device: row.get(4)?,
+        git_commit: row.get(5)?,
+        total: nanos(row.get(6)?),
+        peak_rss: bytes(row.get(7)?),
+        inference: row.get::<_, Option<i64>>(8)?.map(nanos),
+        interrupted: row.get(9)?,
+        run_uuid: row.get(10)?,
+        tags: serde_json::from_str(&row.get::<_, String>(11)?).unwrap_or_default(),
+    })
+}
+
+fn nanos(value: i64) -> Duration {
+    Duration::from_nanos(value.max(0) as u64)
+}
+
+fn bytes(value: i64) -> u64 {
+    value.max(0) as u64
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+
+    // Civil date from days since the epoch, http://howardhinnant.github.io/date_algorithms.html
+    let z: i64 = days as i64 + 719468;
+    let era: i64 = z.div_euclid(146097);
+    let day_of_era: i64 = z.rem_euclid(146097);
+    let year_of_era: i64 =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+impl std::fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inference: String = self
+            .inference
+            .map_or_else(|| "-".to_string(), |inference| format!("{:?}", inference));
+        write!(
+            f,
+            "#{:<5} {}  {:<16} {:<12} {:<14} total {:>12?}  inference {:>12}  peak RSS {:>10}  {}",
+            self.id,
+            format_timestamp(self.timestamp),
+            self.device,
+            self.git_commit,
+            self.task,
+            self.total,
+            inference,
+            format_bytes(self.peak_rss),
+            self.model
+        )?;
+        for (key, value) in &self.tags {
+            write!(f, "  {}={}", key, value)?;
+        }
+        if self.interrupted {
+            write!(f, "  (interrupted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Per-operation comparison of two runs, matching operations by kind and name.
+pub struct RunDiff {
+    pub base: RunSummary,
+    pub other: RunSummary,
+    pub rows: Vec<(String, String, Option<Duration>, Option<Duration>)>,
+}
+
+impl RunDiff {
+    pub fn new(
+        base: RunSummary,
+        base_operations: &[OperationRow],
+        other: RunSummary,
+        other_operations: &[OperationRow],
+    ) -> Self {
+        let mut rows: Vec<(String, String, Option<Duration>, Option<Duration>)> = base_operations
+            .iter()
+            .map(|operation| {
+                let matching: Option<Duration> = other_operations
+                    .iter()
+                    .find(|row| row.kind == operation.kind && row.name == operation.name)
+                    .map(|row| row.wall_clock_time);
+                (
+                    operation.kind.clone(),
+                    operation.name.clone(),
+                    Some(operation.wall_clock_time),
+                    matching,
+                )
+            })
+            .collect();
+        for operation in other_operations {
+            if !base_operations
+                .iter()
+                .any(|row| row.kind == operation.kind && row.name == operation.name)
+            {
+                rows.push((
+                    operation.kind.clone(),
+                    operation.name.clone(),
+                    None,
+                    Some(operation.wall_clock_time),
+                ));
+            }
+        }
+
+        Self { base, other, rows }
+    }
+}
+
+impl std::fmt::Display for RunDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_dash =
+            |value: Option<Duration>| value.map_or_else(|| "-".to_string(), |d| format!("{:?}", d));
+
+        writeln!(
+            f,
+            "============= Run #{} vs Run #{} =============",
+            self.base.id, self.other.id
+        )?;
+        writeln!(f, "Base:  {}", self.base)?;
+        writeln!(f, "Other: {}", self.other)?;
+        for (kind, name, base, other) in &self.rows {
+            let change: String = match (base, other) {
+                (Some(base), Some(other)) if !base.is_zero() => format!(
+                    "{:+.2}%",
+                    (other.as_secs_f64() / base.as_secs_f64() - 1.) * 100.
+                ),
+                _ => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<9} {:<24} {:>14} -> {:>14}  {:>9}",
+                kind,
+                name,
+                or_dash(*base),
+                or_dash(*other),
+                change
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
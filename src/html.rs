@@ -0,0 +1,485 @@
+use crate::{
+    environment::EnvironmentInfo,
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::{format_bytes, format_bytes_delta, Metrics},
+    stats::{mean, millis, percentile},
+    tracker::{BenchmarkTracker, SpanKind, SpanRecord},
+};
+use std::{fmt::Write, time::Duration};
+
+const CHART_WIDTH: f64 = 720.;
+const CHART_HEIGHT: f64 = 240.;
+const MARGIN: f64 = 48.;
+const PALETTE: [&str; 6] = [
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#edc948",
+];
+
+/// Self-contained HTML report of one run: environment, latency histogram, phase breakdown and memory
+/// timeline. Charts are inline SVG, so the file can be mailed or attached without any assets.
+pub struct HtmlReport<'a> {
+    pub model: &'a str,
+    pub metadata: &'a RunMetadata,
+    pub environment: &'a EnvironmentInfo,
+    pub tracker: &'a BenchmarkTracker,
+}
+
+impl HtmlReport<'_> {
+    pub fn write(&self, report_path: &str) -> Result<(), AppError> {
+        std::fs::write(report_path, self.render())
+            .map_err(|e| AppError::Other(format!("Failed to write {}: {}", report_path, e)))
+    }
+
+    pub fn render(&self) -> String {
+        let total: Metrics = self.tracker.get_total_metrics();
+        let spans: &[SpanRecord] = self.tracker.spans();
+        let latencies: Vec<Duration> = spans
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.name == "Inference")
+            .map(|span| span.metrics.wall_clock_time)
+            .collect();
+
+        let mut html: String = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Benchmark report: {model}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+             <h1>Benchmark report</h1>\n<p class=\"model\">{model}</p>\n<p class=\"run\">{run}</p>\n",
+            model = escape(self.model),
+            run = escape(self.metadata.to_string().trim_end()),
+            style = STYLE
+        );
+        if self.tracker.is_interrupted() {
+            html.push_str(
+                "<p class=\"interrupted\">Interrupted: the run stopped early and these results are partial.</p>\n",
+            );
+        }
+
+        html.push_str("<h2>Summary</h2>\n");
+        html.push_str(&summary_table(&total, &latencies));
+
+        html.push_str("<h2>Inference latency distribution</h2>\n");
+        html.push_str(&latency_histogram(&latencies));
+
+        html.push_str("<h2>Phase breakdown</h2>\n");
+        html.push_str(&phase_breakdown(spans));
+
+        html.push_str("<h2>Memory timeline</h2>\n");
+        html.push_str(&memory_timeline(self.tracker, spans));
+
+        html.push_str("<h2>Operations</h2>\n");
+        html.push_str(&operations_table(spans));
+
+        html.push_str("<h2>Environment</h2>\n");
+        html.push_str(&environment_table(self.environment));
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:800px;color:#222}\
+h2{margin-top:1.6em;border-bottom:1px solid #ddd}.model,.run{font-family:monospace;color:#555}\
+table{border-collapse:collapse;width:100%}td,th{padding:3px 8px;border-bottom:1px solid #eee;\
+text-align:left}td.n{text-align:right;font-variant-numeric:tabular-nums}svg text{font-size:11px}\
+.empty{color:#888;font-style:italic}.legend span{display:inline-block;margin-right:1em}\
+.legend i{display:inline-block;width:10px;height:10px;margin-right:4px}\
+.interrupted{color:#b00;font-weight:bold}";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn summary_table(total: &Metrics, latencies: &[Duration]) -> String {
+    let mut rows: Vec<(String, String)> = vec![
+        (
+            "Total wall clock time".to_string(),
+            format!("{:.3} ms", millis(total.wall_clock_time)),
+        ),
+        (
+            "Total CPU time".to_string(),
+            format!("{:.3} ms", millis(total.user_time + total.system_time)),
+        ),
+        ("Peak RSS".to_string(), format_bytes(total.peak_rss)),
+        (
+            "RSS".to_string(),
+            format!(
+                "{} → {} ({})",
+                format_bytes(total.rss_before),
+                format_bytes(total.rss_after),
+                format_bytes_delta(total.rss_delta())
+            ),
+        ),
+        ("Inference runs".to_string(), latencies.len().to_string()),
+    ];
+
+    if !latencies.is_empty() {
+        let mut sorted: Vec<Duration> = latencies.to_vec();
+        sorted.sort();
+        for (label, value) in [
+            ("Min latency", sorted[0]),
+            ("Mean latency", mean(&sorted)),
+            ("Median latency", percentile(&sorted, 0.5)),
+            ("p90 latency", percentile(&sorted, 0.9)),
+            ("p99 latency", percentile(&sorted, 0.99)),
+            ("Max latency", sorted[sorted.len() - 1]),
+        ] {
+            rows.push((label.to_string(), format!("{:.3} ms", millis(value))));
+        }
+    }
+
+    let mut table: String = String::from("<table>\n");
+    for (label, value) in rows {
+        let _ = writeln!(
+            table,
+            "<tr><th>{}</th><td class=\"n\">{}</td></tr>",
+            label, value
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+fn svg_open(height: f64) -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = CHART_WIDTH,
+        h = height
+    )
+}
+
+fn latency_histogram(latencies: &[Duration]) -> String {
+    if latencies.is_empty() {
+        return "<p class=\"empty\">No inference runs were recorded.</p>\n".to_string();
+    }
+
+    let values: Vec<f64> = latencies.iter().map(|&latency| millis(latency)).collect();
+    let min: f64 = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max: f64 = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    // Square-root rule, capped so single runs and huge runs both stay readable
+    let bins: usize = ((values.len() as f64).sqrt().ceil() as usize).clamp(1, 40);
+    let width: f64 = if max > min {
+        (max - min) / bins as f64
+    } else {
+        1.
+    };
+
+    let mut counts: Vec<usize> = vec![0; bins];
+    for value in &values {
+        let bin: usize = (((value - min) / width) as usize).min(bins - 1);
+        counts[bin] += 1;
+    }
+    let highest: usize = counts.iter().copied().max().unwrap_or(1).max(1);
+
+    let plot_width: f64 = CHART_WIDTH - 2. * MARGIN;
+    let plot_height: f64 = CHART_HEIGHT - 2. * MARGIN;
+    let bar_width: f64 = plot_width / bins as f64;
+
+    let mut svg: String = svg_open(CHART_HEIGHT);
+    for (bin, &count) in counts.iter().enumerate() {
+        let height: f64 = count as f64 / highest as f64 * plot_height;
+        let x: f64 = MARGIN + bin as f64 * bar_width;
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\">\
+             <title>{:.3}–{:.3} ms: {} runs</title></rect>",
+            x + 1.,
+            MARGIN + plot_height - height,
+            (bar_width - 2.).max(1.),
+            height,
+            PALETTE[0],
+            min + bin as f64 * width,
+            min + (bin + 1) as f64 * width,
+            count
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#888\"/>\n\
+         <text x=\"{m}\" y=\"{t}\">{min:.3} ms</text>\n\
+         <text x=\"{r}\" y=\"{t}\" text-anchor=\"end\">{max:.3} ms</text>\n\
+         <text x=\"{m}\" y=\"{top}\">{highest} runs</text>",
+        m = MARGIN,
+        r = CHART_WIDTH - MARGIN,
+        b = MARGIN + plot_height,
+        t = MARGIN + plot_height + 16.,
+        top = MARGIN - 8.,
+        min = min,
+        max = if max > min { max } else { min + width },
+        highest = highest
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn phase_breakdown(spans: &[SpanRecord]) -> String {
+    let phases: Vec<&SpanRecord> = spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::Phase)
+        .collect();
+    if phases.is_empty() {
+        return "<p class=\"empty\">No phases were recorded.</p>\n".to_string();
+    }
+
+    // One stacked bar per phase, one segment per distinct operation name in it
+    let mut operation_names: Vec<&str> = Vec::new();
+    for span in spans.iter().filter(|span| span.kind == SpanKind::Operation) {
+        if !operation_names.contains(&span.name.as_str()) {
+            operation_names.push(&span.name);
+        }
+    }
+    let color = |name: &str| {
+        let index: usize = operation_names
+            .iter()
+            .position(|operation| *operation == name)
+            .unwrap_or(0);
+        PALETTE[index % PALETTE.len()]
+    };
+
+    let longest: f64 = phases
+        .iter()
+        .map(|phase| millis(phase.metrics.wall_clock_time))
+        .fold(0., f64::max)
+        .max(f64::EPSILON);
+    let label_width: f64 = 140.;
+    let plot_width: f64 = CHART_WIDTH - label_width - MARGIN;
+    let row_height: f64 = 28.;
+    let height: f64 = phases.len() as f64 * row_height + 16.;
+
+    let mut svg: String = svg_open(height);
+    for (row, phase) in phases.iter().enumerate() {
+        let y: f64 = 8. + row as f64 * row_height;
+        let _ = writeln!(
+            svg,
+            "<text x=\"0\" y=\"{:.1}\">{}</text>",
+            y + 15.,
+            escape(&phase.name)
+        );
+
+        let mut x: f64 = label_width;
+        for operation in spans
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.parent_id == Some(phase.id))
+        {
+            let width: f64 = millis(operation.metrics.wall_clock_time) / longest * plot_width;
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"20\" fill=\"{}\">\
+                 <title>{}: {:.3} ms</title></rect>",
+                x,
+                y,
+                width,
+                color(&operation.name),
+                escape(&operation.name),
+                millis(operation.metrics.wall_clock_time)
+            );
+            x += width;
+        }
+        let _ = writeln!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{:.1}\">{:.3} ms</text>",
+            x + 4.,
+            y + 15.,
+            millis(phase.metrics.wall_clock_time)
+        );
+    }
+    svg.push_str("</svg>\n<p class=\"legend\">");
+    for name in &operation_names {
+        let _ = write!(
+            svg,
+            "<span><i style=\"background:{}\"></i>{}</span>",
+            color(name),
+            escape(name)
+        );
+    }
+    svg.push_str("</p>\n");
+    svg
+}
+
+/// RSS at the end of every operation, with the peak RSS of the process as steps above it.
+fn memory_timeline(tracker: &BenchmarkTracker, spans: &[SpanRecord]) -> String {
+    let points: Vec<(f64, u64, u64, &str)> = spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::Operation)
+        .map(|span| {
+            (
+                millis(span.end.duration_since(tracker.start_time())),
+                span.metrics.rss_after,
+                span.metrics.peak_rss,
+                span.name.as_str(),
+            )
+        })
+        .collect();
+    if points.is_empty() {
+        return "<p class=\"empty\">No operations were recorded.</p>\n".to_string();
+    }
+
+    let last_time: f64 = points
+        .iter()
+        .map(|(time, _, _, _)| *time)
+        .fold(0., f64::max)
+        .max(f64::EPSILON);
+    let peak: u64 = points
+        .iter()
+        .map(|(_, rss, peak_rss, _)| (*rss).max(*peak_rss))
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let plot_width: f64 = CHART_WIDTH - 2. * MARGIN;
+    let plot_height: f64 = CHART_HEIGHT - 2. * MARGIN;
+    let position = |time: f64, rss: u64| {
+        (
+            MARGIN + time / last_time * plot_width,
+            MARGIN + plot_height - rss as f64 / peak as f64 * plot_height,
+        )
+    };
+
+    let mut rss_path: String = String::new();
+    let mut peak_path: String = String::new();
+    let (start_x, start_y) = position(0., points[0].1);
+    let (_, mut previous_peak_y) = position(0., points[0].2);
+    let _ = write!(rss_path, "M{:.1},{:.1}", start_x, start_y);
+    let _ = write!(peak_path, "M{:.1},{:.1}", start_x, previous_peak_y);
+    for &(time, rss, peak_rss, _) in &points {
+        let (x, y) = position(time, rss);
+        let _ = write!(rss_path, " L{:.1},{:.1}", x, y);
+        // Peak RSS only grows, so it is drawn as steps
+        let (_, peak_y) = position(time, peak_rss);
+        let _ = write!(
+            peak_path,
+            " L{:.1},{:.1} L{:.1},{:.1}",
+            x, previous_peak_y, x, peak_y
+        );
+        previous_peak_y = peak_y;
+    }
+
+    let mut svg: String = svg_open(CHART_HEIGHT);
+    let _ = writeln!(
+        svg,
+        "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\" stroke-dasharray=\"4 3\"/>",
+        peak_path, PALETTE[3]
+    );
+    let _ = writeln!(
+        svg,
+        "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+        rss_path, PALETTE[0]
+    );
+    for &(time, rss, peak_rss, name) in &points {
+        let (x, y) = position(time, rss);
+        let _ = writeln!(
+            svg,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{}\">\
+             <title>{} at {:.3} ms: RSS {}, peak {}</title></circle>",
+            x,
+            y,
+            PALETTE[0],
+            escape(name),
+            time,
+            format_bytes(rss),
+            format_bytes(peak_rss)
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<line x1=\"{m}\" y1=\"{b}\" x2=\"{r}\" y2=\"{b}\" stroke=\"#888\"/>\n\
+         <text x=\"{m}\" y=\"{t}\">0 ms</text>\n\
+         <text x=\"{r}\" y=\"{t}\" text-anchor=\"end\">{last:.3} ms</text>\n\
+         <text x=\"{m}\" y=\"{top}\">RSS after each operation, peak RSS {peak} dashed</text>",
+        m = MARGIN,
+        r = CHART_WIDTH - MARGIN,
+        b = MARGIN + plot_height,
+        t = MARGIN + plot_height + 16.,
+        top = MARGIN - 8.,
+        last = last_time,
+        peak = format_bytes(peak)
+    );
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn operations_table(spans: &[SpanRecord]) -> String {
+    let mut table: String = String::from(
+        "<table>\n<tr><th>Operation</th><th>Phase</th><th>Wall clock</th><th>User</th>\
+         <th>System</th><th>RSS before</th><th>RSS after</th><th>RSS change</th>\
+         <th>Peak RSS growth</th><th>CPU</th></tr>\n",
+    );
+    for span in spans.iter().filter(|span| span.kind == SpanKind::Operation) {
+        let metrics: &Metrics = &span.metrics;
+        let _ = writeln!(
+            table,
+            "<tr><td>{}</td><td>{}</td><td class=\"n\">{:.3} ms</td><td class=\"n\">{:.3} ms</td>\
+             <td class=\"n\">{:.3} ms</td><td class=\"n\">{}</td><td class=\"n\">{}</td>\
+             <td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{:.1}%</td></tr>",
+            escape(&span.name),
+            escape(span.parent.as_deref().unwrap_or("-")),
+            millis(metrics.wall_clock_time),
+            millis(metrics.user_time),
+            millis(metrics.system_time),
+            format_bytes(metrics.rss_before),
+            format_bytes(metrics.rss_after),
+            format_bytes_delta(metrics.rss_delta()),
+            format_bytes(metrics.peak_rss_growth),
+            metrics.cpu_usage
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
+
+fn environment_table(environment: &EnvironmentInfo) -> String {
+    let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    let rows: Vec<(&str, String)> = vec![
+        ("Host", environment.hostname.clone()),
+        ("CPU", environment.cpu_model.clone()),
+        (
+            "Cores",
+            match environment.physical_cores {
+                Some(physical) => format!(
+                    "{} logical, {} physical",
+                    environment.logical_cores, physical
+                ),
+                None => format!("{} logical", environment.logical_cores),
+            },
+        ),
+        ("CPU affinity", or_unknown(&environment.cpu_affinity)),
+        ("Governor", or_unknown(&environment.cpu_governor)),
+        (
+            "Total memory",
+            environment
+                .total_memory
+                .map_or_else(|| "-".to_string(), |bytes| format!("{} bytes", bytes)),
+        ),
+        ("OS", environment.os.clone()),
+        (
+            "Kernel",
+            format!("{} ({})", environment.kernel, environment.architecture),
+        ),
+        ("GPU", or_unknown(&environment.gpu)),
+        ("GPU driver", or_unknown(&environment.gpu_driver)),
+        ("ONNX Runtime API", environment.ort_api_version.clone()),
+        ("Git commit", environment.git_commit.clone()),
+        ("Build profile", environment.build_profile.clone()),
+    ];
+
+    let mut table: String = String::from("<table>\n");
+    for (label, value) in rows {
+        let _ = writeln!(
+            table,
+            "<tr><th>{}</th><td>{}</td></tr>",
+            label,
+            escape(&value)
+        );
+    }
+    for warning in &environment.warnings {
+        let _ = writeln!(
+            table,
+            "<tr><th>Warning</th><td>{}</td></tr>",
+            escape(warning)
+        );
+    }
+    table.push_str("</table>\n");
+    table
+}
@@ -0,0 +1,370 @@
+use crate::{
+    error::AppError,
+    preprocess::{process_image, PreprocessConfig},
+    tensor::{validate_input_tensor, NpyTensor},
+};
+use image::{DynamicImage, RgbImage};
+use ort::{session::Input, value::DynValue};
+use std::{fs, io::Read, path::Path, str::FromStr};
+
+/// Path that reads an input from standard input instead of a file.
+pub const STDIN_PATH: &str = "-";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSource {
+    Image(String),
+    /// Already decoded RGB8 pixels without any header, e.g. a framebuffer, for `--raw-rgb WxH`.
+    RawRgb {
+        path: String,
+        size: FrameSize,
+    },
+    Tensor(String),
+}
+
+impl InputSource {
+    /// The positional image, raw RGB pixels of `raw_rgb` size if given.
+    pub fn image(path: &str, raw_rgb: Option<FrameSize>) -> Self {
+        match raw_rgb {
+            Some(size) => InputSource::RawRgb {
+                path: path.to_string(),
+                size,
+            },
+            None => InputSource::Image(path.to_string()),
+        }
+    }
+
+    pub fn from_path(path: &str) -> Self {
+        let is_npy: bool = Path::new(path)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("npy"));
+        if is_npy {
+            InputSource::Tensor(path.to_string())
+        } else {
+            InputSource::Image(path.to_string())
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            InputSource::Image(path)
+            | InputSource::RawRgb { path, .. }
+            | InputSource::Tensor(path) => path,
+        }
+    }
+
+    pub fn is_stdin(&self) -> bool {
+        self.path() == STDIN_PATH
+    }
+
+    /// Operation reading the input from disk or stdin.
+    pub fn operation_name(&self) -> &'static str {
+        match self {
+            InputSource::Image(_) | InputSource::RawRgb { .. } => "readimg",
+            InputSource::Tensor(_) => "readtensor",
+        }
+    }
+
+    /// Operation decoding what [`InputSource::read`] returned.
+    pub fn decode_operation_name(&self) -> &'static str {
+        match self {
+            InputSource::Image(_) | InputSource::RawRgb { .. } => "decodeimg",
+            InputSource::Tensor(_) => "decodetensor",
+        }
+    }
+
+    /// The bytes of the file, or everything written to stdin.
+    pub fn read(&self) -> Result<Vec<u8>, AppError> {
+        if !self.is_stdin() {
+            return fs::read(self.path()).map_err(|e| {
+                AppError::InputError(format!("failed to read {}: {}", self.path(), e))
+            });
+        }
+        let mut bytes: Vec<u8> = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|e| AppError::InputError(format!("failed to read stdin: {}", e)))?;
+        if bytes.is_empty() {
+            return Err(AppError::InputError(
+                "nothing was written to stdin".to_string(),
+            ));
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(&self, bytes: Vec<u8>) -> Result<InputData, AppError> {
+        match self {
+            InputSource::Image(_) => Ok(InputData::Image(image::load_from_memory(&bytes)?)),
+            InputSource::RawRgb { path, size } => {
+                let expected: usize = size.width as usize * size.height as usize * 3;
+                if bytes.len() != expected {
+                    return Err(AppError::InputError(format!(
+                        "{} has {} bytes, {}x{} RGB pixels are {}",
+                        path,
+                        bytes.len(),
+                        size.width,
+                        size.height,
+                        expected
+                    )));
+                }
+                let image: RgbImage = RgbImage::from_raw(size.width, size.height, bytes)
+                    .expect("the buffer holds every pixel");
+                Ok(InputData::Image(DynamicImage::ImageRgb8(image)))
+            }
+            InputSource::Tensor(path) => match NpyTensor::read(&bytes) {
+                Ok(tensor) => Ok(InputData::Tensor(tensor)),
+                Err(AppError::InputError(e)) => {
+                    Err(AppError::InputError(format!("{}: {}", path, e)))
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    pub fn load(&self) -> Result<InputData, AppError> {
+        self.decode(self.read()?)
+    }
+}
+
+/// A `--raw-rgb WxH` argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for FrameSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| value.parse::<u32>().ok().filter(|&value| value > 0);
+        s.split_once('x')
+            .and_then(|(width, height)| {
+                Some(Self {
+                    width: parse(width)?,
+                    height: parse(height)?,
+                })
+            })
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{}'", s))
+    }
+}
+
+/// A `--input NAME=PATH` argument. `.npy` paths are fed as tensors, anything else is decoded as an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputSpec {
+    pub name: String,
+    pub source: InputSource,
+}
+
+impl FromStr for InputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s
+            .split_once('=')
+            .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+            .ok_or_else(|| format!("expected NAME=PATH, got '{}'", s))?;
+        Ok(Self {
+            name: name.to_string(),
+            source: InputSource::from_path(path),
+        })
+    }
+}
+
+/// A `--preprocess [NAME:]KEY=VALUE` argument. Without a name it applies to every image input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessOverride {
+    pub input: Option<String>,
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for PreprocessOverride {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (target, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected [NAME:]KEY=VALUE, got '{}'", s))?;
+        let (input, key) = match target.split_once(':') {
+            Some((input, key)) => (Some(input.to_string()), key),
+            None => (None, target),
+        };
+        Ok(Self {
+            input,
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputBinding {
+    pub name: String,
+    pub source: InputSource,
+}
+
+#[derive(Clone)]
+pub enum InputData {
+    Image(DynamicImage),
+    Tensor(NpyTensor),
+}
+
+/// Pairs every model input with a source. A positional image or `--input-tensor` feeds the first model
+/// input unless `--input` already names it.
+pub fn resolve_bindings(
+    model_inputs: &[Input],
+    positional: Option<InputSource>,
+    specs: &[InputSpec],
+) -> Result<Vec<InputBinding>, AppError> {
+    let mut bindings: Vec<InputBinding> = Vec::new();
+
+    for spec in specs {
+        if !model_inputs.iter().any(|input| input.name == spec.name) {
+            let names: Vec<&str> = model_inputs
+                .iter()
+                .map(|input| input.name.as_str())
+                .collect();
+            return Err(AppError::InputError(format!(
+                "model has no input named '{}' (inputs: {})",
+                spec.name,
+                names.join(", ")
+            )));
+        }
+        if bindings.iter().any(|binding| binding.name == spec.name) {
+            return Err(AppError::InputError(format!(
+                "input '{}' was given more than once",
+                spec.name
+            )));
+        }
+        bindings.push(InputBinding {
+            name: spec.name.clone(),
+            source: spec.source.clone(),
+        });
+    }
+
+    if let Some(source) = positional {
+        let first: &Input = model_inputs
+            .first()
+            .ok_or_else(|| AppError::InputError("model has no inputs".to_string()))?;
+        if bindings.iter().any(|binding| binding.name == first.name) {
+            return Err(AppError::InputError(format!(
+                "input '{}' was given both positionally and with --input",
+                first.name
+            )));
+        }
+        bindings.insert(
+            0,
+            InputBinding {
+                name: first.name.clone(),
+                source,
+            },
+        );
+    }
+
+    if bindings
+        .iter()
+        .filter(|binding| binding.source.is_stdin())
+        .count()
+        > 1
+    {
+        return Err(AppError::InputError(
+            "only one input can be read from stdin".to_string(),
+        ));
+    }
+
+    let missing: Vec<&str> = model_inputs
+        .iter()
+        .filter(|input| !bindings.iter().any(|binding| binding.name == input.name))
+        .map(|input| input.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        return Err(AppError::InputError(format!(
+            "no data given for model inputs: {}",
+            missing.join(", ")
+        )));
+    }
+
+    Ok(bindings)
+}
+
+pub fn check_overrides(
+    bindings: &[InputBinding],
+    overrides: &[PreprocessOverride],
+) -> Result<(), AppError> {
+    for preprocess in overrides {
+        if let Some(input) = &preprocess.input {
+            if !bindings.iter().any(|binding| binding.name == *input) {
+                return Err(AppError::InputError(format!(
+                    "--preprocess refers to unknown input '{}'",
+                    input
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Preprocessing of images fed to `model_input`: derived from its shape, then overridden.
+pub fn image_config(
+    model_input: &Input,
+    overrides: &[PreprocessOverride],
+) -> Result<PreprocessConfig, AppError> {
+    let mut config: PreprocessConfig = PreprocessConfig::for_input(model_input);
+    for preprocess in overrides {
+        let applies: bool = preprocess
+            .input
+            .as_ref()
+            .is_none_or(|input| *input == model_input.name);
+        if applies {
+            config.apply(&preprocess.key, &preprocess.value)?;
+        }
+    }
+    Ok(config)
+}
+
+/// Loaded input data together with everything needed to turn it into a session value.
+#[derive(Clone)]
+pub struct PreparedInput {
+    pub name: String,
+    pub data: InputData,
+    pub config: PreprocessConfig,
+}
+
+impl PreparedInput {
+    pub fn new(
+        model_input: &Input,
+        data: InputData,
+        overrides: &[PreprocessOverride],
+    ) -> Result<Self, AppError> {
+        let config: PreprocessConfig = match &data {
+            InputData::Tensor(tensor) => {
+                validate_input_tensor(model_input, tensor)?;
+                PreprocessConfig::for_input(model_input)
+            }
+            InputData::Image(_) => image_config(model_input, overrides)?,
+        };
+
+        Ok(Self {
+            name: model_input.name.clone(),
+            data,
+            config,
+        })
+    }
+
+    /// Width and height of the decoded image, before any resizing.
+    pub fn image_size(&self) -> Option<(u32, u32)> {
+        match &self.data {
+            InputData::Image(img) => Some((img.width(), img.height())),
+            InputData::Tensor(_) => None,
+        }
+    }
+
+    pub fn into_value(self) -> Result<(String, DynValue), AppError> {
+        let value: DynValue = match self.data {
+            InputData::Image(original_img) => process_image(original_img, &self.config).into_dyn(),
+            InputData::Tensor(tensor) => tensor.into_value()?,
+        };
+        Ok((self.name, value))
+    }
+}
@@ -0,0 +1,154 @@
+use crate::error::AppError;
+use ort::{metadata::ModelMetadata, session::Session, value::ValueType};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+const OPSET_DOMAINS: [&str; 2] = ["ai.onnx", "ai.onnx.ml"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueInfo {
+    pub name: String,
+    pub kind: String,
+    pub element_type: Option<String>,
+    pub shape: Option<Vec<i64>>,
+    pub dimension_symbols: Option<Vec<String>>,
+    pub description: String,
+}
+
+impl ValueInfo {
+    fn new(name: &str, value_type: &ValueType) -> Self {
+        let kind: &str = match value_type {
+            ValueType::Tensor { .. } => "tensor",
+            ValueType::Sequence(_) => "sequence",
+            ValueType::Map { .. } => "map",
+            ValueType::Optional(_) => "optional",
+        };
+
+        let (element_type, shape, dimension_symbols) = match value_type {
+            ValueType::Tensor {
+                ty,
+                shape,
+                dimension_symbols,
+            } => (
+                Some(ty.to_string()),
+                Some(shape.to_vec()),
+                Some(dimension_symbols.to_vec()),
+            ),
+            _ => (None, None, None),
+        };
+
+        Self {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            element_type,
+            shape,
+            dimension_symbols,
+            description: value_type.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelInfo {
+    pub path: String,
+    pub inputs: Vec<ValueInfo>,
+    pub outputs: Vec<ValueInfo>,
+    pub opsets: BTreeMap<String, u32>,
+    pub producer: Option<String>,
+    pub graph_name: Option<String>,
+    pub description: Option<String>,
+    pub graph_description: Option<String>,
+    pub domain: Option<String>,
+    pub version: Option<i64>,
+    pub custom_metadata: BTreeMap<String, String>,
+}
+
+impl ModelInfo {
+    pub fn from_session(path: &str, session: &Session) -> Result<Self, AppError> {
+        let metadata: ModelMetadata<'_> = session.metadata()?;
+
+        let mut opsets: BTreeMap<String, u32> = BTreeMap::new();
+        for domain in OPSET_DOMAINS {
+            // The default ONNX domain is registered under the empty string
+            let lookup: &str = if domain == "ai.onnx" { "" } else { domain };
+            if let Ok(opset) = session.opset_for_domain(lookup) {
+                opsets.insert(domain.to_string(), opset);
+            }
+        }
+
+        let mut custom_metadata: BTreeMap<String, String> = BTreeMap::new();
+        for key in metadata.custom_keys().unwrap_or_default() {
+            if let Ok(Some(value)) = metadata.custom(&key) {
+                custom_metadata.insert(key, value);
+            }
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            inputs: session
+                .inputs
+                .iter()
+                .map(|input| ValueInfo::new(&input.name, &input.input_type))
+                .collect(),
+            outputs: session
+                .outputs
+                .iter()
+                .map(|output| ValueInfo::new(&output.name, &output.output_type))
+                .collect(),
+            opsets,
+            producer: non_empty(metadata.producer()),
+            graph_name: non_empty(metadata.name()),
+            description: non_empty(metadata.description()),
+            graph_description: non_empty(metadata.graph_description()),
+            domain: non_empty(metadata.domain()),
+            version: metadata.version().ok(),
+            custom_metadata,
+        })
+    }
+}
+
+fn non_empty(value: ort::Result<String>) -> Option<String> {
+    value.ok().filter(|value| !value.is_empty())
+}
+
+impl std::fmt::Display for ModelInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+
+        writeln!(f, "============= Model: {} =============", self.path)?;
+        writeln!(f, "Producer: {}", or_unknown(&self.producer))?;
+        writeln!(f, "Graph Name: {}", or_unknown(&self.graph_name))?;
+        writeln!(f, "Description: {}", or_unknown(&self.description))?;
+        writeln!(
+            f,
+            "Graph Description: {}",
+            or_unknown(&self.graph_description)
+        )?;
+        writeln!(f, "Domain: {}", or_unknown(&self.domain))?;
+        match self.version {
+            Some(version) => writeln!(f, "Model Version: {}", version)?,
+            None => writeln!(f, "Model Version: -")?,
+        }
+        for (domain, opset) in &self.opsets {
+            writeln!(f, "Opset ({}): {}", domain, opset)?;
+        }
+
+        writeln!(f, "\nInputs:")?;
+        for input in &self.inputs {
+            writeln!(f, "  {}: {}", input.name, input.description)?;
+        }
+
+        writeln!(f, "\nOutputs:")?;
+        for output in &self.outputs {
+            writeln!(f, "  {}: {}", output.name, output.description)?;
+        }
+
+        if !self.custom_metadata.is_empty() {
+            writeln!(f, "\nCustom Metadata:")?;
+            for (key, value) in &self.custom_metadata {
+                writeln!(f, "  {}: {}", key, value)?;
+            }
+        }
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,32 @@
+use crate::error::AppError;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Turns the first SIGINT or SIGTERM into a request to stop, which benchmark loops poll through
+/// [`interrupted`] between iterations. The handler is reset once it fired, so a second signal
+/// terminates the process as usual.
+pub fn install() -> Result<(), AppError> {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        // Blocking reads, such as video frames from ffmpeg, resume after the handler
+        action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(AppError::SystemError(format!(
+                "failed to install the signal handler: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether SIGINT or SIGTERM was received since [`install`].
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,112 @@
+use crate::{
+    error::AppError,
+    interrupt::interrupted,
+    stats::{mean, millis, percentile},
+    tensor::session_inputs,
+};
+use ort::{session::Session, value::DynValue};
+use serde::Serialize;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Scheduling and latency measurements of an `--interval` run.
+#[derive(Debug, Clone, Serialize)]
+pub struct IntervalReport {
+    pub interval_ms: f64,
+    pub count: usize,
+    /// Ticks that passed while a run was still busy, and were skipped.
+    pub missed_deadlines: usize,
+    pub mean_jitter_ms: f64,
+    pub p99_jitter_ms: f64,
+    pub max_jitter_ms: f64,
+    pub mean_latency_ms: f64,
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub p99_latency_ms: f64,
+    pub max_latency_ms: f64,
+}
+
+/// Fires `count` inferences, one per tick of `interval`. Ticks are scheduled from the start time
+/// rather than from the end of the previous run, so sleeping and running never make the schedule
+/// drift. A run that overruns one or more ticks misses them, and the next run waits for the
+/// following tick.
+pub fn run_periodic(
+    model: &mut Session,
+    inputs: &[(String, DynValue)],
+    interval: Duration,
+    count: u32,
+) -> Result<IntervalReport, AppError> {
+    let mut jitters: Vec<Duration> = Vec::with_capacity(count as usize);
+    let mut latencies: Vec<Duration> = Vec::with_capacity(count as usize);
+    let mut missed_deadlines: usize = 0;
+
+    let start: Instant = Instant::now();
+    let mut tick: u32 = 0;
+    for _ in 0..count {
+        if interrupted() {
+            break;
+        }
+        let deadline: Instant = start + interval * tick;
+        let now: Instant = Instant::now();
+        if now < deadline {
+            thread::sleep(deadline - now);
+        }
+
+        let fired: Instant = Instant::now();
+        jitters.push(fired - deadline);
+        model.run(session_inputs(inputs))?;
+        let finished: Instant = Instant::now();
+        latencies.push(finished - fired);
+
+        // The next tick still ahead of the finished run
+        let elapsed_ticks: u32 = ((finished - start).as_nanos() / interval.as_nanos()) as u32;
+        let next: u32 = elapsed_ticks.max(tick) + 1;
+        missed_deadlines += (next - tick - 1) as usize;
+        tick = next;
+    }
+
+    jitters.sort();
+    latencies.sort();
+    Ok(IntervalReport {
+        interval_ms: millis(interval),
+        count: latencies.len(),
+        missed_deadlines,
+        mean_jitter_ms: millis(mean(&jitters)),
+        p99_jitter_ms: millis(percentile(&jitters, 0.99)),
+        max_jitter_ms: millis(percentile(&jitters, 1.)),
+        mean_latency_ms: millis(mean(&latencies)),
+        p50_latency_ms: millis(percentile(&latencies, 0.5)),
+        p95_latency_ms: millis(percentile(&latencies, 0.95)),
+        p99_latency_ms: millis(percentile(&latencies, 0.99)),
+        max_latency_ms: millis(percentile(&latencies, 1.)),
+    })
+}
+
+impl std::fmt::Display for IntervalReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Interval Metrics =============")?;
+        writeln!(
+            f,
+            "Inferences: {} every {:.3} ms",
+            self.count, self.interval_ms
+        )?;
+        writeln!(f, "Missed deadlines: {}", self.missed_deadlines)?;
+        writeln!(
+            f,
+            "Scheduling jitter: mean {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+            self.mean_jitter_ms, self.p99_jitter_ms, self.max_jitter_ms
+        )?;
+        writeln!(
+            f,
+            "Latency: mean {:.3} ms, p50 {:.3} ms, p95 {:.3} ms, p99 {:.3} ms, max {:.3} ms",
+            self.mean_latency_ms,
+            self.p50_latency_ms,
+            self.p95_latency_ms,
+            self.p99_latency_ms,
+            self.max_latency_ms
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
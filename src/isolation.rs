@@ -0,0 +1,47 @@
+use crate::error::AppError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Set in a child process to the index of the only configuration it measures.
+pub const CONFIGURATION_ENV: &str = "RUST_ML_BENCHMARK_CONFIGURATION";
+
+/// Index of the configuration this process was spawned to measure, `None` in the parent.
+pub fn configuration() -> Option<usize> {
+    std::env::var(CONFIGURATION_ENV).ok()?.parse().ok()
+}
+
+/// Runs the current command line again in a fresh child process that only measures configuration
+/// `index`, so it starts without the ONNX Runtime environment, sessions or heap of the previous
+/// ones. The child sends its result back as JSON over its stdout pipe and logs to the inherited
+/// stderr.
+pub fn run_configuration<T: DeserializeOwned>(index: usize) -> Result<T, AppError> {
+    let executable = std::env::current_exe()
+        .map_err(|e| AppError::SystemError(format!("failed to locate the benchmark: {}", e)))?;
+    tracing::debug!(configuration = index, "spawning child process");
+    let child: Output = Command::new(executable)
+        .args(std::env::args_os().skip(1))
+        .env(CONFIGURATION_ENV, index.to_string())
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| AppError::SystemError(format!("failed to start a child process: {}", e)))?;
+    if !child.status.success() {
+        return Err(AppError::Other(format!(
+            "the child process measuring configuration {} failed ({})",
+            index, child.status
+        )));
+    }
+    Ok(serde_json::from_slice(&child.stdout)?)
+}
+
+/// Sends the result of a child process to its parent. Nothing else may be printed to stdout.
+pub fn send_result<T: Serialize>(result: &T) -> Result<(), AppError> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, result)?;
+    stdout
+        .flush()
+        .map_err(|e| AppError::SystemError(format!("failed to send the result: {}", e)))
+}
@@ -0,0 +1,631 @@
+use crate::{
+    bench_op,
+    bundle::tensor_bytes,
+    error::AppError,
+    stats::{mean, percentile},
+    tensor::{convert_inputs, session_inputs},
+    tracker::BenchmarkTracker,
+};
+use half::f16;
+use ort::{
+    session::{Session, SessionOutputs},
+    tensor::TensorElementType,
+    value::{DynValue, Tensor, ValueType},
+};
+use std::{
+    convert::Infallible,
+    future::Future,
+    marker::PhantomData,
+    net::SocketAddr,
+    sync::{mpsc, Arc},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+use tokio::sync::oneshot;
+use tonic::{
+    body::Body,
+    codegen::{http, BoxFuture, Service, StdError},
+    server::{Grpc, NamedService},
+    Code, Request, Response, Status,
+};
+use tonic_prost::ProstCodec;
+
+/// Messages of the KServe v2 `inference.GRPCInferenceService`, written out by hand so no protoc is needed.
+/// Only the fields this server reads or writes are declared, protobuf skips the others.
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerLiveRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerLiveResponse {
+        #[prost(bool, tag = "1")]
+        pub live: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerReadyRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerReadyResponse {
+        #[prost(bool, tag = "1")]
+        pub ready: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelReadyRequest {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelReadyResponse {
+        #[prost(bool, tag = "1")]
+        pub ready: bool,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerMetadataRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ServerMetadataResponse {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+        #[prost(string, repeated, tag = "3")]
+        pub extensions: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelMetadataRequest {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub version: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TensorMetadata {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub datatype: String,
+        #[prost(int64, repeated, tag = "3")]
+        pub shape: Vec<i64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelMetadataResponse {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, repeated, tag = "2")]
+        pub versions: Vec<String>,
+        #[prost(string, tag = "3")]
+        pub platform: String,
+        #[prost(message, repeated, tag = "4")]
+        pub inputs: Vec<TensorMetadata>,
+        #[prost(message, repeated, tag = "5")]
+        pub outputs: Vec<TensorMetadata>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferTensorContents {
+        #[prost(bool, repeated, tag = "1")]
+        pub bool_contents: Vec<bool>,
+        #[prost(int32, repeated, tag = "2")]
+        pub int_contents: Vec<i32>,
+        #[prost(int64, repeated, tag = "3")]
+        pub int64_contents: Vec<i64>,
+        #[prost(uint32, repeated, tag = "4")]
+        pub uint_contents: Vec<u32>,
+        #[prost(uint64, repeated, tag = "5")]
+        pub uint64_contents: Vec<u64>,
+        #[prost(float, repeated, tag = "6")]
+        pub fp32_contents: Vec<f32>,
+        #[prost(double, repeated, tag = "7")]
+        pub fp64_contents: Vec<f64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferInputTensor {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub datatype: String,
+        #[prost(int64, repeated, tag = "3")]
+        pub shape: Vec<i64>,
+        #[prost(message, optional, tag = "5")]
+        pub contents: Option<InferTensorContents>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferRequestedOutputTensor {
+        #[prost(string, tag = "1")]
+        pub name: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelInferRequest {
+        #[prost(string, tag = "1")]
+        pub model_name: String,
+        #[prost(string, tag = "2")]
+        pub model_version: String,
+        #[prost(string, tag = "3")]
+        pub id: String,
+        #[prost(message, repeated, tag = "5")]
+        pub inputs: Vec<InferInputTensor>,
+        #[prost(message, repeated, tag = "6")]
+        pub outputs: Vec<InferRequestedOutputTensor>,
+        #[prost(bytes = "vec", repeated, tag = "7")]
+        pub raw_input_contents: Vec<Vec<u8>>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InferOutputTensor {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub datatype: String,
+        #[prost(int64, repeated, tag = "3")]
+        pub shape: Vec<i64>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ModelInferResponse {
+        #[prost(string, tag = "1")]
+        pub model_name: String,
+        #[prost(string, tag = "2")]
+        pub model_version: String,
+        #[prost(string, tag = "3")]
+        pub id: String,
+        #[prost(message, repeated, tag = "5")]
+        pub outputs: Vec<InferOutputTensor>,
+        #[prost(bytes = "vec", repeated, tag = "6")]
+        pub raw_output_contents: Vec<Vec<u8>>,
+    }
+}
+
+use proto::*;
+
+const SERVICE_NAME: &str = "inference.GRPCInferenceService";
+const MODEL_VERSION: &str = "1";
+
+type InferJob = (
+    ModelInferRequest,
+    oneshot::Sender<Result<ModelInferResponse, Status>>,
+);
+
+/// KServe v2 datatype name of an ONNX element type.
+fn datatype(element_type: TensorElementType) -> &'static str {
+    match element_type {
+        TensorElementType::Bool => "BOOL",
+        TensorElementType::Uint8 => "UINT8",
+        TensorElementType::Uint16 => "UINT16",
+        TensorElementType::Uint32 => "UINT32",
+        TensorElementType::Uint64 => "UINT64",
+        TensorElementType::Int8 => "INT8",
+        TensorElementType::Int16 => "INT16",
+        TensorElementType::Int32 => "INT32",
+        TensorElementType::Int64 => "INT64",
+        TensorElementType::Float16 => "FP16",
+        TensorElementType::Float32 => "FP32",
+        TensorElementType::Float64 => "FP64",
+        TensorElementType::Bfloat16 => "BF16",
+        TensorElementType::String => "BYTES",
+        _ => "UNKNOWN",
+    }
+}
+
+fn tensor_metadata(name: &str, value_type: &ValueType) -> TensorMetadata {
+    match value_type {
+        ValueType::Tensor { ty, shape, .. } => TensorMetadata {
+            name: name.to_string(),
+            datatype: datatype(*ty).to_string(),
+            shape: shape.to_vec(),
+        },
+        _ => TensorMetadata {
+            name: name.to_string(),
+            datatype: "UNKNOWN".to_string(),
+            shape: Vec::new(),
+        },
+    }
+}
+
+/// Little-endian element bytes of `raw_input_contents`, or the typed `contents` field.
+fn input_value(input: &InferInputTensor, raw: Option<&[u8]>) -> Result<DynValue, Status> {
+    let shape: Vec<i64> = input.shape.clone();
+    let contents: InferTensorContents = input.contents.clone().unwrap_or_default();
+    let invalid =
+        |e: ort::Error| Status::invalid_argument(format!("input '{}': {}", input.name, e));
+
+    macro_rules! tensor {
+        ($type_:ty, $typed:expr) => {{
+            let data: Vec<$type_> = match raw {
+                Some(bytes) => bytes
+                    .chunks_exact(std::mem::size_of::<$type_>())
+                    .map(|chunk| <$type_>::from_le_bytes(chunk.try_into().expect("exact chunk")))
+                    .collect(),
+                None => $typed,
+            };
+            Tensor::from_array((shape, data))
+                .map_err(invalid)?
+                .into_dyn()
+        }};
+    }
+
+    let value: DynValue = match input.datatype.as_str() {
+        "FP32" => tensor!(f32, contents.fp32_contents),
+        "FP64" => tensor!(f64, contents.fp64_contents),
+        "FP16" => tensor!(
+            f16,
+            contents
+                .fp32_contents
+                .iter()
+                .map(|&v| f16::from_f32(v))
+                .collect()
+        ),
+        "INT64" => tensor!(i64, contents.int64_contents),
+        "INT32" => tensor!(i32, contents.int_contents),
+        "INT8" => tensor!(i8, contents.int_contents.iter().map(|&v| v as i8).collect()),
+        "UINT8" => tensor!(
+            u8,
+            contents.uint_contents.iter().map(|&v| v as u8).collect()
+        ),
+        "BOOL" => {
+            let data: Vec<bool> = match raw {
+                Some(bytes) => bytes.iter().map(|&byte| byte != 0).collect(),
+                None => contents.bool_contents,
+            };
+            Tensor::from_array((shape, data))
+                .map_err(invalid)?
+                .into_dyn()
+        }
+        other => {
+            return Err(Status::invalid_argument(format!(
+                "input '{}': unsupported datatype {}",
+                input.name, other
+            )))
+        }
+    };
+    Ok(value)
+}
+
+/// Finished operations the worker keeps for its summary, a few thousand requests' worth, so a long
+/// running server does not grow without bound.
+const RETAINED_SPANS: usize = 3 * 4096;
+
+/// Owns the session and the tracker on one thread, so requests are measured one at a time.
+struct InferenceWorker {
+    model: Session,
+    model_name: String,
+    /// Outputs of the model in its order, returned when a request names none.
+    output_names: Vec<String>,
+    tracker: BenchmarkTracker,
+    requests: usize,
+    failed: usize,
+}
+
+impl InferenceWorker {
+    fn run(mut self, jobs: mpsc::Receiver<InferJob>) {
+        self.tracker.set_span_limit(RETAINED_SPANS);
+        self.tracker.start_phase("Serving");
+        for (request, reply) in jobs {
+            self.requests += 1;
+            let response: Result<ModelInferResponse, Status> = self.infer(request);
+            if let Err(status) = &response {
+                self.failed += 1;
+                tracing::debug!(code = ?status.code(), "{}", status.message());
+            }
+            // The client may have gone away, its request still counts
+            let _ = reply.send(response);
+        }
+        self.tracker.end_phase("Serving");
+        self.print_summary();
+    }
+
+    fn infer(&mut self, request: ModelInferRequest) -> Result<ModelInferResponse, Status> {
+        if !request.model_name.is_empty() && request.model_name != self.model_name {
+            return Err(Status::not_found(format!(
+                "unknown model '{}'",
+                request.model_name
+            )));
+        }
+        if !request.raw_input_contents.is_empty()
+            && request.raw_input_contents.len() != request.inputs.len()
+        {
+            return Err(Status::invalid_argument(
+                "raw_input_contents must hold one entry per input",
+            ));
+        }
+
+        let inputs: Vec<(String, DynValue)> = bench_op!(self.tracker, "Decode", {
+            let decoded: Vec<(String, DynValue)> = request
+                .inputs
+                .iter()
+                .enumerate()
+                .map(|(index, input)| {
+                    let raw: Option<&[u8]> =
+                        request.raw_input_contents.get(index).map(Vec::as_slice);
+                    Ok((input.name.clone(), input_value(input, raw)?))
+                })
+                .collect::<Result<_, Status>>()?;
+            convert_inputs(decoded, &self.model.inputs)
+                .map_err(|e| Status::invalid_argument(e.to_string()))?
+        });
+
+        let outputs: SessionOutputs<'_> = bench_op!(
+            self.tracker,
+            "Inference",
+            self.model
+                .run(session_inputs(&inputs))
+                .map_err(|e| Status::internal(e.to_string()))?
+        );
+
+        let _encode = self.tracker.scoped("Encode");
+        let mut response: ModelInferResponse = ModelInferResponse {
+            model_name: self.model_name.clone(),
+            model_version: MODEL_VERSION.to_string(),
+            id: request.id,
+            ..ModelInferResponse::default()
+        };
+        let names: Vec<&str> = if request.outputs.is_empty() {
+            self.output_names.iter().map(String::as_str).collect()
+        } else {
+            request
+                .outputs
+                .iter()
+                .map(|output| output.name.as_str())
+                .collect()
+        };
+        // Every output keeps its element type, the one `ModelMetadata` reports
+        for name in names {
+            let value: &DynValue = outputs.get(name).ok_or_else(|| {
+                Status::invalid_argument(format!("the model has no output '{}'", name))
+            })?;
+            let (element_type, shape, bytes) =
+                tensor_bytes(name, value).map_err(|e| Status::internal(e.to_string()))?;
+            response.outputs.push(InferOutputTensor {
+                name: name.to_string(),
+                datatype: datatype(element_type).to_string(),
+                shape,
+            });
+            response.raw_output_contents.push(bytes);
+        }
+
+        Ok(response)
+    }
+
+    fn print_summary(&self) {
+        println!("============= KServe Metrics =============");
+        println!("Requests: {} ({} failed)", self.requests, self.failed);
+        for operation in ["Decode", "Inference", "Encode"] {
+            let mut times: Vec<Duration> = self.tracker.operation_times(operation);
+            if times.is_empty() {
+                continue;
+            }
+            times.sort();
+            println!(
+                "{} (last {} runs): mean {:?}  p50 {:?}  p99 {:?}  max {:?}",
+                operation,
+                times.len(),
+                mean(&times),
+                percentile(&times, 0.5),
+                percentile(&times, 0.99),
+                percentile(&times, 1.)
+            );
+        }
+        println!("=======================================");
+        print!("{}", self.tracker.get_total_metrics());
+    }
+}
+
+/// Request handlers. Metadata is answered from a snapshot taken at startup, inference is forwarded
+/// to the worker thread.
+#[derive(Clone)]
+pub struct InferenceService {
+    model_name: String,
+    metadata: Arc<ModelMetadataResponse>,
+    jobs: mpsc::Sender<InferJob>,
+}
+
+impl InferenceService {
+    async fn server_live(
+        &self,
+        _: Request<ServerLiveRequest>,
+    ) -> Result<Response<ServerLiveResponse>, Status> {
+        Ok(Response::new(ServerLiveResponse { live: true }))
+    }
+
+    async fn server_ready(
+        &self,
+        _: Request<ServerReadyRequest>,
+    ) -> Result<Response<ServerReadyResponse>, Status> {
+        Ok(Response::new(ServerReadyResponse { ready: true }))
+    }
+
+    async fn model_ready(
+        &self,
+        request: Request<ModelReadyRequest>,
+    ) -> Result<Response<ModelReadyResponse>, Status> {
+        Ok(Response::new(ModelReadyResponse {
+            ready: request.get_ref().name == self.model_name,
+        }))
+    }
+
+    async fn server_metadata(
+        &self,
+        _: Request<ServerMetadataRequest>,
+    ) -> Result<Response<ServerMetadataResponse>, Status> {
+        Ok(Response::new(ServerMetadataResponse {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            extensions: Vec::new(),
+        }))
+    }
+
+    async fn model_metadata(
+        &self,
+        request: Request<ModelMetadataRequest>,
+    ) -> Result<Response<ModelMetadataResponse>, Status> {
+        if request.get_ref().name != self.model_name {
+            return Err(Status::not_found(format!(
+                "unknown model '{}'",
+                request.get_ref().name
+            )));
+        }
+        Ok(Response::new(self.metadata.as_ref().clone()))
+    }
+
+    async fn model_infer(
+        &self,
+        request: Request<ModelInferRequest>,
+    ) -> Result<Response<ModelInferResponse>, Status> {
+        let (reply, response) = oneshot::channel();
+        self.jobs
+            .send((request.into_inner(), reply))
+            .map_err(|_| Status::unavailable("inference worker stopped"))?;
+        let response: ModelInferResponse = response
+            .await
+            .map_err(|_| Status::internal("inference worker dropped the request"))??;
+        Ok(Response::new(response))
+    }
+}
+
+/// Adapts an async handler to the unary service shape `tonic::server::Grpc` expects.
+struct Unary<F, Res>(F, PhantomData<fn() -> Res>);
+
+impl<Req, Res, F, Fut> Service<Request<Req>> for Unary<F, Res>
+where
+    F: FnMut(Request<Req>) -> Fut,
+    Fut: Future<Output = Result<Response<Res>, Status>>,
+{
+    type Response = Response<Res>;
+    type Error = Status;
+    type Future = Fut;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Req>) -> Self::Future {
+        (self.0)(request)
+    }
+}
+
+macro_rules! route {
+    ($service:expr, $request:expr, $handler:ident) => {{
+        let service: InferenceService = $service.clone();
+        let request = $request;
+        Box::pin(async move {
+            let handler = Unary(
+                move |request| {
+                    let service: InferenceService = service.clone();
+                    async move { service.$handler(request).await }
+                },
+                PhantomData,
+            );
+            Ok(Grpc::new(ProstCodec::default())
+                .unary(handler, request)
+                .await)
+        })
+    }};
+}
+
+impl<B> Service<http::Request<B>> for InferenceService
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let method: &str = request.uri().path().rsplit('/').next().unwrap_or_default();
+        match method {
+            "ServerLive" => route!(self, request, server_live),
+            "ServerReady" => route!(self, request, server_ready),
+            "ModelReady" => route!(self, request, model_ready),
+            "ServerMetadata" => route!(self, request, server_metadata),
+            "ModelMetadata" => route!(self, request, model_metadata),
+            "ModelInfer" => route!(self, request, model_infer),
+            _ => Box::pin(async move { Ok(Status::new(Code::Unimplemented, "").into_http()) }),
+        }
+    }
+}
+
+impl NamedService for InferenceService {
+    const NAME: &'static str = SERVICE_NAME;
+}
+
+/// Serves the model over the KServe v2 gRPC protocol until Ctrl-C, then prints the server-side
+/// metrics of the requests.
+pub fn serve(model: Session, model_name: &str, address: SocketAddr) -> Result<(), AppError> {
+    let metadata: ModelMetadataResponse = ModelMetadataResponse {
+        name: model_name.to_string(),
+        versions: vec![MODEL_VERSION.to_string()],
+        platform: "onnxruntime_onnx".to_string(),
+        inputs: model
+            .inputs
+            .iter()
+            .map(|input| tensor_metadata(&input.name, &input.input_type))
+            .collect(),
+        outputs: model
+            .outputs
+            .iter()
+            .map(|output| tensor_metadata(&output.name, &output.output_type))
+            .collect(),
+    };
+
+    let (sender, receiver) = mpsc::channel::<InferJob>();
+    let worker_model_name: String = model_name.to_string();
+    let worker = thread::spawn(move || {
+        InferenceWorker {
+            output_names: model
+                .outputs
+                .iter()
+                .map(|output| output.name.clone())
+                .collect(),
+            model,
+            model_name: worker_model_name,
+            tracker: BenchmarkTracker::new(),
+            requests: 0,
+            failed: 0,
+        }
+        .run(receiver)
+    });
+
+    let service: InferenceService = InferenceService {
+        model_name: model_name.to_string(),
+        metadata: Arc::new(metadata),
+        jobs: sender,
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| AppError::ServerError(e.to_string()))?;
+    tracing::info!(address = %address, model = model_name, "serving KServe v2 gRPC");
+    runtime
+        .block_on(
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_shutdown(address, async {
+                    let _ = tokio::signal::ctrl_c().await;
+                }),
+        )
+        .map_err(|e| AppError::ServerError(e.to_string()))?;
+    drop(runtime);
+
+    // The last sender went away with the router, so the worker drains its queue and returns
+    worker
+        .join()
+        .map_err(|_| AppError::ServerError("inference worker panicked".to_string()))
+}
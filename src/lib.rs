@@ -0,0 +1,98 @@
+#[cfg(not(target_family = "wasm"))]
+pub mod affinity;
+pub mod allocator;
+#[cfg(all(feature = "audio", not(target_family = "wasm")))]
+pub mod audio;
+#[cfg(not(target_family = "wasm"))]
+pub mod binding;
+#[cfg(not(target_family = "wasm"))]
+pub mod bundle;
+#[cfg(not(target_family = "wasm"))]
+pub mod calibration;
+#[cfg(not(target_family = "wasm"))]
+pub mod cgroup;
+#[cfg(not(target_family = "wasm"))]
+pub mod cuda;
+#[cfg(not(target_family = "wasm"))]
+pub mod detection;
+#[cfg(all(feature = "download", not(target_family = "wasm")))]
+pub mod download;
+#[cfg(not(target_family = "wasm"))]
+pub mod dryrun;
+#[cfg(not(target_family = "wasm"))]
+pub mod environment;
+pub mod error;
+#[cfg(not(target_family = "wasm"))]
+pub mod evaluate;
+#[cfg(all(feature = "flamegraph", not(target_family = "wasm")))]
+pub mod flamegraph;
+pub mod histogram;
+#[cfg(all(feature = "sqlite", not(target_family = "wasm")))]
+pub mod history;
+#[cfg(not(target_family = "wasm"))]
+pub mod html;
+#[cfg(not(target_family = "wasm"))]
+pub mod input;
+#[cfg(not(target_family = "wasm"))]
+pub mod inspect;
+#[cfg(not(target_family = "wasm"))]
+pub mod interrupt;
+#[cfg(not(target_family = "wasm"))]
+pub mod interval;
+#[cfg(not(target_family = "wasm"))]
+pub mod isolation;
+#[cfg(all(feature = "kserve", not(target_family = "wasm")))]
+pub mod kserve;
+pub mod logging;
+#[cfg(not(target_family = "wasm"))]
+pub mod markdown;
+#[cfg(not(target_family = "wasm"))]
+pub mod matrix;
+#[cfg(not(target_family = "wasm"))]
+pub mod metadata;
+pub mod metrics;
+#[cfg(not(target_family = "wasm"))]
+pub mod model;
+#[cfg(all(feature = "mqtt", not(target_family = "wasm")))]
+pub mod mqtt;
+#[cfg(all(feature = "otlp", not(target_family = "wasm")))]
+pub mod otlp;
+pub mod outliers;
+pub mod parse;
+#[cfg(not(target_family = "wasm"))]
+pub mod pipeline;
+#[cfg(not(target_family = "wasm"))]
+pub mod postprocess;
+#[cfg(not(target_family = "wasm"))]
+pub mod precision;
+#[cfg(not(target_family = "wasm"))]
+pub mod prefetch;
+#[cfg(not(target_family = "wasm"))]
+pub mod preflight;
+pub mod preprocess;
+#[cfg(not(target_family = "wasm"))]
+pub mod report;
+#[cfg(not(target_family = "wasm"))]
+pub mod resize;
+#[cfg(not(target_family = "wasm"))]
+pub mod segmentation;
+#[cfg(all(feature = "server", not(target_family = "wasm")))]
+pub mod server;
+pub mod sink;
+#[cfg(not(target_family = "wasm"))]
+pub mod sla;
+pub mod stats;
+#[cfg(not(target_family = "wasm"))]
+pub mod tensor;
+#[cfg(not(target_family = "wasm"))]
+pub mod tensorrt;
+#[cfg(all(feature = "text", not(target_family = "wasm")))]
+pub mod text;
+#[cfg(not(target_family = "wasm"))]
+pub mod thermal;
+pub mod threads;
+pub mod tracker;
+#[cfg(not(target_family = "wasm"))]
+pub mod video;
+#[cfg(feature = "wasi-nn")]
+pub mod wasi;
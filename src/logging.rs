@@ -0,0 +1,52 @@
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// How much the benchmark logs to stderr, from `--quiet` to `-vv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+    Trace,
+}
+
+impl Verbosity {
+    pub fn from_flags(verbose: u8, quiet: bool) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Trace,
+        }
+    }
+
+    /// ONNX Runtime logs through `tracing` as well, it is only let through from `-v` on.
+    fn directives(self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "error",
+            Verbosity::Normal => "warn,rust_ml_benchmark=info",
+            Verbosity::Verbose => "warn,ort=info,rust_ml_benchmark=debug",
+            Verbosity::Trace => "info,ort=debug,rust_ml_benchmark=trace",
+        }
+    }
+}
+
+/// Installs the global subscriber. `RUST_LOG` overrides the verbosity flags when set.
+pub fn init_logging(verbosity: Verbosity, json: bool) {
+    let filter: EnvFilter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(verbosity.directives()));
+    let span_events: FmtSpan = if verbosity >= Verbosity::Trace {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(std::io::stderr);
+    if json {
+        builder.json().with_current_span(true).init();
+    } else {
+        builder.with_target(verbosity >= Verbosity::Verbose).init();
+    }
+}
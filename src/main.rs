@@ -1,323 +1,2828 @@
-use image::{imageops::FilterType, DynamicImage, GenericImageView};
-use libc::{getrusage, rusage, RUSAGE_SELF};
-use ndarray::{Array, ArrayBase, Dim, OwnedRepr};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use image::{DynamicImage, RgbImage};
 use num_threads::num_threads;
 use ort::{
-    execution_providers::CUDAExecutionProvider,
-    session::{Session, SessionOutputs},
-    value::{TensorValueType, Value},
-    Error as OrtError,
+    execution_providers::{CUDAExecutionProvider, ExecutionProviderDispatch},
+    io_binding::IoBinding,
+    session::{Input, Session, SessionOutputs},
+    tensor::TensorElementType,
+    value::DynValue,
 };
+#[cfg(feature = "audio")]
+use rust_ml_benchmark::audio::{audio_input, load_wav, AudioClip, AudioInputKind, MelConfig};
+#[cfg(feature = "download")]
+use rust_ml_benchmark::download::{CachedModel, ModelCache, RemoteModel};
+#[cfg(feature = "flamegraph")]
+use rust_ml_benchmark::flamegraph::FlamegraphProfiler;
+#[cfg(feature = "mqtt")]
+use rust_ml_benchmark::mqtt::MqttPublisher;
+#[cfg(feature = "otlp")]
+use rust_ml_benchmark::otlp::OtlpExporter;
+#[cfg(feature = "server")]
+use rust_ml_benchmark::server::{BenchmarkRequest, BenchmarkServer};
+use rust_ml_benchmark::{
+    affinity::{pin_process, CoreSet},
+    allocator::{self, AllocatorKind, CountingAllocator, ALLOCATOR_ENV, COUNT_ALLOCATIONS_ENV},
+    bench_op,
+    binding::{bind_session, run_bound, BindingDevice, BOUND_INFERENCE},
+    bundle::{Bundle, BundleSource, RecordedOptions, ReplayReport},
+    calibration::{calibrate, CalibrationConfig, CalibrationReport},
+    cgroup::{CgroupInfo, CgroupReport, CpuStat},
+    cuda::{ArenaStrategy, ConvAlgorithmSearch, CudaOptions, CudaReport, GpuMemoryMonitor},
+    detection::{
+        decode_detections, non_max_suppression, Detection, DetectionConfig, DetectionFormat,
+    },
+    dryrun::{
+        check_inputs, DryRunReport, InputCheck, ProviderCheck, RequestedProvider,
+        DRY_RUN_FAILED_EXIT_CODE,
+    },
+    environment::EnvironmentInfo,
+    error::AppError,
+    evaluate::{evaluate, load_dataset, EvaluationReport, Sample},
+    html::HtmlReport,
+    input::{
+        check_overrides, resolve_bindings, FrameSize, InputBinding, InputData, InputSource,
+        InputSpec, PreparedInput, PreprocessOverride,
+    },
+    inspect::ModelInfo,
+    interrupt::{self, interrupted},
+    interval::{run_periodic, IntervalReport},
+    isolation,
+    logging::{init_logging, Verbosity},
+    markdown::Markdown,
+    matrix::{CellResult, MatrixCell, MatrixDefinition, MatrixReport},
+    metadata::{RunMetadata, Tag},
+    metrics::Metrics,
+    model::{
+        load_model, load_model_from_memory, load_model_profiled, load_model_with_options,
+        load_optimized_model, ModelBytes, ModelOptions, OptimizedModelCache,
+    },
+    parse::{parse_interval, parse_size},
+    pipeline::{load_stages, run_pipeline, PipelineDefinition, PipelineReport, Stage, StageResult},
+    postprocess::{
+        extract_outputs, post_process_outputs, ExtractedOutputs, OutputSchema, OutputSummary,
+    },
+    precision::{run_repeated, PrecisionReport, PrecisionResult, RepeatedRun},
+    prefetch::{PrefetchConfig, Prefetcher},
+    preflight::check_environment,
+    preprocess::{PreprocessConfig, ResizeFilter, ResizeMode},
+    report::{RunReport, TaskOutput},
+    resize::{compare_resizing, ResizeComparison},
+    segmentation::{argmax_mask, colorize_mask, SegmentationMask},
+    sink::SinkSpec,
+    sla::{SlaLimits, SlaReport, SLA_FAILED_EXIT_CODE},
+    tensor::{convert_inputs, convert_value, session_inputs},
+    tensorrt::{TensorRtOptions, TensorRtReport},
+    thermal::{ThermalMonitor, ThermalReport},
+    tracker::{BenchmarkTracker, SpanKind},
+    video::{FramePacer, FrameStage, VideoReport, VideoSource},
+};
+#[cfg(feature = "sqlite")]
+use rust_ml_benchmark::{
+    history::{HistoryFilter, ResultsDb, RunDiff, RunSummary},
+    metrics::format_bytes,
+};
+#[cfg(feature = "flamegraph")]
+use std::sync::{Arc, Mutex};
 use std::{
-    collections::HashMap,
-    env,
     num::NonZero,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    sync::OnceLock,
     time::{Duration, Instant},
 };
-use thiserror::Error;
-
-#[derive(Debug, Error)]
-enum AppError {
-    #[error("Usage: {} <model> <image>", .0)]
-    UsageError(String),
-    #[error("Failed to load image: {0}")]
-    ImageLoadError(#[from] image::ImageError),
-    #[error("ORT error: {0}")]
-    OrtError(#[from] OrtError),
-    #[error("Other error: {0}")]
-    Other(String),
-}
-
-#[derive(Debug, Clone)]
-struct Metrics {
-    name: String,
-    timestamp: Instant,
-    wall_clock_time: Duration,
-    user_time: Duration,
-    system_time: Duration,
-    max_rss: u64,
-    cpu_usage: f32,
-}
-
-impl Metrics {
-    fn current(name: String) -> Self {
-        unsafe {
-            let mut usage: rusage = std::mem::zeroed();
-            getrusage(RUSAGE_SELF, &mut usage);
-
-            let user_time: Duration = Duration::from_secs(usage.ru_utime.tv_sec as u64)
-                + Duration::from_micros(usage.ru_utime.tv_usec as u64);
-
-            let system_time: Duration = Duration::from_secs(usage.ru_stime.tv_sec as u64)
-                + Duration::from_micros(usage.ru_stime.tv_usec as u64);
-
-            let cpu_usage: f32 = 0.0;
-            Self {
-                name,
-                timestamp: Instant::now(),
-                wall_clock_time: Duration::default(),
-                user_time,
-                system_time,
-                max_rss: usage.ru_maxrss as u64,
-                cpu_usage,
-            }
-        }
-    }
-
-    fn diff(&self, prev: &Self) -> Self {
-        let wall_clock_time: Duration = self.timestamp.duration_since(prev.timestamp);
-        let user_time: Duration = self.user_time - prev.user_time;
-        let system_time: Duration = self.system_time - prev.system_time;
-
-        let cpu_usage: f32 = if wall_clock_time.as_secs_f32() > 0.0 {
-            let cpu_time: f32 = (user_time + system_time).as_secs_f32();
-            (cpu_time / wall_clock_time.as_secs_f32()) * 100.0
-        } else {
-            0.0
-        };
+#[cfg(feature = "text")]
+use {
+    rust_ml_benchmark::text::{
+        load_tokenizer, model_sequence_length, text_inputs, tokenize, TokenizedText,
+    },
+    tokenizers::Tokenizer,
+};
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Benchmark ML inference using Rust",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Log more details to stderr: -v for per-operation metrics, -vv for span timings and ONNX Runtime logs
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Format of the log lines written to stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Heap allocator to run with: system, jemalloc or mimalloc (the latter two need the feature of the same name)
+    #[arg(long, global = true)]
+    allocator: Option<AllocatorKind>,
+
+    /// Count the heap allocations of every operation, at the cost of atomic updates on every allocation and free
+    #[arg(long, global = true)]
+    count_allocations: bool,
+
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print the inputs, outputs and metadata of a model
+    Inspect {
+        /// Path of the model to inspect
+        model: String,
+
+        /// Format of the printed model information
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Run an fp32 model and its fp16/int8 variants on the same input and compare latency, memory and outputs
+    ComparePrecision {
+        /// Path of the fp32 reference model
+        reference: String,
+
+        /// Path of the image fed to every variant
+        #[arg(required_unless_present = "input_tensor")]
+        image: Option<String>,
+
+        /// Path of a reduced-precision variant of the reference model (repeatable)
+        #[arg(long, required = true)]
+        variant: Vec<String>,
+
+        /// Feed a saved .npy tensor instead of an image
+        #[arg(long, value_name = "NPY", conflicts_with = "image")]
+        input_tensor: Option<String>,
+
+        /// Override the image preprocessing, see the main command
+        #[arg(long, value_name = "[NAME:]KEY=VALUE")]
+        preprocess: Vec<PreprocessOverride>,
+
+        /// Number of measured inference runs per variant, after one warm-up run
+        #[arg(long, default_value_t = 10)]
+        runs: usize,
+
+        /// Measure every model in a fresh child process, so later variants do not run in the ONNX Runtime
+        /// environment and heap the earlier ones left behind
+        #[arg(long)]
+        isolate: bool,
+
+        /// Attach KEY=VALUE metadata to the results, exported with the run ID (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        tag: Vec<Tag>,
+
+        /// Format of the printed results; markdown has a column per model
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Measure top-1 and top-5 accuracy and throughput of a classifier over a labelled dataset
+    Evaluate {
+        /// Path of the classification model
+        model: String,
+
+        /// ImageNet-style directory with one sub-directory of images per class, or a CSV file of
+        /// path,label rows
+        dataset: String,
+
+        /// Class names in the order of the model's output, one per line, to map directory names and CSV
+        /// labels to class indices
+        #[arg(long, value_name = "FILE")]
+        labels: Option<String>,
+
+        /// Override the image preprocessing, see the main command
+        #[arg(long, value_name = "[NAME:]KEY=VALUE")]
+        preprocess: Vec<PreprocessOverride>,
+
+        /// Evaluate only the first N images
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        /// Decode and pre-process images on worker threads while inference runs, keeping up to DEPTH
+        /// prepared images queued
+        #[arg(long, value_name = "DEPTH", value_parser = clap::value_parser!(u16).range(1..))]
+        prefetch: Option<u16>,
+
+        /// Worker threads decoding and pre-processing images for --prefetch
+        #[arg(long, value_name = "N", default_value_t = 1, requires = "prefetch", value_parser = clap::value_parser!(u16).range(1..))]
+        prefetch_workers: u16,
+
+        /// Attach KEY=VALUE metadata to the results, exported with the run ID (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        tag: Vec<Tag>,
+
+        /// Format of the printed results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Run a chain of models defined in a JSON file, e.g. a detector whose boxes are cropped and classified
+    Pipeline {
+        /// JSON file listing the stages in order
+        definition: String,
+
+        /// Path of the image fed to the pipeline
+        image: String,
+
+        /// Attach KEY=VALUE metadata to the results, exported with the run ID (repeatable)
+        #[arg(long, value_name = "KEY=VALUE")]
+        tag: Vec<Tag>,
+
+        /// Format of the printed results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Run every combination of models, execution providers, thread counts and batch sizes defined in a
+    /// JSON file and compare them in one table
+    Matrix {
+        /// JSON file listing the models, providers, thread counts and batch sizes
+        definition: String,
+
+        /// Run every combination in a fresh child process, so no ONNX Runtime or allocator state is shared
+        /// between them; needed to compare several providers
+        #[arg(long)]
+        isolate: bool,
+
+        /// Format of the printed results; json has the full report of every run
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Measure the overhead of the harness itself: metric snapshots, operation and phase bookkeeping,
+    /// and the background samplers
+    Calibrate {
+        /// Calls timed per measurement
+        #[arg(long, default_value_t = 10000, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+
+        /// Sampling period of the thermal monitor, as with --thermal-interval
+        #[arg(long, value_name = "DURATION", value_parser = parse_interval, default_value = "500ms")]
+        thermal_interval: Duration,
+
+        /// Also measure the nvidia-smi sampler of --gpu-memory on this CUDA device
+        #[arg(long, value_name = "ID", num_args = 0..=1, default_missing_value = "0")]
+        gpu_memory: Option<i32>,
+
+        /// Format of the printed results
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+    },
+    /// Load the model and preprocess its inputs as the main command would, then save the model, the
+    /// preprocessed tensors and the environment to a bundle directory for `replay`
+    Record {
+        /// Directory the bundle is written to, which must be empty or not exist yet
+        bundle: PathBuf,
+
+        #[command(flatten)]
+        run: Box<RunArgs>,
+    },
+    /// Measure inference on the model and preprocessed inputs of a bundle saved with `record`
+    Replay(ReplayArgs),
+    /// List, show or compare runs recorded with --db
+    #[cfg(feature = "sqlite")]
+    History {
+        /// SQLite database the runs were recorded in
+        #[arg(long, value_name = "SQLITE")]
+        db: String,
+
+        /// Only list runs whose model path contains this
+        #[arg(long)]
+        model: Option<String>,
+
+        /// Only list runs recorded on this device (host name)
+        #[arg(long)]
+        device: Option<String>,
+
+        /// Maximum number of runs listed
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print every operation of one run
+        #[arg(long, value_name = "RUN", conflicts_with = "diff")]
+        show: Option<i64>,
+
+        /// Compare the operations of two runs
+        #[arg(long, num_args = 2, value_names = ["BASE", "OTHER"])]
+        diff: Option<Vec<i64>>,
+    },
+    /// Serve a REST API to trigger benchmarks remotely and collect their results as JSON
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address and port the API listens on; the API has no authentication, so only listen on other
+        /// interfaces than loopback in a trusted network
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Append the metrics of every finished run to this file as csv or JSON lines, as the main
+        /// command; requests cannot set sinks themselves (repeatable)
+        #[arg(long, value_name = "json|csv=PATH")]
+        sink: Vec<SinkSpec>,
+    },
+    /// Serve a model over the KServe v2 gRPC inference protocol, to drive it with existing load generators
+    #[cfg(feature = "kserve")]
+    Kserve {
+        /// Path of the model to serve
+        model: String,
+
+        /// Address and port the gRPC server listens on; the server has no authentication, so only
+        /// listen on other interfaces than loopback in a trusted network
+        #[arg(long, default_value = "127.0.0.1:8001")]
+        listen: std::net::SocketAddr,
+
+        /// Name clients address the model by, defaults to the file name without extension
+        #[arg(long)]
+        model_name: Option<String>,
+
+        /// Serve on the CPU only, without the CUDA execution provider
+        #[arg(long, conflicts_with = "tensorrt")]
+        cpu: bool,
+
+        /// Put the TensorRT execution provider in front of CUDA
+        #[arg(long)]
+        tensorrt: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    /// Bundle directory written by `record`
+    bundle: PathBuf,
+
+    /// Number of timed inference runs on the recorded inputs
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+
+    /// Number of ONNX Runtime intra-op threads, instead of the recorded threads and their affinities
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    intra_threads: Option<u16>,
+
+    /// Run on the CPU only, without the recorded CUDA and TensorRT execution providers
+    #[arg(long, conflicts_with = "tensorrt")]
+    cpu: bool,
+
+    /// Put the TensorRT execution provider in front of CUDA, with the recorded TensorRT settings if any
+    #[arg(long)]
+    tensorrt: bool,
+
+    /// CUDA device CUDA and TensorRT run on, instead of the recorded one
+    #[arg(long, value_name = "ID")]
+    cuda_device: Option<i32>,
+
+    /// Attach KEY=VALUE metadata to the results (repeatable)
+    #[arg(long, value_name = "KEY=VALUE")]
+    tag: Vec<Tag>,
+
+    /// Append the metrics of the replay to this file as csv or JSON lines, as the main command
+    /// (repeatable)
+    #[arg(long, value_name = "json|csv=PATH")]
+    sink: Vec<SinkSpec>,
+
+    /// Record the replay in this SQLite database, as the main command
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "SQLITE")]
+    db: Option<String>,
+
+    /// Format of the printed results
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+}
+
+/// Command line of a single run, used to validate the options of a `serve` request or a matrix cell.
+#[derive(Debug, Parser)]
+struct RunRequest {
+    #[command(flatten)]
+    run: RunArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    /// GitHub-flavored tables, to paste into issues and reports
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Task {
+    Classification,
+    Detection,
+    Segmentation,
+    #[cfg(feature = "text")]
+    Text,
+    #[cfg(feature = "audio")]
+    Audio,
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// Path of the model which will be used for inference. With the download feature also an https:// URL
+    /// or hf://org/repo[@revision]/model.onnx, which is downloaded to the model cache first
+    #[arg(required = true)]
+    model: Option<String>,
+
+    /// Path of the image which will be used for inference, - to read it from stdin
+    image: Option<String>,
+
+    /// Read the image as raw RGB8 pixels of this size, e.g. a framebuffer, instead of an encoded image
+    #[arg(long, value_name = "WxH", requires = "image")]
+    raw_rgb: Option<FrameSize>,
+
+    /// Feed a saved .npy tensor directly to the model instead of an image
+    #[arg(long, value_name = "NPY", conflicts_with = "image")]
+    input_tensor: Option<String>,
+
+    /// Decode a video file or V4L2 camera with ffmpeg and run inference on every frame. The first frame is
+    /// benchmarked like a single image, the others are streamed
+    #[arg(
+        long,
+        value_name = "PATH|/dev/videoN",
+        conflicts_with_all = ["image", "input_tensor", "input"]
+    )]
+    video: Option<String>,
+
+    /// Rate --video frames arrive at; frames arriving while the previous one is processed are dropped
+    #[arg(long, value_name = "FPS", requires = "video", value_parser = positive_fps)]
+    target_fps: Option<f64>,
+
+    /// Stop a --video run after this many frames
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "video",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_frames: Option<usize>,
+
+    /// Decode and pre-process --video frames on worker threads while inference runs, keeping up to DEPTH
+    /// prepared frames queued
+    #[arg(
+        long,
+        value_name = "DEPTH",
+        requires = "video",
+        conflicts_with = "target_fps",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    prefetch: Option<u16>,
+
+    /// Worker threads decoding and pre-processing frames for --prefetch
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "prefetch", value_parser = clap::value_parser!(u16).range(1..))]
+    prefetch_workers: u16,
+
+    /// Feed a named model input from an image or a .npy tensor (repeatable)
+    #[arg(long, value_name = "NAME=PATH")]
+    input: Vec<InputSpec>,
+
+    /// Override the image preprocessing of one input, or of all image inputs without a NAME (repeatable).
+    /// Keys: size=WxH, batch=N, layout=nchw|nhwc, scale=F, mean=R,G,B, std=R,G,B
+    #[arg(long, value_name = "[NAME:]KEY=VALUE")]
+    preprocess: Vec<PreprocessOverride>,
+
+    /// Filter images are resized with; several comma-separated filters are compared with each other
+    #[arg(
+        long,
+        value_name = "nearest|bilinear|catmullrom|lanczos3",
+        value_delimiter = ',',
+        default_value = "catmullrom"
+    )]
+    resize_filter: Vec<ResizeFilter>,
+
+    /// How images are fitted into the model input; several comma-separated modes are compared with each other
+    #[arg(
+        long,
+        value_name = "stretch|letterbox|center-crop",
+        value_delimiter = ',',
+        default_value = "stretch"
+    )]
+    resize_mode: Vec<ResizeMode>,
+
+    /// Convert images pixel by pixel as before the parallel preprocessing, to benchmark the difference
+    #[arg(long)]
+    legacy_preprocess: bool,
+
+    /// Post-processing applied to the model outputs
+    #[arg(long, value_enum, default_value_t = Task::Classification)]
+    task: Task,
+
+    /// Number of timed inference runs on the same inputs; the outputs of the last one are post-processed
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+
+    /// Also fire --count inferences on a fixed schedule, one every interval (e.g. 100ms), like a periodic
+    /// sensor, and report scheduling jitter, missed deadlines and latency percentiles
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    interval: Option<Duration>,
+
+    /// Number of inferences fired by --interval
+    #[arg(long, default_value_t = 100, requires = "interval", value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+
+    /// Also time --iterations runs through an ORT IOBinding with the inputs bound once and the outputs
+    /// pre-allocated on this device (cpu or cuda), and compare them with the plain runs
+    #[arg(long, value_name = "DEVICE")]
+    io_binding: Option<BindingDevice>,
+
+    /// Format of the printed results; json prints a single document with the metrics and task output,
+    /// markdown a table of every operation and phase
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Write a self-contained HTML report with latency, phase and memory charts to this file
+    #[arg(long, value_name = "HTML")]
+    report: Option<String>,
+
+    /// Layout of the detector outputs: auto, yolov5, yolov8 or ssd
+    #[arg(long, default_value = "auto")]
+    detection_format: DetectionFormat,
+
+    /// Minimum confidence of a detected box
+    #[arg(long, default_value_t = 0.25)]
+    conf_threshold: f32,
+
+    /// IoU above which overlapping boxes of the same class are suppressed
+    #[arg(long, default_value_t = 0.45)]
+    iou_threshold: f32,
+
+    /// Maximum number of boxes kept after NMS
+    #[arg(long, default_value_t = 300)]
+    max_detections: usize,
+
+    /// Write the colorized segmentation mask to this PNG file
+    #[arg(long, value_name = "PNG")]
+    mask_output: Option<String>,
+
+    /// Number of ONNX Runtime intra-op threads (defaults to one per physical core)
+    #[arg(long, value_name = "N", conflicts_with = "pin_ort_threads", value_parser = clap::value_parser!(u16).range(1..))]
+    intra_threads: Option<u16>,
+
+    /// Restrict the process to these CPU cores before measuring, e.g. 0-3 or 0,2,4-5
+    #[arg(long, value_name = "CORES")]
+    pin_cores: Option<CoreSet>,
+
+    /// Also pin each ONNX Runtime intra-op thread to its own core of --pin-cores
+    #[arg(long, requires = "pin_cores")]
+    pin_ort_threads: bool,
+
+    /// Sample the CPU time of every thread during each operation and report the effective parallelism
+    #[arg(long)]
+    thread_cpu: bool,
+
+    /// Sample temperatures and CPU/GPU clock frequencies during the GREEN BOX phase and report throttling
+    #[arg(long)]
+    thermal: bool,
+
+    /// Sampling period of --thermal
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval, default_value = "500ms", requires = "thermal")]
+    thermal_interval: Duration,
+
+    /// CUDA device the model runs on
+    #[arg(long, value_name = "ID")]
+    cuda_device: Option<i32>,
+
+    /// Largest size of the CUDA memory arena, e.g. 4G
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    cuda_memory_limit: Option<usize>,
+
+    /// How cuDNN picks convolution algorithms: exhaustive (ONNX Runtime's default), heuristic or default
+    #[arg(long, value_name = "SEARCH")]
+    cudnn_conv_algo_search: Option<ConvAlgorithmSearch>,
+
+    /// How the CUDA memory arena grows: next-power-of-two (default) or same-as-requested
+    #[arg(long, value_name = "STRATEGY")]
+    cuda_arena_strategy: Option<ArenaStrategy>,
+
+    /// Capture the first inference in a CUDA graph and replay it, needs inputs and outputs at fixed
+    /// addresses as with --io-binding cuda
+    #[arg(long, requires = "io_binding")]
+    cuda_graph: bool,
+
+    /// Sample the memory used on the whole CUDA device, by every process, with nvidia-smi every
+    /// 100ms and report its high-water mark (not available on Jetson, which has no nvidia-smi)
+    #[arg(long)]
+    gpu_memory: bool,
+
+    /// Run the model on the CPU only, without the CUDA execution provider
+    #[arg(long, conflicts_with = "tensorrt")]
+    cpu: bool,
+
+    /// Run the model with the TensorRT execution provider, falling back to CUDA and the CPU for what it
+    /// does not support
+    #[arg(long)]
+    tensorrt: bool,
+
+    /// Save built TensorRT engines to this directory and load them from there on later runs, reported as
+    /// "loadmodel (engine build)" and "loadmodel (cached engine)"
+    #[arg(long, value_name = "DIR", requires = "tensorrt")]
+    trt_engine_cache: Option<PathBuf>,
+
+    /// Let TensorRT build fp16 kernels
+    #[arg(long, requires = "tensorrt")]
+    trt_fp16: bool,
+
+    /// Let TensorRT build int8 kernels
+    #[arg(long, requires = "tensorrt")]
+    trt_int8: bool,
+
+    /// GPU memory TensorRT may use while building an engine, e.g. 2G or 512M
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, requires = "tensorrt")]
+    trt_workspace_size: Option<usize>,
+
+    /// Directory downloaded models are cached in (defaults to ~/.cache/rust-ml-benchmark/models)
+    #[cfg(feature = "download")]
+    #[arg(long, value_name = "DIR")]
+    model_cache: Option<String>,
+
+    /// Expected SHA-256 of a downloaded model; a cached copy with another checksum is downloaded again
+    #[cfg(feature = "download")]
+    #[arg(long, value_name = "HEX")]
+    model_sha256: Option<String>,
+
+    /// Save the model as optimized by ONNX Runtime to this directory and load it from there on later runs,
+    /// reported as "loadmodel (cold)" and "loadmodel (warm)"
+    #[arg(long, value_name = "DIR")]
+    optimized_cache: Option<String>,
+
+    /// Map the model file into memory instead of reading it into a buffer, so its pages are read from disk
+    /// while ONNX Runtime parses the graph and the file is never copied whole
+    #[arg(long, conflicts_with = "optimized_cache")]
+    mmap_model: bool,
+
+    /// Split loadmodel into disk-read, graph-parse and session-init, building the session from memory with
+    /// ONNX Runtime's profiler running
+    #[arg(long, conflicts_with = "optimized_cache")]
+    load_breakdown: bool,
+
+    /// Fail the run with exit code 3 when the p95 inference latency exceeds this, e.g. 50ms
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    sla_latency: Option<Duration>,
+
+    /// Fail the run with exit code 3 when the peak RSS of the process exceeds this, e.g. 512MB
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    sla_memory: Option<usize>,
+
+    /// Fail instead of warning when the machine looks too noisy to benchmark on
+    #[arg(long)]
+    strict: bool,
 
-        Self {
-            name: self.name.clone(),
-            timestamp: self.timestamp,
-            wall_clock_time,
-            user_time,
-            system_time,
-            max_rss: self.max_rss - prev.max_rss,
-            cpu_usage,
+    /// Load the model and prepare its inputs without running inference, checking the inputs fit the
+    /// model and the execution providers run it instead of the CPU; exits with code 4 when they do not
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Attach KEY=VALUE metadata to the results, e.g. an experiment ID or firmware version; it is exported
+    /// with the run ID to every output (repeatable)
+    #[arg(long, value_name = "KEY=VALUE")]
+    tag: Vec<Tag>,
+
+    /// Append the metrics of every operation and phase and the total, with the run ID and tags, to this
+    /// file as csv or JSON lines; - writes them to stdout, except with --output json (repeatable)
+    #[arg(long, value_name = "json|csv=PATH")]
+    sink: Vec<SinkSpec>,
+
+    /// Append the run, its environment and the metrics of every operation to this SQLite database
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "SQLITE")]
+    db: Option<String>,
+
+    /// Export the phases and operations as OpenTelemetry spans to this OTLP/HTTP collector
+    #[cfg(feature = "otlp")]
+    #[arg(long, value_name = "ENDPOINT")]
+    otlp: Option<String>,
+
+    /// Publish the JSON report to this MQTT broker (host[:port], port 1883 by default) with QoS 1
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "BROKER")]
+    mqtt: Option<String>,
+
+    /// MQTT topic the report is published to
+    #[cfg(feature = "mqtt")]
+    #[arg(
+        long,
+        value_name = "TOPIC",
+        default_value = "rust-ml-benchmark/results"
+    )]
+    topic: String,
+
+    /// Device ID sent along with the results, defaults to the host name
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "ID")]
+    device_id: Option<String>,
+
+    /// Also publish the latency of every inference run to <TOPIC>/samples
+    #[cfg(feature = "mqtt")]
+    #[arg(long, requires = "mqtt")]
+    mqtt_samples: bool,
+
+    /// Sample the CPU stacks of every thread during the GREEN BOX phase and write them as a flamegraph SVG
+    #[cfg(feature = "flamegraph")]
+    #[arg(long, value_name = "SVG")]
+    flamegraph: Option<String>,
+
+    /// Only sample the runs of this operation for --flamegraph, e.g. Pre-processing or Inference
+    #[cfg(feature = "flamegraph")]
+    #[arg(long, value_name = "NAME", requires = "flamegraph")]
+    flamegraph_operation: Option<String>,
+
+    /// HF tokenizer.json used by --task text
+    #[cfg(feature = "text")]
+    #[arg(long, value_name = "JSON")]
+    tokenizer: Option<String>,
+
+    /// Sentence that is tokenized and fed to the model by --task text
+    #[cfg(feature = "text")]
+    #[arg(long)]
+    prompt: Option<String>,
+
+    /// Pad or truncate the prompt to this many tokens (defaults to the model's static sequence length)
+    #[cfg(feature = "text")]
+    #[arg(long)]
+    max_length: Option<usize>,
+
+    /// WAV file fed to the model by --task audio
+    #[cfg(feature = "audio")]
+    #[arg(long, value_name = "WAV")]
+    audio: Option<String>,
+
+    /// Sample rate the audio is resampled to before feature extraction
+    #[cfg(feature = "audio")]
+    #[arg(long, default_value_t = 16000)]
+    sample_rate: u32,
+
+    /// Number of mel bands (the model's static mel dimension takes precedence)
+    #[cfg(feature = "audio")]
+    #[arg(long, default_value_t = 64)]
+    n_mels: usize,
+
+    /// FFT window size of the spectrogram
+    #[cfg(feature = "audio")]
+    #[arg(long, default_value_t = 512, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    n_fft: usize,
+
+    /// Samples between successive spectrogram frames
+    #[cfg(feature = "audio")]
+    #[arg(long, default_value_t = 160, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    hop_length: usize,
+}
+
+enum Workload {
+    Vision(Vec<PreparedInput>),
+    #[cfg(feature = "text")]
+    Text {
+        tokenizer: Box<Tokenizer>,
+        prompt: String,
+        max_length: Option<usize>,
+    },
+    #[cfg(feature = "audio")]
+    Audio {
+        input_name: String,
+        clip: AudioClip,
+        config: MelConfig,
+        kind: AudioInputKind,
+    },
+}
+
+impl Workload {
+    fn image_input(&self) -> Option<&PreparedInput> {
+        match self {
+            Workload::Vision(prepared) => {
+                prepared.iter().find(|input| input.image_size().is_some())
+            }
+            #[cfg(feature = "text")]
+            Workload::Text { .. } => None,
+            #[cfg(feature = "audio")]
+            Workload::Audio { .. } => None,
         }
     }
 
-    fn combine(&self, other: &Self) -> Self {
-        let combined_wall_clock = self.wall_clock_time + other.wall_clock_time;
-        let combined_user_time = self.user_time + other.user_time;
-        let combined_system_time = self.system_time + other.system_time;
+    fn preprocess_operation_name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "audio")]
+            Workload::Audio {
+                kind: AudioInputKind::Spectrogram { .. },
+                ..
+            } => "Spectrogram",
+            _ => "Pre-processing",
+        }
+    }
 
-        let cpu_usage = if combined_wall_clock.as_secs_f32() > 0.0 {
-            let cpu_time = (combined_user_time + combined_system_time).as_secs_f32();
-            (cpu_time / combined_wall_clock.as_secs_f32()) * 100.0
-        } else {
-            0.0
-        };
+    fn into_inputs(self, model_inputs: &[Input]) -> Result<Vec<(String, DynValue)>, AppError> {
+        match self {
+            Workload::Vision(prepared) => {
+                let inputs: Vec<(String, DynValue)> = prepared
+                    .into_iter()
+                    .map(PreparedInput::into_value)
+                    .collect::<Result<_, _>>()?;
+                convert_inputs(inputs, model_inputs)
+            }
+            #[cfg(feature = "text")]
+            Workload::Text {
+                mut tokenizer,
+                prompt,
+                max_length,
+            } => {
+                let text: TokenizedText = tokenize(&mut tokenizer, &prompt, max_length)?;
+                text_inputs(model_inputs, &text)
+            }
+            #[cfg(feature = "audio")]
+            Workload::Audio {
+                input_name,
+                clip,
+                config,
+                kind,
+            } => Ok(vec![(input_name, audio_input(&clip, &config, kind)?)]),
+        }
+    }
+}
+
+fn main() -> Result<(), AppError> {
+    let cli: Cli = Cli::parse();
+    init_logging(
+        Verbosity::from_flags(cli.verbose, cli.quiet),
+        cli.log_format == LogFormat::Json,
+    );
+    configure_allocator(cli.allocator, cli.count_allocations)?;
 
-        Self {
-            name: self.name.clone(),
-            timestamp: self.timestamp,
-            wall_clock_time: combined_wall_clock,
-            user_time: combined_user_time,
-            system_time: combined_system_time,
-            max_rss: self.max_rss.max(other.max_rss),
-            cpu_usage,
+    match cli.command {
+        Some(Command::Inspect { model, output }) => inspect(&model, output),
+        Some(Command::ComparePrecision {
+            reference,
+            image,
+            variant,
+            input_tensor,
+            preprocess,
+            runs,
+            isolate,
+            tag,
+            output,
+        }) => {
+            let positional: InputSource = match (input_tensor, image) {
+                (Some(tensor_path), _) => InputSource::Tensor(tensor_path),
+                (None, Some(image_path)) => InputSource::Image(image_path),
+                (None, None) => unreachable!("clap requires either an image or an input tensor"),
+            };
+            let models: Vec<String> = std::iter::once(reference).chain(variant).collect();
+            let metadata: RunMetadata = RunMetadata::new(&tag)?;
+            compare_precision(
+                metadata,
+                &models,
+                &positional,
+                &preprocess,
+                runs,
+                isolate,
+                output,
+            )
+        }
+        Some(Command::Evaluate {
+            model,
+            dataset,
+            labels,
+            preprocess,
+            limit,
+            prefetch,
+            prefetch_workers,
+            tag,
+            output,
+        }) => evaluate_dataset(
+            RunMetadata::new(&tag)?,
+            &model,
+            &dataset,
+            labels.as_deref(),
+            &preprocess,
+            limit,
+            prefetch.map(|depth| PrefetchConfig {
+                depth: depth.into(),
+                workers: prefetch_workers.into(),
+            }),
+            output,
+        ),
+        Some(Command::Pipeline {
+            definition,
+            image,
+            tag,
+            output,
+        }) => pipeline(&definition, &image, &tag, output),
+        Some(Command::Matrix {
+            definition,
+            isolate,
+            output,
+        }) => matrix(&definition, isolate, output),
+        Some(Command::Calibrate {
+            iterations,
+            thermal_interval,
+            gpu_memory,
+            output,
+        }) => {
+            let report: CalibrationReport = calibrate(&CalibrationConfig {
+                iterations: iterations as usize,
+                thermal_interval,
+                gpu_device: gpu_memory,
+            });
+            match output {
+                OutputFormat::Text => print!("{}", report),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+                OutputFormat::Markdown => print!("{}", report.markdown()),
+            }
+            Ok(())
         }
+        Some(Command::Record { bundle, run }) => record(&bundle, &run),
+        Some(Command::Replay(args)) => replay(&args),
+        #[cfg(feature = "sqlite")]
+        Some(Command::History {
+            db,
+            model,
+            device,
+            limit,
+            show,
+            diff,
+        }) => history(
+            &db,
+            HistoryFilter {
+                model,
+                device,
+                limit,
+            },
+            show,
+            diff,
+        ),
+        #[cfg(feature = "server")]
+        Some(Command::Serve { listen, sink }) => serve(&listen, sink),
+        #[cfg(feature = "kserve")]
+        Some(Command::Kserve {
+            model,
+            listen,
+            model_name,
+            cpu,
+            tensorrt,
+        }) => kserve(&model, listen, model_name, cpu, tensorrt),
+        None => run_benchmark(cli.run),
     }
 }
 
-impl std::fmt::Display for Metrics {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "============= {} Metrics =============", self.name)?;
-        writeln!(f, "Wall Clock Time: {:?}", self.wall_clock_time)?;
-        writeln!(f, "User time: {:?}", self.user_time)?;
-        writeln!(f, "System time: {:?}", self.system_time)?;
-        writeln!(f, "Max RSS: {} bytes", self.max_rss)?;
-        writeln!(f, "CPU Usage: {}%", self.cpu_usage)?;
-        writeln!(f, "=======================================")
+#[cfg(feature = "sqlite")]
+fn history(
+    db_path: &str,
+    filter: HistoryFilter,
+    show: Option<i64>,
+    diff: Option<Vec<i64>>,
+) -> Result<(), AppError> {
+    let db: ResultsDb = ResultsDb::open(db_path)?;
+    let find_run = |run_id: i64| -> Result<RunSummary, AppError> {
+        db.run(run_id)?
+            .ok_or_else(|| AppError::InputError(format!("no run #{} in {}", run_id, db_path)))
+    };
+
+    if let Some(run_id) = show {
+        let run: RunSummary = find_run(run_id)?;
+        println!("{}", run);
+        println!("  run ID {}", run.run_uuid);
+        for operation in db.operations(run_id)? {
+            println!(
+                "  {:<9} {:<24} {:>14?}  user {:>12?}  system {:>12?}  RSS {:>10} -> {:>10}  peak RSS +{:<10}  CPU {:.1}%",
+                operation.kind,
+                operation.name,
+                operation.wall_clock_time,
+                operation.user_time,
+                operation.system_time,
+                format_bytes(operation.rss_before),
+                format_bytes(operation.rss_after),
+                format_bytes(operation.peak_rss_growth),
+                operation.cpu_usage
+            );
+        }
+        return Ok(());
     }
+
+    if let Some([base, other]) = diff.as_deref() {
+        let run_diff: RunDiff = RunDiff::new(
+            find_run(*base)?,
+            &db.operations(*base)?,
+            find_run(*other)?,
+            &db.operations(*other)?,
+        );
+        print!("{}", run_diff);
+        return Ok(());
+    }
+
+    for run in db.runs(&filter)? {
+        println!("{}", run);
+    }
+    Ok(())
 }
 
-#[derive(Debug)]
-struct BenchmarkTracker {
-    start_metrics: Metrics,
-    current_operation: Option<Metrics>,
-    completed_metrics: Vec<Metrics>,
-    active_phases: HashMap<String, Metrics>,
-    phase_metrics: Vec<(String, Metrics)>,
-    phase_order: Vec<String>,
+#[cfg(feature = "server")]
+fn serve(listen: &str, sinks: Vec<SinkSpec>) -> Result<(), AppError> {
+    BenchmarkServer::bind(listen)?.serve(move |request: BenchmarkRequest| {
+        let mut args: RunArgs = request_args(&request)?;
+        args.sink.extend(sinks.iter().cloned());
+        let (report, mut tracker) = execute_run(&args)?;
+        export_run(&args, &report, &mut tracker)?;
+        Ok(report)
+    })
 }
 
-impl BenchmarkTracker {
-    fn new() -> Self {
-        Self {
-            start_metrics: Metrics::current("Total".to_string()),
-            current_operation: None,
-            completed_metrics: Vec::new(),
-            active_phases: HashMap::new(),
-            phase_metrics: Vec::new(),
-            phase_order: Vec::new(),
+/// Options a `serve` request may pass in `args`. Options writing files, exporting results or exiting
+/// the process are left out, a request must not choose paths on the device or stop the server. So are
+/// the options changing the server for later jobs: pinning the process, and the execution providers
+/// and their settings, which the first environment of the process fixes.
+#[cfg(feature = "server")]
+const REQUEST_OPTIONS: &[&str] = &[
+    "--raw-rgb",
+    "--video",
+    "--target-fps",
+    "--max-frames",
+    "--prefetch",
+    "--prefetch-workers",
+    "--input",
+    "--preprocess",
+    "--resize-filter",
+    "--resize-mode",
+    "--legacy-preprocess",
+    "--task",
+    "--interval",
+    "--count",
+    "--io-binding",
+    "--detection-format",
+    "--conf-threshold",
+    "--iou-threshold",
+    "--max-detections",
+    "--intra-threads",
+    "--thread-cpu",
+    "--thermal",
+    "--thermal-interval",
+    "--model-sha256",
+    "--mmap-model",
+    "--load-breakdown",
+    "--sla-latency",
+    "--sla-memory",
+    "--strict",
+    "--tag",
+    "--tokenizer",
+    "--prompt",
+    "--max-length",
+    "--audio",
+    "--sample-rate",
+    "--n-mels",
+    "--n-fft",
+    "--hop-length",
+];
+
+/// Turns a `serve` request into the command line of a run, so it gets the same validation.
+#[cfg(feature = "server")]
+fn request_args(request: &BenchmarkRequest) -> Result<RunArgs, AppError> {
+    for arg in request.args.iter().filter(|arg| arg.starts_with('-')) {
+        let option: &str = arg
+            .split_once('=')
+            .map_or(arg.as_str(), |(option, _)| option);
+        if !REQUEST_OPTIONS.contains(&option) {
+            return Err(AppError::InputError(format!(
+                "{} cannot be set by a request",
+                option
+            )));
         }
     }
 
-    fn start_operation(&mut self, name: &str) {
-        self.current_operation = Some(Metrics::current(name.to_string()));
+    let mut argv: Vec<String> = vec![request.model.clone()];
+    argv.extend(request.image.clone());
+    if let Some(tensor_path) = &request.input_tensor {
+        argv.extend(["--input-tensor".to_string(), tensor_path.clone()]);
+    }
+    argv.extend(["--iterations".to_string(), request.iterations.to_string()]);
+    argv.extend(request.args.iter().cloned());
+    parse_run_args(&argv)
+}
+
+/// Parses the options of a run given without the program name, keeping the first line of clap's message.
+fn parse_run_args(args: &[String]) -> Result<RunArgs, AppError> {
+    RunRequest::try_parse_from(
+        std::iter::once(env!("CARGO_PKG_NAME")).chain(args.iter().map(String::as_str)),
+    )
+    .map(|request| request.run)
+    .map_err(|e| {
+        let message: String = e.to_string();
+        let first_line: &str = message.lines().next().unwrap_or_default();
+        AppError::InputError(first_line.trim_start_matches("error: ").to_string())
+    })
+}
+
+fn matrix(definition_path: &str, isolate: bool, output: OutputFormat) -> Result<(), AppError> {
+    let definition: MatrixDefinition = MatrixDefinition::load(definition_path)?;
+    let cells: Vec<MatrixCell> = definition.cells();
+
+    // A child spawned by --isolate measures a single cell and sends its report back
+    if let Some(index) = isolation::configuration() {
+        let cell: &MatrixCell = cells.get(index).ok_or_else(|| {
+            AppError::InputError(format!("the matrix has no configuration {}", index))
+        })?;
+        return isolation::send_result(&run_cell(&definition, cell)?);
     }
 
-    fn finish_operation(&mut self) {
-        if let Some(start_metrics) = self.current_operation.take() {
-            self.finish_operation_internal(start_metrics);
+    // The environment is created once per process and keeps the providers of the first run
+    if !isolate && definition.providers.len() > 1 {
+        return Err(AppError::InputError(
+            "ONNX Runtime keeps the execution providers it was first initialized with, compare several providers with --isolate".to_string(),
+        ));
+    }
+    interrupt::install()?;
+
+    let count: usize = cells.len();
+    let mut results: Vec<CellResult> = Vec::new();
+    for (index, cell) in cells.into_iter().enumerate() {
+        if interrupted() {
+            tracing::warn!("interrupted, reporting the configurations run so far");
+            break;
         }
+        tracing::info!(
+            cell = index + 1,
+            of = count,
+            model = %cell.model,
+            provider = %cell.provider,
+            threads = ?cell.threads,
+            batch_size = ?cell.batch_size,
+            "running configuration"
+        );
+
+        let report: Result<serde_json::Value, AppError> = if isolate {
+            isolation::run_configuration(index)
+        } else {
+            run_cell(&definition, &cell)
+        };
+        results.push(match report {
+            Ok(report) => CellResult::from_report(cell, report),
+            Err(e) => {
+                tracing::warn!(model = %cell.model, provider = %cell.provider, "{}", e);
+                CellResult::failed(cell, e.to_string())
+            }
+        });
+    }
+
+    let report: MatrixReport = MatrixReport {
+        isolated: isolate,
+        cells: results,
+    };
+    match output {
+        OutputFormat::Text => print!("{}", report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Markdown => print!("{}", report.markdown()),
+    }
+    Ok(())
+}
+
+/// Measures one cell of the matrix in this process, as the main command with the cell's options.
+fn run_cell(
+    definition: &MatrixDefinition,
+    cell: &MatrixCell,
+) -> Result<serde_json::Value, AppError> {
+    let args: RunArgs = parse_run_args(&cell.args(definition))?;
+    let (report, mut tracker) = execute_run(&args)?;
+    export_run(&args, &report, &mut tracker)?;
+    Ok(serde_json::to_value(&report)?)
+}
+
+#[cfg(feature = "kserve")]
+fn kserve(
+    model_path: &str,
+    listen: std::net::SocketAddr,
+    model_name: Option<String>,
+    cpu: bool,
+    tensorrt: bool,
+) -> Result<(), AppError> {
+    let mut providers: Vec<ExecutionProviderDispatch> = Vec::new();
+    if tensorrt {
+        providers.push(TensorRtOptions::default().execution_provider(Path::new(model_path)));
+    }
+    if !cpu {
+        providers.push(CudaOptions::default().execution_provider());
+    }
+    ort::init().with_execution_providers(providers).commit()?;
+
+    let model_name: String = model_name.unwrap_or_else(|| {
+        std::path::Path::new(model_path).file_stem().map_or_else(
+            || model_path.to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        )
+    });
+    let model: Session = load_model(model_path)?;
+    rust_ml_benchmark::kserve::serve(model, &model_name, listen)
+}
+
+fn inspect(model_path: &str, output: OutputFormat) -> Result<(), AppError> {
+    let model: Session = load_model(model_path)?;
+    let info: ModelInfo = ModelInfo::from_session(model_path, &model)?;
+
+    match output {
+        OutputFormat::Text => print!("{}", info),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&info)?),
+        OutputFormat::Markdown => print!("{}", info.markdown()),
     }
 
-    fn finish_operation_internal(&mut self, start_metrics: Metrics) {
-        let end_metrics: Metrics = Metrics::current(start_metrics.name.clone());
-        let diff_metrics: Metrics = end_metrics.diff(&start_metrics);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn evaluate_dataset(
+    metadata: RunMetadata,
+    model_path: &str,
+    dataset: &str,
+    labels: Option<&str>,
+    overrides: &[PreprocessOverride],
+    limit: Option<usize>,
+    prefetch: Option<PrefetchConfig>,
+    output: OutputFormat,
+) -> Result<(), AppError> {
+    let mut samples: Vec<Sample> = load_dataset(dataset, labels)?;
+    samples.truncate(limit.unwrap_or(usize::MAX));
+    tracing::info!(samples = samples.len(), dataset, "dataset loaded");
 
-        self.completed_metrics.push(diff_metrics.clone());
+    ort::init()
+        .with_execution_providers([CUDAExecutionProvider::default().build()])
+        .commit()?;
+    let environment: EnvironmentInfo = EnvironmentInfo::capture();
+    let mut model: Session = load_model(model_path)?;
+    let report: EvaluationReport = evaluate(
+        metadata, model_path, &mut model, &samples, overrides, prefetch,
+    )?;
 
-        for (_, phase_metrics) in self.active_phases.iter_mut() {
-            *phase_metrics = phase_metrics.combine(&diff_metrics);
+    match output {
+        OutputFormat::Text => {
+            print!("{}", report.metadata);
+            print!("{}", environment);
+            print!("{}", report);
         }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Markdown => print!("{}", report.markdown()),
     }
+    Ok(())
+}
 
-    fn start_phase(&mut self, phase_name: &str) {
-        let zero_metrics = Metrics {
-            name: phase_name.to_string(),
-            timestamp: Instant::now(),
-            wall_clock_time: Duration::default(),
-            user_time: Duration::default(),
-            system_time: Duration::default(),
-            max_rss: 0,
-            cpu_usage: 0.0,
-        };
+fn pipeline(
+    definition_path: &str,
+    image_path: &str,
+    tags: &[Tag],
+    output: OutputFormat,
+) -> Result<(), AppError> {
+    let metadata: RunMetadata = RunMetadata::new(tags)?;
+    let definition: PipelineDefinition = PipelineDefinition::load(definition_path)?;
+    let environment: EnvironmentInfo = EnvironmentInfo::capture();
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+
+    tracker.start_phase("RED BOX Phase");
+
+    bench_op!(
+        tracker,
+        "envload",
+        ort::init()
+            .with_execution_providers([CUDAExecutionProvider::default().build()])
+            .commit()?
+    );
 
-        self.active_phases
-            .insert(phase_name.to_string(), zero_metrics);
+    let mut stages: Vec<Stage> = load_stages(definition, &mut tracker)?;
 
-        if !self.phase_order.contains(&phase_name.to_string()) {
-            self.phase_order.push(phase_name.to_string());
+    let image: DynamicImage = bench_op!(tracker, "readimg", image::open(image_path)?);
+
+    tracker.end_phase("RED BOX Phase");
+
+    tracker.start_phase("GREEN BOX Phase");
+    let results: Vec<StageResult> = run_pipeline(&mut stages, &image, &mut tracker)?;
+    tracker.end_phase("GREEN BOX Phase");
+
+    match output {
+        OutputFormat::Text => {
+            print!("{}", metadata);
+            print!("{}", environment);
+            tracker.print_all_metrics();
+            for result in &results {
+                print!("{}", result);
+            }
+        }
+        OutputFormat::Json => {
+            let report: PipelineReport =
+                PipelineReport::new(metadata, environment, &tracker, results);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        OutputFormat::Markdown => {
+            let report: PipelineReport =
+                PipelineReport::new(metadata, environment, &tracker, results);
+            print!("{}", report.markdown());
         }
     }
+    Ok(())
+}
+
+fn compare_precision(
+    metadata: RunMetadata,
+    models: &[String],
+    positional: &InputSource,
+    overrides: &[PreprocessOverride],
+    runs: usize,
+    isolate: bool,
+    output: OutputFormat,
+) -> Result<(), AppError> {
+    // A child spawned by --isolate measures a single model and sends its result back
+    if let Some(index) = isolation::configuration() {
+        let model_path: &String = models.get(index).ok_or_else(|| {
+            AppError::InputError(format!("the comparison has no model {}", index))
+        })?;
+        ort::init()
+            .with_execution_providers([CUDAExecutionProvider::default().build()])
+            .commit()?;
+        let result: PrecisionResult = measure_precision(model_path, positional, overrides, runs)?;
+        return isolation::send_result(&result);
+    }
 
-    fn end_phase(&mut self, phase_name: &str) {
-        if let Some(metrics) = self.active_phases.remove(phase_name) {
-            self.phase_metrics.push((phase_name.to_string(), metrics));
+    let mut results: Vec<PrecisionResult> = Vec::new();
+    if isolate {
+        for index in 0..models.len() {
+            results.push(isolation::run_configuration(index)?);
         }
+    } else {
+        ort::init()
+            .with_execution_providers([CUDAExecutionProvider::default().build()])
+            .commit()?;
+        for model_path in models {
+            results.push(measure_precision(model_path, positional, overrides, runs)?);
+        }
+    }
+    let environment: EnvironmentInfo = EnvironmentInfo::capture();
+
+    let (reference, variants) = results.split_first_mut().expect("at least one model");
+    for variant in variants.iter_mut() {
+        variant.compare_with(reference);
+    }
+
+    let report: PrecisionReport = PrecisionReport { metadata, results };
+    match output {
+        OutputFormat::Text => {
+            print!("{}", report.metadata);
+            print!("{}", environment);
+            for result in &report.results {
+                print!("{}", result);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Markdown => print!("{}", report.markdown()),
+    }
+
+    Ok(())
+}
+
+/// Loads one model of a precision comparison and times its runs after a warm-up run.
+fn measure_precision(
+    model_path: &str,
+    positional: &InputSource,
+    overrides: &[PreprocessOverride],
+    runs: usize,
+) -> Result<PrecisionResult, AppError> {
+    let before: Metrics = Metrics::current(model_path.to_string());
+    let load_start: Instant = Instant::now();
+    let mut model: Session = load_model(model_path)?;
+    let load_time: Duration = load_start.elapsed();
+
+    let bindings: Vec<InputBinding> =
+        resolve_bindings(&model.inputs, Some(positional.clone()), &[])?;
+    let mut inputs: Vec<(String, DynValue)> = Vec::new();
+    for binding in &bindings {
+        let model_input: &Input = model
+            .inputs
+            .iter()
+            .find(|input| input.name == binding.name)
+            .expect("bindings only name model inputs");
+        let prepared: PreparedInput =
+            PreparedInput::new(model_input, binding.source.load()?, overrides)?;
+        inputs.push(prepared.into_value()?);
     }
+    let inputs: Vec<(String, DynValue)> = convert_inputs(inputs, &model.inputs)?;
+
+    run_repeated(&mut model, &inputs, 1)?;
+    let measured: RepeatedRun = run_repeated(&mut model, &inputs, runs)?;
+    let after: Metrics = Metrics::current(model_path.to_string());
+
+    Ok(PrecisionResult {
+        model: model_path.to_string(),
+        input_types: model
+            .inputs
+            .iter()
+            .map(|input| format!("{}: {}", input.name, input.input_type))
+            .collect(),
+        load_time,
+        latencies: measured.latencies,
+        peak_rss_growth: after.diff(&before).peak_rss_growth,
+        outputs: measured.outputs,
+        divergence: Vec::new(),
+    })
+}
 
-    fn get_total_metrics(&self) -> Metrics {
-        let current: Metrics = Metrics::current("Total".to_string());
-        current.diff(&self.start_metrics)
+fn model_options(args: &RunArgs) -> ModelOptions {
+    match (&args.pin_cores, args.pin_ort_threads) {
+        (Some(cores), true) => ModelOptions {
+            intra_threads: Some(cores.len()),
+            // A single thread has no workers to pin, the process mask already covers it
+            intra_thread_affinities: (cores.len() > 1).then(|| cores.ort_intra_op_affinities()),
+        },
+        _ => ModelOptions {
+            intra_threads: args.intra_threads.map(usize::from),
+            ..ModelOptions::default()
+        },
     }
+}
 
-    fn print_all_metrics(&self) {
-        let total: Metrics = self.get_total_metrics();
+fn cuda_options(args: &RunArgs) -> CudaOptions {
+    CudaOptions {
+        device_id: args.cuda_device,
+        memory_limit: args.cuda_memory_limit,
+        conv_algorithm_search: args.cudnn_conv_algo_search,
+        arena_extend_strategy: args.cuda_arena_strategy,
+        cuda_graph: args.cuda_graph,
+    }
+}
 
-        for metrics in &self.completed_metrics {
-            print!("{}", metrics);
+fn tensorrt_options(args: &RunArgs) -> Option<TensorRtOptions> {
+    args.tensorrt.then(|| TensorRtOptions {
+        device_id: args.cuda_device,
+        engine_cache: args.trt_engine_cache.clone(),
+        fp16: args.trt_fp16,
+        int8: args.trt_int8,
+        workspace_size: args.trt_workspace_size,
+    })
+}
+
+/// Execution providers of a run in order of preference, ONNX Runtime assigns every node to the first
+/// one that supports it and runs the rest on the CPU.
+fn execution_providers(args: &RunArgs, local_path: &Path) -> Vec<ExecutionProviderDispatch> {
+    let mut providers: Vec<ExecutionProviderDispatch> = Vec::new();
+    if let Some(options) = tensorrt_options(args) {
+        providers.push(options.execution_provider(local_path));
+    }
+    if !args.cpu {
+        providers.push(cuda_options(args).execution_provider());
+    }
+    providers
+}
+
+/// Local file of the model: remote models are fetched into the cache first, timed as `download`.
+/// Resolved before the environment, whose TensorRT engine cache is keyed on the file.
+fn resolve_model(
+    args: &RunArgs,
+    model_path: &str,
+    tracker: &mut BenchmarkTracker,
+) -> Result<PathBuf, AppError> {
+    #[cfg(feature = "download")]
+    return fetch_model(args, model_path, tracker);
+    #[cfg(not(feature = "download"))]
+    {
+        let _ = (args, tracker);
+        Ok(PathBuf::from(model_path))
+    }
+}
+
+/// Loads the model from its resolved local file. Reading the file and creating the session are timed
+/// as their own operations, so `loadmodel` stays comparable.
+fn load_session(
+    args: &RunArgs,
+    local_path: &Path,
+    options: &ModelOptions,
+    tracker: &mut BenchmarkTracker,
+) -> Result<Session, AppError> {
+    if let Some(cache_dir) = &args.optimized_cache {
+        return load_cached_model(cache_dir, local_path, options, tracker);
+    }
+
+    // Cached TensorRT engines skip the engine build, which dominates the cold start
+    let operation: &str = match tensorrt_options(args) {
+        Some(options) if options.engine_cache.is_some() => {
+            if options.cached_engines(local_path).is_empty() {
+                "loadmodel (engine build)"
+            } else {
+                "loadmodel (cached engine)"
+            }
         }
+        _ => "loadmodel",
+    };
+    if !args.load_breakdown && !args.mmap_model {
+        return Ok(bench_op!(
+            tracker,
+            operation,
+            load_model_with_options(local_path, options)?
+        ));
+    }
+
+    let mut load = tracker.scoped(operation);
+    let model_bytes: ModelBytes = bench_op!(
+        load,
+        "disk-read",
+        ModelBytes::read(local_path, args.mmap_model)?
+    );
+    if !args.load_breakdown {
+        return Ok(load_model_from_memory(local_path, &model_bytes, options)?);
+    }
+    let commit_start: Instant = Instant::now();
+    let (model, breakdown) = load_model_profiled(local_path, &model_bytes, options)?;
+    let commit_end: Instant = Instant::now();
+    // The profile only has durations: parsing starts the commit and initialization ends it
+    if let Some(breakdown) = breakdown {
+        load.record_operation(
+            "graph-parse",
+            commit_start,
+            commit_start + breakdown.graph_parse,
+        );
+        load.record_operation(
+            "session-init",
+            commit_end - breakdown.session_init,
+            commit_end,
+        );
+    }
+    Ok(model)
+}
+
+/// Loads the optimized graph of the model from `cache_dir` as "loadmodel (warm)", or optimizes the
+/// model and saves its graph there as "loadmodel (cold)".
+fn load_cached_model(
+    cache_dir: &str,
+    model_path: &Path,
+    options: &ModelOptions,
+    tracker: &mut BenchmarkTracker,
+) -> Result<Session, AppError> {
+    let cache: OptimizedModelCache = OptimizedModelCache::new(cache_dir);
+    let entry: PathBuf = cache.entry(model_path)?;
+
+    if entry.exists() {
+        let model: Session = bench_op!(
+            tracker,
+            "loadmodel (warm)",
+            load_optimized_model(&entry, options)?
+        );
+        tracing::debug!(path = %entry.display(), "loaded optimized model");
+        return Ok(model);
+    }
+
+    let model: Session = bench_op!(
+        tracker,
+        "loadmodel (cold)",
+        cache.optimize(model_path, &entry, options)?
+    );
+    tracing::debug!(path = %entry.display(), "saved optimized model");
+    Ok(model)
+}
+
+/// Downloads a remote model into the cache and returns the path of the cached copy. Local models are
+/// passed through untouched.
+#[cfg(feature = "download")]
+fn fetch_model(
+    args: &RunArgs,
+    model_path: &str,
+    tracker: &mut BenchmarkTracker,
+) -> Result<PathBuf, AppError> {
+    let Some(remote) = RemoteModel::parse(model_path)? else {
+        return Ok(PathBuf::from(model_path));
+    };
+
+    let cached: CachedModel = bench_op!(
+        tracker,
+        "download",
+        ModelCache::new(args.model_cache.as_deref())
+            .fetch(&remote, args.model_sha256.as_deref())?
+    );
+    tracing::debug!(
+        path = %cached.path.display(),
+        downloaded = cached.downloaded,
+        size = cached.size,
+        sha256 = %cached.sha256,
+        "model cached"
+    );
+    Ok(cached.path)
+}
+
+/// Reads everything the GREEN BOX needs from disk, timing each read as its own operation.
+fn load_workload(
+    args: &RunArgs,
+    model: &Session,
+    tracker: &mut BenchmarkTracker,
+) -> Result<Workload, AppError> {
+    #[cfg(feature = "text")]
+    if args.task == Task::Text {
+        let (Some(tokenizer_path), Some(prompt)) = (&args.tokenizer, &args.prompt) else {
+            return Err(AppError::InputError(
+                "--task text requires --tokenizer and --prompt".to_string(),
+            ));
+        };
+
+        let tokenizer: Tokenizer =
+            bench_op!(tracker, "loadtokenizer", load_tokenizer(tokenizer_path)?);
+
+        return Ok(Workload::Text {
+            tokenizer: Box::new(tokenizer),
+            prompt: prompt.clone(),
+            max_length: args
+                .max_length
+                .or_else(|| model_sequence_length(&model.inputs)),
+        });
+    }
+
+    #[cfg(feature = "audio")]
+    if args.task == Task::Audio {
+        let Some(audio_path) = &args.audio else {
+            return Err(AppError::InputError(
+                "--task audio requires --audio".to_string(),
+            ));
+        };
+        let model_input: &Input = model
+            .inputs
+            .first()
+            .ok_or_else(|| AppError::InputError("model has no inputs".to_string()))?;
+
+        let clip: AudioClip = bench_op!(tracker, "readaudio", load_wav(audio_path)?);
+
+        return Ok(Workload::Audio {
+            input_name: model_input.name.clone(),
+            clip,
+            config: MelConfig {
+                sample_rate: args.sample_rate,
+                n_fft: args.n_fft,
+                hop_length: args.hop_length,
+                n_mels: args.n_mels,
+                ..MelConfig::default()
+            },
+            kind: AudioInputKind::for_input(model_input, args.n_mels),
+        });
+    }
+
+    if args.image.is_none() && args.input_tensor.is_none() && args.input.is_empty() {
+        return Err(AppError::InputError(
+            "an image, --input-tensor or --input is required".to_string(),
+        ));
+    }
+
+    let positional: Option<InputSource> = match (&args.input_tensor, &args.image) {
+        (Some(tensor_path), _) => Some(InputSource::Tensor(tensor_path.clone())),
+        (None, Some(image_path)) => Some(InputSource::image(image_path, args.raw_rgb)),
+        (None, None) => None,
+    };
+    let bindings: Vec<InputBinding> = resolve_bindings(&model.inputs, positional, &args.input)?;
+    check_overrides(&bindings, &args.preprocess)?;
+
+    let mut loaded: Vec<(String, InputData)> = Vec::new();
+    for binding in &bindings {
+        let operation = |operation: &str| {
+            if bindings.len() > 1 {
+                format!("{} {}", operation, binding.name)
+            } else {
+                operation.to_string()
+            }
+        };
+        // Timed apart, so the noise of the read stays out of the decode
+        let bytes: Vec<u8> = bench_op!(
+            tracker,
+            &operation(binding.source.operation_name()),
+            binding.source.read()?
+        );
+        let data: InputData = bench_op!(
+            tracker,
+            &operation(binding.source.decode_operation_name()),
+            binding.source.decode(bytes)?
+        );
+        loaded.push((binding.name.clone(), data));
+    }
+
+    let mut prepared: Vec<PreparedInput> = Vec::new();
+    for (name, data) in loaded {
+        let model_input: &Input = model
+            .inputs
+            .iter()
+            .find(|input| input.name == name)
+            .expect("bindings only name model inputs");
+        prepared.push(prepare_input(args, model_input, data)?);
+    }
+
+    Ok(Workload::Vision(prepared))
+}
+
+/// The allocator and whether it counts are picked on the first allocation, long before the
+/// arguments are parsed, so switching either restarts the benchmark with `ALLOCATOR_ENV` and
+/// `COUNT_ALLOCATIONS_ENV` set.
+fn configure_allocator(kind: Option<AllocatorKind>, count: bool) -> Result<(), AppError> {
+    if let Some(kind) = kind.filter(|kind| !kind.is_available()) {
+        return Err(AppError::EnvironmentError(format!(
+            "the {} allocator is not built in, enable the '{}' feature",
+            kind, kind
+        )));
+    }
+    let switch: Option<AllocatorKind> = kind.filter(|&kind| allocator::active() != kind);
+    let start_counting: bool = count && !allocator::counting();
+    if switch.is_none() && !start_counting {
+        return Ok(());
+    }
+    if let Some(kind) = switch {
+        if std::env::var(ALLOCATOR_ENV).is_ok_and(|value| value == kind.to_string()) {
+            return Err(AppError::EnvironmentError(format!(
+                "the {} allocator was requested but {} is active",
+                kind,
+                allocator::active()
+            )));
+        }
+    }
+    if start_counting && std::env::var(COUNT_ALLOCATIONS_ENV).is_ok_and(|value| value == "1") {
+        return Err(AppError::EnvironmentError(
+            "allocation counting was requested but is not active".to_string(),
+        ));
+    }
+
+    let executable = std::env::current_exe()
+        .map_err(|e| AppError::SystemError(format!("failed to locate the benchmark: {}", e)))?;
+    let mut command = std::process::Command::new(executable);
+    command.args(std::env::args_os().skip(1));
+    if let Some(kind) = switch {
+        tracing::debug!(allocator = %kind, "restarting with another allocator");
+        command.env(ALLOCATOR_ENV, kind.to_string());
+    }
+    if start_counting {
+        tracing::debug!("restarting with allocation counting");
+        command.env(COUNT_ALLOCATIONS_ENV, "1");
+    }
+    let error = command.exec();
+    Err(AppError::SystemError(format!(
+        "failed to restart the benchmark: {}",
+        error
+    )))
+}
+
+fn positive_fps(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fps) if fps > 0. && fps.is_finite() => Ok(fps),
+        Ok(_) => Err("the target FPS must be positive".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn prepare_input(
+    args: &RunArgs,
+    model_input: &Input,
+    data: InputData,
+) -> Result<PreparedInput, AppError> {
+    let mut input: PreparedInput = PreparedInput::new(model_input, data, &args.preprocess)?;
+    input.config.filter = args.resize_filter[0];
+    input.config.mode = args.resize_mode[0];
+    input.config.legacy = args.legacy_preprocess;
+    Ok(input)
+}
+
+/// Opens `--video` and prepares its first frame, which is benchmarked like a single image.
+fn open_video(
+    args: &RunArgs,
+    path: &str,
+    model: &Session,
+    tracker: &mut BenchmarkTracker,
+) -> Result<(Workload, VideoSource), AppError> {
+    let [model_input] = model.inputs.as_slice() else {
+        return Err(AppError::InputError(format!(
+            "--video feeds a single model input, the model has {}",
+            model.inputs.len()
+        )));
+    };
+
+    let mut source: VideoSource = bench_op!(tracker, "openvideo", VideoSource::open(path)?);
+
+    let frame: RgbImage = bench_op!(
+        tracker,
+        "readframe",
+        source
+            .next_frame()?
+            .ok_or_else(|| AppError::VideoError(format!("{} has no frames", path)))?
+    );
+
+    let input: PreparedInput = prepare_input(
+        args,
+        model_input,
+        InputData::Image(DynamicImage::ImageRgb8(frame)),
+    )?;
+    Ok((Workload::Vision(vec![input]), source))
+}
 
-        if !self.phase_metrics.is_empty() {
-            println!("\n=========== Phase Metrics ===========");
+/// Where streamed frames come from: read and pre-processed in turn on the main thread, or prepared
+/// ahead by `--prefetch` workers.
+enum FrameSource {
+    Paced(VideoSource),
+    Prefetched(Prefetcher<(String, DynValue)>),
+}
+
+/// Runs every further frame of the video through pre-processing, inference and post-processing,
+/// paced by `--target-fps`. Returns the output of the last frame, `None` for single-frame videos.
+/// With `--prefetch`, frames are decoded and pre-processed on worker threads, so the frame latency
+/// only covers inference and post-processing. The whole stream is one "Video stream" operation, the
+/// steps of the frames are summarized in the video report rather than recorded one by one.
+fn stream_video(
+    args: &RunArgs,
+    source: VideoSource,
+    (name, config): (&str, &PreprocessConfig),
+    model: &mut Session,
+    (output_schema, detection_config): (&OutputSchema, &DetectionConfig),
+    tracker: &mut BenchmarkTracker,
+) -> Result<Option<(TaskOutput, VideoReport)>, AppError> {
+    let mut pacer: FramePacer = FramePacer::new(args.target_fps);
+    let mut frame_tracker: BenchmarkTracker = BenchmarkTracker::new();
+    let mut last_output: Option<TaskOutput> = None;
+    // The first frame was already processed
+    let mut frames: usize = 1;
 
-            let group_map: HashMap<String, &Metrics> = self
-                .phase_metrics
+    let mut source: FrameSource = match args.prefetch {
+        Some(depth) => {
+            let mut source: VideoSource = source;
+            let (name, config): (String, PreprocessConfig) = (name.to_string(), config.clone());
+            let target: Option<TensorElementType> = model
+                .inputs
                 .iter()
-                .map(|(name, metrics)| (name.clone(), metrics))
-                .collect();
+                .find(|input| input.name == name)
+                .and_then(|input| input.input_type.tensor_type());
+            FrameSource::Prefetched(Prefetcher::start(
+                PrefetchConfig {
+                    depth: depth.into(),
+                    workers: args.prefetch_workers.into(),
+                },
+                move || source.next_frame(),
+                move |frame: RgbImage| {
+                    let input: PreparedInput = PreparedInput {
+                        name: name.clone(),
+                        data: InputData::Image(DynamicImage::ImageRgb8(frame)),
+                        config: config.clone(),
+                    };
+                    let (name, value) = input.into_value()?;
+                    match target {
+                        Some(target) => Ok((name, convert_value(value, target)?)),
+                        None => Ok((name, value)),
+                    }
+                },
+            ))
+        }
+        None => FrameSource::Paced(source),
+    };
+
+    let _stream = tracker.scoped("Video stream");
+    while !interrupted() && args.max_frames.is_none_or(|max_frames| frames < max_frames) {
+        let (start, inputs): (Instant, Vec<(String, DynValue)>) = match &mut source {
+            FrameSource::Paced(source) => {
+                let Some(frame) = pacer.next_frame(source)? else {
+                    break;
+                };
+                let start: Instant = Instant::now();
+
+                let input: PreparedInput = PreparedInput {
+                    name: name.to_string(),
+                    data: InputData::Image(DynamicImage::ImageRgb8(frame)),
+                    config: config.clone(),
+                };
+                let inputs: Vec<(String, DynValue)> = bench_op!(
+                    frame_tracker,
+                    "Pre-processing",
+                    convert_inputs(vec![input.into_value()?], &model.inputs)?
+                );
+                (start, inputs)
+            }
+            FrameSource::Prefetched(prefetcher) => {
+                let Some(input) = prefetcher.next_item() else {
+                    break;
+                };
+                (Instant::now(), vec![input?])
+            }
+        };
+
+        let outputs: SessionOutputs<'_> = bench_op!(
+            frame_tracker,
+            "Inference",
+            model.run(session_inputs(&inputs))?
+        );
+
+        last_output = Some(post_process(
+            args,
+            &outputs,
+            output_schema,
+            detection_config,
+            &mut frame_tracker,
+        )?);
+        pacer.record(start.elapsed());
+        frames += 1;
+    }
+
+    let mut report: VideoReport = pacer.report();
+    report.stages = FrameStage::from_tracker(&frame_tracker);
+    if let FrameSource::Prefetched(prefetcher) = &source {
+        report.prefetch = Some(prefetcher.report());
+    }
+    Ok(last_output.map(|output| (output, report)))
+}
+
+fn run_benchmark(args: RunArgs) -> Result<(), AppError> {
+    if args.dry_run {
+        return dry_run(&args);
+    }
+    interrupt::install()?;
+    let (report, mut tracker) = execute_run(&args)?;
 
-            for phase_name in &self.phase_order {
-                if let Some(metrics) = group_map.get(phase_name) {
-                    print!("{}", metrics);
-                }
+    print_report(args.output, &report, &tracker)?;
+    export_run(&args, &report, &mut tracker)?;
+    if report.sla.as_ref().is_some_and(|sla| !sla.passed) {
+        tracing::error!("the run missed its SLA");
+        std::process::exit(SLA_FAILED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// Prints the report of a run or a replay in the chosen format.
+fn print_report(
+    output: OutputFormat,
+    report: &RunReport,
+    tracker: &BenchmarkTracker,
+) -> Result<(), AppError> {
+    match output {
+        OutputFormat::Text => {
+            if report.interrupted {
+                println!("*** Interrupted: partial results ***");
+            }
+            print!("{}", report.metadata);
+            print!("{}", report.environment);
+            tracker.print_all_metrics();
+            if let Some(container) = &report.container {
+                print!("{}", container);
+            }
+            if let Some(output) = &report.output {
+                print!("{}", output);
+            }
+            if let Some(latency) = &report.latency {
+                print!("{}", latency);
+            }
+            if let Some(outliers) = &report.outliers {
+                print!("{}", outliers);
+            }
+            if let Some(thread_cpu) = &report.thread_cpu {
+                print!("{}", thread_cpu);
+            }
+            if let Some(comparison) = &report.io_binding {
+                print!("{}", comparison);
+            }
+            if let Some(comparison) = &report.resize {
+                print!("{}", comparison);
+            }
+            if let Some(video) = &report.video {
+                print!("{}", video);
+            }
+            if let Some(interval) = &report.interval {
+                print!("{}", interval);
+            }
+            if let Some(thermal) = &report.thermal {
+                print!("{}", thermal);
+            }
+            if let Some(cuda) = &report.cuda {
+                print!("{}", cuda);
             }
-            println!("====================================\n");
+            if let Some(tensorrt) = &report.tensorrt {
+                print!("{}", tensorrt);
+            }
+            #[cfg(feature = "flamegraph")]
+            if let Some(flamegraph) = &report.flamegraph {
+                print!("{}", flamegraph);
+            }
+            if let Some(sla) = &report.sla {
+                print!("{}", sla);
+            }
+            if let Some(replay) = &report.replay {
+                print!("{}", replay);
+            }
+
+            let number_threads: NonZero<usize> = num_threads().unwrap();
+            println!("Number of Threads: {:?}", number_threads);
         }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        OutputFormat::Markdown => print!("{}", report.markdown()),
+    }
+    Ok(())
+}
+
+/// Loads the model and prepares its inputs as a run would, but stops before inference: reports whether
+/// the requested execution providers would run the model and the inputs fit it, and what would be
+/// measured.
+fn dry_run(args: &RunArgs) -> Result<(), AppError> {
+    let model_path: String = args.model.clone().expect("clap requires a model path");
+    // Pinned like a run so cores outside the allowed set fail here rather than in the real run
+    if let Some(cores) = &args.pin_cores {
+        pin_process(cores)?;
+    }
+    let model_options: ModelOptions = model_options(args);
+
+    // The loading operations are tracked only to list what a run would record
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    tracker.start_phase("RED BOX Phase");
+    let local_path: PathBuf = resolve_model(args, &model_path, &mut tracker)?;
+    bench_op!(
+        tracker,
+        "envload",
+        ort::init()
+            .with_execution_providers(execution_providers(args, &local_path))
+            .commit()?
+    );
+    let model: Session = load_session(args, &local_path, &model_options, &mut tracker)?;
+    let workload: Workload = match &args.video {
+        Some(path) => open_video(args, path, &model, &mut tracker)?.0,
+        None => load_workload(args, &model, &mut tracker)?,
+    };
+    tracker.end_phase("RED BOX Phase");
+
+    let preprocess_operation: &str = workload.preprocess_operation_name();
+    let (inputs, preprocess_error): (Vec<InputCheck>, Option<String>) =
+        match workload.into_inputs(&model.inputs) {
+            Ok(inputs) => (check_inputs(&model.inputs, &inputs), None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        };
+    drop(model);
+
+    let mut requested: Vec<RequestedProvider> = Vec::new();
+    if let Some(options) = tensorrt_options(args) {
+        requested.push(RequestedProvider::tensorrt(
+            options.execution_provider(&local_path),
+        ));
+    }
+    if !args.cpu {
+        requested.push(RequestedProvider::cuda(
+            cuda_options(args).execution_provider(),
+        ));
+    }
+    let providers: Option<ProviderCheck> =
+        (!requested.is_empty()).then(|| ProviderCheck::run(requested, &local_path, &model_options));
 
-        print!("{}", total);
+    let plan: Vec<String> = dry_run_plan(args, &tracker, preprocess_operation);
+    let report: DryRunReport =
+        DryRunReport::new(&model_path, providers, inputs, preprocess_error, plan);
+    match args.output {
+        OutputFormat::Text => print!("{}", report),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Markdown => print!("{}", report.markdown()),
     }
+    if !report.passed {
+        tracing::error!("the dry run found problems, see the report");
+        std::process::exit(DRY_RUN_FAILED_EXIT_CODE);
+    }
+    Ok(())
 }
 
-fn load_model(model_path: &str) -> Result<Session, OrtError> {
-    let model: Session = Session::builder()?
-        .with_intra_op_spinning(false)?
-        .commit_from_file(model_path)?;
-    Ok(model)
+/// Operations and samplers a run with these arguments would record, loading as it was just tracked.
+fn dry_run_plan(
+    args: &RunArgs,
+    tracker: &BenchmarkTracker,
+    preprocess_operation: &str,
+) -> Vec<String> {
+    let mut loading: Vec<&str> = Vec::new();
+    for span in tracker.spans() {
+        if span.kind == SpanKind::Operation && !loading.contains(&span.name.as_str()) {
+            loading.push(&span.name);
+        }
+    }
+    let mut plan: Vec<String> = vec![format!("RED BOX Phase: {}", loading.join(", "))];
+
+    let combinations: usize = args.resize_filter.len() * args.resize_mode.len();
+    if combinations > 1 {
+        plan.push(format!(
+            "Resize comparison: {} filter and mode combinations, {} runs each",
+            combinations, args.iterations
+        ));
+    }
+    plan.push(format!(
+        "GREEN BOX Phase: {}, then Inference x {}",
+        preprocess_operation, args.iterations
+    ));
+    if let Some(device) = args.io_binding {
+        plan.push(format!(
+            "Bind, a warm-up run each, then {} x {} interleaved with Inference, buffers on {:?}",
+            BOUND_INFERENCE, args.iterations, device
+        ));
+    }
+    if let Some(period) = args.interval {
+        plan.push(format!("Interval: {} runs every {:?}", args.count, period));
+    }
+    let task: String = args
+        .task
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string());
+    plan.push(format!("Post-processing of the last outputs for {}", task));
+    if let Some(path) = &args.video {
+        let frames: String = args.max_frames.map_or_else(
+            || "every frame".to_string(),
+            |max| format!("up to {} frames", max),
+        );
+        plan.push(format!(
+            "Video: {} of {}{}",
+            frames,
+            path,
+            args.target_fps
+                .map_or_else(String::new, |fps| format!(" paced at {} fps", fps))
+        ));
+    }
+
+    let mut samplers: Vec<String> = Vec::new();
+    if args.thread_cpu {
+        samplers.push("thread CPU".to_string());
+    }
+    if args.thermal {
+        samplers.push(format!("thermal every {:?}", args.thermal_interval));
+    }
+    if args.gpu_memory {
+        samplers.push(format!(
+            "GPU memory of device {}",
+            args.cuda_device.unwrap_or(0)
+        ));
+    }
+    #[cfg(feature = "flamegraph")]
+    if let Some(path) = &args.flamegraph {
+        samplers.push(format!("flamegraph to {}", path));
+    }
+    if !samplers.is_empty() {
+        plan.push(format!("Samplers: {}", samplers.join(", ")));
+    }
+
+    let mut exports: Vec<String> = Vec::new();
+    if let Some(path) = &args.report {
+        exports.push(format!("HTML report {}", path));
+    }
+    for spec in &args.sink {
+        exports.push(format!("{} sink {}", spec.format.name(), spec.path));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &args.db {
+        exports.push(format!("database {}", path));
+    }
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = &args.otlp {
+        exports.push(format!("OTLP {}", endpoint));
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(broker) = &args.mqtt {
+        exports.push(format!("MQTT {}", broker));
+    }
+    if !exports.is_empty() {
+        plan.push(format!("Exports: {}", exports.join(", ")));
+    }
+    plan
 }
 
-fn process_image(original_img: DynamicImage) -> Value<TensorValueType<f32>> {
-    let img: DynamicImage = original_img.resize_exact(224, 224, FilterType::CatmullRom);
-    let mut input: ArrayBase<OwnedRepr<f32>, Dim<[usize; 4]>> = Array::zeros((1, 3, 224, 224));
-    for pixel in img.pixels() {
-        let x: usize = pixel.0 as _;
-        let y: usize = pixel.1 as _;
-        let [r, g, b, _] = pixel.2 .0;
-        input[[0, 0, y, x]] = (r as f32) / 255.;
-        input[[0, 1, y, x]] = (g as f32) / 255.;
-        input[[0, 2, y, x]] = (b as f32) / 255.;
+/// Loads and preprocesses the inputs of a run, then saves them with the model and the environment to
+/// a bundle instead of running inference. A `--video` is recorded as its first frame.
+fn record(dir: &Path, args: &RunArgs) -> Result<(), AppError> {
+    let model_path: String = args.model.clone().expect("clap requires a model path");
+    let metadata: RunMetadata = RunMetadata::new(&args.tag)?;
+    let environment: EnvironmentInfo = EnvironmentInfo::capture();
+    let model_options: ModelOptions = model_options(args);
+    let options: RecordedOptions = RecordedOptions {
+        cpu: args.cpu,
+        cuda: cuda_options(args),
+        tensorrt: tensorrt_options(args),
+        session: model_options.clone(),
+        pin_cores: args.pin_cores.as_ref().map(ToString::to_string),
+        io_binding: args.io_binding,
+        optimized_cache: args.optimized_cache.clone(),
+    };
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    let local_path: PathBuf = resolve_model(args, &model_path, &mut tracker)?;
+    ort::init()
+        .with_execution_providers(execution_providers(args, &local_path))
+        .commit()?;
+    let model: Session = load_session(args, &local_path, &model_options, &mut tracker)?;
+    let workload: Workload = match &args.video {
+        Some(path) => open_video(args, path, &model, &mut tracker)?.0,
+        None => load_workload(args, &model, &mut tracker)?,
+    };
+    let inputs: Vec<(String, DynValue)> = workload.into_inputs(&model.inputs)?;
+
+    let task: String = args
+        .task
+        .to_possible_value()
+        .map_or_else(String::new, |value| value.get_name().to_string());
+    let bundle: Bundle = Bundle::record(
+        dir,
+        &BundleSource {
+            run_id: &metadata.run_id,
+            model: &model_path,
+            model_file: &local_path,
+            task: &task,
+            environment: &environment,
+            options: &options,
+        },
+        &inputs,
+    )?;
+    tracing::info!(bundle = %dir.display(), inputs = inputs.len(), "recorded bundle");
+
+    match args.output {
+        OutputFormat::Text | OutputFormat::Markdown => print!("{}", bundle.manifest),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&bundle.manifest)?),
     }
-    Value::from_array(input).unwrap()
+    Ok(())
 }
 
-fn post_process_outputs(outputs: SessionOutputs<'_>) -> Result<(usize, f32), AppError> {
-    let (shape, data): (&ort::tensor::Shape, &[f32]) = outputs[0].try_extract_tensor::<f32>()?;
+/// Measures inference on the inputs of a bundle. Nothing is decoded or preprocessed, so runs replayed
+/// weeks apart or on other devices feed the model the exact same tensors.
+fn replay(args: &ReplayArgs) -> Result<(), AppError> {
+    interrupt::install()?;
+    check_sinks(&args.sink, args.output)?;
+    let bundle: Bundle = Bundle::open(&args.bundle)?;
+    let model_path: PathBuf = bundle.model_path();
+    let metadata: RunMetadata = RunMetadata::new(&args.tag)?;
+    tracing::info!(run = %metadata.run_id, recorded = %bundle.manifest.recorded_run, "starting replay");
 
-    let dims: Vec<usize> = shape.iter().map(|&d| d as usize).collect();
-    let dim: Dim<ndarray::IxDynImpl> = ndarray::IxDyn(&dims);
+    let mut environment: EnvironmentInfo = EnvironmentInfo::capture();
+    environment.warnings = check_environment(&environment);
+    for warning in &environment.warnings {
+        tracing::warn!("{}", warning);
+    }
 
-    let output_array: ArrayBase<ndarray::ViewRepr<&f32>, Dim<ndarray::IxDynImpl>> =
-        ndarray::ArrayView::from_shape(dim, data)
-            .map_err(|e| AppError::Other(format!("Shape error: {}", e)))?;
-    let (predicted_index, &score) = output_array
-        .iter()
-        .enumerate()
-        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
-        .unwrap();
+    // The recorded settings apply unless the command line of the replay overrides them
+    let recorded: &RecordedOptions = &bundle.manifest.options;
+    let mut cuda: CudaOptions = recorded.cuda.clone();
+    let mut tensorrt: Option<TensorRtOptions> = match (args.cpu, args.tensorrt) {
+        (true, _) => None,
+        (false, true) => Some(recorded.tensorrt.clone().unwrap_or_default()),
+        (false, false) => recorded.tensorrt.clone(),
+    };
+    if let Some(device_id) = args.cuda_device {
+        cuda.device_id = Some(device_id);
+        if let Some(tensorrt) = &mut tensorrt {
+            tensorrt.device_id = Some(device_id);
+        }
+    }
+    let mut providers: Vec<ExecutionProviderDispatch> = Vec::new();
+    if let Some(options) = &tensorrt {
+        providers.push(options.execution_provider(&model_path));
+    }
+    if !args.cpu && !recorded.cpu {
+        providers.push(cuda.execution_provider());
+    }
+    let model_options: ModelOptions = match args.intra_threads {
+        Some(threads) => ModelOptions {
+            intra_threads: Some(usize::from(threads)),
+            ..ModelOptions::default()
+        },
+        None => recorded.session.clone(),
+    };
+    if let Some(cores) = &recorded.pin_cores {
+        let cores: CoreSet = cores
+            .parse()
+            .map_err(|e| AppError::InputError(format!("bundle has invalid pinned cores: {}", e)))?;
+        pin_process(&cores)?;
+        tracing::info!(cores = %cores, "pinned process to the recorded cores");
+    }
+
+    let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    register_sinks(&mut tracker, &args.sink)?;
+    'run: {
+        tracker.start_phase("RED BOX Phase");
+        bench_op!(
+            tracker,
+            "envload",
+            ort::init().with_execution_providers(providers).commit()?
+        );
+        let mut model: Session = match &recorded.optimized_cache {
+            Some(cache_dir) => {
+                load_cached_model(cache_dir, &model_path, &model_options, &mut tracker)?
+            }
+            None => bench_op!(
+                tracker,
+                "loadmodel",
+                load_model_with_options(&model_path, &model_options)?
+            ),
+        };
+        let inputs: Vec<(String, DynValue)> =
+            bench_op!(tracker, "Load bundle", bundle.load_inputs()?);
+        tracker.end_phase("RED BOX Phase");
+
+        if let Some(input) = check_inputs(&model.inputs, &inputs)
+            .into_iter()
+            .find(|input| !input.compatible)
+        {
+            return Err(AppError::InputShapeError(format!(
+                "input '{}' expects {}, the bundle has {}",
+                input.name,
+                input.expected,
+                input.actual.as_deref().unwrap_or("nothing")
+            )));
+        }
 
-    Ok((predicted_index, score))
+        tracker.start_phase("GREEN BOX Phase");
+        let binding: Option<IoBinding> = match recorded.io_binding {
+            Some(device) => Some(bind_warmed_up(
+                &mut model,
+                &inputs,
+                device,
+                cuda.device_id.unwrap_or(0),
+                &mut tracker,
+            )?),
+            None => None,
+        };
+        for _ in 0..args.iterations {
+            if interrupted() {
+                break 'run;
+            }
+            bench_op!(tracker, "Inference", model.run(session_inputs(&inputs))?);
+            if let Some(binding) = &binding {
+                bench_op!(tracker, BOUND_INFERENCE, run_bound(&mut model, binding)?);
+            }
+        }
+        tracker.end_phase("GREEN BOX Phase");
+    }
+    if interrupted() {
+        tracing::warn!("interrupted, reporting partial results");
+        tracker.interrupt();
+    }
+
+    let replay: ReplayReport = ReplayReport::new(&bundle, &environment)?;
+    let mut report: RunReport = RunReport::new(
+        metadata,
+        &bundle.manifest.model,
+        args.iterations,
+        environment,
+        &tracker,
+        None,
+        None,
+    );
+    report.replay = Some(replay);
+    print_report(args.output, &report, &tracker)?;
+    if !args.sink.is_empty() {
+        tracker.export_sinks(&report.metadata)?;
+        tracing::info!(sinks = args.sink.len(), "exported metrics");
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(db_path) = &args.db {
+        let run_id: i64 = ResultsDb::open(db_path)?.record_run(
+            &report.model,
+            &bundle.manifest.task,
+            &report.metadata,
+            &report.environment,
+            &tracker,
+        )?;
+        tracing::info!(db = %db_path, run = run_id, "recorded replay");
+    }
+    Ok(())
 }
 
-fn main() -> Result<(), AppError> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 3 {
-        return Err(AppError::UsageError(args[0].clone()));
+/// Rejects a `-` sink with `--output json`, its records would end up in the JSON document on stdout.
+fn check_sinks(sinks: &[SinkSpec], output: OutputFormat) -> Result<(), AppError> {
+    if output == OutputFormat::Json && sinks.iter().any(SinkSpec::is_stdout) {
+        return Err(AppError::InputError(
+            "a - sink writes to stdout, which --output json prints the results to".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Opens the `--sink`s of a run and registers them with its tracker, so a path that cannot be opened
+/// fails the run before anything is measured.
+fn register_sinks(tracker: &mut BenchmarkTracker, sinks: &[SinkSpec]) -> Result<(), AppError> {
+    for spec in sinks {
+        tracker.register_sink(spec.open()?);
+    }
+    Ok(())
+}
+
+/// Binds the inputs and outputs of `model`, then runs it once plainly and once through the binding
+/// without timing either, so neither path of the comparison pays for the cold first run.
+fn bind_warmed_up(
+    model: &mut Session,
+    inputs: &[(String, DynValue)],
+    device: BindingDevice,
+    device_id: i32,
+    tracker: &mut BenchmarkTracker,
+) -> Result<IoBinding, AppError> {
+    let binding: IoBinding = bench_op!(
+        tracker,
+        "Bind",
+        bind_session(model, inputs, device, device_id)?
+    );
+    model.run(session_inputs(inputs))?;
+    run_bound(model, &binding)?;
+    Ok(binding)
+}
+
+/// Execution providers of the ONNX Runtime environment, which the first commit fixes for the process.
+static COMMITTED_PROVIDERS: OnceLock<String> = OnceLock::new();
+
+/// Commits the environment with `providers`. A process only has one environment, so when it already
+/// exists with other providers, e.g. for an earlier job of `serve` or matrix cell, the run fails rather
+/// than report providers its sessions do not use.
+fn commit_environment(providers: Vec<ExecutionProviderDispatch>) -> Result<(), AppError> {
+    let requested: String = format!("{:?}", providers);
+    let created: bool = ort::init().with_execution_providers(providers).commit()?;
+    let committed: &String = COMMITTED_PROVIDERS.get_or_init(|| requested.clone());
+    if !created && *committed != requested {
+        return Err(AppError::EnvironmentError(format!(
+            "ONNX Runtime already runs with the execution providers {}, {} cannot be used in this \
+             process",
+            committed, requested
+        )));
+    }
+    Ok(())
+}
+
+/// Measures one run without printing anything, shared by the command line and `serve`.
+fn execute_run(args: &RunArgs) -> Result<(RunReport, BenchmarkTracker), AppError> {
+    let model_path: String = args.model.clone().expect("clap requires a model path");
+    let metadata: RunMetadata = RunMetadata::new(&args.tag)?;
+    check_sinks(&args.sink, args.output)?;
+    tracing::info!(run = %metadata.run_id, "starting run");
+
+    if let Some(cores) = &args.pin_cores {
+        pin_process(cores)?;
+        tracing::info!(cores = %cores, "pinned process to cores");
+    }
+    let model_options: ModelOptions = model_options(args);
+
+    // Captured before the tracker starts so probing the machine is not part of the totals
+    let mut environment: EnvironmentInfo = EnvironmentInfo::capture();
+    environment.warnings = check_environment(&environment);
+    for warning in &environment.warnings {
+        tracing::warn!("{}", warning);
     }
+    if args.strict && !environment.warnings.is_empty() {
+        return Err(AppError::EnvironmentError(environment.warnings.join("; ")));
+    }
+
+    let cgroup: Option<CgroupInfo> = CgroupInfo::detect().filter(CgroupInfo::is_limited);
+    let throttling_before: CpuStat = cgroup
+        .as_ref()
+        .map(CgroupInfo::cpu_stat)
+        .unwrap_or_default();
 
-    let model_path: &str = &args[1];
-    let image_path: &str = &args[2];
+    let tensorrt: Option<TensorRtOptions> = tensorrt_options(args);
 
     let mut tracker: BenchmarkTracker = BenchmarkTracker::new();
+    register_sinks(&mut tracker, &args.sink)?;
+    tracker.set_thread_sampling(args.thread_cpu);
+    #[cfg(feature = "flamegraph")]
+    let profiler: Option<Arc<Mutex<FlamegraphProfiler>>> = args.flamegraph.as_ref().map(|_| {
+        let profiler = Arc::new(Mutex::new(FlamegraphProfiler::new(
+            args.flamegraph_operation.clone(),
+        )));
+        tracker.add_hook(profiler.clone());
+        profiler
+    });
+    let mut task_output: Option<TaskOutput> = None;
+    let mut resize: Option<ResizeComparison> = None;
+    let mut interval: Option<IntervalReport> = None;
+    let mut video_report: Option<VideoReport> = None;
+    let mut thermal: Option<ThermalMonitor> = None;
+    // Started before ONNX Runtime, so the baseline does not include the CUDA context
+    let gpu_memory: Option<GpuMemoryMonitor> = if args.gpu_memory {
+        GpuMemoryMonitor::start(args.cuda_device.unwrap_or(0))
+            .inspect_err(|e| tracing::warn!("{}", e))
+            .ok()
+    } else {
+        None
+    };
 
     // RED BOX: Environment setup, image loading, processing, and model loading
     tracker.start_phase("RED BOX Phase");
+    let local_path: PathBuf = resolve_model(args, &model_path, &mut tracker)?;
+    let engines_before: Vec<PathBuf> = tensorrt
+        .as_ref()
+        .map(|options| options.cached_engines(&local_path))
+        .unwrap_or_default();
 
-    tracker.start_operation("envload");
-    ort::init()
-        .with_execution_providers([CUDAExecutionProvider::default().build()])
-        .commit()?;
-    tracker.finish_operation();
+    // SIGINT and SIGTERM leave the block early, which keeps the metrics recorded so far
+    'run: {
+        bench_op!(
+            tracker,
+            "envload",
+            commit_environment(execution_providers(args, &local_path))?
+        );
 
-    tracker.start_operation("loadmodel");
-    let mut model: Session = load_model(model_path).map_err(AppError::OrtError)?;
-    tracker.finish_operation();
+        let mut model: Session = load_session(args, &local_path, &model_options, &mut tracker)?;
+        if interrupted() {
+            break 'run;
+        }
+        tracing::debug!(
+            model = %model_path,
+            inputs = model.inputs.len(),
+            outputs = model.outputs.len(),
+            "model loaded"
+        );
 
-    tracker.start_operation("readimg");
-    let original_img: DynamicImage = image::open(image_path)?;
-    tracker.finish_operation();
+        let (workload, mut video): (Workload, Option<VideoSource>) = match &args.video {
+            Some(path) => {
+                let (workload, source) = open_video(args, path, &model, &mut tracker)?;
+                (workload, Some(source))
+            }
+            None => (load_workload(args, &model, &mut tracker)?, None),
+        };
+        if interrupted() {
+            break 'run;
+        }
 
-    tracker.end_phase("RED BOX Phase");
+        tracker.end_phase("RED BOX Phase");
 
-    // GREEN BOX: Model inference and post-processing
-    tracker.start_phase("GREEN BOX Phase");
+        let mut detection_config: DetectionConfig = DetectionConfig {
+            format: args.detection_format,
+            conf_threshold: args.conf_threshold,
+            iou_threshold: args.iou_threshold,
+            max_detections: args.max_detections,
+            ..DetectionConfig::default()
+        };
+        if let Some(image_input) = workload.image_input() {
+            let (width, height) = image_input.image_size().expect("image input");
+            let config: &PreprocessConfig = &image_input.config;
+            let ((resized_width, resized_height), (x, y)) = config.placement(width, height);
+            detection_config.input_size = (config.width, config.height);
+            detection_config.scale = (
+                width as f32 / resized_width as f32,
+                height as f32 / resized_height as f32,
+            );
+            detection_config.offset = (x as f32, y as f32);
+        }
 
-    tracker.start_operation("Pre-processing");
-    let input: Value<TensorValueType<f32>> = process_image(original_img);
-    tracker.finish_operation();
+        // Only the name and preprocessing of the first frame are kept, the image itself is consumed below
+        let frame_input: Option<(String, PreprocessConfig)> = video
+            .as_ref()
+            .and(workload.image_input())
+            .map(|input| (input.name.clone(), input.config.clone()));
 
-    tracker.start_operation("Inference");
+        // Output types are read up front: the session stays borrowed while its outputs are alive
+        let output_schema: OutputSchema = OutputSchema::new(&model.outputs);
 
-    let outputs: SessionOutputs<'_> = model.run(ort::inputs![input])?;
-    tracker.finish_operation();
+        // GREEN BOX: Model inference and post-processing
+        tracker.start_phase("GREEN BOX Phase");
+        if args.thermal {
+            thermal = Some(ThermalMonitor::start(args.thermal_interval));
+        }
 
-    tracker.start_operation("Post-processing");
-    let (predicted_index, score) = post_process_outputs(outputs).unwrap();
-    tracker.finish_operation();
+        let combinations: Vec<(ResizeFilter, ResizeMode)> = args
+            .resize_filter
+            .iter()
+            .flat_map(|&filter| args.resize_mode.iter().map(move |&mode| (filter, mode)))
+            .collect();
+        if let (Workload::Vision(prepared), true) = (&workload, combinations.len() > 1) {
+            resize = Some(compare_resizing(
+                &mut model,
+                prepared,
+                &combinations,
+                args.iterations,
+                &mut tracker,
+            )?);
+            if interrupted() {
+                break 'run;
+            }
+        }
 
-    tracker.end_phase("GREEN BOX Phase");
+        let inputs: Vec<(String, DynValue)> = bench_op!(
+            tracker,
+            workload.preprocess_operation_name(),
+            workload.into_inputs(&model.inputs)?
+        );
 
-    tracker.print_all_metrics();
+        // With IOBinding both paths get an untimed warm-up run and their runs are interleaved, so
+        // neither pays for the cold first run or profits from the other having warmed the caches
+        let binding: Option<IoBinding> = match args.io_binding {
+            Some(device) => Some(bind_warmed_up(
+                &mut model,
+                &inputs,
+                device,
+                args.cuda_device.unwrap_or(0),
+                &mut tracker,
+            )?),
+            None => None,
+        };
 
-    println!("Predicted Class Index: {}", predicted_index);
-    println!("Confidence Score: {:.4}", score);
+        // Every run but the last drops its outputs right away, the last ones are post-processed
+        for iteration in 0..args.iterations {
+            if interrupted() {
+                break 'run;
+            }
+            if iteration > 0 {
+                bench_op!(tracker, "Inference", model.run(session_inputs(&inputs))?);
+            }
+            if let Some(binding) = &binding {
+                bench_op!(tracker, BOUND_INFERENCE, run_bound(&mut model, binding)?);
+            }
+        }
 
-    let number_threads: NonZero<usize> = num_threads().unwrap();
-    println!("Number of Threads: {:?}", number_threads);
+        if let Some(period) = args.interval {
+            interval = Some(bench_op!(
+                tracker,
+                "Interval",
+                run_periodic(&mut model, &inputs, period, args.count)?
+            ));
+            if interrupted() {
+                break 'run;
+            }
+        }
+
+        let outputs: SessionOutputs<'_> =
+            bench_op!(tracker, "Inference", model.run(session_inputs(&inputs))?);
+
+        task_output = Some(post_process(
+            args,
+            &outputs,
+            &output_schema,
+            &detection_config,
+            &mut tracker,
+        )?);
+        // Releases the session for the remaining video frames
+        drop(outputs);
+
+        if let (Some(source), Some((name, config))) = (video.take(), frame_input) {
+            let streamed: Option<(TaskOutput, VideoReport)> = stream_video(
+                args,
+                source,
+                (&name, &config),
+                &mut model,
+                (&output_schema, &detection_config),
+                &mut tracker,
+            )?;
+            if let Some((last_output, report)) = streamed {
+                task_output = Some(last_output);
+                video_report = Some(report);
+            }
+        }
+
+        tracker.end_phase("GREEN BOX Phase");
+    }
+    let thermal_report: Option<ThermalReport> = thermal.map(|monitor| monitor.finish(&tracker));
+    let cuda: CudaOptions = cuda_options(args);
+    let cuda_report: Option<CudaReport> =
+        (args.gpu_memory || !cuda.is_default()).then(|| CudaReport {
+            options: cuda,
+            memory: gpu_memory.map(GpuMemoryMonitor::finish),
+        });
+    if interrupted() {
+        tracing::warn!("interrupted, reporting partial results");
+        tracker.interrupt();
+    }
+
+    let peak_rss: u64 = Metrics::current("Peak".to_string()).peak_rss;
+    let cgroup_report: Option<CgroupReport> = cgroup.map(|cgroup| CgroupReport {
+        peak_rss,
+        memory_peak: cgroup.memory_peak(),
+        throttling: cgroup.cpu_stat().diff(&throttling_before),
+        cgroup,
+    });
+
+    let mut report: RunReport = RunReport::new(
+        metadata,
+        &model_path,
+        args.iterations,
+        environment,
+        &tracker,
+        cgroup_report,
+        task_output,
+    );
+    report.resize = resize;
+    report.video = video_report;
+    report.interval = interval;
+    report.thermal = thermal_report;
+    report.cuda = cuda_report;
+    report.tensorrt = tensorrt.map(|options| {
+        let engines_after: Vec<PathBuf> = options.cached_engines(&local_path);
+        TensorRtReport::new(&options, &engines_before, &engines_after, &tracker)
+    });
+    let sla: SlaLimits = SlaLimits {
+        latency: args.sla_latency,
+        memory: args.sla_memory.map(|memory| memory as u64),
+    };
+    if sla.is_set() {
+        report.sla = Some(SlaReport::new(&sla, report.latency.as_ref(), peak_rss));
+    }
+    #[cfg(feature = "flamegraph")]
+    if let (Some(profiler), Some(path)) = (profiler, &args.flamegraph) {
+        // A profile that could not be taken should not discard the metrics of the run
+        match profiler.lock().map(|profiler| profiler.write(path)) {
+            Ok(Ok(flamegraph)) => report.flamegraph = Some(flamegraph),
+            Ok(Err(e)) => tracing::warn!("{}", e),
+            Err(_) => tracing::warn!("the CPU profiler panicked"),
+        }
+    }
+    Ok((report, tracker))
+}
+
+/// Decodes, reduces or maps the model outputs into the result of `--task`.
+fn post_process(
+    args: &RunArgs,
+    outputs: &SessionOutputs<'_>,
+    output_schema: &OutputSchema,
+    detection_config: &DetectionConfig,
+    tracker: &mut BenchmarkTracker,
+) -> Result<TaskOutput, AppError> {
+    let task_output: TaskOutput = match args.task {
+        Task::Detection => {
+            tracker.start_operation("Post-processing");
+            let extracted: ExtractedOutputs = extract_outputs(outputs, output_schema);
+            let candidates: Vec<Detection> =
+                decode_detections(&extracted.tensors, detection_config)?;
+            tracker.finish_operation();
+
+            let detections: Vec<Detection> = bench_op!(
+                tracker,
+                "NMS",
+                non_max_suppression(
+                    candidates,
+                    detection_config.iou_threshold,
+                    detection_config.max_detections,
+                )
+            );
+            TaskOutput::Detection(detections)
+        }
+        Task::Segmentation => {
+            tracker.start_operation("Post-processing");
+            let extracted: ExtractedOutputs = extract_outputs(outputs, output_schema);
+            let mask: SegmentationMask = argmax_mask(&extracted.tensors)?;
+            tracker.finish_operation();
+
+            if let Some(mask_path) = &args.mask_output {
+                let colorized: RgbImage =
+                    bench_op!(tracker, "Mask colorization", colorize_mask(&mask));
+
+                bench_op!(tracker, "writemask", colorized.save(mask_path)?);
+            }
+            TaskOutput::Segmentation(mask)
+        }
+        // Classification, and the text and audio tasks, whose models are classifiers too. A wildcard
+        // because `#[cfg]` cannot be put on the patterns of an or-pattern
+        _ => {
+            tracker.start_operation("Post-processing");
+            let extracted: ExtractedOutputs = extract_outputs(outputs, output_schema);
+            let summaries: Vec<OutputSummary> = post_process_outputs(&extracted)?;
+            tracker.finish_operation();
+            TaskOutput::Classification(summaries)
+        }
+    };
+    Ok(task_output)
+}
+
+/// Writes the report file, sink records, database row, trace and MQTT messages a run was asked for.
+fn export_run(
+    args: &RunArgs,
+    report: &RunReport,
+    tracker: &mut BenchmarkTracker,
+) -> Result<(), AppError> {
+    let (model_path, environment) = (&report.model, &report.environment);
+
+    if let Some(report_path) = &args.report {
+        HtmlReport {
+            model: model_path,
+            metadata: &report.metadata,
+            environment,
+            tracker,
+        }
+        .write(report_path)?;
+        tracing::info!(report = %report_path, "wrote HTML report");
+    }
+
+    if !args.sink.is_empty() {
+        tracker.export_sinks(&report.metadata)?;
+        tracing::info!(sinks = args.sink.len(), "exported metrics");
+    }
+
+    #[cfg(feature = "sqlite")]
+    if let Some(db_path) = &args.db {
+        let task: String = args
+            .task
+            .to_possible_value()
+            .map_or_else(String::new, |value| value.get_name().to_string());
+        let run_id: i64 = ResultsDb::open(db_path)?.record_run(
+            model_path,
+            &task,
+            &report.metadata,
+            environment,
+            tracker,
+        )?;
+        tracing::info!(db = %db_path, run = run_id, "recorded run");
+    }
+
+    #[cfg(feature = "otlp")]
+    if let Some(endpoint) = &args.otlp {
+        // The report is already printed, a collector that is down should not fail the run
+        match OtlpExporter::new(endpoint).export(tracker, &report.metadata, environment, model_path)
+        {
+            Ok(()) => tracing::info!(endpoint = %endpoint, "exported spans"),
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
+
+    #[cfg(feature = "mqtt")]
+    if let Some(broker) = &args.mqtt {
+        let device_id: &str = args.device_id.as_deref().unwrap_or(&environment.hostname);
+        match MqttPublisher::new(broker, &args.topic, device_id)?.publish(
+            report,
+            tracker,
+            args.mqtt_samples,
+        ) {
+            Ok(messages) => tracing::info!(broker = %broker, messages, "published results"),
+            Err(e) => tracing::warn!("{}", e),
+        }
+    }
 
     Ok(())
 }
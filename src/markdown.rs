@@ -0,0 +1,608 @@
+use crate::{
+    calibration::CalibrationReport,
+    dryrun::DryRunReport,
+    evaluate::EvaluationReport,
+    inspect::{ModelInfo, ValueInfo},
+    matrix::{CellResult, MatrixCell, MatrixReport},
+    metadata::RunMetadata,
+    metrics::{format_bytes, format_bytes_delta, MetricsSummary},
+    pipeline::PipelineReport,
+    precision::{OutputDivergence, PrecisionReport, PrecisionResult},
+    report::RunReport,
+};
+use serde_json::Value;
+
+/// Renders a report as GitHub-flavored markdown for `--output markdown`, to paste into issues.
+pub trait Markdown {
+    fn markdown(&self) -> String;
+}
+
+/// A markdown table whose first column is left-aligned and the others, numbers, right-aligned.
+/// Columns are padded so the table stays readable before it is rendered.
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownTable {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl MarkdownTable {
+    pub fn new<S: AsRef<str>>(header: &[S]) -> Self {
+        Self {
+            header: header.iter().map(|cell| escape(cell.as_ref())).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row, padded with empty cells or cut to the width of the header.
+    pub fn row(&mut self, cells: Vec<String>) {
+        let mut cells: Vec<String> = cells.iter().map(|cell| escape(cell)).collect();
+        cells.resize(self.header.len(), String::new());
+        self.rows.push(cells);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+impl std::fmt::Display for MarkdownTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let widths: Vec<usize> = (0..self.header.len())
+            .map(|column| {
+                std::iter::once(&self.header)
+                    .chain(&self.rows)
+                    .map(|row| row[column].chars().count())
+                    .max()
+                    .unwrap_or_default()
+                    .max(3)
+            })
+            .collect();
+        let line = |f: &mut std::fmt::Formatter<'_>, cells: &[String]| {
+            for (column, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+                if column == 0 {
+                    write!(f, "| {:<width$} ", cell)?;
+                } else {
+                    write!(f, "| {:>width$} ", cell)?;
+                }
+            }
+            writeln!(f, "|")
+        };
+
+        line(f, &self.header)?;
+        for (column, width) in widths.iter().enumerate() {
+            if column == 0 {
+                write!(f, "| {} ", "-".repeat(*width))?;
+            } else {
+                write!(f, "| {}: ", "-".repeat(width - 1))?;
+            }
+        }
+        writeln!(f, "|")?;
+        for row in &self.rows {
+            line(f, row)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pipes would end the cell and line breaks the row.
+fn escape(cell: &str) -> String {
+    cell.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// Escapes the characters markdown would format in running text, e.g. `*` or a backtick.
+fn escape_text(text: &str) -> String {
+    let mut escaped: String = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\r' | '\n' => escaped.push(' '),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The run id and tags of a report, the tags escaped since they are free text of the command line.
+fn run_line(metadata: &RunMetadata) -> String {
+    let mut line: String = format!("Run `{}`", metadata.run_id);
+    for (key, value) in &metadata.tags {
+        line.push_str(&format!(", {}={}", escape_text(key), escape_text(value)));
+    }
+    line
+}
+
+fn millis(value: f64) -> String {
+    format!("{:.3}", value)
+}
+
+fn optional<T>(value: Option<T>, format: impl Fn(T) -> String) -> String {
+    value.map_or_else(|| "-".to_string(), format)
+}
+
+/// Runs of one operation or phase, in the order the first of them finished.
+struct Aggregate<'a> {
+    kind: &'static str,
+    name: &'a str,
+    runs: Vec<&'a MetricsSummary>,
+}
+
+fn aggregate<'a>(kind: &'static str, summaries: &'a [MetricsSummary]) -> Vec<Aggregate<'a>> {
+    let mut aggregates: Vec<Aggregate<'a>> = Vec::new();
+    for summary in summaries {
+        match aggregates
+            .iter_mut()
+            .find(|aggregate| aggregate.name == summary.name)
+        {
+            Some(aggregate) => aggregate.runs.push(summary),
+            None => aggregates.push(Aggregate {
+                kind,
+                name: &summary.name,
+                runs: vec![summary],
+            }),
+        }
+    }
+    aggregates
+}
+
+/// Operations and phases with one row per name, the times being means over its runs, and the whole
+/// run last. The RSS goes from the start of the first run to the end of the last one.
+fn metrics_table(
+    operations: &[MetricsSummary],
+    phases: &[MetricsSummary],
+    total: &MetricsSummary,
+) -> MarkdownTable {
+    let mut table: MarkdownTable = MarkdownTable::new(&[
+        "Name",
+        "Kind",
+        "Runs",
+        "Mean wall (ms)",
+        "Total wall (ms)",
+        "User (ms)",
+        "System (ms)",
+        "RSS before",
+        "RSS after",
+        "RSS change",
+        "Peak RSS",
+        "Peak RSS growth",
+        "CPU (%)",
+    ]);
+    let total = Aggregate {
+        kind: "total",
+        name: &total.name,
+        runs: vec![total],
+    };
+    for aggregate in aggregate("operation", operations)
+        .into_iter()
+        .chain(aggregate("phase", phases))
+        .chain(std::iter::once(total))
+    {
+        let runs: f64 = aggregate.runs.len() as f64;
+        let (first, last) = (aggregate.runs[0], aggregate.runs[aggregate.runs.len() - 1]);
+        let mean = |metric: fn(&MetricsSummary) -> f64| {
+            aggregate.runs.iter().map(|run| metric(run)).sum::<f64>() / runs
+        };
+        table.row(vec![
+            aggregate.name.to_string(),
+            aggregate.kind.to_string(),
+            aggregate.runs.len().to_string(),
+            millis(mean(|run| run.wall_clock_time_ms)),
+            millis(mean(|run| run.wall_clock_time_ms) * runs),
+            millis(mean(|run| run.user_time_ms)),
+            millis(mean(|run| run.system_time_ms)),
+            format_bytes(first.rss_before),
+            format_bytes(last.rss_after),
+            format_bytes_delta(last.rss_after as i64 - first.rss_before as i64),
+            format_bytes(
+                aggregate
+                    .runs
+                    .iter()
+                    .map(|run| run.peak_rss)
+                    .max()
+                    .unwrap_or_default(),
+            ),
+            format_bytes(aggregate.runs.iter().map(|run| run.peak_rss_growth).sum()),
+            format!("{:.1}", mean(|run| run.cpu_usage.into())),
+        ]);
+    }
+    table
+}
+
+impl Markdown for RunReport {
+    fn markdown(&self) -> String {
+        let mut markdown: String = format!("### {}\n\n", escape(&self.model));
+        markdown.push_str(&run_line(&self.metadata));
+        markdown.push_str(&format!(
+            ", {} iterations{}\n\n",
+            self.iterations,
+            if self.interrupted {
+                ", **interrupted: partial results**"
+            } else {
+                ""
+            }
+        ));
+        markdown.push_str(&metrics_table(&self.operations, &self.phases, &self.total).to_string());
+
+        if let Some(latency) = &self.latency {
+            let mut table: MarkdownTable = MarkdownTable::new(&[
+                "Operation",
+                "Count",
+                "Min (ms)",
+                "Mean (ms)",
+                "p50 (ms)",
+                "p90 (ms)",
+                "p95 (ms)",
+                "p99 (ms)",
+                "p99.9 (ms)",
+                "Max (ms)",
+            ]);
+            table.row(vec![
+                latency.operation.clone(),
+                latency.count.to_string(),
+                millis(latency.min_ms),
+                millis(latency.mean_ms),
+                millis(latency.p50_ms),
+                millis(latency.p90_ms),
+                millis(latency.p95_ms),
+                millis(latency.p99_ms),
+                millis(latency.p999_ms),
+                millis(latency.max_ms),
+            ]);
+            markdown.push('\n');
+            markdown.push_str(&table.to_string());
+        }
+
+        if let Some(replay) = &self.replay {
+            markdown.push_str(&format!(
+                "\nReplayed from `{}`, recorded in run `{}`\n\n",
+                replay.bundle, replay.recorded_run
+            ));
+            let mut table: MarkdownTable = MarkdownTable::new(&["Environment", "Recorded", "Now"]);
+            for change in &replay.changes {
+                table.row(vec![
+                    change.field.clone(),
+                    change.recorded.clone(),
+                    change.current.clone(),
+                ]);
+            }
+            if !table.is_empty() {
+                markdown.push_str(&table.to_string());
+            }
+        }
+        markdown
+    }
+}
+
+impl Markdown for PipelineReport {
+    fn markdown(&self) -> String {
+        let stages: Vec<String> = self
+            .stages
+            .iter()
+            .map(|stage| format!("{} (`{}`)", stage.name, stage.model))
+            .collect();
+        format!(
+            "### Pipeline: {}\n\n{}\n\n{}",
+            escape(&stages.join(" → ")),
+            run_line(&self.metadata),
+            metrics_table(&self.operations, &self.phases, &self.total)
+        )
+    }
+}
+
+/// One column per model, the reference first, with the divergence of every output from it.
+impl Markdown for PrecisionReport {
+    fn markdown(&self) -> String {
+        let results: &[PrecisionResult] = &self.results;
+        let mut header: Vec<&str> = vec!["Metric"];
+        header.extend(results.iter().map(|result| result.model.as_str()));
+        let mut table: MarkdownTable = MarkdownTable::new(&header);
+
+        let mut metric_row = |name: &str, value: &dyn Fn(&PrecisionResult) -> String| {
+            let mut row: Vec<String> = vec![name.to_string()];
+            row.extend(results.iter().map(value));
+            table.row(row);
+        };
+        metric_row("Input types", &|result| result.input_types.join(", "));
+        metric_row("Load time (ms)", &|result| {
+            millis(result.load_time.as_secs_f64() * 1000.)
+        });
+        metric_row("Mean latency (ms)", &|result| {
+            millis(result.mean_latency().as_secs_f64() * 1000.)
+        });
+        metric_row("Min latency (ms)", &|result| {
+            millis(result.min_latency().as_secs_f64() * 1000.)
+        });
+        metric_row("Peak RSS growth", &|result| {
+            format_bytes(result.peak_rss_growth)
+        });
+
+        let outputs: Vec<&str> = results
+            .first()
+            .map(|reference| {
+                reference
+                    .outputs
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect()
+            })
+            .unwrap_or_default();
+        for output in outputs {
+            let divergence = |result: &PrecisionResult, value: fn(&OutputDivergence) -> String| {
+                result
+                    .divergence
+                    .iter()
+                    .find(|divergence| divergence.output == output)
+                    .map_or_else(|| "-".to_string(), value)
+            };
+            metric_row(&format!("{}: cosine similarity", output), &|result| {
+                divergence(result, |divergence| {
+                    format!("{:.6}", divergence.cosine_similarity)
+                })
+            });
+            metric_row(&format!("{}: max abs diff", output), &|result| {
+                divergence(result, |divergence| {
+                    format!("{:.6}", divergence.max_abs_diff)
+                })
+            });
+            metric_row(&format!("{}: top-1", output), &|result| {
+                divergence(result, |divergence| {
+                    if divergence.top1_match {
+                        "match".to_string()
+                    } else {
+                        "**MISMATCH**".to_string()
+                    }
+                })
+            });
+        }
+        format!(
+            "### Precision comparison\n\n{}\n\n{}",
+            run_line(&self.metadata),
+            table
+        )
+    }
+}
+
+/// Short name of a matrix configuration for a column header.
+fn cell_label(cell: &MatrixCell) -> String {
+    let mut label: String = format!("{} ({}", cell.model, cell.provider);
+    if let Some(threads) = cell.threads {
+        label.push_str(&format!(", {} threads", threads));
+    }
+    if let Some(batch_size) = cell.batch_size {
+        label.push_str(&format!(", batch {}", batch_size));
+    }
+    label.push(')');
+    label
+}
+
+/// A row per configuration with its latency, then the mean time of every operation with a column
+/// per configuration that ran.
+impl Markdown for MatrixReport {
+    fn markdown(&self) -> String {
+        let mut summary: MarkdownTable = MarkdownTable::new(&[
+            "Configuration",
+            "Load (ms)",
+            "Mean (ms)",
+            "p50 (ms)",
+            "p95 (ms)",
+            "p99 (ms)",
+            "Images/s",
+            "Peak RSS",
+        ]);
+        for result in &self.cells {
+            let mut row: Vec<String> = vec![cell_label(&result.cell)];
+            match &result.error {
+                Some(error) => row.push(format!("failed: {}", error)),
+                None => row.extend([
+                    optional(result.load_ms, millis),
+                    optional(result.mean_ms, millis),
+                    optional(result.p50_ms, millis),
+                    optional(result.p95_ms, millis),
+                    optional(result.p99_ms, millis),
+                    optional(result.throughput, |v| format!("{:.1}", v)),
+                    optional(result.peak_rss, format_bytes),
+                ]),
+            }
+            summary.row(row);
+        }
+
+        let ran: Vec<&CellResult> = self
+            .cells
+            .iter()
+            .filter(|result| result.report.is_some())
+            .collect();
+        // Mean wall clock time of every operation of a cell, by name in the order they first ran
+        let operations: Vec<Vec<(String, f64, usize)>> = ran
+            .iter()
+            .map(|result| {
+                let mut means: Vec<(String, f64, usize)> = Vec::new();
+                let report: &Value = result.report.as_ref().expect("ran cells have a report");
+                for operation in report["operations"].as_array().into_iter().flatten() {
+                    let (Some(name), Some(time)) = (
+                        operation["name"].as_str(),
+                        operation["wall_clock_time_ms"].as_f64(),
+                    ) else {
+                        continue;
+                    };
+                    match means.iter_mut().find(|(known, _, _)| known == name) {
+                        Some((_, sum, runs)) => {
+                            *sum += time;
+                            *runs += 1;
+                        }
+                        None => means.push((name.to_string(), time, 1)),
+                    }
+                }
+                means
+            })
+            .collect();
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _, _) in operations.iter().flatten() {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+
+        let mut header: Vec<String> = vec!["Operation (mean ms)".to_string()];
+        header.extend(ran.iter().map(|result| cell_label(&result.cell)));
+        let mut per_cell: MarkdownTable = MarkdownTable::new(&header);
+        for name in names {
+            let mut row: Vec<String> = vec![name.to_string()];
+            row.extend(operations.iter().map(|means| {
+                means
+                    .iter()
+                    .find(|(known, _, _)| known == name)
+                    .map_or_else(
+                        || "-".to_string(),
+                        |(_, sum, runs)| millis(sum / *runs as f64),
+                    )
+            }));
+            per_cell.row(row);
+        }
+
+        let mut markdown: String = format!("### Benchmark matrix\n\n{}", summary);
+        if !per_cell.is_empty() {
+            markdown.push('\n');
+            markdown.push_str(&per_cell.to_string());
+        }
+        markdown
+    }
+}
+
+impl Markdown for EvaluationReport {
+    fn markdown(&self) -> String {
+        let mut table: MarkdownTable = MarkdownTable::new(&[
+            "Model",
+            "Samples",
+            "Top-1 (%)",
+            "Top-5 (%)",
+            "Images/s",
+            "Pre-processing (ms)",
+            "Inference (ms)",
+        ]);
+        table.row(vec![
+            self.model.clone(),
+            self.samples.to_string(),
+            format!("{:.2}", self.top1_accuracy * 100.),
+            format!("{:.2}", self.top5_accuracy * 100.),
+            format!("{:.2}", self.throughput),
+            millis(self.mean_preprocess_ms),
+            millis(self.mean_inference_ms),
+        ]);
+        format!(
+            "### Evaluation\n\n{}\n\n{}",
+            run_line(&self.metadata),
+            table
+        )
+    }
+}
+
+impl Markdown for ModelInfo {
+    fn markdown(&self) -> String {
+        let mut table: MarkdownTable = MarkdownTable::new(&["Name", "Direction", "Type"]);
+        let mut value_row = |direction: &str, value: &ValueInfo| {
+            table.row(vec![
+                value.name.clone(),
+                direction.to_string(),
+                format!("`{}`", value.description),
+            ]);
+        };
+        for input in &self.inputs {
+            value_row("input", input);
+        }
+        for output in &self.outputs {
+            value_row("output", output);
+        }
+        format!("### {}\n\n{}", escape(&self.path), table)
+    }
+}
+
+impl Markdown for CalibrationReport {
+    fn markdown(&self) -> String {
+        let micros = |nanos: f64| format!("{:.3}", nanos / 1000.);
+        let mut calls: MarkdownTable =
+            MarkdownTable::new(&["Measurement", "Calls", "Mean (µs)", "p50 (µs)", "p99 (µs)"]);
+        for stat in self
+            .calls
+            .iter()
+            .chain(std::iter::once(&self.operation_floor))
+        {
+            calls.row(vec![
+                stat.name.clone(),
+                stat.calls.to_string(),
+                micros(stat.mean_ns),
+                micros(stat.p50_ns),
+                micros(stat.p99_ns),
+            ]);
+        }
+        let mut samplers: MarkdownTable = MarkdownTable::new(&[
+            "Sampler",
+            "Probe (µs)",
+            "Probe sampled (µs)",
+            "Overhead (%)",
+        ]);
+        for sampler in &self.samplers {
+            samplers.row(match &sampler.error {
+                Some(error) => vec![sampler.name.clone(), format!("not measured: {}", error)],
+                None => vec![
+                    sampler.name.clone(),
+                    micros(sampler.baseline_mean_ns),
+                    micros(sampler.sampled_mean_ns),
+                    format!("{:+.2}", sampler.overhead_percent),
+                ],
+            });
+        }
+        format!("### Calibration\n\n{}\n{}", calls, samplers)
+    }
+}
+
+impl Markdown for DryRunReport {
+    fn markdown(&self) -> String {
+        let mut providers: MarkdownTable = MarkdownTable::new(&["Provider", "Status", "Detail"]);
+        match &self.providers {
+            None => providers.row(vec!["CPU".to_string(), "active".to_string(), String::new()]),
+            Some(check) => providers.row(vec![
+                check.provider.clone(),
+                check.status.to_string(),
+                check.detail.clone().unwrap_or_default(),
+            ]),
+        }
+        let mut inputs: MarkdownTable =
+            MarkdownTable::new(&["Input", "Expected", "Preprocessed", "Compatible"]);
+        for input in &self.inputs {
+            inputs.row(vec![
+                input.name.clone(),
+                format!("`{}`", input.expected),
+                input
+                    .actual
+                    .as_ref()
+                    .map_or_else(|| "-".to_string(), |actual| format!("`{}`", actual)),
+                if input.compatible { "yes" } else { "no" }.to_string(),
+            ]);
+        }
+        let plan: String = self
+            .plan
+            .iter()
+            .map(|step| format!("- {}\n", step))
+            .collect();
+        let mut markdown: String = format!(
+            "### Dry run: {}\n\n**{}**\n\n{}\n",
+            escape(&self.model),
+            if self.passed {
+                "Ready to run"
+            } else {
+                "Would not measure what was asked"
+            },
+            providers
+        );
+        if let Some(error) = &self.preprocess_error {
+            markdown.push_str(&format!("Preprocessing failed: {}\n\n", escape(error)));
+        }
+        if !inputs.is_empty() {
+            markdown.push_str(&format!("{}\n", inputs));
+        }
+        markdown.push_str(&format!("Would measure:\n\n{}", plan));
+        markdown
+    }
+}
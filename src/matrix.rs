@@ -0,0 +1,312 @@
+use crate::{error::AppError, metrics::format_bytes};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+
+/// Execution provider a matrix cell runs the model with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Cpu,
+    Cuda,
+    Tensorrt,
+}
+
+impl Provider {
+    /// Options of the main command selecting the provider.
+    pub fn args(self) -> &'static [&'static str] {
+        match self {
+            Provider::Cpu => &["--cpu"],
+            Provider::Cuda => &[],
+            Provider::Tensorrt => &["--tensorrt"],
+        }
+    }
+}
+
+impl std::fmt::Display for Provider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Provider::Cpu => "cpu",
+            Provider::Cuda => "cuda",
+            Provider::Tensorrt => "tensorrt",
+        })
+    }
+}
+
+fn default_providers() -> Vec<Provider> {
+    vec![Provider::Cuda]
+}
+
+fn default_iterations() -> u32 {
+    10
+}
+
+/// A JSON matrix file: `{"models": ["a.onnx", "b.onnx"], "providers": ["cpu", "cuda"], "threads": [1, 4],
+/// "batch_sizes": [1, 8], "image": "dog.jpg"}`. Every combination is run once.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MatrixDefinition {
+    pub models: Vec<String>,
+    #[serde(default = "default_providers")]
+    pub providers: Vec<Provider>,
+    /// ONNX Runtime intra-op threads, empty for its default.
+    #[serde(default)]
+    pub threads: Vec<usize>,
+    /// Batch sizes the image is repeated to, empty for the model's own.
+    #[serde(default)]
+    pub batch_sizes: Vec<usize>,
+    pub image: String,
+    #[serde(default = "default_iterations")]
+    pub iterations: u32,
+    /// Options of the main command added to every run, e.g. `["--task", "detection"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl MatrixDefinition {
+    pub fn load(path: &str) -> Result<Self, AppError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| AppError::InputError(format!("failed to read {}: {}", path, e)))?;
+        let definition: MatrixDefinition = serde_json::from_str(&text)?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        if self.models.is_empty() {
+            return Err(AppError::InputError("matrix has no models".to_string()));
+        }
+        if self.providers.is_empty() {
+            return Err(AppError::InputError("matrix has no providers".to_string()));
+        }
+        if self.threads.contains(&0) {
+            return Err(AppError::InputError(
+                "matrix thread counts must be positive".to_string(),
+            ));
+        }
+        if self.batch_sizes.contains(&0) {
+            return Err(AppError::InputError(
+                "matrix batch sizes must be positive".to_string(),
+            ));
+        }
+        if self.iterations == 0 {
+            return Err(AppError::InputError(
+                "matrix iterations must be positive".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The cross-product of models, providers, thread counts and batch sizes, in that order.
+    pub fn cells(&self) -> Vec<MatrixCell> {
+        let optional = |values: &[usize]| -> Vec<Option<usize>> {
+            if values.is_empty() {
+                vec![None]
+            } else {
+                values.iter().copied().map(Some).collect()
+            }
+        };
+        let (threads, batch_sizes) = (optional(&self.threads), optional(&self.batch_sizes));
+
+        let mut cells: Vec<MatrixCell> = Vec::new();
+        for model in &self.models {
+            for &provider in &self.providers {
+                for &threads in &threads {
+                    for &batch_size in &batch_sizes {
+                        cells.push(MatrixCell {
+                            model: model.clone(),
+                            provider,
+                            threads,
+                            batch_size,
+                        });
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+/// One configuration of the matrix.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixCell {
+    pub model: String,
+    pub provider: Provider,
+    pub threads: Option<usize>,
+    pub batch_size: Option<usize>,
+}
+
+impl MatrixCell {
+    /// Command line of the run measuring this cell, without the program name.
+    pub fn args(&self, definition: &MatrixDefinition) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            self.model.clone(),
+            definition.image.clone(),
+            "--iterations".to_string(),
+            definition.iterations.to_string(),
+        ];
+        if let Some(threads) = self.threads {
+            args.extend(["--intra-threads".to_string(), threads.to_string()]);
+        }
+        if let Some(batch_size) = self.batch_size {
+            args.extend(["--preprocess".to_string(), format!("batch={}", batch_size)]);
+        }
+        args.extend(self.provider.args().iter().map(|arg| arg.to_string()));
+        args.extend(definition.args.iter().cloned());
+        args
+    }
+}
+
+/// What one cell measured, read from the JSON report of its run.
+#[derive(Debug, Clone, Serialize)]
+pub struct CellResult {
+    #[serde(flatten)]
+    pub cell: MatrixCell,
+    pub load_ms: Option<f64>,
+    pub mean_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    /// Images per second, the batch size over the mean inference latency.
+    pub throughput: Option<f64>,
+    /// Peak RSS of the run's process, in bytes.
+    pub peak_rss: Option<u64>,
+    /// Why the run failed, `None` when it succeeded.
+    pub error: Option<String>,
+    pub report: Option<Value>,
+}
+
+impl CellResult {
+    pub fn from_report(cell: MatrixCell, report: Value) -> Self {
+        let latency = |key: &str| report["latency"][key].as_f64();
+        // An optimized or TensorRT cache names the operation after the load it did
+        let loads: Vec<f64> = report["operations"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter(|operation| {
+                operation["name"]
+                    .as_str()
+                    .is_some_and(|name| name.starts_with("loadmodel"))
+            })
+            .filter_map(|operation| operation["wall_clock_time_ms"].as_f64())
+            .collect();
+        let load_ms: Option<f64> = (!loads.is_empty()).then(|| loads.iter().sum());
+        let mean_ms: Option<f64> = latency("mean_ms");
+
+        Self {
+            load_ms,
+            mean_ms,
+            p50_ms: latency("p50_ms"),
+            p95_ms: latency("p95_ms"),
+            p99_ms: latency("p99_ms"),
+            throughput: mean_ms
+                .filter(|&mean| mean > 0.)
+                .map(|mean| cell.batch_size.unwrap_or(1) as f64 * 1000. / mean),
+            peak_rss: report["total"]["peak_rss"].as_u64(),
+            error: None,
+            report: Some(report),
+            cell,
+        }
+    }
+
+    pub fn failed(cell: MatrixCell, error: String) -> Self {
+        Self {
+            cell,
+            load_ms: None,
+            mean_ms: None,
+            p50_ms: None,
+            p95_ms: None,
+            p99_ms: None,
+            throughput: None,
+            peak_rss: None,
+            error: Some(error),
+            report: None,
+        }
+    }
+}
+
+/// Results of every cell of a matrix, in the order they ran.
+#[derive(Debug, Clone, Serialize)]
+pub struct MatrixReport {
+    /// Whether every cell ran in a child process of its own.
+    pub isolated: bool,
+    pub cells: Vec<CellResult>,
+}
+
+impl std::fmt::Display for MatrixReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let optional =
+            |value: Option<usize>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+        let millis =
+            |value: Option<f64>| value.map_or_else(|| "-".to_string(), |v| format!("{:.3}", v));
+        let model_width: usize = self
+            .cells
+            .iter()
+            .map(|result| result.cell.model.len())
+            .chain(std::iter::once("Model".len()))
+            .max()
+            .unwrap_or_default();
+
+        writeln!(f, "============= Matrix Metrics =============")?;
+        writeln!(
+            f,
+            "{:<model_width$}  {:<8}  {:>7}  {:>5}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+            "Model",
+            "Provider",
+            "Threads",
+            "Batch",
+            "Load (ms)",
+            "Mean (ms)",
+            "p50 (ms)",
+            "p95 (ms)",
+            "p99 (ms)",
+            "Images/s",
+            "Peak RSS",
+        )?;
+        for result in &self.cells {
+            let cell: &MatrixCell = &result.cell;
+            write!(
+                f,
+                "{:<model_width$}  {:<8}  {:>7}  {:>5}  ",
+                cell.model,
+                cell.provider.to_string(),
+                optional(cell.threads),
+                optional(cell.batch_size),
+            )?;
+            match &result.error {
+                Some(error) => writeln!(f, "failed: {}", error)?,
+                None => writeln!(
+                    f,
+                    "{:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}  {:>10}",
+                    millis(result.load_ms),
+                    millis(result.mean_ms),
+                    millis(result.p50_ms),
+                    millis(result.p95_ms),
+                    millis(result.p99_ms),
+                    result
+                        .throughput
+                        .map_or_else(|| "-".to_string(), |v| format!("{:.1}", v)),
+                    result
+                        .peak_rss
+                        .map_or_else(|| "-".to_string(), format_bytes),
+                )?,
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} configurations ran{}",
+            self.cells
+                .iter()
+                .filter(|result| result.error.is_none())
+                .count(),
+            self.cells.len(),
+            if self.isolated {
+                ", each in its own process"
+            } else {
+                ""
+            }
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
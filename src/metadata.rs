@@ -0,0 +1,100 @@
+use crate::error::AppError;
+use serde::Serialize;
+use std::{
+    collections::{hash_map::RandomState, BTreeMap},
+    hash::{BuildHasher, Hasher},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A `--tag KEY=VALUE` argument, e.g. `device=jetson-04` or `firmware=1.2.3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Tag {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", s))?;
+        Ok(Self {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+/// Identifies a run in every output it is exported to, so results can be joined with deployment
+/// metadata downstream.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMetadata {
+    /// Random UUID of the run.
+    pub run_id: String,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl RunMetadata {
+    /// A new run id with the `--tag`s of the run. A key may only be tagged once.
+    pub fn new(tags: &[Tag]) -> Result<Self, AppError> {
+        let mut tagged: BTreeMap<String, String> = BTreeMap::new();
+        for tag in tags {
+            if tagged.insert(tag.key.clone(), tag.value.clone()).is_some() {
+                return Err(AppError::InputError(format!(
+                    "tag '{}' was given more than once",
+                    tag.key
+                )));
+            }
+        }
+        Ok(Self {
+            run_id: new_run_id(),
+            tags: tagged,
+        })
+    }
+}
+
+impl std::fmt::Display for RunMetadata {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Run: {}", self.run_id)?;
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self
+                .tags
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            write!(f, " ({})", tags.join(", "))?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Random version 4 UUID, from the randomly keyed std hasher like the OTLP trace ids.
+pub fn new_run_id() -> String {
+    let random = || {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos())
+                .unwrap_or_default(),
+        );
+        hasher.finish()
+    };
+    let (high, low): (u64, u64) = (random(), random());
+    // Version 4 in the 13th hex digit and the RFC 4122 variant in the 17th
+    let high: u64 = (high & !0xf000) | 0x4000;
+    let low: u64 = (low & !(0b11 << 62)) | (0b10 << 62);
+
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
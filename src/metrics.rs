@@ -0,0 +1,313 @@
+use crate::allocator::{self, AllocationStats};
+#[cfg(not(target_family = "wasm"))]
+use libc::{getrusage, rusage, RUSAGE_SELF};
+use serde::Serialize;
+use std::time::{Duration, Instant};
+#[cfg(target_os = "linux")]
+use std::{fs::File, os::unix::fs::FileExt, sync::OnceLock};
+
+#[cfg(target_family = "wasm")]
+const WASM_PAGE_SIZE: usize = 65536;
+/// Bytes per unit of `ru_maxrss`, which Linux reports in kilobytes and macOS in bytes.
+#[cfg(all(not(target_family = "wasm"), target_os = "macos"))]
+const MAXRSS_UNIT: u64 = 1;
+#[cfg(all(not(target_family = "wasm"), not(target_os = "macos")))]
+const MAXRSS_UNIT: u64 = 1024;
+
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub name: String,
+    pub timestamp: Instant,
+    pub wall_clock_time: Duration,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Resident set size at the start of the span, in bytes. A snapshot has the same before and after.
+    pub rss_before: u64,
+    /// Resident set size at the end of the span, in bytes.
+    pub rss_after: u64,
+    /// Highest resident set size of the process so far, in bytes, rather than a difference.
+    pub peak_rss: u64,
+    /// How much the span raised the peak RSS of the process, zero when it stayed below an earlier peak.
+    pub peak_rss_growth: u64,
+    pub cpu_usage: f32,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+    pub major_faults: u64,
+    /// Heap allocations and the bytes they requested, zero unless the allocations are counted.
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    /// Highest heap usage of the whole process since it started, at the end of the span. It is never
+    /// reset, so a span below an earlier peak reports that peak rather than its own.
+    pub process_heap_peak: u64,
+}
+
+impl Metrics {
+    #[cfg(not(target_family = "wasm"))]
+    pub fn current(name: String) -> Self {
+        unsafe {
+            let mut usage: rusage = std::mem::zeroed();
+            getrusage(RUSAGE_SELF, &mut usage);
+
+            let user_time: Duration = Duration::from_secs(usage.ru_utime.tv_sec as u64)
+                + Duration::from_micros(usage.ru_utime.tv_usec as u64);
+
+            let system_time: Duration = Duration::from_secs(usage.ru_stime.tv_sec as u64)
+                + Duration::from_micros(usage.ru_stime.tv_usec as u64);
+
+            let cpu_usage: f32 = 0.0;
+            let heap: AllocationStats = allocator::stats();
+            let rss: Option<u64> = current_rss();
+            // The kernel updates the high-water mark lazily, it may trail the RSS just read
+            let peak_rss: u64 = (usage.ru_maxrss as u64 * MAXRSS_UNIT).max(rss.unwrap_or_default());
+            let rss: u64 = rss.unwrap_or(peak_rss);
+            Self {
+                name,
+                timestamp: Instant::now(),
+                wall_clock_time: Duration::default(),
+                user_time,
+                system_time,
+                rss_before: rss,
+                rss_after: rss,
+                peak_rss,
+                peak_rss_growth: 0,
+                cpu_usage,
+                voluntary_switches: usage.ru_nvcsw as u64,
+                involuntary_switches: usage.ru_nivcsw as u64,
+                major_faults: usage.ru_majflt as u64,
+                allocations: heap.allocations,
+                allocated_bytes: heap.allocated_bytes,
+                process_heap_peak: heap.peak,
+            }
+        }
+    }
+
+    /// WASI has no resource usage of the process: CPU times and context switches stay at zero, and
+    /// the size of the linear memory stands in for the RSS as it only ever grows.
+    #[cfg(target_family = "wasm")]
+    pub fn current(name: String) -> Self {
+        let heap: AllocationStats = allocator::stats();
+        let memory: u64 = (core::arch::wasm32::memory_size(0) * WASM_PAGE_SIZE) as u64;
+        Self {
+            name,
+            timestamp: Instant::now(),
+            wall_clock_time: Duration::default(),
+            user_time: Duration::default(),
+            system_time: Duration::default(),
+            rss_before: memory,
+            rss_after: memory,
+            peak_rss: memory,
+            peak_rss_growth: 0,
+            cpu_usage: 0.0,
+            voluntary_switches: 0,
+            involuntary_switches: 0,
+            major_faults: 0,
+            allocations: heap.allocations,
+            allocated_bytes: heap.allocated_bytes,
+            process_heap_peak: heap.peak,
+        }
+    }
+
+    /// Change from the snapshot `prev` to this one. Counters saturate at zero rather than underflow,
+    /// and the RSS is kept as the absolute values at both ends.
+    pub fn diff(&self, prev: &Self) -> Self {
+        let wall_clock_time: Duration = self.timestamp.saturating_duration_since(prev.timestamp);
+        let user_time: Duration = self.user_time.saturating_sub(prev.user_time);
+        let system_time: Duration = self.system_time.saturating_sub(prev.system_time);
+
+        let cpu_usage: f32 = if wall_clock_time.as_secs_f32() > 0.0 {
+            let cpu_time: f32 = (user_time + system_time).as_secs_f32();
+            (cpu_time / wall_clock_time.as_secs_f32()) * 100.0
+        } else {
+            0.0
+        };
+
+        Self {
+            name: self.name.clone(),
+            timestamp: self.timestamp,
+            wall_clock_time,
+            user_time,
+            system_time,
+            rss_before: prev.rss_after,
+            rss_after: self.rss_after,
+            peak_rss: self.peak_rss,
+            peak_rss_growth: self.peak_rss.saturating_sub(prev.peak_rss),
+            cpu_usage,
+            voluntary_switches: self
+                .voluntary_switches
+                .saturating_sub(prev.voluntary_switches),
+            involuntary_switches: self
+                .involuntary_switches
+                .saturating_sub(prev.involuntary_switches),
+            major_faults: self.major_faults.saturating_sub(prev.major_faults),
+            allocations: self.allocations.saturating_sub(prev.allocations),
+            allocated_bytes: self.allocated_bytes.saturating_sub(prev.allocated_bytes),
+            process_heap_peak: self.process_heap_peak,
+        }
+    }
+
+    /// Adds `other`, which ran after this span, to it: the RSS goes from this start to its end.
+    pub fn combine(&self, other: &Self) -> Self {
+        let combined_wall_clock = self.wall_clock_time + other.wall_clock_time;
+        let combined_user_time = self.user_time + other.user_time;
+        let combined_system_time = self.system_time + other.system_time;
+
+        let cpu_usage = if combined_wall_clock.as_secs_f32() > 0.0 {
+            let cpu_time = (combined_user_time + combined_system_time).as_secs_f32();
+            (cpu_time / combined_wall_clock.as_secs_f32()) * 100.0
+        } else {
+            0.0
+        };
+
+        Self {
+            name: self.name.clone(),
+            timestamp: self.timestamp,
+            wall_clock_time: combined_wall_clock,
+            user_time: combined_user_time,
+            system_time: combined_system_time,
+            rss_before: self.rss_before,
+            rss_after: other.rss_after,
+            peak_rss: self.peak_rss.max(other.peak_rss),
+            peak_rss_growth: self.peak_rss_growth + other.peak_rss_growth,
+            cpu_usage,
+            voluntary_switches: self.voluntary_switches + other.voluntary_switches,
+            involuntary_switches: self.involuntary_switches + other.involuntary_switches,
+            major_faults: self.major_faults + other.major_faults,
+            allocations: self.allocations + other.allocations,
+            allocated_bytes: self.allocated_bytes + other.allocated_bytes,
+            process_heap_peak: self.process_heap_peak.max(other.process_heap_peak),
+        }
+    }
+
+    /// Change of the RSS over the span, negative when memory was given back.
+    pub fn rss_delta(&self) -> i64 {
+        self.rss_after as i64 - self.rss_before as i64
+    }
+}
+
+/// `/proc/self/statm` and the page size, opened and queried once. Every snapshot reads the RSS, so
+/// opening the file each time would add an open and a close to every span it measures.
+#[cfg(target_os = "linux")]
+static STATM: OnceLock<Option<(File, u64)>> = OnceLock::new();
+
+/// Current resident set size from `/proc/self/statm`, which counts it in pages. The file is read
+/// again from the start with `pread`, which makes the kernel regenerate it, into a buffer on the stack.
+#[cfg(target_os = "linux")]
+pub fn current_rss() -> Option<u64> {
+    let (statm, page_size) = STATM
+        .get_or_init(|| {
+            let page_size: libc::c_long = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+            let statm: File = File::open("/proc/self/statm").ok()?;
+            Some((statm, page_size.max(0) as u64))
+        })
+        .as_ref()?;
+    // Seven counters of at most 20 digits each
+    let mut buffer: [u8; 160] = [0; 160];
+    let length: usize = statm.read_at(&mut buffer, 0).ok()?;
+    let pages: u64 = std::str::from_utf8(&buffer[..length])
+        .ok()?
+        .split_ascii_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()?;
+    Some(pages * page_size)
+}
+
+/// Only the peak is known without `/proc`.
+#[cfg(all(not(target_family = "wasm"), not(target_os = "linux")))]
+pub fn current_rss() -> Option<u64> {
+    None
+}
+
+/// Bytes in binary units, e.g. `512 B` or `23.41 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value: f64 = bytes as f64;
+    let mut unit: usize = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+/// A signed difference of bytes in binary units, e.g. `+1.50 MiB` or `-512 B`.
+pub fn format_bytes_delta(delta: i64) -> String {
+    let sign: char = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, format_bytes(delta.unsigned_abs()))
+}
+
+impl std::fmt::Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= {} Metrics =============", self.name)?;
+        writeln!(f, "Wall Clock Time: {:?}", self.wall_clock_time)?;
+        writeln!(f, "User time: {:?}", self.user_time)?;
+        writeln!(f, "System time: {:?}", self.system_time)?;
+        writeln!(
+            f,
+            "RSS: {} -> {} ({})",
+            format_bytes(self.rss_before),
+            format_bytes(self.rss_after),
+            format_bytes_delta(self.rss_delta())
+        )?;
+        writeln!(
+            f,
+            "Peak RSS: {} (+{} during the span)",
+            format_bytes(self.peak_rss),
+            format_bytes(self.peak_rss_growth)
+        )?;
+        writeln!(f, "CPU Usage: {}%", self.cpu_usage)?;
+        if allocator::counting() {
+            writeln!(
+                f,
+                "Allocations: {} ({}), Process Heap Peak: {}",
+                self.allocations,
+                format_bytes(self.allocated_bytes),
+                format_bytes(self.process_heap_peak)
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
+
+/// Metrics of one operation, phase or the whole run, in units that survive JSON.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSummary {
+    pub name: String,
+    pub wall_clock_time_ms: f64,
+    pub user_time_ms: f64,
+    pub system_time_ms: f64,
+    /// Resident set sizes in bytes, see [`Metrics`].
+    pub rss_before: u64,
+    pub rss_after: u64,
+    pub rss_delta: i64,
+    pub peak_rss: u64,
+    pub peak_rss_growth: u64,
+    pub cpu_usage: f32,
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    pub process_heap_peak: u64,
+}
+
+impl From<&Metrics> for MetricsSummary {
+    fn from(metrics: &Metrics) -> Self {
+        Self {
+            name: metrics.name.clone(),
+            wall_clock_time_ms: metrics.wall_clock_time.as_secs_f64() * 1000.,
+            user_time_ms: metrics.user_time.as_secs_f64() * 1000.,
+            system_time_ms: metrics.system_time.as_secs_f64() * 1000.,
+            rss_before: metrics.rss_before,
+            rss_after: metrics.rss_after,
+            rss_delta: metrics.rss_delta(),
+            peak_rss: metrics.peak_rss,
+            peak_rss_growth: metrics.peak_rss_growth,
+            cpu_usage: metrics.cpu_usage,
+            allocations: metrics.allocations,
+            allocated_bytes: metrics.allocated_bytes,
+            process_heap_peak: metrics.process_heap_peak,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use crate::error::AppError;
+use memmap2::Mmap;
+use ort::{
+    execution_providers::ExecutionProviderDispatch,
+    session::{
+        builder::{GraphOptimizationLevel, SessionBuilder},
+        Session,
+    },
+    Error as OrtError,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Event ONNX Runtime's profiler records for parsing a model passed in memory.
+const GRAPH_PARSE_EVENT: &str = "model_loading_array";
+const SESSION_INIT_EVENT: &str = "session_initialization";
+
+/// Session settings that can be changed from the command line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelOptions {
+    pub intra_threads: Option<usize>,
+    /// ONNX Runtime `session.intra_op_thread_affinities` value pinning each intra-op worker thread.
+    pub intra_thread_affinities: Option<String>,
+}
+
+pub fn load_model(model_path: &str) -> Result<Session, OrtError> {
+    load_model_with_options(model_path, &ModelOptions::default())
+}
+
+pub fn load_model_with_options(
+    model_path: impl AsRef<Path>,
+    options: &ModelOptions,
+) -> Result<Session, OrtError> {
+    let model: Session = session_builder(options)?.commit_from_file(model_path.as_ref())?;
+    Ok(model)
+}
+
+/// Builds the session from a model already read into memory, so reading the file can be timed apart.
+/// External data files are looked up next to `model_path`.
+pub fn load_model_from_memory(
+    model_path: &Path,
+    model_bytes: &[u8],
+    options: &ModelOptions,
+) -> Result<Session, OrtError> {
+    let model: Session =
+        memory_builder(model_path, session_builder(options)?)?.commit_from_memory(model_bytes)?;
+    Ok(model)
+}
+
+/// Builds the session on `providers` alone, in that order, failing when one cannot be registered instead
+/// of falling back to the CPU. Without `allow_cpu_fallback`, nodes none of them supports fail the
+/// session too.
+pub fn load_model_on(
+    model_path: &Path,
+    options: &ModelOptions,
+    providers: Vec<ExecutionProviderDispatch>,
+    allow_cpu_fallback: bool,
+) -> Result<Session, OrtError> {
+    let providers: Vec<ExecutionProviderDispatch> = providers
+        .into_iter()
+        .map(ExecutionProviderDispatch::error_on_failure)
+        .collect();
+    let mut builder: SessionBuilder = session_builder(options)?
+        .with_no_environment_execution_providers()?
+        .with_execution_providers(providers)?;
+    if !allow_cpu_fallback {
+        builder = builder.with_config_entry("session.disable_cpu_ep_fallback", "1")?;
+    }
+    let model: Session = builder.commit_from_file(model_path)?;
+    Ok(model)
+}
+
+/// A model file in memory: read into a buffer, or mapped so its pages are only read from disk as
+/// ONNX Runtime parses them, without a copy of the whole file on the heap.
+pub enum ModelBytes {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl ModelBytes {
+    pub fn read(path: &Path, mmap: bool) -> Result<Self, AppError> {
+        let read_error = |e: std::io::Error| {
+            AppError::Other(format!("Failed to read {}: {}", path.display(), e))
+        };
+        if !mmap {
+            return fs::read(path).map(ModelBytes::Read).map_err(read_error);
+        }
+        let file: File = File::open(path).map_err(read_error)?;
+        // The model must not be truncated while it is mapped, which only matters until the session is
+        // built: ONNX Runtime copies what it keeps of the graph and weights
+        let map: Mmap = unsafe { Mmap::map(&file) }.map_err(read_error)?;
+        Ok(ModelBytes::Mapped(map))
+    }
+}
+
+impl Deref for ModelBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ModelBytes::Read(bytes) => bytes,
+            ModelBytes::Mapped(map) => map,
+        }
+    }
+}
+
+/// How long ONNX Runtime took to parse the graph and to initialize the session, together the time of
+/// committing a session from memory.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadBreakdown {
+    pub graph_parse: Duration,
+    pub session_init: Duration,
+}
+
+/// Builds the session like [`load_model_from_memory`], with ONNX Runtime's profiler running until the
+/// session is committed to tell parsing the graph from initializing the session. The breakdown is
+/// `None` if the profile could not be read.
+pub fn load_model_profiled(
+    model_path: &Path,
+    model_bytes: &[u8],
+    options: &ModelOptions,
+) -> Result<(Session, Option<LoadBreakdown>), OrtError> {
+    let prefix: PathBuf =
+        std::env::temp_dir().join(format!("rust-ml-benchmark-load-{}", std::process::id()));
+    let builder: SessionBuilder = session_builder(options)?.with_profiling(&prefix)?;
+    let mut model: Session =
+        memory_builder(model_path, builder)?.commit_from_memory(model_bytes)?;
+
+    let profile: String = model.end_profiling()?;
+    let breakdown: Option<LoadBreakdown> = read_load_profile(Path::new(&profile));
+    if breakdown.is_none() {
+        tracing::warn!(profile, "failed to read the model load profile");
+    }
+    let _ = fs::remove_file(&profile);
+    Ok((model, breakdown))
+}
+
+/// Durations of the load events in an ONNX Runtime profile, a JSON array of trace events with their
+/// `dur` in microseconds.
+fn read_load_profile(profile: &Path) -> Option<LoadBreakdown> {
+    let events: Vec<Value> = serde_json::from_slice(&fs::read(profile).ok()?).ok()?;
+    let duration = |name: &str| {
+        events
+            .iter()
+            .find(|event| event["name"].as_str() == Some(name))
+            .and_then(|event| event["dur"].as_u64())
+            .map(Duration::from_micros)
+    };
+    Some(LoadBreakdown {
+        graph_parse: duration(GRAPH_PARSE_EVENT)?,
+        session_init: duration(SESSION_INIT_EVENT)?,
+    })
+}
+
+/// A model in memory has no path its external data files could be found relative to.
+fn memory_builder(model_path: &Path, builder: SessionBuilder) -> Result<SessionBuilder, OrtError> {
+    match model_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+    {
+        Some(dir) => builder.with_config_entry(
+            "session.model_external_initializers_file_folder_path",
+            dir.to_string_lossy(),
+        ),
+        None => Ok(builder),
+    }
+}
+
+fn session_builder(options: &ModelOptions) -> Result<SessionBuilder, OrtError> {
+    let mut builder: SessionBuilder = Session::builder()?.with_intra_op_spinning(false)?;
+    if let Some(threads) = options.intra_threads {
+        builder = builder.with_intra_threads(threads)?;
+    }
+    if let Some(affinities) = &options.intra_thread_affinities {
+        builder = builder.with_config_entry("session.intra_op_thread_affinities", affinities)?;
+    }
+    Ok(builder)
+}
+
+/// Directory of models as ONNX Runtime optimized them, so later runs can skip the graph optimizations.
+/// Optimized graphs may contain nodes specific to the execution provider and CPU, the cache is only
+/// valid on the machine that filled it.
+#[derive(Debug, Clone)]
+pub struct OptimizedModelCache {
+    dir: PathBuf,
+}
+
+impl OptimizedModelCache {
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Cache file of the model. The name holds the model's size and modification time and the ORT
+    /// version, so a changed model or runtime misses the cache instead of loading a stale graph.
+    pub fn entry(&self, model_path: &Path) -> Result<PathBuf, AppError> {
+        let metadata: fs::Metadata = fs::metadata(model_path).map_err(|e| {
+            AppError::Other(format!("Failed to read {}: {}", model_path.display(), e))
+        })?;
+        let modified: u64 = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+        let stem: String = model_path.file_stem().map_or_else(
+            || "model".to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+
+        Ok(self.dir.join(format!(
+            "{}-{:x}-{:x}-ort1.{}.onnx",
+            stem,
+            metadata.len(),
+            modified,
+            ort::MINOR_VERSION
+        )))
+    }
+
+    /// Cold start: loads the model with all optimizations and saves the optimized graph to `entry`.
+    pub fn optimize(
+        &self,
+        model_path: &Path,
+        entry: &Path,
+        options: &ModelOptions,
+    ) -> Result<Session, AppError> {
+        fs::create_dir_all(&self.dir).map_err(|e| {
+            AppError::Other(format!("Failed to create {}: {}", self.dir.display(), e))
+        })?;
+
+        // ORT writes the file while committing, a crash must not leave a truncated cache entry
+        let partial: PathBuf = entry.with_extension("partial");
+        let model: Session = session_builder(options)?
+            .with_optimized_model_path(&partial)?
+            .commit_from_file(model_path)?;
+        fs::rename(&partial, entry)
+            .map_err(|e| AppError::Other(format!("Failed to write {}: {}", entry.display(), e)))?;
+        Ok(model)
+    }
+}
+
+/// Warm start: loads a graph saved by [`OptimizedModelCache::optimize`] without optimizing it again.
+pub fn load_optimized_model(entry: &Path, options: &ModelOptions) -> Result<Session, OrtError> {
+    let model: Session = session_builder(options)?
+        .with_optimization_level(GraphOptimizationLevel::Disable)?
+        .commit_from_file(entry)?;
+    Ok(model)
+}
@@ -0,0 +1,189 @@
+use crate::{
+    error::AppError,
+    report::RunReport,
+    tracker::{BenchmarkTracker, SpanKind},
+};
+use rumqttc::{Client, Event, Incoming, MqttOptions, Outgoing, QoS, RecvTimeoutError};
+use serde::Serialize;
+use std::{
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const DEFAULT_PORT: u16 = 1883;
+/// Longest wait for the broker to acknowledge a message before giving up.
+const BROKER_TIMEOUT: Duration = Duration::from_secs(10);
+/// Reports of long runs with every span easily exceed the 10 KiB default of rumqttc.
+const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
+/// Messages queued between the publishing thread and the connection.
+const QUEUE_CAPACITY: usize = 64;
+
+/// The final report of a run with the metadata the edge orchestrator routes results by.
+#[derive(Debug, Serialize)]
+struct ResultMessage<'a> {
+    device_id: &'a str,
+    hostname: &'a str,
+    /// Milliseconds since the Unix epoch when the message was published.
+    timestamp_ms: u128,
+    report: &'a RunReport,
+}
+
+/// Latency of one inference run, published to `<topic>/samples`.
+#[derive(Debug, Serialize)]
+struct SampleMessage<'a> {
+    device_id: &'a str,
+    run_id: &'a str,
+    operation: &'a str,
+    /// Index of the run, from 0.
+    iteration: usize,
+    /// Start of the run since the start of the benchmark.
+    start_ms: f64,
+    latency_ms: f64,
+}
+
+/// Publishes run results to an MQTT broker with QoS 1, one connection per run.
+pub struct MqttPublisher {
+    host: String,
+    port: u16,
+    topic: String,
+    device_id: String,
+}
+
+impl MqttPublisher {
+    /// `broker` is `host`, `host:port` or `mqtt://host:port`, the port defaults to 1883.
+    pub fn new(broker: &str, topic: &str, device_id: &str) -> Result<Self, AppError> {
+        let address: &str = broker
+            .strip_prefix("mqtt://")
+            .or_else(|| broker.strip_prefix("tcp://"))
+            .unwrap_or(broker)
+            .trim_end_matches('/');
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>().map_err(|e| {
+                    AppError::ExportError(format!("invalid MQTT broker port '{}': {}", port, e))
+                })?,
+            ),
+            None => (address, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(AppError::ExportError(format!(
+                "invalid MQTT broker '{}', expected host[:port]",
+                broker
+            )));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            topic: topic.trim_end_matches('/').to_string(),
+            device_id: device_id.to_string(),
+        })
+    }
+
+    /// Publishes the report to the topic and, with `samples`, the latency of every inference run
+    /// to `<topic>/samples`. Returns once the broker acknowledged every message.
+    pub fn publish(
+        &self,
+        report: &RunReport,
+        tracker: &BenchmarkTracker,
+        samples: bool,
+    ) -> Result<usize, AppError> {
+        let mut messages: Vec<(String, Vec<u8>)> = vec![(
+            self.topic.clone(),
+            serde_json::to_vec(&ResultMessage {
+                device_id: &self.device_id,
+                hostname: &report.environment.hostname,
+                timestamp_ms: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|since| since.as_millis())
+                    .unwrap_or_default(),
+                report,
+            })?,
+        )];
+        if samples {
+            let samples_topic: String = format!("{}/samples", self.topic);
+            for (iteration, span) in tracker
+                .spans()
+                .iter()
+                .filter(|span| span.kind == SpanKind::Operation && span.name == "Inference")
+                .enumerate()
+            {
+                let sample: SampleMessage<'_> = SampleMessage {
+                    device_id: &self.device_id,
+                    run_id: &report.metadata.run_id,
+                    operation: &span.name,
+                    iteration,
+                    start_ms: span
+                        .start
+                        .duration_since(tracker.start_time())
+                        .as_secs_f64()
+                        * 1000.,
+                    latency_ms: span.metrics.wall_clock_time.as_secs_f64() * 1000.,
+                };
+                messages.push((samples_topic.clone(), serde_json::to_vec(&sample)?));
+            }
+        }
+        self.send(messages)
+    }
+
+    fn send(&self, messages: Vec<(String, Vec<u8>)>) -> Result<usize, AppError> {
+        let broker_error =
+            |e: &dyn std::fmt::Display| AppError::ExportError(format!("{}: {}", self.broker(), e));
+
+        let client_id: String = format!(
+            "{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            self.device_id,
+            std::process::id()
+        );
+        let mut options: MqttOptions = MqttOptions::new(client_id, &self.host, self.port);
+        options.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+
+        // The connection only makes progress while it is polled, so the messages are queued from
+        // another thread in case there are more than the queue holds
+        let expected: usize = messages.len();
+        let publisher = thread::spawn(move || {
+            for (topic, payload) in messages {
+                client.publish(topic, QoS::AtLeastOnce, false, payload)?;
+            }
+            Ok::<Client, rumqttc::ClientError>(client)
+        });
+
+        let mut acknowledged: usize = 0;
+        while acknowledged < expected {
+            match connection.recv_timeout(BROKER_TIMEOUT) {
+                Ok(Ok(Event::Incoming(Incoming::PubAck(_)))) => acknowledged += 1,
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(broker_error(&e)),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(broker_error(&format!(
+                        "{} of {} messages acknowledged before the timeout",
+                        acknowledged, expected
+                    )))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(broker_error(&"connection closed"))
+                }
+            }
+        }
+
+        let client: Client = publisher
+            .join()
+            .map_err(|_| broker_error(&"publishing thread panicked"))?
+            .map_err(|e| broker_error(&e))?;
+        client.disconnect().map_err(|e| broker_error(&e))?;
+        // Waits for the disconnect to be written, the broker closes the connection afterwards
+        while let Ok(Ok(event)) = connection.recv_timeout(BROKER_TIMEOUT) {
+            if let Event::Outgoing(Outgoing::Disconnect) = event {
+                break;
+            }
+        }
+        Ok(acknowledged)
+    }
+
+    fn broker(&self) -> String {
+        format!("mqtt://{}:{}", self.host, self.port)
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{
+    environment::EnvironmentInfo,
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::Metrics,
+    tracker::{BenchmarkTracker, SpanKind, SpanRecord},
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+const SCOPE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// OTLP/HTTP exporter sending one trace per benchmark run: a root span for the whole run, a child
+/// span per phase and a grandchild span per operation, each carrying its resource metrics.
+pub struct OtlpExporter {
+    endpoint: String,
+    /// A wall clock reading and the matching `Instant`, to place tracker instants on the wall clock.
+    anchor: (SystemTime, Instant),
+}
+
+impl OtlpExporter {
+    /// `endpoint` is the collector base URL, e.g. `http://localhost:4318`, or the full traces URL.
+    pub fn new(endpoint: &str) -> Self {
+        let endpoint: &str = endpoint.trim_end_matches('/');
+        let endpoint: String = if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{}/v1/traces", endpoint)
+        };
+
+        Self {
+            endpoint,
+            anchor: (SystemTime::now(), Instant::now()),
+        }
+    }
+
+    pub fn export(
+        &self,
+        tracker: &BenchmarkTracker,
+        metadata: &RunMetadata,
+        environment: &EnvironmentInfo,
+        model_path: &str,
+    ) -> Result<(), AppError> {
+        let body: Value = self.trace_request(tracker, metadata, environment, model_path);
+
+        ureq::post(&self.endpoint)
+            .content_type("application/json")
+            .send(body.to_string())
+            .map_err(|e| AppError::ExportError(format!("{}: {}", self.endpoint, e)))?;
+        Ok(())
+    }
+
+    fn trace_request(
+        &self,
+        tracker: &BenchmarkTracker,
+        metadata: &RunMetadata,
+        environment: &EnvironmentInfo,
+        model_path: &str,
+    ) -> Value {
+        let trace_id: String = format!("{:016x}{:016x}", random_id(), random_id());
+        let root_id: String = format!("{:016x}", random_id());
+        let total: Metrics = tracker.get_total_metrics();
+
+        let mut spans: Vec<Value> = vec![self.span(
+            &trace_id,
+            &root_id,
+            None,
+            "benchmark",
+            tracker.start_time(),
+            total.timestamp,
+            &total,
+            "total",
+        )];
+
+        // Every span, and so every run of a repeated phase, gets its own id
+        let span_ids: HashMap<u64, String> = tracker
+            .spans()
+            .iter()
+            .map(|span| (span.id, format!("{:016x}", random_id())))
+            .collect();
+
+        for record in tracker.spans() {
+            let SpanRecord {
+                kind,
+                id,
+                name,
+                parent_id,
+                start,
+                end,
+                metrics,
+                ..
+            } = record;
+            let span_id: &str = &span_ids[id];
+            let parent_id: &str = parent_id
+                .and_then(|parent_id| span_ids.get(&parent_id))
+                .unwrap_or(&root_id);
+            let kind: &str = match kind {
+                SpanKind::Phase => "phase",
+                SpanKind::Operation => "operation",
+            };
+            spans.push(self.span(
+                &trace_id,
+                span_id,
+                Some(parent_id),
+                name,
+                *start,
+                *end,
+                metrics,
+                kind,
+            ));
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": { "attributes": resource_attributes(metadata, environment, model_path) },
+                "scopeSpans": [{
+                    "scope": { "name": SCOPE_NAME, "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn span(
+        &self,
+        trace_id: &str,
+        span_id: &str,
+        parent_id: Option<&str>,
+        name: &str,
+        start: Instant,
+        end: Instant,
+        metrics: &Metrics,
+        kind: &str,
+    ) -> Value {
+        let attributes: Vec<Value> = vec![
+            string_attribute("benchmark.kind", kind),
+            double_attribute(
+                "benchmark.wall_clock_time_ms",
+                metrics.wall_clock_time.as_secs_f64() * 1000.,
+            ),
+            double_attribute(
+                "benchmark.user_time_ms",
+                metrics.user_time.as_secs_f64() * 1000.,
+            ),
+            double_attribute(
+                "benchmark.system_time_ms",
+                metrics.system_time.as_secs_f64() * 1000.,
+            ),
+            int_attribute("benchmark.rss_before_bytes", metrics.rss_before),
+            int_attribute("benchmark.rss_after_bytes", metrics.rss_after),
+            int_attribute("benchmark.peak_rss_bytes", metrics.peak_rss),
+            int_attribute("benchmark.peak_rss_growth_bytes", metrics.peak_rss_growth),
+            double_attribute("benchmark.cpu_usage_percent", metrics.cpu_usage as f64),
+        ];
+
+        let mut span: Value = json!({
+            "traceId": trace_id,
+            "spanId": span_id,
+            "name": name,
+            // SPAN_KIND_INTERNAL
+            "kind": 1,
+            "startTimeUnixNano": self.unix_nanos(start).to_string(),
+            "endTimeUnixNano": self.unix_nanos(end).to_string(),
+            "attributes": attributes,
+        });
+        if let Some(parent_id) = parent_id {
+            span["parentSpanId"] = json!(parent_id);
+        }
+        span
+    }
+
+    fn unix_nanos(&self, instant: Instant) -> u128 {
+        let (system, anchor) = self.anchor;
+        let time: SystemTime = if instant >= anchor {
+            system + (instant - anchor)
+        } else {
+            system - (anchor - instant)
+        };
+        time.duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos())
+            .unwrap_or_default()
+    }
+}
+
+fn resource_attributes(
+    metadata: &RunMetadata,
+    environment: &EnvironmentInfo,
+    model_path: &str,
+) -> Vec<Value> {
+    let mut attributes: Vec<Value> = vec![
+        string_attribute("benchmark.run_id", &metadata.run_id),
+        string_attribute("service.name", SCOPE_NAME),
+        string_attribute("service.version", env!("CARGO_PKG_VERSION")),
+        string_attribute("host.name", &environment.hostname),
+        string_attribute("host.arch", &environment.architecture),
+        string_attribute("os.description", &environment.os),
+        string_attribute("os.kernel", &environment.kernel),
+        string_attribute("host.cpu.model.name", &environment.cpu_model),
+        int_attribute("host.cpu.logical_cores", environment.logical_cores as u64),
+        string_attribute("benchmark.model", model_path),
+        string_attribute("benchmark.ort_version", &environment.ort_api_version),
+        string_attribute("benchmark.git_commit", &environment.git_commit),
+        string_attribute("benchmark.build_profile", &environment.build_profile),
+    ];
+    if let Some(memory) = environment.total_memory {
+        attributes.push(int_attribute("host.memory.total", memory));
+    }
+    if let Some(gpu) = &environment.gpu {
+        attributes.push(string_attribute("host.gpu.name", gpu));
+    }
+    if let Some(affinity) = &environment.cpu_affinity {
+        attributes.push(string_attribute("benchmark.cpu_affinity", affinity));
+    }
+    for (key, value) in &metadata.tags {
+        attributes.push(string_attribute(&format!("benchmark.tag.{}", key), value));
+    }
+    attributes
+}
+
+fn string_attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attribute(key: &str, value: u64) -> Value {
+    // OTLP/JSON encodes 64 bit integers as strings
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+fn double_attribute(key: &str, value: f64) -> Value {
+    json!({ "key": key, "value": { "doubleValue": value } })
+}
+
+/// Random non-zero id from the randomly keyed std hasher, good enough for trace and span ids.
+fn random_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_nanos())
+            .unwrap_or_default(),
+    );
+    hasher.finish().max(1)
+}
@@ -0,0 +1,144 @@
+use crate::tracker::{BenchmarkTracker, SpanKind, SpanRecord};
+use serde::Serialize;
+
+/// Iterations with a modified z-score above this are outliers (Iglewicz and Hoaglin).
+const OUTLIER_THRESHOLD: f64 = 3.5;
+/// Share of the fastest and of the slowest iterations left out of the trimmed mean.
+const TRIM_FRACTION: f64 = 0.1;
+
+/// An iteration far from the median, with what the system did to the process meanwhile.
+#[derive(Debug, Clone, Serialize)]
+pub struct Outlier {
+    /// Index of the iteration, from 0.
+    pub iteration: usize,
+    pub latency_ms: f64,
+    pub modified_z_score: f64,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+    pub major_faults: u64,
+}
+
+/// Robust statistics of the latencies of one operation across iterations.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlierReport {
+    pub operation: String,
+    pub count: usize,
+    pub mean_ms: f64,
+    pub median_ms: f64,
+    /// Median absolute deviation from the median.
+    pub mad_ms: f64,
+    /// Mean without the fastest and the slowest 10% of the iterations.
+    pub trimmed_mean_ms: f64,
+    pub outliers: Vec<Outlier>,
+}
+
+impl OutlierReport {
+    /// Flags outliers among every `operation` recorded by the tracker, when it ran at least three
+    /// times.
+    pub fn from_tracker(tracker: &BenchmarkTracker, operation: &str) -> Option<Self> {
+        let spans: Vec<&SpanRecord> = tracker
+            .spans()
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.name == operation)
+            .collect();
+        if spans.len() < 3 {
+            return None;
+        }
+
+        let latencies: Vec<f64> = spans
+            .iter()
+            .map(|span| span.metrics.wall_clock_time.as_secs_f64() * 1000.)
+            .collect();
+        let mut sorted: Vec<f64> = latencies.clone();
+        sorted.sort_by(f64::total_cmp);
+
+        let median_ms: f64 = median(&sorted);
+        let mut deviations: Vec<f64> = sorted.iter().map(|x| (x - median_ms).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        let mad_ms: f64 = median(&deviations);
+        // The MAD is 0 when most iterations take exactly the median time, the scaled mean absolute
+        // deviation stands in for it then
+        let mean_deviation: f64 = deviations.iter().sum::<f64>() / deviations.len() as f64;
+        let score = |x: f64| {
+            if mad_ms > 0. {
+                0.6745 * (x - median_ms) / mad_ms
+            } else if mean_deviation > 0. {
+                (x - median_ms) / (1.253314 * mean_deviation)
+            } else {
+                0.
+            }
+        };
+
+        let trim: usize = (sorted.len() as f64 * TRIM_FRACTION) as usize;
+        let kept: &[f64] = &sorted[trim..sorted.len() - trim];
+
+        let outliers: Vec<Outlier> = spans
+            .iter()
+            .zip(&latencies)
+            .enumerate()
+            .filter(|(_, (_, &latency))| score(latency).abs() > OUTLIER_THRESHOLD)
+            .map(|(iteration, (span, &latency))| Outlier {
+                iteration,
+                latency_ms: latency,
+                modified_z_score: score(latency),
+                voluntary_switches: span.metrics.voluntary_switches,
+                involuntary_switches: span.metrics.involuntary_switches,
+                major_faults: span.metrics.major_faults,
+            })
+            .collect();
+
+        Some(Self {
+            operation: operation.to_string(),
+            count: latencies.len(),
+            mean_ms: latencies.iter().sum::<f64>() / latencies.len() as f64,
+            median_ms,
+            mad_ms,
+            trimmed_mean_ms: kept.iter().sum::<f64>() / kept.len() as f64,
+            outliers,
+        })
+    }
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let middle: usize = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.
+    } else {
+        sorted[middle]
+    }
+}
+
+impl std::fmt::Display for OutlierReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= {} Outliers =============", self.operation)?;
+        writeln!(
+            f,
+            "Mean: {:.3} ms, Trimmed mean (10%): {:.3} ms",
+            self.mean_ms, self.trimmed_mean_ms
+        )?;
+        writeln!(
+            f,
+            "Median: {:.3} ms, MAD: {:.3} ms",
+            self.median_ms, self.mad_ms
+        )?;
+        writeln!(
+            f,
+            "Outliers: {} of {} iterations",
+            self.outliers.len(),
+            self.count
+        )?;
+        for outlier in &self.outliers {
+            writeln!(
+                f,
+                "  Iteration {}: {:.3} ms (z {:.1}), {} involuntary and {} voluntary context switches, {} major faults",
+                outlier.iteration,
+                outlier.latency_ms,
+                outlier.modified_z_score,
+                outlier.involuntary_switches,
+                outlier.voluntary_switches,
+                outlier.major_faults
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+/// Parses a duration such as an `--interval` or `--sla-latency`, e.g. `100ms`, `1.5s`, `250us` or
+/// `500000ns`.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value: &str = value.trim();
+    let split: usize = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}', expected e.g. 100ms", value))?;
+    let seconds: f64 = match unit.trim() {
+        "s" => number,
+        "ms" => number / 1e3,
+        "us" | "µs" => number / 1e6,
+        "ns" => number / 1e9,
+        other => {
+            return Err(format!(
+                "unknown interval unit '{}', expected s, ms, us or ns",
+                other
+            ))
+        }
+    };
+    if !seconds.is_finite() || seconds <= 0. {
+        return Err("the interval must be positive".to_string());
+    }
+    // Values under half a nanosecond pass the check above but round to a zero interval
+    let interval: Duration = Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("interval '{}' is too large", value))?;
+    if interval.is_zero() {
+        return Err("the interval must be at least 1ns".to_string());
+    }
+    Ok(interval)
+}
+
+/// Parses a byte count with an optional K, M or G suffix (powers of 1024), e.g. `2G`, `512MB` or
+/// `1GiB`.
+pub fn parse_size(value: &str) -> Result<usize, String> {
+    let value: &str = value.trim();
+    let bytes: &str = value
+        .strip_suffix("iB")
+        .or_else(|| value.strip_suffix('B'))
+        .unwrap_or(value);
+    let (number, multiplier) = match bytes.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&bytes[..index], 1 << 10),
+        Some((index, 'm' | 'M')) => (&bytes[..index], 1 << 20),
+        Some((index, 'g' | 'G')) => (&bytes[..index], 1 << 30),
+        _ => (bytes, 1),
+    };
+    let number: usize = number.parse().map_err(|_| {
+        format!(
+            "invalid size '{}', expected bytes or a K, M or G suffix",
+            value
+        )
+    })?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", value))
+}
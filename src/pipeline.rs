@@ -0,0 +1,355 @@
+use crate::{
+    detection::{decode_detections, non_max_suppression, Detection, DetectionConfig},
+    environment::EnvironmentInfo,
+    error::AppError,
+    input::{InputData, PreparedInput, PreprocessOverride},
+    metadata::RunMetadata,
+    metrics::MetricsSummary,
+    model::load_model,
+    postprocess::{
+        extract_outputs, post_process_outputs, ExtractedOutputs, OutputSchema, OutputTensor,
+    },
+    report::TaskOutput,
+    segmentation::argmax_mask,
+    tensor::{convert_inputs, session_inputs, NpyTensor},
+    tracker::{BenchmarkTracker, PhaseNode, SpanKind},
+};
+use image::DynamicImage;
+use ndarray::ArrayD;
+use ort::{
+    session::{Input, Session, SessionOutputs},
+    value::DynValue,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// What a stage does with the outputs of its model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageTask {
+    Classification,
+    Detection,
+    Segmentation,
+}
+
+/// What a stage is fed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageInput {
+    /// The pipeline image.
+    #[default]
+    Image,
+    /// One crop of the pipeline image per box of the previous detection stage.
+    Crops,
+    /// The first output tensor of the previous stage, as is.
+    Output,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageDefinition {
+    pub name: String,
+    pub model: String,
+    pub task: StageTask,
+    #[serde(default)]
+    pub input: StageInput,
+    /// `KEY=VALUE` preprocessing overrides, as with `--preprocess`.
+    #[serde(default)]
+    pub preprocess: Vec<String>,
+    /// Largest number of crops fed to a `crops` stage, highest scores first.
+    pub max_crops: Option<usize>,
+    pub detection_format: Option<String>,
+    pub conf_threshold: Option<f32>,
+    pub iou_threshold: Option<f32>,
+    pub max_detections: Option<usize>,
+}
+
+/// A JSON pipeline file: `{"stages": [{"name": "detector", "model": "yolov8n.onnx", "task": "detection"},
+/// {"name": "classifier", "model": "mobilenetv2-10.onnx", "task": "classification", "input": "crops"}]}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineDefinition {
+    pub stages: Vec<StageDefinition>,
+}
+
+impl PipelineDefinition {
+    pub fn load(path: &str) -> Result<Self, AppError> {
+        let text: String = fs::read_to_string(path)
+            .map_err(|e| AppError::InputError(format!("failed to read {}: {}", path, e)))?;
+        let definition: PipelineDefinition = serde_json::from_str(&text)?;
+        definition.validate()?;
+        Ok(definition)
+    }
+
+    fn validate(&self) -> Result<(), AppError> {
+        let Some(first) = self.stages.first() else {
+            return Err(AppError::InputError("pipeline has no stages".to_string()));
+        };
+        if first.input != StageInput::Image {
+            return Err(AppError::InputError(format!(
+                "stage '{}' has no previous stage to take its input from",
+                first.name
+            )));
+        }
+        for pair in self.stages.windows(2) {
+            let (previous, stage) = (&pair[0], &pair[1]);
+            // Boxes are only in pipeline image coordinates when the detector saw the whole image
+            let detects_image: bool =
+                previous.task == StageTask::Detection && previous.input == StageInput::Image;
+            if stage.input == StageInput::Crops && !detects_image {
+                return Err(AppError::InputError(format!(
+                    "stage '{}' crops the boxes of '{}', which is not a detection stage on the image",
+                    stage.name, previous.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One stage with its model loaded and its options parsed.
+pub struct Stage {
+    pub definition: StageDefinition,
+    model: Session,
+    overrides: Vec<PreprocessOverride>,
+    detection: DetectionConfig,
+}
+
+/// Results of one stage: one output per crop for `crops` stages, a single one otherwise.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageResult {
+    pub name: String,
+    pub model: String,
+    pub outputs: Vec<TaskOutput>,
+}
+
+impl std::fmt::Display for StageResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Stage {} =============", self.name)?;
+        writeln!(f, "Model: {}", self.model)?;
+        for (index, output) in self.outputs.iter().enumerate() {
+            if self.outputs.len() > 1 {
+                writeln!(f, "Crop {}:", index)?;
+            }
+            write!(f, "{}", output)?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
+
+/// What a stage hands to the next one.
+#[derive(Default)]
+struct Handoff {
+    detections: Vec<Detection>,
+    tensor: Option<OutputTensor>,
+}
+
+/// Loads every stage model, each timed as "loadmodel <stage>".
+pub fn load_stages(
+    definition: PipelineDefinition,
+    tracker: &mut BenchmarkTracker,
+) -> Result<Vec<Stage>, AppError> {
+    definition
+        .stages
+        .into_iter()
+        .map(|definition| {
+            let overrides: Vec<PreprocessOverride> = definition
+                .preprocess
+                .iter()
+                .map(|preprocess| preprocess.parse().map_err(AppError::InputError))
+                .collect::<Result<_, _>>()?;
+            let defaults: DetectionConfig = DetectionConfig::default();
+            let detection: DetectionConfig = DetectionConfig {
+                format: match &definition.detection_format {
+                    Some(format) => format.parse().map_err(AppError::InputError)?,
+                    None => defaults.format,
+                },
+                conf_threshold: definition.conf_threshold.unwrap_or(defaults.conf_threshold),
+                iou_threshold: definition.iou_threshold.unwrap_or(defaults.iou_threshold),
+                max_detections: definition.max_detections.unwrap_or(defaults.max_detections),
+                ..defaults
+            };
+
+            tracker.start_operation(&format!("loadmodel {}", definition.name));
+            let model: Session = load_model(&definition.model)?;
+            tracker.finish_operation();
+            if model.inputs.len() != 1 {
+                return Err(AppError::InputError(format!(
+                    "stage '{}' needs a model with one input, {} has {}",
+                    definition.name,
+                    definition.model,
+                    model.inputs.len()
+                )));
+            }
+
+            Ok(Stage {
+                definition,
+                model,
+                overrides,
+                detection,
+            })
+        })
+        .collect()
+}
+
+/// Runs the stages in order on `image`. Every stage records "Pre-processing <stage>", "Inference
+/// <stage>" and "Post-processing <stage>"; the glue between stages is timed on its own as
+/// "Crop <stage>" or "Handoff <stage>". All of them run in a "Stage <stage>" phase nested in the
+/// current one.
+pub fn run_pipeline(
+    stages: &mut [Stage],
+    image: &DynamicImage,
+    tracker: &mut BenchmarkTracker,
+) -> Result<Vec<StageResult>, AppError> {
+    let mut handoff: Handoff = Handoff::default();
+    let mut results: Vec<StageResult> = Vec::with_capacity(stages.len());
+
+    for stage in stages.iter_mut() {
+        let name: String = stage.definition.name.clone();
+        let phase: String = format!("Stage {}", name);
+        tracker.start_phase(&phase);
+        let sources: Vec<InputData> = match stage.definition.input {
+            StageInput::Image => vec![InputData::Image(image.clone())],
+            StageInput::Crops => {
+                tracker.start_operation(&format!("Crop {}", name));
+                let crops: Vec<InputData> = handoff
+                    .detections
+                    .iter()
+                    .take(stage.definition.max_crops.unwrap_or(usize::MAX))
+                    .filter_map(|detection| crop(image, detection))
+                    .map(InputData::Image)
+                    .collect();
+                tracker.finish_operation();
+                crops
+            }
+            StageInput::Output => {
+                tracker.start_operation(&format!("Handoff {}", name));
+                let output: OutputTensor = handoff.tensor.take().ok_or_else(|| {
+                    AppError::InputError(format!(
+                        "stage '{}' has no output tensor of the previous stage to feed",
+                        name
+                    ))
+                })?;
+                let tensor: ArrayD<f32> = ArrayD::from_shape_vec(output.shape, output.data)
+                    .map_err(|e| AppError::Other(format!("Shape error: {}", e)))?;
+                tracker.finish_operation();
+                vec![InputData::Tensor(NpyTensor::Float32(tensor))]
+            }
+        };
+
+        let mut next: Handoff = Handoff::default();
+        let mut outputs: Vec<TaskOutput> = Vec::with_capacity(sources.len());
+        for data in sources {
+            let (output, stage_handoff) = run_stage(stage, data, tracker)?;
+            outputs.push(output);
+            next.detections.extend(stage_handoff.detections);
+            next.tensor = next.tensor.or(stage_handoff.tensor);
+        }
+        next.detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+        handoff = next;
+        tracker.end_phase(&phase);
+
+        results.push(StageResult {
+            name,
+            model: stage.definition.model.clone(),
+            outputs,
+        });
+    }
+
+    Ok(results)
+}
+
+fn run_stage(
+    stage: &mut Stage,
+    data: InputData,
+    tracker: &mut BenchmarkTracker,
+) -> Result<(TaskOutput, Handoff), AppError> {
+    let name: &str = &stage.definition.name;
+    let model_input: &Input = &stage.model.inputs[0];
+
+    tracker.start_operation(&format!("Pre-processing {}", name));
+    let prepared: PreparedInput = PreparedInput::new(model_input, data, &stage.overrides)?;
+    let mut detection: DetectionConfig = stage.detection.clone();
+    if let Some((width, height)) = prepared.image_size() {
+        let config = &prepared.config;
+        let ((resized_width, resized_height), (x, y)) = config.placement(width, height);
+        detection.input_size = (config.width, config.height);
+        detection.scale = (
+            width as f32 / resized_width as f32,
+            height as f32 / resized_height as f32,
+        );
+        detection.offset = (x as f32, y as f32);
+    }
+    let inputs: Vec<(String, DynValue)> =
+        convert_inputs(vec![prepared.into_value()?], &stage.model.inputs)?;
+    tracker.finish_operation();
+
+    let schema: OutputSchema = OutputSchema::new(&stage.model.outputs);
+    tracker.start_operation(&format!("Inference {}", name));
+    let outputs: SessionOutputs<'_> = stage.model.run(session_inputs(&inputs))?;
+    tracker.finish_operation();
+
+    tracker.start_operation(&format!("Post-processing {}", name));
+    let extracted: ExtractedOutputs = extract_outputs(&outputs, &schema);
+    let mut handoff: Handoff = Handoff::default();
+    let output: TaskOutput = match stage.definition.task {
+        StageTask::Classification => TaskOutput::Classification(post_process_outputs(&extracted)?),
+        StageTask::Detection => {
+            let candidates: Vec<Detection> = decode_detections(&extracted.tensors, &detection)?;
+            let detections: Vec<Detection> = non_max_suppression(
+                candidates,
+                detection.iou_threshold,
+                detection.max_detections,
+            );
+            handoff.detections = detections.clone();
+            TaskOutput::Detection(detections)
+        }
+        StageTask::Segmentation => TaskOutput::Segmentation(argmax_mask(&extracted.tensors)?),
+    };
+    handoff.tensor = extracted.tensors.into_iter().next();
+    tracker.finish_operation();
+
+    Ok((output, handoff))
+}
+
+/// The box of `detection` cut out of `image`, clamped to the image. `None` for empty boxes.
+fn crop(image: &DynamicImage, detection: &Detection) -> Option<DynamicImage> {
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let [x1, y1, x2, y2] = detection.bbox;
+    let (x1, x2) = (x1.clamp(0., width), x2.clamp(0., width));
+    let (y1, y2) = (y1.clamp(0., height), y2.clamp(0., height));
+    let (crop_width, crop_height) = ((x2 - x1).round() as u32, (y2 - y1).round() as u32);
+    if crop_width == 0 || crop_height == 0 {
+        return None;
+    }
+    Some(image.crop_imm(x1 as u32, y1 as u32, crop_width, crop_height))
+}
+
+/// Everything a pipeline run measured and produced, for `--output json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineReport {
+    pub metadata: RunMetadata,
+    pub environment: EnvironmentInfo,
+    pub operations: Vec<MetricsSummary>,
+    pub phases: Vec<MetricsSummary>,
+    pub phase_tree: Vec<PhaseNode>,
+    pub total: MetricsSummary,
+    pub stages: Vec<StageResult>,
+}
+
+impl PipelineReport {
+    pub fn new(
+        metadata: RunMetadata,
+        environment: EnvironmentInfo,
+        tracker: &BenchmarkTracker,
+        stages: Vec<StageResult>,
+    ) -> Self {
+        Self {
+            metadata,
+            environment,
+            operations: tracker.summaries(SpanKind::Operation),
+            phases: tracker.summaries(SpanKind::Phase),
+            phase_tree: tracker.phase_tree(),
+            total: MetricsSummary::from(&tracker.get_total_metrics()),
+            stages,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::error::AppError;
+use half::{bf16, f16};
+use ndarray::{ArrayBase, Dim};
+use ort::{
+    session::{Output, SessionOutputs},
+    tensor::{PrimitiveTensorElementType, TensorElementType},
+    value::DynValue,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum OutputSummary {
+    Prediction {
+        name: String,
+        shape: Vec<i64>,
+        predicted_index: usize,
+        score: f32,
+    },
+    Skipped {
+        name: String,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for OutputSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputSummary::Prediction {
+                name,
+                shape,
+                predicted_index,
+                score,
+            } => {
+                writeln!(f, "Output: {} {:?}", name, shape)?;
+                writeln!(f, "Predicted Class Index: {}", predicted_index)?;
+                writeln!(f, "Confidence Score: {:.4}", score)
+            }
+            OutputSummary::Skipped { name, reason } => {
+                writeln!(f, "Output: {} (skipped: {})", name, reason)
+            }
+        }
+    }
+}
+
+/// Element types of the model outputs, read from the session metadata before inference so the
+/// session itself does not need to be borrowed while its outputs are alive.
+#[derive(Debug, Clone, Default)]
+pub struct OutputSchema {
+    pub outputs: Vec<(String, Option<TensorElementType>)>,
+}
+
+impl OutputSchema {
+    pub fn new(model_outputs: &[Output]) -> Self {
+        Self {
+            outputs: model_outputs
+                .iter()
+                .map(|output| (output.name.clone(), output.output_type.tensor_type()))
+                .collect(),
+        }
+    }
+
+    pub fn element_type(&self, name: &str) -> Option<TensorElementType> {
+        self.outputs
+            .iter()
+            .find(|(output, _)| output == name)
+            .and_then(|(_, element_type)| *element_type)
+    }
+}
+
+/// A model output converted to the common f32 representation used by all post-processing.
+#[derive(Debug, Clone)]
+pub struct OutputTensor {
+    pub name: String,
+    pub element_type: TensorElementType,
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtractedOutputs {
+    pub tensors: Vec<OutputTensor>,
+    /// Outputs that could not be converted, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+trait ToF32: PrimitiveTensorElementType + Copy + std::fmt::Debug {
+    fn to_f32(self) -> f32;
+}
+
+macro_rules! impl_to_f32 {
+    ($($type_:ty => $convert:expr),+ $(,)?) => {
+        $(impl ToF32 for $type_ {
+            fn to_f32(self) -> f32 {
+                let convert: fn($type_) -> f32 = $convert;
+                convert(self)
+            }
+        })+
+    };
+}
+
+impl_to_f32!(
+    f32 => |v| v,
+    f64 => |v| v as f32,
+    f16 => |v| v.to_f32(),
+    bf16 => |v| v.to_f32(),
+    u8 => |v| v as f32,
+    i8 => |v| v as f32,
+    u16 => |v| v as f32,
+    i16 => |v| v as f32,
+    u32 => |v| v as f32,
+    i32 => |v| v as f32,
+    u64 => |v| v as f32,
+    i64 => |v| v as f32,
+    bool => |v| if v { 1. } else { 0. },
+);
+
+fn extract_as<T: ToF32>(value: &DynValue) -> Result<(Vec<usize>, Vec<f32>), AppError> {
+    let (shape, data) = value.try_extract_tensor::<T>()?;
+    let dims: Vec<usize> = shape.iter().map(|&d| d as usize).collect();
+    Ok((dims, data.iter().map(|&v| v.to_f32()).collect()))
+}
+
+/// Copies a numeric tensor of the given element type into f32.
+pub fn extract_f32_as(
+    value: &DynValue,
+    element_type: TensorElementType,
+) -> Result<(Vec<usize>, Vec<f32>), AppError> {
+    match element_type {
+        TensorElementType::Float32 => extract_as::<f32>(value),
+        TensorElementType::Float64 => extract_as::<f64>(value),
+        TensorElementType::Float16 => extract_as::<f16>(value),
+        TensorElementType::Bfloat16 => extract_as::<bf16>(value),
+        TensorElementType::Uint8 => extract_as::<u8>(value),
+        TensorElementType::Int8 => extract_as::<i8>(value),
+        TensorElementType::Uint16 => extract_as::<u16>(value),
+        TensorElementType::Int16 => extract_as::<i16>(value),
+        TensorElementType::Uint32 => extract_as::<u32>(value),
+        TensorElementType::Int32 => extract_as::<i32>(value),
+        TensorElementType::Uint64 => extract_as::<u64>(value),
+        TensorElementType::Int64 => extract_as::<i64>(value),
+        TensorElementType::Bool => extract_as::<bool>(value),
+        other => Err(AppError::OutputError(format!(
+            "cannot convert {} to f32",
+            other
+        ))),
+    }
+}
+
+/// Copies a numeric tensor into f32 using the value's own element type.
+pub fn extract_f32(value: &DynValue) -> Result<(Vec<usize>, Vec<f32>), AppError> {
+    match value.dtype().tensor_type() {
+        Some(element_type) => extract_f32_as(value, element_type),
+        None => Err(AppError::OutputError(format!(
+            "not a tensor ({})",
+            value.dtype()
+        ))),
+    }
+}
+
+/// Converts every output using the element type declared in the session metadata. An output whose
+/// value has another element type than declared is skipped, since its data would be misread.
+pub fn extract_outputs(outputs: &SessionOutputs<'_>, schema: &OutputSchema) -> ExtractedOutputs {
+    let mut extracted: ExtractedOutputs = ExtractedOutputs::default();
+
+    for (name, value) in outputs.iter() {
+        let runtime_type: Option<TensorElementType> = value.dtype().tensor_type();
+        let element_type: Option<TensorElementType> = match schema.element_type(name) {
+            Some(declared) if Some(declared) != runtime_type => {
+                extracted.skipped.push((
+                    name.to_string(),
+                    format!("model declares {} but produced {}", declared, value.dtype()),
+                ));
+                continue;
+            }
+            declared => declared.or(runtime_type),
+        };
+
+        let Some(element_type) = element_type else {
+            extracted.skipped.push((
+                name.to_string(),
+                format!("not a tensor ({})", value.dtype()),
+            ));
+            continue;
+        };
+
+        match extract_f32_as(&value, element_type) {
+            Ok((shape, data)) => extracted.tensors.push(OutputTensor {
+                name: name.to_string(),
+                element_type,
+                shape,
+                data,
+            }),
+            Err(e) => extracted.skipped.push((name.to_string(), e.to_string())),
+        }
+    }
+
+    extracted
+}
+
+pub fn post_process_outputs(outputs: &ExtractedOutputs) -> Result<Vec<OutputSummary>, AppError> {
+    let mut summaries: Vec<OutputSummary> = outputs
+        .tensors
+        .iter()
+        .map(post_process_output)
+        .collect::<Result<_, _>>()?;
+
+    summaries.extend(
+        outputs
+            .skipped
+            .iter()
+            .map(|(name, reason)| OutputSummary::Skipped {
+                name: name.clone(),
+                reason: reason.clone(),
+            }),
+    );
+    Ok(summaries)
+}
+
+fn post_process_output(tensor: &OutputTensor) -> Result<OutputSummary, AppError> {
+    let dim: Dim<ndarray::IxDynImpl> = ndarray::IxDyn(&tensor.shape);
+
+    let output_array: ArrayBase<ndarray::ViewRepr<&f32>, Dim<ndarray::IxDynImpl>> =
+        ndarray::ArrayView::from_shape(dim, &tensor.data)
+            .map_err(|e| AppError::Other(format!("Shape error: {}", e)))?;
+    let Some((predicted_index, &score)) = output_array
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+    else {
+        return Ok(OutputSummary::Skipped {
+            name: tensor.name.clone(),
+            reason: "empty tensor".to_string(),
+        });
+    };
+
+    Ok(OutputSummary::Prediction {
+        name: tensor.name.clone(),
+        shape: tensor.shape.iter().map(|&d| d as i64).collect(),
+        predicted_index,
+        score,
+    })
+}
@@ -0,0 +1,164 @@
+use crate::{
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::format_bytes,
+    postprocess::{extract_outputs, OutputSchema},
+    tensor::session_inputs,
+};
+use ort::{
+    session::{Session, SessionOutputs},
+    value::DynValue,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputDivergence {
+    pub output: String,
+    pub max_abs_diff: f32,
+    pub mean_abs_diff: f32,
+    pub cosine_similarity: f32,
+    pub top1_match: bool,
+}
+
+impl OutputDivergence {
+    pub fn between(output: &str, reference: &[f32], variant: &[f32]) -> Self {
+        let pairs = || reference.iter().zip(variant);
+        let count: usize = reference.len().min(variant.len()).max(1);
+
+        let max_abs_diff: f32 = pairs().fold(0., |max: f32, (a, b)| max.max((a - b).abs()));
+        let mean_abs_diff: f32 = pairs().map(|(a, b)| (a - b).abs()).sum::<f32>() / count as f32;
+
+        let dot: f32 = pairs().map(|(a, b)| a * b).sum();
+        let norms: f32 = reference.iter().map(|a| a * a).sum::<f32>().sqrt()
+            * variant.iter().map(|b| b * b).sum::<f32>().sqrt();
+        let cosine_similarity: f32 = if norms > 0. { dot / norms } else { 1. };
+
+        let argmax = |values: &[f32]| {
+            values
+                .iter()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(b.1))
+                .map(|(index, _)| index)
+        };
+
+        Self {
+            output: output.to_string(),
+            max_abs_diff,
+            mean_abs_diff,
+            cosine_similarity,
+            top1_match: reference.len() == variant.len() && argmax(reference) == argmax(variant),
+        }
+    }
+}
+
+/// The models of a precision comparison, the reference first, with the run they were measured in.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrecisionReport {
+    pub metadata: RunMetadata,
+    pub results: Vec<PrecisionResult>,
+}
+
+/// Measurements of one model variant in a precision comparison, sent back by `--isolate` children.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrecisionResult {
+    pub model: String,
+    pub input_types: Vec<String>,
+    pub load_time: Duration,
+    pub latencies: Vec<Duration>,
+    /// How much the variant raised the peak RSS of the process, in bytes.
+    pub peak_rss_growth: u64,
+    pub outputs: Vec<(String, Vec<f32>)>,
+    pub divergence: Vec<OutputDivergence>,
+}
+
+impl PrecisionResult {
+    pub fn mean_latency(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::default();
+        }
+        self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32
+    }
+
+    pub fn min_latency(&self) -> Duration {
+        self.latencies.iter().min().copied().unwrap_or_default()
+    }
+
+    /// Fills in `divergence` against the reference variant's outputs, matched by output name.
+    pub fn compare_with(&mut self, reference: &PrecisionResult) {
+        self.divergence = self
+            .outputs
+            .iter()
+            .filter_map(|(name, values)| {
+                reference
+                    .outputs
+                    .iter()
+                    .find(|(reference_name, _)| reference_name == name)
+                    .map(|(_, reference_values)| {
+                        OutputDivergence::between(name, reference_values, values)
+                    })
+            })
+            .collect();
+    }
+}
+
+impl std::fmt::Display for PrecisionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= {} =============", self.model)?;
+        writeln!(f, "Input types: {}", self.input_types.join(", "))?;
+        writeln!(f, "Load time: {:?}", self.load_time)?;
+        writeln!(f, "Mean latency: {:?}", self.mean_latency())?;
+        writeln!(f, "Min latency: {:?}", self.min_latency())?;
+        writeln!(f, "Peak RSS growth: {}", format_bytes(self.peak_rss_growth))?;
+        for divergence in &self.divergence {
+            writeln!(
+                f,
+                "Output {}: max abs diff {:.6}, mean abs diff {:.6}, cosine {:.6}, top-1 {}",
+                divergence.output,
+                divergence.max_abs_diff,
+                divergence.mean_abs_diff,
+                divergence.cosine_similarity,
+                if divergence.top1_match {
+                    "match"
+                } else {
+                    "MISMATCH"
+                }
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
+
+pub struct RepeatedRun {
+    pub latencies: Vec<Duration>,
+    /// Outputs of the last run converted to f32, skipping outputs that cannot be converted.
+    pub outputs: Vec<(String, Vec<f32>)>,
+}
+
+/// Runs the session `runs` times on the same inputs.
+pub fn run_repeated(
+    model: &mut Session,
+    inputs: &[(String, DynValue)],
+    runs: usize,
+) -> Result<RepeatedRun, AppError> {
+    let mut latencies: Vec<Duration> = Vec::with_capacity(runs);
+    let schema: OutputSchema = OutputSchema::new(&model.outputs);
+    let mut last_outputs: Vec<(String, Vec<f32>)> = Vec::new();
+
+    for _ in 0..runs.max(1) {
+        let start: Instant = Instant::now();
+        let outputs: SessionOutputs<'_> = model.run(session_inputs(inputs))?;
+        latencies.push(start.elapsed());
+
+        last_outputs = extract_outputs(&outputs, &schema)
+            .tensors
+            .into_iter()
+            .map(|tensor| (tensor.name, tensor.data))
+            .collect();
+    }
+
+    Ok(RepeatedRun {
+        latencies,
+        outputs: last_outputs,
+    })
+}
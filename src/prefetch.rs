@@ -0,0 +1,231 @@
+use crate::error::AppError;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How far ahead decoding and pre-processing run of the inference.
+#[derive(Debug, Clone, Copy)]
+pub struct PrefetchConfig {
+    /// Prepared items that may wait for the consumer before the workers block.
+    pub depth: usize,
+    pub workers: usize,
+}
+
+/// Item of a prefetch queue with its position in the source and the time it took to prepare.
+type Prepared<T> = (usize, Result<T, AppError>, Duration);
+/// Source shared by the workers with the index of the next item it yields.
+type SharedSource<S> = Arc<Mutex<(S, usize)>>;
+
+/// Producer/consumer queue: worker threads take items from `source` and prepare them while the
+/// consumer runs inference on the items prepared before. Items come out in the order of the source,
+/// whichever worker prepared them.
+pub struct Prefetcher<T> {
+    config: PrefetchConfig,
+    receiver: Option<Receiver<Prepared<T>>>,
+    workers: Vec<JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    /// Items prepared ahead of one an earlier worker is still preparing.
+    pending: BTreeMap<usize, (Result<T, AppError>, Duration)>,
+    next: usize,
+    start: Instant,
+    consumed: usize,
+    prepare_time: Duration,
+    /// Time the consumer waited for the next item, the queue being empty.
+    wait_time: Duration,
+}
+
+impl<T: Send + 'static> Prefetcher<T> {
+    /// Starts the workers. `source` yields the raw items, e.g. frames or image paths, and `prepare`
+    /// turns one into what the consumer needs; an error of either is handed to the consumer in
+    /// place of the item and stops the workers.
+    pub fn start<I, S, P>(config: PrefetchConfig, source: S, prepare: P) -> Self
+    where
+        S: FnMut() -> Result<Option<I>, AppError> + Send + 'static,
+        P: Fn(I) -> Result<T, AppError> + Send + Sync + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel::<Prepared<T>>(config.depth.max(1));
+        let source: SharedSource<S> = Arc::new(Mutex::new((source, 0)));
+        let prepare: Arc<P> = Arc::new(prepare);
+        let stop: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+
+        let workers: Vec<JoinHandle<()>> = (0..config.workers.max(1))
+            .map(|_| {
+                let (sender, source, prepare, stop): (SyncSender<Prepared<T>>, _, _, _) = (
+                    sender.clone(),
+                    Arc::clone(&source),
+                    Arc::clone(&prepare),
+                    Arc::clone(&stop),
+                );
+                thread::spawn(move || prefetch_worker(sender, source, prepare, stop))
+            })
+            .collect();
+
+        Self {
+            config,
+            receiver: Some(receiver),
+            workers,
+            stop,
+            pending: BTreeMap::new(),
+            next: 0,
+            start: Instant::now(),
+            consumed: 0,
+            prepare_time: Duration::default(),
+            wait_time: Duration::default(),
+        }
+    }
+}
+
+fn prefetch_worker<I, T, S, P>(
+    sender: SyncSender<Prepared<T>>,
+    source: SharedSource<S>,
+    prepare: Arc<P>,
+    stop: Arc<AtomicBool>,
+) where
+    S: FnMut() -> Result<Option<I>, AppError>,
+    P: Fn(I) -> Result<T, AppError>,
+{
+    while !stop.load(Ordering::Relaxed) {
+        let (index, item) = {
+            let Ok(mut source) = source.lock() else {
+                return;
+            };
+            let (next_item, next_index) = &mut *source;
+            let index: usize = *next_index;
+            *next_index += 1;
+            (index, next_item())
+        };
+        let start: Instant = Instant::now();
+        let prepared: Result<T, AppError> = match item {
+            Ok(Some(item)) => prepare(item),
+            Ok(None) => return,
+            Err(e) => Err(e),
+        };
+        let failed: bool = prepared.is_err();
+        // The consumer is gone once it hung up, nothing is waiting for the item
+        if sender.send((index, prepared, start.elapsed())).is_err() || failed {
+            stop.store(true, Ordering::Relaxed);
+            return;
+        }
+    }
+}
+
+impl<T> Prefetcher<T> {
+    /// The next prepared item in source order, waiting for it if it is not ready, `None` once the
+    /// source is exhausted.
+    pub fn next_item(&mut self) -> Option<Result<T, AppError>> {
+        let wait_start: Instant = Instant::now();
+        let item = loop {
+            if let Some((item, prepare_time)) = self.pending.remove(&self.next) {
+                self.prepare_time += prepare_time;
+                break Some(item);
+            }
+            match self.receiver.as_ref()?.recv() {
+                Ok((index, item, prepare_time)) => {
+                    self.pending.insert(index, (item, prepare_time));
+                }
+                // Every worker finished: later items were never taken from the source
+                Err(_) => break None,
+            }
+        };
+        self.wait_time += wait_start.elapsed();
+        if item.is_some() {
+            self.next += 1;
+            self.consumed += 1;
+        }
+        item
+    }
+
+    /// Throughput and utilization of both sides of the queue so far.
+    pub fn report(&self) -> PrefetchReport {
+        let elapsed: Duration = self.start.elapsed();
+        let seconds: f64 = elapsed.as_secs_f64();
+        let ratio = |busy: f64, capacity: f64| {
+            if capacity > 0. {
+                (busy / capacity * 100.).min(100.)
+            } else {
+                0.
+            }
+        };
+        let workers: usize = self.config.workers.max(1);
+
+        PrefetchReport {
+            workers,
+            queue_depth: self.config.depth.max(1),
+            items: self.consumed,
+            elapsed_ms: seconds * 1000.,
+            throughput: if seconds > 0. {
+                self.consumed as f64 / seconds
+            } else {
+                0.
+            },
+            mean_prepare_ms: self.prepare_time.as_secs_f64() * 1000. / self.consumed.max(1) as f64,
+            worker_utilization: ratio(self.prepare_time.as_secs_f64(), seconds * workers as f64),
+            consumer_utilization: ratio(seconds - self.wait_time.as_secs_f64(), seconds),
+            consumer_wait_ms: self.wait_time.as_secs_f64() * 1000.,
+        }
+    }
+}
+
+impl<T> Drop for Prefetcher<T> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Hanging up unblocks the workers waiting for room in the queue
+        drop(self.receiver.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Overlapped throughput of a prefetched run and how busy each stage was.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchReport {
+    pub workers: usize,
+    pub queue_depth: usize,
+    pub items: usize,
+    pub elapsed_ms: f64,
+    /// Items per second through the whole pipeline, with preparation and inference overlapped.
+    pub throughput: f64,
+    /// Mean decoding and pre-processing time of an item on a worker.
+    pub mean_prepare_ms: f64,
+    /// Share of the workers' time spent preparing items, in percent.
+    pub worker_utilization: f64,
+    /// Share of the consumer's time spent on inference and post-processing rather than waiting.
+    pub consumer_utilization: f64,
+    pub consumer_wait_ms: f64,
+}
+
+impl std::fmt::Display for PrefetchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Prefetch Metrics =============")?;
+        writeln!(
+            f,
+            "Workers: {}, queue depth: {}",
+            self.workers, self.queue_depth
+        )?;
+        writeln!(
+            f,
+            "Items: {} in {:.3} ms ({:.2} items/s overlapped)",
+            self.items, self.elapsed_ms, self.throughput
+        )?;
+        writeln!(
+            f,
+            "Decode + pre-processing: {:.3} ms per item, workers busy {:.1}%",
+            self.mean_prepare_ms, self.worker_utilization
+        )?;
+        writeln!(
+            f,
+            "Inference stage busy {:.1}%, waited {:.3} ms for input",
+            self.consumer_utilization, self.consumer_wait_ms
+        )?;
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{
+    affinity::current_affinity,
+    environment::{read_trimmed, EnvironmentInfo},
+};
+use std::fs;
+
+/// Share of the cores that may already be busy before the run counts as noisy.
+const MAX_LOAD_PER_CORE: f32 = 0.5;
+
+/// Looks for conditions that make the measurements untrustworthy and describes each one found.
+pub fn check_environment(environment: &EnvironmentInfo) -> Vec<String> {
+    let mut warnings: Vec<String> = Vec::new();
+    let cores: Vec<usize> = current_affinity()
+        .map(|cores| cores.cores.into_iter().collect())
+        .unwrap_or_else(|| (0..environment.logical_cores).collect());
+
+    if cfg!(debug_assertions) {
+        warnings.push(
+            "benchmark is a debug build, run with --release for representative numbers".to_string(),
+        );
+    }
+
+    let governors: Vec<String> = cores
+        .iter()
+        .filter_map(|&core| {
+            read_trimmed(&format!(
+                "/sys/devices/system/cpu/cpu{}/cpufreq/scaling_governor",
+                core
+            ))
+        })
+        .filter(|governor| governor != "performance")
+        .collect();
+    if let Some(governor) = governors.first() {
+        warnings.push(format!(
+            "CPU frequency governor is '{}' on {} of {} cores instead of 'performance'",
+            governor,
+            governors.len(),
+            cores.len()
+        ));
+    }
+
+    if let Some(load) = read_trimmed("/proc/loadavg")
+        .and_then(|loadavg| loadavg.split_whitespace().next()?.parse::<f32>().ok())
+    {
+        if load > environment.logical_cores as f32 * MAX_LOAD_PER_CORE {
+            warnings.push(format!(
+                "system load average is {:.2} on {} cores",
+                load, environment.logical_cores
+            ));
+        }
+    }
+
+    let throttled: Vec<usize> = cores
+        .iter()
+        .copied()
+        .filter(|&core| {
+            read_trimmed(&format!(
+                "/sys/devices/system/cpu/cpu{}/thermal_throttle/core_throttle_count",
+                core
+            ))
+            .and_then(|count| count.parse::<u64>().ok())
+            .is_some_and(|count| count > 0)
+        })
+        .collect();
+    if !throttled.is_empty() {
+        warnings.push(format!(
+            "{} cores have been thermally throttled since boot",
+            throttled.len()
+        ));
+    }
+    warnings.extend(hot_thermal_zones());
+
+    if let Some(tracer) = read_trimmed("/proc/self/status").and_then(|status| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))
+            .map(|pid| pid.trim().to_string())
+    }) {
+        if tracer != "0" {
+            warnings.push(format!("process is traced by pid {} (debugger)", tracer));
+        }
+    }
+
+    warnings
+}
+
+/// Thermal zones already at or above their passive trip point, where the kernel starts throttling.
+fn hot_thermal_zones() -> Vec<String> {
+    let Ok(zones) = fs::read_dir("/sys/class/thermal") else {
+        return Vec::new();
+    };
+
+    let mut warnings: Vec<String> = Vec::new();
+    for zone in zones.flatten() {
+        let path: std::path::PathBuf = zone.path();
+        let name: String = zone.file_name().to_string_lossy().into_owned();
+        if !name.starts_with("thermal_zone") {
+            continue;
+        }
+        let read = |file: &str| read_trimmed(&path.join(file).to_string_lossy());
+        let Some(temp) = read("temp").and_then(|temp| temp.parse::<i64>().ok()) else {
+            continue;
+        };
+
+        let passive: Option<i64> = (0..16).find_map(|trip| {
+            (read(&format!("trip_point_{}_type", trip))? == "passive")
+                .then(|| read(&format!("trip_point_{}_temp", trip))?.parse().ok())
+                .flatten()
+        });
+        if let Some(passive) = passive.filter(|&passive| passive > 0 && temp >= passive) {
+            warnings.push(format!(
+                "{} ({}) is at {:.1}°C, above its throttling point of {:.1}°C",
+                name,
+                read("type").unwrap_or_default(),
+                temp as f32 / 1000.,
+                passive as f32 / 1000.
+            ));
+        }
+    }
+    warnings
+}
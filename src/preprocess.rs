@@ -0,0 +1,363 @@
+use crate::error::AppError;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgb, RgbImage};
+use ndarray::{Array, Array4};
+#[cfg(not(target_family = "wasm"))]
+use ort::{
+    session::Input,
+    value::{TensorValueType, Value, ValueType},
+};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::str::FromStr;
+
+const DEFAULT_SIZE: u32 = 224;
+/// Gray the letterbox borders are filled with, as in the YOLO preprocessing.
+const LETTERBOX_FILL: u8 = 114;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Nchw,
+    Nhwc,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nchw" => Ok(Layout::Nchw),
+            "nhwc" => Ok(Layout::Nhwc),
+            other => Err(format!("unknown layout '{}', expected nchw or nhwc", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeFilter {
+    Nearest,
+    Bilinear,
+    CatmullRom,
+    Lanczos3,
+}
+
+impl FromStr for ResizeFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(ResizeFilter::Nearest),
+            "bilinear" => Ok(ResizeFilter::Bilinear),
+            "catmullrom" => Ok(ResizeFilter::CatmullRom),
+            "lanczos3" => Ok(ResizeFilter::Lanczos3),
+            other => Err(format!(
+                "unknown resize filter '{}', expected nearest, bilinear, catmullrom or lanczos3",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResizeFilter::Nearest => "nearest",
+            ResizeFilter::Bilinear => "bilinear",
+            ResizeFilter::CatmullRom => "catmullrom",
+            ResizeFilter::Lanczos3 => "lanczos3",
+        })
+    }
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How the image is fitted into the model input size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResizeMode {
+    /// Resized to the input size, ignoring the aspect ratio.
+    Stretch,
+    /// Resized to fit inside the input, keeping the aspect ratio, and padded with gray borders.
+    Letterbox,
+    /// Resized to cover the input, keeping the aspect ratio, and cropped around the center.
+    CenterCrop,
+}
+
+impl FromStr for ResizeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stretch" => Ok(ResizeMode::Stretch),
+            "letterbox" => Ok(ResizeMode::Letterbox),
+            "center-crop" => Ok(ResizeMode::CenterCrop),
+            other => Err(format!(
+                "unknown resize mode '{}', expected stretch, letterbox or center-crop",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ResizeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResizeMode::Stretch => "stretch",
+            ResizeMode::Letterbox => "letterbox",
+            ResizeMode::CenterCrop => "center-crop",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessConfig {
+    pub batch: usize,
+    pub width: u32,
+    pub height: u32,
+    pub layout: Layout,
+    pub scale: f32,
+    pub mean: [f32; 3],
+    pub std: [f32; 3],
+    pub filter: ResizeFilter,
+    pub mode: ResizeMode,
+    /// Converts pixel by pixel through `GenericImageView::pixels()` instead of the parallel
+    /// conversion of the raw RGB buffer, to benchmark one against the other.
+    pub legacy: bool,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            batch: 1,
+            width: DEFAULT_SIZE,
+            height: DEFAULT_SIZE,
+            layout: Layout::Nchw,
+            scale: 255.,
+            mean: [0.; 3],
+            std: [1.; 3],
+            filter: ResizeFilter::CatmullRom,
+            mode: ResizeMode::Stretch,
+            legacy: false,
+        }
+    }
+}
+
+impl PreprocessConfig {
+    /// Derives the batch size, layout and spatial size from the model input where they are static.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn for_input(input: &Input) -> Self {
+        let mut config: PreprocessConfig = PreprocessConfig::default();
+        let ValueType::Tensor { shape, .. } = &input.input_type else {
+            return config;
+        };
+        if shape.len() != 4 {
+            return config;
+        }
+
+        let static_dim = |dim: i64| if dim > 0 { Some(dim) } else { None };
+        let (layout, height, width) = if shape[3] == 3 && shape[1] != 3 {
+            (Layout::Nhwc, shape[1], shape[2])
+        } else {
+            (Layout::Nchw, shape[2], shape[3])
+        };
+
+        config.layout = layout;
+        if let Some(batch) = static_dim(shape[0]) {
+            config.batch = batch as usize;
+        }
+        if let Some(height) = static_dim(height) {
+            config.height = height as u32;
+        }
+        if let Some(width) = static_dim(width) {
+            config.width = width as u32;
+        }
+        config
+    }
+
+    pub fn apply(&mut self, key: &str, value: &str) -> Result<(), AppError> {
+        let invalid = |reason: String| {
+            AppError::InputError(format!(
+                "invalid preprocessing {}={}: {}",
+                key, value, reason
+            ))
+        };
+
+        match key {
+            "size" => {
+                let (width, height) = value
+                    .split_once('x')
+                    .ok_or_else(|| invalid("expected WIDTHxHEIGHT".to_string()))?;
+                self.width = width.parse().map_err(|e| invalid(format!("{}", e)))?;
+                self.height = height.parse().map_err(|e| invalid(format!("{}", e)))?;
+            }
+            "batch" => self.batch = value.parse().map_err(|e| invalid(format!("{}", e)))?,
+            "layout" => self.layout = value.parse().map_err(invalid)?,
+            "scale" => self.scale = value.parse().map_err(|e| invalid(format!("{}", e)))?,
+            "mean" => self.mean = parse_channels(value).map_err(invalid)?,
+            "std" => self.std = parse_channels(value).map_err(invalid)?,
+            other => {
+                return Err(AppError::InputError(format!(
+                    "unknown preprocessing option '{}', expected size, batch, layout, scale, mean or std",
+                    other
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn shape(&self) -> [usize; 4] {
+        let (height, width) = (self.height as usize, self.width as usize);
+        match self.layout {
+            Layout::Nchw => [self.batch, 3, height, width],
+            Layout::Nhwc => [self.batch, height, width, 3],
+        }
+    }
+
+    /// Size of a `width`x`height` image once resized, and the position of its top left corner in
+    /// the model input. The position is negative when the image is cropped.
+    pub fn placement(&self, width: u32, height: u32) -> ((u32, u32), (i64, i64)) {
+        let (input_width, input_height) = (self.width, self.height);
+        let ratio_x: f64 = input_width as f64 / width.max(1) as f64;
+        let ratio_y: f64 = input_height as f64 / height.max(1) as f64;
+        let ratio: f64 = match self.mode {
+            ResizeMode::Stretch => return ((input_width, input_height), (0, 0)),
+            ResizeMode::Letterbox => ratio_x.min(ratio_y),
+            ResizeMode::CenterCrop => ratio_x.max(ratio_y),
+        };
+
+        let resized_width: u32 = ((width as f64 * ratio).round() as u32).max(1);
+        let resized_height: u32 = ((height as f64 * ratio).round() as u32).max(1);
+        let offset = |input: u32, resized: u32| (input as i64 - resized as i64) / 2;
+        (
+            (resized_width, resized_height),
+            (
+                offset(input_width, resized_width),
+                offset(input_height, resized_height),
+            ),
+        )
+    }
+
+    fn resize(&self, img: DynamicImage) -> DynamicImage {
+        let filter: FilterType = self.filter.into();
+        let ((width, height), (x, y)) = self.placement(img.width(), img.height());
+        let resized: DynamicImage = img.resize_exact(width, height, filter);
+        match self.mode {
+            ResizeMode::Stretch => resized,
+            ResizeMode::Letterbox => {
+                let mut canvas: RgbImage =
+                    RgbImage::from_pixel(self.width, self.height, Rgb([LETTERBOX_FILL; 3]));
+                image::imageops::overlay(&mut canvas, &resized.into_rgb8(), x, y);
+                DynamicImage::ImageRgb8(canvas)
+            }
+            ResizeMode::CenterCrop => {
+                resized.crop_imm((-x) as u32, (-y) as u32, self.width, self.height)
+            }
+        }
+    }
+}
+
+fn parse_channels(value: &str) -> Result<[f32; 3], String> {
+    let channels: Vec<f32> = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>().map_err(|e| format!("{}", e)))
+        .collect::<Result<_, _>>()?;
+    match channels.as_slice() {
+        [value] => Ok([*value; 3]),
+        [r, g, b] => Ok([*r, *g, *b]),
+        _ => Err("expected one value or three comma-separated values".to_string()),
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+pub fn process_image(
+    original_img: DynamicImage,
+    config: &PreprocessConfig,
+) -> Value<TensorValueType<f32>> {
+    Value::from_array(image_tensor(original_img, config)).unwrap()
+}
+
+/// The input tensor of `config` filled with the resized and normalized image, repeated for every
+/// item of the batch. Pure Rust, so backends without ONNX Runtime share it.
+pub fn image_tensor(original_img: DynamicImage, config: &PreprocessConfig) -> Array4<f32> {
+    let img: DynamicImage = config.resize(original_img);
+    if config.legacy {
+        return process_pixels(img, config);
+    }
+
+    // Reuses the buffer of RGB images, everything else is converted once
+    let rgb: RgbImage = img.into_rgb8();
+    let image: Vec<f32> = normalize(&rgb, config);
+    let mut data: Vec<f32> = Vec::with_capacity(image.len() * config.batch);
+    for _ in 0..config.batch {
+        data.extend_from_slice(&image);
+    }
+    Array::from_shape_vec(config.shape(), data).expect("buffer matches the input shape")
+}
+
+/// One normalized image in `config.layout`, converted row by row in parallel. Every channel has
+/// only 256 possible values, so they are looked up instead of computed per pixel.
+fn normalize(rgb: &RgbImage, config: &PreprocessConfig) -> Vec<f32> {
+    let width: usize = rgb.width() as usize;
+    let plane: usize = width * rgb.height() as usize;
+    let mut lookup: [[f32; 256]; 3] = [[0.; 256]; 3];
+    for (c, table) in lookup.iter_mut().enumerate() {
+        for (value, entry) in table.iter_mut().enumerate() {
+            *entry = ((value as f32) / config.scale - config.mean[c]) / config.std[c];
+        }
+    }
+
+    let raw: &[u8] = rgb.as_raw();
+    let mut image: Vec<f32> = vec![0.; plane * 3];
+    match config.layout {
+        Layout::Nhwc => image
+            .par_chunks_mut(width * 3)
+            .zip(raw.par_chunks(width * 3))
+            .for_each(|(out, row)| {
+                for (out, (i, &value)) in out.iter_mut().zip(row.iter().enumerate()) {
+                    *out = lookup[i % 3][value as usize];
+                }
+            }),
+        Layout::Nchw => image
+            .par_chunks_mut(plane)
+            .enumerate()
+            .for_each(|(c, channel)| {
+                channel
+                    .par_chunks_mut(width)
+                    .zip(raw.par_chunks(width * 3))
+                    .for_each(|(out, row)| {
+                        for (out, pixel) in out.iter_mut().zip(row.chunks_exact(3)) {
+                            *out = lookup[c][pixel[c] as usize];
+                        }
+                    })
+            }),
+    }
+    image
+}
+
+fn process_pixels(img: DynamicImage, config: &PreprocessConfig) -> Array4<f32> {
+    let mut input: Array4<f32> = Array::zeros(config.shape());
+    for pixel in img.pixels() {
+        let x: usize = pixel.0 as _;
+        let y: usize = pixel.1 as _;
+        let [r, g, b, _] = pixel.2 .0;
+        for (c, value) in [r, g, b].into_iter().enumerate() {
+            let value: f32 = ((value as f32) / config.scale - config.mean[c]) / config.std[c];
+            for n in 0..config.batch {
+                match config.layout {
+                    Layout::Nchw => input[[n, c, y, x]] = value,
+                    Layout::Nhwc => input[[n, y, x, c]] = value,
+                }
+            }
+        }
+    }
+    input
+}
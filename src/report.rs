@@ -0,0 +1,145 @@
+#[cfg(feature = "flamegraph")]
+use crate::flamegraph::FlamegraphReport;
+use crate::{
+    binding::BindingComparison,
+    bundle::ReplayReport,
+    cgroup::CgroupReport,
+    cuda::CudaReport,
+    detection::Detection,
+    environment::EnvironmentInfo,
+    histogram::LatencyHistogram,
+    interval::IntervalReport,
+    metadata::RunMetadata,
+    metrics::MetricsSummary,
+    outliers::OutlierReport,
+    postprocess::OutputSummary,
+    resize::ResizeComparison,
+    segmentation::SegmentationMask,
+    sla::SlaReport,
+    tensorrt::TensorRtReport,
+    thermal::ThermalReport,
+    threads::ThreadCpuReport,
+    tracker::{BenchmarkTracker, PhaseNode, SpanKind},
+    video::VideoReport,
+};
+use serde::Serialize;
+
+/// What the post-processing of a task produced.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "task", content = "result", rename_all = "snake_case")]
+pub enum TaskOutput {
+    Classification(Vec<OutputSummary>),
+    Detection(Vec<Detection>),
+    Segmentation(SegmentationMask),
+}
+
+impl std::fmt::Display for TaskOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskOutput::Classification(summaries) => {
+                for summary in summaries {
+                    write!(f, "{}", summary)?;
+                }
+                Ok(())
+            }
+            TaskOutput::Detection(detections) => {
+                writeln!(f, "Detections: {}", detections.len())?;
+                for detection in detections {
+                    writeln!(f, "  {}", detection)?;
+                }
+                Ok(())
+            }
+            TaskOutput::Segmentation(mask) => write!(f, "{}", mask),
+        }
+    }
+}
+
+/// Everything a run measured and produced, for `--output json` and the `serve` API.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunReport {
+    /// Run id and `--tag`s, at the top level of the JSON.
+    #[serde(flatten)]
+    pub metadata: RunMetadata,
+    pub model: String,
+    pub iterations: u32,
+    pub environment: EnvironmentInfo,
+    pub operations: Vec<MetricsSummary>,
+    pub phases: Vec<MetricsSummary>,
+    /// Phases nested as they ran, with inclusive and exclusive times.
+    pub phase_tree: Vec<PhaseNode>,
+    pub total: MetricsSummary,
+    pub container: Option<CgroupReport>,
+    /// Percentiles and buckets of the inference latency, when there were several iterations.
+    pub latency: Option<LatencyHistogram>,
+    /// Robust statistics and MAD-flagged outliers of the inference latency.
+    pub outliers: Option<OutlierReport>,
+    /// CPU time per thread and parallelism of every operation, for `--thread-cpu` runs.
+    pub thread_cpu: Option<ThreadCpuReport>,
+    /// Plain against IOBinding inference latency, for `--io-binding` runs.
+    pub io_binding: Option<BindingComparison>,
+    /// Latency and top-1 prediction per resize configuration, when several were given.
+    pub resize: Option<ResizeComparison>,
+    /// Per-frame latency and throughput of a `--video` run.
+    pub video: Option<VideoReport>,
+    /// Scheduling jitter and latency of an `--interval` run.
+    pub interval: Option<IntervalReport>,
+    /// Temperatures, clock frequencies and throttling of a `--thermal` run.
+    pub thermal: Option<ThermalReport>,
+    /// CUDA execution provider settings and GPU memory high-water mark, when any was asked for.
+    pub cuda: Option<CudaReport>,
+    /// Engine cache, precision and cold start of a `--tensorrt` run.
+    pub tensorrt: Option<TensorRtReport>,
+    /// Pass/fail verdict against `--sla-latency` and `--sla-memory`.
+    pub sla: Option<SlaReport>,
+    /// Where the CPU flamegraph of a `--flamegraph` run was written.
+    #[cfg(feature = "flamegraph")]
+    pub flamegraph: Option<FlamegraphReport>,
+    /// Bundle the inputs of a `replay` came from and what changed since they were recorded.
+    pub replay: Option<ReplayReport>,
+    /// Whether SIGINT or SIGTERM stopped the run, leaving partial metrics.
+    pub interrupted: bool,
+    /// `None` when the run was interrupted before its outputs were post-processed, and for replays.
+    pub output: Option<TaskOutput>,
+    pub threads: Option<usize>,
+}
+
+impl RunReport {
+    pub fn new(
+        metadata: RunMetadata,
+        model: &str,
+        iterations: u32,
+        environment: EnvironmentInfo,
+        tracker: &BenchmarkTracker,
+        container: Option<CgroupReport>,
+        output: Option<TaskOutput>,
+    ) -> Self {
+        Self {
+            metadata,
+            model: model.to_string(),
+            iterations,
+            environment,
+            operations: tracker.summaries(SpanKind::Operation),
+            phases: tracker.summaries(SpanKind::Phase),
+            phase_tree: tracker.phase_tree(),
+            total: MetricsSummary::from(&tracker.get_total_metrics()),
+            container,
+            latency: LatencyHistogram::from_tracker(tracker, "Inference"),
+            outliers: OutlierReport::from_tracker(tracker, "Inference"),
+            thread_cpu: ThreadCpuReport::from_tracker(tracker),
+            io_binding: BindingComparison::from_tracker(tracker),
+            resize: None,
+            video: None,
+            interval: None,
+            thermal: None,
+            cuda: None,
+            tensorrt: None,
+            sla: None,
+            #[cfg(feature = "flamegraph")]
+            flamegraph: None,
+            replay: None,
+            interrupted: tracker.is_interrupted(),
+            output,
+            threads: num_threads::num_threads().map(usize::from),
+        }
+    }
+}
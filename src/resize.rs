@@ -0,0 +1,136 @@
+use crate::{
+    error::AppError,
+    input::PreparedInput,
+    interrupt::interrupted,
+    postprocess::{extract_outputs, post_process_outputs, OutputSchema, OutputSummary},
+    preprocess::{ResizeFilter, ResizeMode},
+    stats::{mean, millis},
+    tensor::{convert_inputs, session_inputs},
+    tracker::BenchmarkTracker,
+};
+use ort::{
+    session::{Session, SessionOutputs},
+    value::DynValue,
+};
+use serde::Serialize;
+
+/// Preprocessing and inference measured with one resize filter and mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResizeResult {
+    pub filter: ResizeFilter,
+    pub mode: ResizeMode,
+    pub preprocess_ms: f64,
+    pub inference_mean_ms: f64,
+    /// Top-1 prediction of the first model output.
+    pub predicted_index: Option<usize>,
+    pub score: Option<f32>,
+}
+
+/// Every `--resize-filter` and `--resize-mode` combination run on the same images.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResizeComparison {
+    pub results: Vec<ResizeResult>,
+}
+
+fn operation_name(operation: &str, filter: ResizeFilter, mode: ResizeMode) -> String {
+    format!("{} ({}, {})", operation, filter, mode)
+}
+
+/// Preprocesses the images once per combination and runs `iterations` inferences on the result.
+/// Both are recorded as operations named after the combination, e.g. "Inference (nearest, stretch)".
+pub fn compare_resizing(
+    model: &mut Session,
+    prepared: &[PreparedInput],
+    combinations: &[(ResizeFilter, ResizeMode)],
+    iterations: u32,
+    tracker: &mut BenchmarkTracker,
+) -> Result<ResizeComparison, AppError> {
+    let schema: OutputSchema = OutputSchema::new(&model.outputs);
+    let mut results: Vec<ResizeResult> = Vec::with_capacity(combinations.len());
+
+    for &(filter, mode) in combinations {
+        if interrupted() {
+            break;
+        }
+        let preprocess: String = operation_name("Pre-processing", filter, mode);
+        tracker.start_operation(&preprocess);
+        let values: Vec<(String, DynValue)> = prepared
+            .iter()
+            .cloned()
+            .map(|mut input| {
+                input.config.filter = filter;
+                input.config.mode = mode;
+                input.into_value()
+            })
+            .collect::<Result<_, _>>()?;
+        let inputs: Vec<(String, DynValue)> = convert_inputs(values, &model.inputs)?;
+        tracker.finish_operation();
+
+        let inference: String = operation_name("Inference", filter, mode);
+        for _ in 1..iterations {
+            if interrupted() {
+                break;
+            }
+            tracker.start_operation(&inference);
+            model.run(session_inputs(&inputs))?;
+            tracker.finish_operation();
+        }
+        tracker.start_operation(&inference);
+        let outputs: SessionOutputs<'_> = model.run(session_inputs(&inputs))?;
+        tracker.finish_operation();
+
+        let summaries: Vec<OutputSummary> =
+            post_process_outputs(&extract_outputs(&outputs, &schema))?;
+        let prediction: Option<(usize, f32)> = summaries.iter().find_map(|summary| match summary {
+            OutputSummary::Prediction {
+                predicted_index,
+                score,
+                ..
+            } => Some((*predicted_index, *score)),
+            OutputSummary::Skipped { .. } => None,
+        });
+
+        results.push(ResizeResult {
+            filter,
+            mode,
+            preprocess_ms: mean_ms(tracker, &preprocess),
+            inference_mean_ms: mean_ms(tracker, &inference),
+            predicted_index: prediction.map(|(index, _)| index),
+            score: prediction.map(|(_, score)| score),
+        });
+    }
+
+    Ok(ResizeComparison { results })
+}
+
+fn mean_ms(tracker: &BenchmarkTracker, name: &str) -> f64 {
+    millis(mean(&tracker.operation_times(name)))
+}
+
+impl std::fmt::Display for ResizeComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Resize Metrics =============")?;
+        writeln!(
+            f,
+            "{:<12} {:<12} {:>14} {:>14} {:>8} {:>10}",
+            "Filter", "Mode", "Preprocess ms", "Inference ms", "Class", "Score"
+        )?;
+        for result in &self.results {
+            let (class, score) = match (result.predicted_index, result.score) {
+                (Some(index), Some(score)) => (index.to_string(), format!("{:.4}", score)),
+                _ => ("-".to_string(), "-".to_string()),
+            };
+            writeln!(
+                f,
+                "{:<12} {:<12} {:>14.3} {:>14.3} {:>8} {:>10}",
+                result.filter.to_string(),
+                result.mode.to_string(),
+                result.preprocess_ms,
+                result.inference_mean_ms,
+                class,
+                score
+            )?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
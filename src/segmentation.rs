@@ -0,0 +1,131 @@
+use crate::{error::AppError, postprocess::OutputTensor};
+use image::{Rgb, RgbImage};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+#[derive(Debug, Clone)]
+pub struct SegmentationMask {
+    pub width: usize,
+    pub height: usize,
+    pub num_classes: usize,
+    /// Row-major class index of every pixel.
+    pub classes: Vec<u32>,
+}
+
+impl SegmentationMask {
+    /// Pixel count of every class that appears in the mask, most frequent first.
+    pub fn class_histogram(&self) -> Vec<(u32, usize)> {
+        let mut counts: Vec<usize> = vec![0; self.num_classes.max(1)];
+        for &class in &self.classes {
+            if class as usize >= counts.len() {
+                counts.resize(class as usize + 1, 0);
+            }
+            counts[class as usize] += 1;
+        }
+
+        let mut histogram: Vec<(u32, usize)> = counts
+            .into_iter()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+            .map(|(class, count)| (class as u32, count))
+            .collect();
+        histogram.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        histogram
+    }
+}
+
+/// Serialized as the class histogram, the per-pixel classes are too large for a report.
+impl Serialize for SegmentationMask {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut mask = serializer.serialize_struct("SegmentationMask", 4)?;
+        mask.serialize_field("width", &self.width)?;
+        mask.serialize_field("height", &self.height)?;
+        mask.serialize_field("num_classes", &self.num_classes)?;
+        mask.serialize_field("class_histogram", &self.class_histogram())?;
+        mask.end()
+    }
+}
+
+impl std::fmt::Display for SegmentationMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total: f32 = self.classes.len().max(1) as f32;
+        writeln!(f, "Segmentation Mask: {}x{}", self.width, self.height)?;
+        for (class, count) in self.class_histogram() {
+            writeln!(f, "  class {}: {:.2}%", class, count as f32 / total * 100.)?;
+        }
+        Ok(())
+    }
+}
+
+/// Argmaxes the first output into a per-pixel class map. Accepts `[1, C, H, W]` and `[1, H, W, C]`
+/// score maps as well as `[1, H, W]` maps that already hold class indices.
+pub fn argmax_mask(outputs: &[OutputTensor]) -> Result<SegmentationMask, AppError> {
+    let Some(OutputTensor { shape, data, .. }) = outputs.first() else {
+        return Err(AppError::OutputError(
+            "model has no numeric output to segment".to_string(),
+        ));
+    };
+
+    match shape.as_slice() {
+        [_, height, width] => Ok(SegmentationMask {
+            width: *width,
+            height: *height,
+            num_classes: data.iter().fold(0., |max: f32, &v| max.max(v)) as usize + 1,
+            classes: data[..height * width].iter().map(|&v| v as u32).collect(),
+        }),
+        [_, a, b, c] => {
+            // Class counts are much smaller than spatial sizes, so the smaller axis holds the classes
+            let channels_first: bool = a <= c;
+            let (num_classes, height, width) = if channels_first {
+                (*a, *b, *c)
+            } else {
+                (*c, *a, *b)
+            };
+            let plane: usize = height * width;
+
+            let classes: Vec<u32> = (0..plane)
+                .map(|pixel| {
+                    let score = |class: usize| -> f32 {
+                        if channels_first {
+                            data[class * plane + pixel]
+                        } else {
+                            data[pixel * num_classes + class]
+                        }
+                    };
+                    (0..num_classes)
+                        .max_by(|&x, &y| score(x).total_cmp(&score(y)))
+                        .unwrap_or(0) as u32
+                })
+                .collect();
+
+            Ok(SegmentationMask {
+                width,
+                height,
+                num_classes,
+                classes,
+            })
+        }
+        other => Err(AppError::OutputError(format!(
+            "segmentation output must be [1, H, W] or a rank 4 score map, got {:?}",
+            other
+        ))),
+    }
+}
+
+/// PASCAL VOC style palette: spreads the bits of the class index over the three channels.
+fn class_color(class: u32) -> Rgb<u8> {
+    let (mut r, mut g, mut b) = (0u8, 0u8, 0u8);
+    let mut index: u32 = class;
+    for shift in (0..8).rev() {
+        r |= ((index & 1) as u8) << shift;
+        g |= (((index >> 1) & 1) as u8) << shift;
+        b |= (((index >> 2) & 1) as u8) << shift;
+        index >>= 3;
+    }
+    Rgb([r, g, b])
+}
+
+pub fn colorize_mask(mask: &SegmentationMask) -> RgbImage {
+    RgbImage::from_fn(mask.width as u32, mask.height as u32, |x, y| {
+        class_color(mask.classes[y as usize * mask.width + x as usize])
+    })
+}
@@ -0,0 +1,194 @@
+use crate::{error::AppError, report::RunReport};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// Body of `POST /benchmark`. Paths are resolved on the device running the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BenchmarkRequest {
+    pub model: String,
+    pub image: Option<String>,
+    pub input_tensor: Option<String>,
+    #[serde(default = "default_iterations")]
+    pub iterations: u32,
+    /// Further command line options of a run, e.g. `["--task", "detection"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+fn default_iterations() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum JobStatus {
+    Queued,
+    Running,
+    Done { result: Box<RunReport> },
+    Failed { error: String },
+}
+
+impl JobStatus {
+    fn finished(&self) -> bool {
+        matches!(self, JobStatus::Done { .. } | JobStatus::Failed { .. })
+    }
+}
+
+/// Finished jobs whose results stay available, older ones are dropped so a long-running server does
+/// not keep every report.
+pub const RETAINED_JOBS: usize = 64;
+
+type Jobs = Arc<Mutex<HashMap<u64, JobStatus>>>;
+
+/// Drops the oldest finished jobs beyond [`RETAINED_JOBS`].
+fn evict_finished(jobs: &mut HashMap<u64, JobStatus>) {
+    let mut finished: Vec<u64> = jobs
+        .iter()
+        .filter(|(_, status)| status.finished())
+        .map(|(&id, _)| id)
+        .collect();
+    if finished.len() <= RETAINED_JOBS {
+        return;
+    }
+    finished.sort_unstable();
+    for id in &finished[..finished.len() - RETAINED_JOBS] {
+        jobs.remove(id);
+    }
+}
+
+/// REST API to trigger benchmarks remotely. Runs are queued and executed one at a time on a worker
+/// thread, so concurrent requests never measure each other. The API has no authentication, anyone
+/// who can reach the address can run models on the device.
+pub struct BenchmarkServer {
+    server: Server,
+    jobs: Jobs,
+    next_id: u64,
+}
+
+impl BenchmarkServer {
+    pub fn bind(address: &str) -> Result<Self, AppError> {
+        let server: Server = Server::http(address).map_err(|e| {
+            AppError::ServerError(format!("failed to listen on {}: {}", address, e))
+        })?;
+        Ok(Self {
+            server,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_id: 1,
+        })
+    }
+
+    /// Serves requests until the process is stopped. `run` executes one queued benchmark.
+    pub fn serve<F>(self, run: F) -> Result<(), AppError>
+    where
+        F: Fn(BenchmarkRequest) -> Result<RunReport, AppError> + Send + 'static,
+    {
+        let Self {
+            server,
+            jobs,
+            mut next_id,
+        } = self;
+
+        let (sender, receiver) = mpsc::channel::<(u64, BenchmarkRequest)>();
+        let worker_jobs: Jobs = Arc::clone(&jobs);
+        thread::spawn(move || {
+            let jobs: Jobs = worker_jobs;
+            for (id, request) in receiver {
+                jobs.lock().unwrap().insert(id, JobStatus::Running);
+                tracing::info!(job = id, model = %request.model, "benchmark started");
+                // A panicking run must not take the worker, and every later job, down with it
+                let status: JobStatus = match panic::catch_unwind(AssertUnwindSafe(|| run(request)))
+                    .unwrap_or_else(|_| Err(AppError::Other("benchmark panicked".to_string())))
+                {
+                    Ok(report) => JobStatus::Done {
+                        result: Box::new(report),
+                    },
+                    Err(e) => {
+                        tracing::warn!(job = id, "benchmark failed: {}", e);
+                        JobStatus::Failed {
+                            error: e.to_string(),
+                        }
+                    }
+                };
+                let mut jobs = jobs.lock().unwrap();
+                jobs.insert(id, status);
+                evict_finished(&mut jobs);
+            }
+        });
+
+        tracing::info!(address = %server.server_addr(), "listening");
+        for mut request in server.incoming_requests() {
+            let (code, body) = handle(&mut request, &jobs, &mut next_id, &sender);
+            let header: Header = Header::from_bytes("Content-Type", "application/json")
+                .expect("static header is valid");
+            let response = Response::from_string(body.to_string())
+                .with_status_code(code)
+                .with_header(header);
+            if let Err(e) = request.respond(response) {
+                tracing::warn!("failed to respond: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn handle(
+    request: &mut Request,
+    jobs: &Jobs,
+    next_id: &mut u64,
+    sender: &mpsc::Sender<(u64, BenchmarkRequest)>,
+) -> (u16, serde_json::Value) {
+    let url: String = request.url().to_string();
+    let path: &str = url.split('?').next().unwrap_or_default();
+    tracing::debug!(method = %request.method(), path, "request");
+
+    match (request.method(), path) {
+        (Method::Post, "/benchmark") => {
+            let mut body: String = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return (400, json!({ "error": e.to_string() }));
+            }
+            let benchmark: BenchmarkRequest = match serde_json::from_str(&body) {
+                Ok(benchmark) => benchmark,
+                Err(e) => return (400, json!({ "error": e.to_string() })),
+            };
+
+            let id: u64 = *next_id;
+            *next_id += 1;
+            jobs.lock().unwrap().insert(id, JobStatus::Queued);
+            if sender.send((id, benchmark)).is_err() {
+                return (500, json!({ "error": "benchmark worker stopped" }));
+            }
+            (202, json!({ "id": id, "status": "queued" }))
+        }
+        (Method::Get, path) if path.starts_with("/results/") => {
+            let Ok(id) = path["/results/".len()..].parse::<u64>() else {
+                return (400, json!({ "error": "run ids are numbers" }));
+            };
+            match jobs.lock().unwrap().get(&id) {
+                Some(status) => {
+                    let mut body: serde_json::Value = json!(status);
+                    body["id"] = json!(id);
+                    (200, body)
+                }
+                None => (
+                    404,
+                    json!({
+                        "error": format!(
+                            "no run #{}, only the last {} finished runs are kept",
+                            id, RETAINED_JOBS
+                        )
+                    }),
+                ),
+            }
+        }
+        (_, "/benchmark") => (405, json!({ "error": "use POST" })),
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
@@ -0,0 +1,412 @@
+use crate::{
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::{Metrics, MetricsSummary},
+    tracker::{BenchmarkTracker, PhaseNode},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    str::FromStr,
+};
+
+/// What a record handed to a sink measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    Operation,
+    Phase,
+    Total,
+}
+
+impl RecordKind {
+    fn name(self) -> &'static str {
+        match self {
+            RecordKind::Operation => "operation",
+            RecordKind::Phase => "phase",
+            RecordKind::Total => "total",
+        }
+    }
+}
+
+/// Consumes the metrics of a finished run, e.g. to export them to a fleet API. A sink is handed the
+/// run first, then every operation in the order they finished, every run of the phases in the order
+/// the phases first started, and the total last.
+pub trait MetricsSink: std::fmt::Debug + Send {
+    /// Called before the records with the run id and `--tag`s of the run they belong to.
+    fn begin(&mut self, run_id: &str, tags: &BTreeMap<String, String>) -> Result<(), AppError> {
+        let _ = (run_id, tags);
+        Ok(())
+    }
+
+    fn operation(&mut self, metrics: &Metrics) -> Result<(), AppError>;
+
+    fn phase(&mut self, metrics: &Metrics) -> Result<(), AppError>;
+
+    /// The metrics of the whole run, with the phases nested as they ran.
+    fn total(&mut self, metrics: &Metrics, phase_tree: &[PhaseNode]) -> Result<(), AppError>;
+
+    /// Called after the total, to flush what was buffered.
+    fn finish(&mut self) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// Sinks the metrics of a run are handed to once it finished.
+#[derive(Debug, Default)]
+pub struct SinkRegistry {
+    sinks: Vec<Box<dyn MetricsSink>>,
+}
+
+impl SinkRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, sink: Box<dyn MetricsSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Hands the metrics of `tracker` to every sink in the order they were registered. A sink that
+    /// fails is logged and the others still get the run, the error then names how many failed.
+    pub fn export(
+        &mut self,
+        tracker: &BenchmarkTracker,
+        metadata: &RunMetadata,
+    ) -> Result<(), AppError> {
+        let mut failed: usize = 0;
+        for sink in &mut self.sinks {
+            let exported: Result<(), AppError> = sink
+                .begin(&metadata.run_id, &metadata.tags)
+                .and_then(|_| tracker.emit_metrics(sink.as_mut()))
+                .and_then(|_| sink.finish());
+            if let Err(e) = exported {
+                tracing::error!(sink = ?sink, "{}", e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(AppError::ExportError(format!(
+                "{} of {} metric sinks failed",
+                failed,
+                self.sinks.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Prints the metrics as the text output of a run.
+#[derive(Debug, Default)]
+pub struct StdoutSink {
+    phases: bool,
+}
+
+impl MetricsSink for StdoutSink {
+    fn operation(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        print!("{}", metrics);
+        Ok(())
+    }
+
+    fn phase(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        if !self.phases {
+            println!("\n=========== Phase Metrics ===========");
+            self.phases = true;
+        }
+        print!("{}", metrics);
+        Ok(())
+    }
+
+    fn total(&mut self, metrics: &Metrics, phase_tree: &[PhaseNode]) -> Result<(), AppError> {
+        if self.phases {
+            println!("====================================\n");
+
+            println!("============ Phase Tree ============");
+            print_phase_nodes(phase_tree, 0);
+            println!("====================================\n");
+        }
+        print!("{}", metrics);
+        Ok(())
+    }
+}
+
+fn print_phase_nodes(nodes: &[PhaseNode], depth: usize) {
+    for node in nodes {
+        let runs: String = if node.count > 1 {
+            format!(" (x{})", node.count)
+        } else {
+            String::new()
+        };
+        println!(
+            "{:indent$}{}{}: inclusive {:.3} ms, exclusive {:.3} ms",
+            "",
+            node.name,
+            runs,
+            node.inclusive_ms,
+            node.exclusive_ms,
+            indent = depth * 2
+        );
+        print_phase_nodes(&node.children, depth + 1);
+    }
+}
+
+/// Destination of a file sink: a file the records are appended to, or stdout for `-`.
+type SinkWriter = BufWriter<Box<dyn Write + Send>>;
+
+fn open_writer(path: &str) -> Result<(SinkWriter, bool), AppError> {
+    if path == "-" {
+        return Ok((BufWriter::new(Box::new(io::stdout())), true));
+    }
+    let file: File = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| AppError::ExportError(format!("{}: {}", path, e)))?;
+    let empty: bool = file.metadata().map_or(true, |metadata| metadata.len() == 0);
+    Ok((BufWriter::new(Box::new(file)), empty))
+}
+
+fn write_error(e: io::Error) -> AppError {
+    AppError::ExportError(format!("failed to write metrics: {}", e))
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    run_id: &'a str,
+    tags: &'a BTreeMap<String, String>,
+    kind: RecordKind,
+    #[serde(flatten)]
+    metrics: MetricsSummary,
+}
+
+/// Writes every record as a line of JSON, so the runs appended to one file can be streamed.
+pub struct JsonSink {
+    writer: SinkWriter,
+    run_id: String,
+    tags: BTreeMap<String, String>,
+}
+
+impl std::fmt::Debug for JsonSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonSink")
+            .field("run_id", &self.run_id)
+            .finish()
+    }
+}
+
+impl JsonSink {
+    /// Appends to the file at `path`, or writes to stdout for `-`.
+    pub fn create(path: &str) -> Result<Self, AppError> {
+        let (writer, _) = open_writer(path)?;
+        Ok(Self {
+            writer,
+            run_id: String::new(),
+            tags: BTreeMap::new(),
+        })
+    }
+
+    fn write(&mut self, kind: RecordKind, metrics: &Metrics) -> Result<(), AppError> {
+        let record: JsonRecord<'_> = JsonRecord {
+            run_id: &self.run_id,
+            tags: &self.tags,
+            kind,
+            metrics: MetricsSummary::from(metrics),
+        };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer).map_err(write_error)
+    }
+}
+
+impl MetricsSink for JsonSink {
+    fn begin(&mut self, run_id: &str, tags: &BTreeMap<String, String>) -> Result<(), AppError> {
+        self.run_id = run_id.to_string();
+        self.tags = tags.clone();
+        Ok(())
+    }
+
+    fn operation(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        self.write(RecordKind::Operation, metrics)
+    }
+
+    fn phase(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        self.write(RecordKind::Phase, metrics)
+    }
+
+    fn total(&mut self, metrics: &Metrics, _phase_tree: &[PhaseNode]) -> Result<(), AppError> {
+        self.write(RecordKind::Total, metrics)
+    }
+
+    fn finish(&mut self) -> Result<(), AppError> {
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+const CSV_HEADER: &str = "run_id,tags,kind,name,wall_clock_time_ms,user_time_ms,system_time_ms,\
+rss_before,rss_after,rss_delta,peak_rss,peak_rss_growth,cpu_usage,allocations,allocated_bytes,process_heap_peak";
+
+/// Writes a CSV row per record, with the run id and the tags as `KEY=VALUE;...` on every row so the
+/// runs appended to one file can be told apart. The header is only written to an empty file.
+pub struct CsvSink {
+    writer: SinkWriter,
+    header: bool,
+    run_id: String,
+    tags: String,
+}
+
+impl std::fmt::Debug for CsvSink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CsvSink")
+            .field("run_id", &self.run_id)
+            .finish()
+    }
+}
+
+impl CsvSink {
+    /// Appends to the file at `path`, or writes to stdout for `-`.
+    pub fn create(path: &str) -> Result<Self, AppError> {
+        let (writer, header) = open_writer(path)?;
+        Ok(Self {
+            writer,
+            header,
+            run_id: String::new(),
+            tags: String::new(),
+        })
+    }
+
+    fn write(&mut self, kind: RecordKind, metrics: &Metrics) -> Result<(), AppError> {
+        let summary: MetricsSummary = MetricsSummary::from(metrics);
+        writeln!(
+            self.writer,
+            "{},{},{},{},{:.3},{:.3},{:.3},{},{},{},{},{},{:.2},{},{},{}",
+            csv_field(&self.run_id),
+            csv_field(&self.tags),
+            kind.name(),
+            csv_field(&summary.name),
+            summary.wall_clock_time_ms,
+            summary.user_time_ms,
+            summary.system_time_ms,
+            summary.rss_before,
+            summary.rss_after,
+            summary.rss_delta,
+            summary.peak_rss,
+            summary.peak_rss_growth,
+            summary.cpu_usage,
+            summary.allocations,
+            summary.allocated_bytes,
+            summary.process_heap_peak
+        )
+        .map_err(write_error)
+    }
+}
+
+impl MetricsSink for CsvSink {
+    fn begin(&mut self, run_id: &str, tags: &BTreeMap<String, String>) -> Result<(), AppError> {
+        self.run_id = run_id.to_string();
+        self.tags = tags
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<String>>()
+            .join(";");
+        if self.header {
+            writeln!(self.writer, "{}", CSV_HEADER).map_err(write_error)?;
+            self.header = false;
+        }
+        Ok(())
+    }
+
+    fn operation(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        self.write(RecordKind::Operation, metrics)
+    }
+
+    fn phase(&mut self, metrics: &Metrics) -> Result<(), AppError> {
+        self.write(RecordKind::Phase, metrics)
+    }
+
+    fn total(&mut self, metrics: &Metrics, _phase_tree: &[PhaseNode]) -> Result<(), AppError> {
+        self.write(RecordKind::Total, metrics)
+    }
+
+    fn finish(&mut self) -> Result<(), AppError> {
+        self.writer.flush().map_err(write_error)
+    }
+}
+
+/// Quotes a field holding a separator, a quote or a line break, doubling its quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkFormat {
+    Json,
+    Csv,
+}
+
+impl SinkFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            SinkFormat::Json => "json",
+            SinkFormat::Csv => "csv",
+        }
+    }
+}
+
+/// A `--sink FORMAT=PATH` argument, e.g. `csv=metrics.csv` or `json=-` for stdout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkSpec {
+    pub format: SinkFormat,
+    pub path: String,
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s
+            .split_once('=')
+            .filter(|(_, path)| !path.is_empty())
+            .ok_or_else(|| format!("expected FORMAT=PATH, got '{}'", s))?;
+        let format: SinkFormat = match format.to_ascii_lowercase().as_str() {
+            "json" => SinkFormat::Json,
+            "csv" => SinkFormat::Csv,
+            other => {
+                return Err(format!(
+                    "unknown sink format '{}', expected json or csv",
+                    other
+                ))
+            }
+        };
+        Ok(Self {
+            format,
+            path: path.to_string(),
+        })
+    }
+}
+
+impl SinkSpec {
+    /// Whether the records go to stdout, where they would mix with the printed results.
+    pub fn is_stdout(&self) -> bool {
+        self.path == "-"
+    }
+
+    pub fn open(&self) -> Result<Box<dyn MetricsSink>, AppError> {
+        Ok(match self.format {
+            SinkFormat::Json => Box::new(JsonSink::create(&self.path)?),
+            SinkFormat::Csv => Box::new(CsvSink::create(&self.path)?),
+        })
+    }
+}
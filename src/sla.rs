@@ -0,0 +1,106 @@
+use crate::histogram::LatencyHistogram;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Exit code of a run that finished but missed one of its SLAs, apart from the 1 of a failed run.
+pub const SLA_FAILED_EXIT_CODE: i32 = 3;
+
+/// Limits a run has to stay within, for `--sla-latency` and `--sla-memory`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlaLimits {
+    /// Highest p95 inference latency.
+    pub latency: Option<Duration>,
+    /// Highest peak RSS of the process, in bytes.
+    pub memory: Option<u64>,
+}
+
+impl SlaLimits {
+    pub fn is_set(&self) -> bool {
+        self.latency.is_some() || self.memory.is_some()
+    }
+}
+
+/// One limit against what the run measured. A metric the run did not measure, e.g. the latency of
+/// a run interrupted before its first inference, fails its check.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlaCheck {
+    pub metric: String,
+    pub unit: String,
+    pub limit: f64,
+    pub measured: Option<f64>,
+    pub passed: bool,
+}
+
+impl SlaCheck {
+    fn new(metric: &str, unit: &str, limit: f64, measured: Option<f64>) -> Self {
+        Self {
+            metric: metric.to_string(),
+            unit: unit.to_string(),
+            limit,
+            measured,
+            passed: measured.is_some_and(|measured| measured <= limit),
+        }
+    }
+}
+
+/// Verdict of a run against its SLAs.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlaReport {
+    pub checks: Vec<SlaCheck>,
+    pub passed: bool,
+}
+
+impl SlaReport {
+    /// `peak_memory` is the highest RSS of the process over the run, in bytes.
+    pub fn new(limits: &SlaLimits, latency: Option<&LatencyHistogram>, peak_memory: u64) -> Self {
+        let mut checks: Vec<SlaCheck> = Vec::new();
+        if let Some(limit) = limits.latency {
+            checks.push(SlaCheck::new(
+                "p95 latency",
+                "ms",
+                limit.as_secs_f64() * 1000.,
+                latency.map(|latency| latency.p95_ms),
+            ));
+        }
+        if let Some(limit) = limits.memory {
+            checks.push(SlaCheck::new(
+                "Peak memory",
+                "bytes",
+                limit as f64,
+                Some(peak_memory as f64),
+            ));
+        }
+
+        Self {
+            passed: checks.iter().all(|check| check.passed),
+            checks,
+        }
+    }
+}
+
+impl std::fmt::Display for SlaReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |value: f64, unit: &str| match unit {
+            "ms" => format!("{:.3} {}", value, unit),
+            _ => format!("{:.0} {}", value, unit),
+        };
+        let verdict = |passed: bool| if passed { "PASS" } else { "FAIL" };
+
+        writeln!(f, "============= SLA Metrics =============")?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{}: {} (limit {}) {}",
+                check.metric,
+                check.measured.map_or_else(
+                    || "not measured".to_string(),
+                    |measured| value(measured, &check.unit)
+                ),
+                value(check.limit, &check.unit),
+                verdict(check.passed)
+            )?;
+        }
+        writeln!(f, "Verdict: {}", verdict(self.passed))?;
+        writeln!(f, "=======================================")
+    }
+}
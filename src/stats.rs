@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// A duration in milliseconds, the unit of the reports.
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+/// Mean of `durations`, zero when there are none.
+pub fn mean(durations: &[Duration]) -> Duration {
+    if durations.is_empty() {
+        return Duration::ZERO;
+    }
+    durations.iter().sum::<Duration>() / durations.len() as u32
+}
+
+/// Nearest-rank percentile of durations sorted in ascending order: the shortest duration that at
+/// least `fraction` of them do not exceed. Zero when there are none.
+pub fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    let rank: usize = (sorted.len() as f64 * fraction).ceil() as usize;
+    sorted
+        .get(rank.saturating_sub(1))
+        .copied()
+        .unwrap_or_default()
+}
@@ -0,0 +1,242 @@
+use crate::error::AppError;
+use half::f16;
+use ndarray::ArrayD;
+use ndarray_npy::{ReadNpyError, ReadNpyExt, ReadableElement};
+use ort::{
+    session::{Input, SessionInputValue},
+    tensor::{Shape, TensorElementType},
+    value::{DynValue, Tensor, ValueType},
+};
+
+/// A tensor saved with `numpy.save`, in the element type it was saved with.
+#[derive(Debug, Clone)]
+pub enum NpyTensor {
+    Float32(ArrayD<f32>),
+    Float64(ArrayD<f64>),
+    Int8(ArrayD<i8>),
+    Uint8(ArrayD<u8>),
+    Int32(ArrayD<i32>),
+    Int64(ArrayD<i64>),
+    Bool(ArrayD<bool>),
+}
+
+/// Reads `bytes` as a `.npy` tensor of `T`, or gives back the dtype it was saved with instead.
+fn read_npy_as<T: ReadableElement>(bytes: &[u8]) -> Result<Result<ArrayD<T>, String>, AppError> {
+    match ArrayD::<T>::read_npy(bytes) {
+        Ok(array) => Ok(Ok(array)),
+        Err(ReadNpyError::WrongDescriptor(descriptor)) => Ok(Err(descriptor.to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+impl NpyTensor {
+    /// Decodes a `.npy` file in the dtype its header declares.
+    pub fn read(bytes: &[u8]) -> Result<Self, AppError> {
+        if let Ok(array) = read_npy_as::<f32>(bytes)? {
+            return Ok(NpyTensor::Float32(array));
+        }
+        if let Ok(array) = read_npy_as::<f64>(bytes)? {
+            return Ok(NpyTensor::Float64(array));
+        }
+        if let Ok(array) = read_npy_as::<i8>(bytes)? {
+            return Ok(NpyTensor::Int8(array));
+        }
+        if let Ok(array) = read_npy_as::<u8>(bytes)? {
+            return Ok(NpyTensor::Uint8(array));
+        }
+        if let Ok(array) = read_npy_as::<i32>(bytes)? {
+            return Ok(NpyTensor::Int32(array));
+        }
+        if let Ok(array) = read_npy_as::<i64>(bytes)? {
+            return Ok(NpyTensor::Int64(array));
+        }
+        match read_npy_as::<bool>(bytes)? {
+            Ok(array) => Ok(NpyTensor::Bool(array)),
+            Err(dtype) => Err(AppError::InputError(format!(
+                "the tensor has dtype {}, supported are float32, float64, int8, uint8, int32, int64 and bool",
+                dtype
+            ))),
+        }
+    }
+
+    pub fn element_type(&self) -> TensorElementType {
+        match self {
+            NpyTensor::Float32(_) => TensorElementType::Float32,
+            NpyTensor::Float64(_) => TensorElementType::Float64,
+            NpyTensor::Int8(_) => TensorElementType::Int8,
+            NpyTensor::Uint8(_) => TensorElementType::Uint8,
+            NpyTensor::Int32(_) => TensorElementType::Int32,
+            NpyTensor::Int64(_) => TensorElementType::Int64,
+            NpyTensor::Bool(_) => TensorElementType::Bool,
+        }
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        match self {
+            NpyTensor::Float32(array) => array.shape(),
+            NpyTensor::Float64(array) => array.shape(),
+            NpyTensor::Int8(array) => array.shape(),
+            NpyTensor::Uint8(array) => array.shape(),
+            NpyTensor::Int32(array) => array.shape(),
+            NpyTensor::Int64(array) => array.shape(),
+            NpyTensor::Bool(array) => array.shape(),
+        }
+    }
+
+    pub fn into_value(self) -> Result<DynValue, AppError> {
+        let value: DynValue = match self {
+            NpyTensor::Float32(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Float64(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Int8(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Uint8(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Int32(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Int64(array) => Tensor::from_array(array)?.into_dyn(),
+            NpyTensor::Bool(array) => Tensor::from_array(array)?.into_dyn(),
+        };
+        Ok(value)
+    }
+}
+
+/// Checks the shape, and the element type of tensors that are not f32: [`convert_inputs`] casts f32
+/// tensors to the element type the input declares, any other is fed as it was saved.
+pub fn validate_input_tensor(input: &Input, tensor: &NpyTensor) -> Result<(), AppError> {
+    let ValueType::Tensor { ty, shape, .. } = &input.input_type else {
+        return Err(AppError::InputShapeError(format!(
+            "input '{}' is not a tensor ({})",
+            input.name, input.input_type
+        )));
+    };
+
+    let element_type: TensorElementType = tensor.element_type();
+    if element_type != TensorElementType::Float32 && element_type != *ty {
+        return Err(AppError::InputError(format!(
+            "input '{}' takes {}, the tensor is {}",
+            input.name, ty, element_type
+        )));
+    }
+
+    let actual: &[usize] = tensor.shape();
+    let matches: bool = shape.len() == actual.len()
+        && shape
+            .iter()
+            .zip(actual)
+            .all(|(&expected, &actual)| expected < 0 || expected as usize == actual);
+
+    if !matches {
+        return Err(AppError::InputShapeError(format!(
+            "input '{}' expects {}, tensor has {:?}",
+            input.name, shape, actual
+        )));
+    }
+
+    Ok(())
+}
+
+/// Casts an f32 value to the element type the model input declares, e.g. for fp16 or integer-input
+/// quantized models. Values that are not f32 tensors are passed through unchanged, integer inputs
+/// must already hold integers in range.
+pub fn convert_value(value: DynValue, target: TensorElementType) -> Result<DynValue, AppError> {
+    if value.dtype().tensor_type() != Some(TensorElementType::Float32)
+        || target == TensorElementType::Float32
+    {
+        return Ok(value);
+    }
+
+    let (shape, data) = value.try_extract_tensor::<f32>()?;
+    let shape: Shape = shape.clone();
+    let converted: DynValue = match target {
+        TensorElementType::Float16 => {
+            let data: Vec<f16> = data.iter().map(|&v| f16::from_f32(v)).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        TensorElementType::Float64 => {
+            let data: Vec<f64> = data.iter().map(|&v| v as f64).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        TensorElementType::Uint8 => {
+            check_integral(data, target, 0., 256.)?;
+            let data: Vec<u8> = data.iter().map(|&v| v as u8).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        TensorElementType::Int8 => {
+            check_integral(data, target, -128., 128.)?;
+            let data: Vec<i8> = data.iter().map(|&v| v as i8).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        TensorElementType::Int32 => {
+            check_integral(data, target, -2f32.powi(31), 2f32.powi(31))?;
+            let data: Vec<i32> = data.iter().map(|&v| v as i32).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        TensorElementType::Int64 => {
+            check_integral(data, target, -2f32.powi(63), 2f32.powi(63))?;
+            let data: Vec<i64> = data.iter().map(|&v| v as i64).collect();
+            Tensor::from_array((shape, data))?.into_dyn()
+        }
+        other => {
+            return Err(AppError::InputError(format!(
+                "cannot convert f32 input to {}",
+                other
+            )))
+        }
+    };
+    Ok(converted)
+}
+
+/// Largest magnitude up to which f32 holds every integer exactly.
+const MAX_EXACT_INTEGER: f32 = 16_777_216.;
+
+/// Refuses values an integer input cannot hold in `[min, end)` instead of rounding them: the pixels
+/// of the default preprocessing are normalized to [0, 1] and would all become 0 or 1. Beyond 2^24 an
+/// f32 may already be a neighbour of the integer that was meant, so such values are refused too.
+fn check_integral(
+    data: &[f32],
+    target: TensorElementType,
+    min: f32,
+    end: f32,
+) -> Result<(), AppError> {
+    if let Some(v) = data
+        .iter()
+        .find(|&&v| v.fract() != 0. || !(min..end).contains(&v))
+    {
+        return Err(AppError::InputError(format!(
+            "{} input takes integers in [{}, {}), got {}; pass --preprocess scale=1 to feed raw 0-255 pixels",
+            target, min, end, v
+        )));
+    }
+    match data.iter().find(|&&v| v.abs() > MAX_EXACT_INTEGER) {
+        Some(v) => Err(AppError::InputError(format!(
+            "{} input got {}, beyond 2^24 f32 cannot hold every integer; save the tensor as int32 or int64 instead",
+            target, v
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Applies [`convert_value`] to every named input using the model's declared input types.
+pub fn convert_inputs(
+    inputs: Vec<(String, DynValue)>,
+    model_inputs: &[Input],
+) -> Result<Vec<(String, DynValue)>, AppError> {
+    inputs
+        .into_iter()
+        .map(|(name, value)| {
+            let target: Option<TensorElementType> = model_inputs
+                .iter()
+                .find(|input| input.name == name)
+                .and_then(|input| input.input_type.tensor_type());
+            match target {
+                Some(target) => Ok((name, convert_value(value, target)?)),
+                None => Ok((name, value)),
+            }
+        })
+        .collect()
+}
+
+/// Borrows prepared inputs for `Session::run`, so the same inputs can be fed to several runs.
+pub fn session_inputs(inputs: &[(String, DynValue)]) -> Vec<(&str, SessionInputValue<'_>)> {
+    inputs
+        .iter()
+        .map(|(name, value)| (name.as_str(), SessionInputValue::from(value)))
+        .collect()
+}
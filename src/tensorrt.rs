@@ -0,0 +1,201 @@
+use crate::tracker::{BenchmarkTracker, SpanKind, SpanRecord};
+use ort::execution_providers::{ExecutionProviderDispatch, TensorRTExecutionProvider};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// TensorRT execution provider settings that can be changed from the command line.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TensorRtOptions {
+    /// CUDA device the engines are built for, as the CUDA execution provider.
+    pub device_id: Option<i32>,
+    /// Directory built engines are saved to and loaded from on later runs.
+    pub engine_cache: Option<PathBuf>,
+    pub fp16: bool,
+    pub int8: bool,
+    /// Largest GPU memory TensorRT may use while building an engine, in bytes.
+    pub workspace_size: Option<usize>,
+}
+
+impl TensorRtOptions {
+    /// Engines are cached under a prefix named after the model file, so the engines of one model can
+    /// be told apart from those of the others sharing the directory. `model` is the local file, after
+    /// any download.
+    pub fn execution_provider(&self, model: &Path) -> ExecutionProviderDispatch {
+        let mut provider: TensorRTExecutionProvider = TensorRTExecutionProvider::default()
+            .with_fp16(self.fp16)
+            .with_int8(self.int8);
+        if let Some(device_id) = self.device_id {
+            provider = provider.with_device_id(device_id);
+        }
+        if let Some(size) = self.workspace_size {
+            provider = provider.with_max_workspace_size(size);
+        }
+        if let Some(dir) = &self.engine_cache {
+            provider = provider
+                .with_engine_cache(true)
+                .with_engine_cache_path(dir.display())
+                .with_engine_cache_prefix(engine_prefix(model))
+                .with_timing_cache(true)
+                .with_timing_cache_path(dir.display());
+        }
+        provider.build()
+    }
+
+    /// Engines of the model already in the cache directory, empty without one.
+    pub fn cached_engines(&self, model: &Path) -> Vec<PathBuf> {
+        let Some(dir) = &self.engine_cache else {
+            return Vec::new();
+        };
+        let prefix: String = engine_prefix(model);
+        let mut engines: Vec<PathBuf> = fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        engines.retain(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "engine")
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+        });
+        engines.sort();
+        engines
+    }
+}
+
+/// File stem of the model, restricted to characters that are safe in a file name, with its size and
+/// modification time like the optimized model cache, so a changed or replaced model file misses the
+/// cache instead of loading the engines of the old one.
+fn engine_prefix(model: &Path) -> String {
+    let stem: String = model
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let stem: &str = if stem.is_empty() { "model" } else { &stem };
+
+    // A model that cannot be read fails to load anyway, it only needs a prefix of its own
+    let Ok(metadata) = fs::metadata(model) else {
+        return stem.to_string();
+    };
+    let modified: u64 = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    format!("{}-{:x}-{:x}", stem, metadata.len(), modified)
+}
+
+/// Cold start of a run with the TensorRT execution provider. Building an engine happens while the
+/// session is created, or on the first inference for models with dynamic shapes, so both are
+/// reported apart from the inference runs that follow.
+#[derive(Debug, Clone, Serialize)]
+pub struct TensorRtReport {
+    pub engine_cache: Option<String>,
+    pub fp16: bool,
+    pub int8: bool,
+    pub workspace_size: Option<usize>,
+    /// Whether an engine of the model was in the cache before the run.
+    pub cached_engine: bool,
+    /// Engines of the model written to the cache by this run.
+    pub engines_built: usize,
+    pub session_ms: Option<f64>,
+    pub first_inference_ms: Option<f64>,
+    /// Mean of the inference runs after the first.
+    pub steady_inference_ms: Option<f64>,
+    /// Session creation and first inference, until the model answers at full speed.
+    pub startup_ms: Option<f64>,
+}
+
+impl TensorRtReport {
+    pub fn new(
+        options: &TensorRtOptions,
+        engines_before: &[PathBuf],
+        engines_after: &[PathBuf],
+        tracker: &BenchmarkTracker,
+    ) -> Self {
+        let millis = |span: &SpanRecord| span.metrics.wall_clock_time.as_secs_f64() * 1000.;
+        let session_ms: Option<f64> = tracker
+            .spans()
+            .iter()
+            .find(|span| span.kind == SpanKind::Operation && span.name.starts_with("loadmodel"))
+            .map(millis);
+        let inferences: Vec<f64> = tracker
+            .spans()
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.name == "Inference")
+            .map(millis)
+            .collect();
+        let first_inference_ms: Option<f64> = inferences.first().copied();
+        let steady_inference_ms: Option<f64> = (inferences.len() > 1)
+            .then(|| inferences[1..].iter().sum::<f64>() / (inferences.len() - 1) as f64);
+
+        Self {
+            engine_cache: options
+                .engine_cache
+                .as_ref()
+                .map(|dir| dir.display().to_string()),
+            fp16: options.fp16,
+            int8: options.int8,
+            workspace_size: options.workspace_size,
+            cached_engine: !engines_before.is_empty(),
+            engines_built: engines_after
+                .iter()
+                .filter(|engine| !engines_before.contains(engine))
+                .count(),
+            session_ms,
+            first_inference_ms,
+            steady_inference_ms,
+            startup_ms: session_ms.map(|session| session + first_inference_ms.unwrap_or(0.)),
+        }
+    }
+}
+
+impl std::fmt::Display for TensorRtReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= TensorRT Metrics =============")?;
+        let precision: &str = match (self.fp16, self.int8) {
+            (true, true) => "fp16 + int8",
+            (false, true) => "int8",
+            (true, false) => "fp16",
+            (false, false) => "fp32",
+        };
+        writeln!(f, "Precision: {}", precision)?;
+        if let Some(size) = self.workspace_size {
+            writeln!(f, "Workspace size: {} bytes", size)?;
+        }
+        match &self.engine_cache {
+            Some(dir) if self.cached_engine => {
+                writeln!(f, "Engine cache: {} (cached engine)", dir)?
+            }
+            Some(dir) => writeln!(
+                f,
+                "Engine cache: {} ({} engines built)",
+                dir, self.engines_built
+            )?,
+            None => writeln!(f, "Engine cache: none, the engine is built on every run")?,
+        }
+        let line = |f: &mut std::fmt::Formatter<'_>, label: &str, value: Option<f64>| match value {
+            Some(value) => writeln!(f, "{}: {:.3} ms", label, value),
+            None => Ok(()),
+        };
+        line(f, "Session creation", self.session_ms)?;
+        line(f, "First inference", self.first_inference_ms)?;
+        line(f, "Steady inference (mean)", self.steady_inference_ms)?;
+        line(f, "Startup", self.startup_ms)?;
+        writeln!(f, "=======================================")
+    }
+}
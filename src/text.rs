@@ -0,0 +1,100 @@
+use crate::error::AppError;
+use ort::{
+    session::Input,
+    tensor::TensorElementType,
+    value::{DynValue, Tensor, ValueType},
+};
+use tokenizers::{Encoding, PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+pub fn load_tokenizer(tokenizer_path: &str) -> Result<Tokenizer, AppError> {
+    Tokenizer::from_file(tokenizer_path).map_err(|e| AppError::TokenizerError(e.to_string()))
+}
+
+/// Static sequence length of the model's token inputs, if it has one.
+pub fn model_sequence_length(model_inputs: &[Input]) -> Option<usize> {
+    model_inputs
+        .iter()
+        .find_map(|input| match &input.input_type {
+            ValueType::Tensor { shape, .. } if shape.len() == 2 && shape[1] > 0 => {
+                Some(shape[1] as usize)
+            }
+            _ => None,
+        })
+}
+
+/// Tokenized prompt, truncated or padded to a fixed length when one is given.
+pub struct TokenizedText {
+    pub input_ids: Vec<i64>,
+    pub attention_mask: Vec<i64>,
+    pub token_type_ids: Vec<i64>,
+}
+
+pub fn tokenize(
+    tokenizer: &mut Tokenizer,
+    prompt: &str,
+    max_length: Option<usize>,
+) -> Result<TokenizedText, AppError> {
+    // Truncated and padded by the tokenizer, so the special tokens it adds survive the truncation
+    if let Some(length) = max_length {
+        let truncation: TruncationParams = TruncationParams {
+            max_length: length,
+            ..tokenizer.get_truncation().cloned().unwrap_or_default()
+        };
+        tokenizer
+            .with_truncation(Some(truncation))
+            .map_err(|e| AppError::TokenizerError(e.to_string()))?;
+        // Keeps the pad token and id of a tokenizer that configures its own padding
+        let padding: PaddingParams = PaddingParams {
+            strategy: PaddingStrategy::Fixed(length),
+            pad_to_multiple_of: None,
+            ..tokenizer.get_padding().cloned().unwrap_or_default()
+        };
+        tokenizer.with_padding(Some(padding));
+    }
+
+    let encoding: Encoding = tokenizer
+        .encode(prompt, true)
+        .map_err(|e| AppError::TokenizerError(e.to_string()))?;
+
+    let widen = |values: &[u32]| -> Vec<i64> { values.iter().map(|&v| v as i64).collect() };
+    Ok(TokenizedText {
+        input_ids: widen(encoding.get_ids()),
+        attention_mask: widen(encoding.get_attention_mask()),
+        token_type_ids: widen(encoding.get_type_ids()),
+    })
+}
+
+/// Builds a `[1, seq]` tensor for every model input, matched by name to ids, mask or token types.
+pub fn text_inputs(
+    model_inputs: &[Input],
+    text: &TokenizedText,
+) -> Result<Vec<(String, DynValue)>, AppError> {
+    model_inputs
+        .iter()
+        .map(|input| {
+            let name: String = input.name.to_ascii_lowercase();
+            let values: &[i64] = if name.contains("mask") {
+                &text.attention_mask
+            } else if name.contains("type") || name.contains("segment") {
+                &text.token_type_ids
+            } else if name.contains("ids") || name.contains("token") {
+                &text.input_ids
+            } else {
+                return Err(AppError::InputError(format!(
+                    "cannot feed model input '{}' from a tokenized prompt",
+                    input.name
+                )));
+            };
+
+            let shape: [usize; 2] = [1, values.len()];
+            let value: DynValue = match input.input_type.tensor_type() {
+                Some(TensorElementType::Int32) => {
+                    let values: Vec<i32> = values.iter().map(|&v| v as i32).collect();
+                    Tensor::from_array((shape, values))?.into_dyn()
+                }
+                _ => Tensor::from_array((shape, values.to_vec()))?.into_dyn(),
+            };
+            Ok((input.name.clone(), value))
+        })
+        .collect()
+}
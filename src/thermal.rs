@@ -0,0 +1,339 @@
+use crate::{
+    environment::read_trimmed,
+    tracker::{BenchmarkTracker, SpanKind},
+};
+use serde::Serialize;
+use std::{
+    fs,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Share of the samples, and of the inference runs, compared at both ends of the run.
+const WINDOW_FRACTION: f64 = 0.1;
+/// Frequency drop between the first and the last window that counts as throttling.
+const THROTTLING_DROP: f64 = 0.05;
+/// GPU devfreq devices of common edge boards: Jetson (gp10b, gv11b, ga10b) and Mali.
+const GPU_DEVFREQ: [&str; 5] = ["gpu", "gp10b", "gv11b", "ga10b", "mali"];
+
+/// Temperature and clock sources found under `/sys` when monitoring starts.
+#[derive(Debug, Clone, Default)]
+struct Sensors {
+    /// Type and `temp` file of every thermal zone.
+    zones: Vec<(String, PathBuf)>,
+    /// `scaling_cur_freq` of every CPU, in kHz.
+    cpu_frequencies: Vec<PathBuf>,
+    /// `cur_freq` of GPU devfreq devices in Hz, or `gt_cur_freq_mhz` of DRM cards in MHz.
+    gpu_frequencies: Vec<(PathBuf, f64)>,
+}
+
+impl Sensors {
+    fn discover() -> Self {
+        let entries = |dir: &str| -> Vec<PathBuf> {
+            let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+                .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                .unwrap_or_default();
+            paths.sort();
+            paths
+        };
+        let name = |path: &PathBuf| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+
+        let zones: Vec<(String, PathBuf)> = entries("/sys/class/thermal")
+            .into_iter()
+            .filter(|zone| name(zone).starts_with("thermal_zone"))
+            .map(|zone| {
+                let kind: String = read_trimmed(&zone.join("type").to_string_lossy())
+                    .unwrap_or_else(|| name(&zone));
+                (kind, zone.join("temp"))
+            })
+            .filter(|(_, temp)| temp.exists())
+            .collect();
+
+        let cpu_frequencies: Vec<PathBuf> = entries("/sys/devices/system/cpu")
+            .into_iter()
+            .filter(|cpu| {
+                name(cpu)
+                    .strip_prefix("cpu")
+                    .is_some_and(|index| index.parse::<usize>().is_ok())
+            })
+            .map(|cpu| cpu.join("cpufreq/scaling_cur_freq"))
+            .filter(|frequency| frequency.exists())
+            .collect();
+
+        let devfreq = entries("/sys/class/devfreq")
+            .into_iter()
+            .filter(|device| {
+                let device: String = name(device).to_lowercase();
+                GPU_DEVFREQ.iter().any(|gpu| device.contains(gpu))
+            })
+            .map(|device| (device.join("cur_freq"), 1e-6));
+        let drm = entries("/sys/class/drm")
+            .into_iter()
+            .map(|card| (card.join("gt_cur_freq_mhz"), 1.));
+        let gpu_frequencies: Vec<(PathBuf, f64)> = devfreq
+            .chain(drm)
+            .filter(|(frequency, _)| frequency.exists())
+            .collect();
+
+        Self {
+            zones,
+            cpu_frequencies,
+            gpu_frequencies,
+        }
+    }
+
+    fn sample(&self, elapsed: Duration) -> ThermalSample {
+        let read = |path: &PathBuf| read_trimmed(&path.to_string_lossy())?.parse::<f64>().ok();
+        let mean = |values: Vec<f64>| {
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+        ThermalSample {
+            elapsed_ms: elapsed.as_secs_f64() * 1000.,
+            temperatures_c: self
+                .zones
+                .iter()
+                .map(|(_, temp)| read(temp).map(|temp| temp / 1000.))
+                .collect(),
+            cpu_frequency_mhz: mean(
+                self.cpu_frequencies
+                    .iter()
+                    .filter_map(read)
+                    .map(|khz| khz / 1000.)
+                    .collect(),
+            ),
+            gpu_frequency_mhz: mean(
+                self.gpu_frequencies
+                    .iter()
+                    .filter_map(|(path, to_mhz)| read(path).map(|value| value * to_mhz))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Temperatures and clocks at one instant of the run.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThermalSample {
+    /// Time since monitoring started.
+    pub elapsed_ms: f64,
+    /// One per zone of the report, `None` when the zone could not be read.
+    pub temperatures_c: Vec<Option<f64>>,
+    /// Mean of the current frequency of every CPU.
+    pub cpu_frequency_mhz: Option<f64>,
+    pub gpu_frequency_mhz: Option<f64>,
+}
+
+/// Samples temperatures and clock frequencies on a background thread until it is finished.
+pub struct ThermalMonitor {
+    interval: Duration,
+    zones: Vec<String>,
+    stop: Sender<()>,
+    sampler: JoinHandle<Vec<ThermalSample>>,
+}
+
+impl ThermalMonitor {
+    pub fn start(interval: Duration) -> Self {
+        let sensors: Sensors = Sensors::discover();
+        let zones: Vec<String> = sensors.zones.iter().map(|(kind, _)| kind.clone()).collect();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let sampler: JoinHandle<Vec<ThermalSample>> = thread::spawn(move || {
+            let start: Instant = Instant::now();
+            let mut samples: Vec<ThermalSample> = vec![sensors.sample(Duration::ZERO)];
+            // Waiting on the channel doubles as the sampling period, finish ends it right away
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                samples.push(sensors.sample(start.elapsed()));
+            }
+            samples.push(sensors.sample(start.elapsed()));
+            samples
+        });
+        Self {
+            interval,
+            zones,
+            stop,
+            sampler,
+        }
+    }
+
+    /// Stops sampling and compares the start and the end of the run, including the inference
+    /// latencies recorded by the tracker meanwhile.
+    pub fn finish(self, tracker: &BenchmarkTracker) -> ThermalReport {
+        let _ = self.stop.send(());
+        let samples: Vec<ThermalSample> = self.sampler.join().unwrap_or_default();
+        let latencies: Vec<f64> = tracker
+            .spans()
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.name == "Inference")
+            .map(|span| span.metrics.wall_clock_time.as_secs_f64() * 1000.)
+            .collect();
+        ThermalReport::new(self.zones, self.interval, samples, &latencies)
+    }
+}
+
+/// Start, end and extreme of one temperature or frequency over the run. Start and end are the
+/// means of the first and last windows of samples.
+#[derive(Debug, Clone, Serialize)]
+pub struct Trend {
+    pub start: f64,
+    pub end: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Trend {
+    fn new(values: &[f64]) -> Option<Self> {
+        let (first, last) = windows(values)?;
+        Some(Self {
+            start: first,
+            end: last,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+
+    /// Relative drop from the start to the end, 0 when it did not drop.
+    fn drop(&self) -> f64 {
+        if self.start > 0. {
+            (1. - self.end / self.start).max(0.)
+        } else {
+            0.
+        }
+    }
+}
+
+/// Means of the first and last `WINDOW_FRACTION` of `values`, at least one value each.
+fn windows(values: &[f64]) -> Option<(f64, f64)> {
+    if values.is_empty() {
+        return None;
+    }
+    let size: usize = ((values.len() as f64 * WINDOW_FRACTION) as usize).max(1);
+    let mean = |window: &[f64]| window.iter().sum::<f64>() / window.len() as f64;
+    Some((mean(&values[..size]), mean(&values[values.len() - size..])))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ZoneTemperature {
+    pub zone: String,
+    pub celsius: Trend,
+}
+
+/// Temperatures and clocks sampled during the GREEN BOX phase, for `--thermal` runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThermalReport {
+    pub interval_ms: f64,
+    pub zones: Vec<ZoneTemperature>,
+    pub cpu_frequency_mhz: Option<Trend>,
+    pub gpu_frequency_mhz: Option<Trend>,
+    /// Mean inference latency of the last window of runs over that of the first window.
+    pub inference_slowdown: Option<f64>,
+    /// Largest relative drop of the CPU or GPU frequency from the start to the end of the run.
+    pub frequency_drop: f64,
+    pub throttled: bool,
+    pub samples: Vec<ThermalSample>,
+}
+
+impl ThermalReport {
+    pub fn new(
+        zones: Vec<String>,
+        interval: Duration,
+        samples: Vec<ThermalSample>,
+        latencies: &[f64],
+    ) -> Self {
+        let zones: Vec<ZoneTemperature> = zones
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, zone)| {
+                let temperatures: Vec<f64> = samples
+                    .iter()
+                    .filter_map(|sample| sample.temperatures_c.get(index).copied().flatten())
+                    .collect();
+                Some(ZoneTemperature {
+                    zone,
+                    celsius: Trend::new(&temperatures)?,
+                })
+            })
+            .collect();
+        let trend = |frequency: fn(&ThermalSample) -> Option<f64>| {
+            Trend::new(&samples.iter().filter_map(frequency).collect::<Vec<f64>>())
+        };
+        let cpu_frequency_mhz: Option<Trend> = trend(|sample| sample.cpu_frequency_mhz);
+        let gpu_frequency_mhz: Option<Trend> = trend(|sample| sample.gpu_frequency_mhz);
+        let frequency_drop: f64 = [&cpu_frequency_mhz, &gpu_frequency_mhz]
+            .into_iter()
+            .flatten()
+            .map(Trend::drop)
+            .fold(0., f64::max);
+        let inference_slowdown: Option<f64> = (latencies.len() >= 2)
+            .then(|| windows(latencies))
+            .flatten()
+            .filter(|(first, _)| *first > 0.)
+            .map(|(first, last)| last / first);
+
+        Self {
+            interval_ms: interval.as_secs_f64() * 1000.,
+            zones,
+            cpu_frequency_mhz,
+            gpu_frequency_mhz,
+            inference_slowdown,
+            frequency_drop,
+            throttled: frequency_drop > THROTTLING_DROP,
+            samples,
+        }
+    }
+}
+
+impl std::fmt::Display for ThermalReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Thermal Metrics =============")?;
+        writeln!(
+            f,
+            "Samples: {} every {:.0} ms",
+            self.samples.len(),
+            self.interval_ms
+        )?;
+        if self.zones.is_empty() {
+            writeln!(f, "Temperature: no thermal zones found")?;
+        }
+        for zone in &self.zones {
+            writeln!(
+                f,
+                "Temperature {}: {:.1}°C -> {:.1}°C (max {:.1}°C)",
+                zone.zone, zone.celsius.start, zone.celsius.end, zone.celsius.max
+            )?;
+        }
+        for (name, trend) in [
+            ("CPU", &self.cpu_frequency_mhz),
+            ("GPU", &self.gpu_frequency_mhz),
+        ] {
+            if let Some(trend) = trend {
+                writeln!(
+                    f,
+                    "{} frequency: {:.0} MHz -> {:.0} MHz (min {:.0} MHz)",
+                    name, trend.start, trend.end, trend.min
+                )?;
+            }
+        }
+        if let Some(slowdown) = self.inference_slowdown {
+            writeln!(
+                f,
+                "Inference latency: the last runs took {:.2}x as long as the first",
+                slowdown
+            )?;
+        }
+        if self.throttled {
+            writeln!(
+                f,
+                "Throttling: frequency dropped {:.0}% during the iteration window",
+                self.frequency_drop * 100.
+            )?;
+        } else {
+            writeln!(f, "Throttling: none detected")?;
+        }
+        writeln!(f, "=======================================")
+    }
+}
@@ -0,0 +1,217 @@
+use crate::tracker::{BenchmarkTracker, SpanKind};
+use serde::Serialize;
+use std::{collections::HashMap, fs, time::Duration};
+
+/// Name and CPU time of threads, by thread id.
+type ThreadTimes = HashMap<u32, (String, Duration)>;
+
+/// CPU time of every thread of the process at one instant, read from `/proc/self/task`. Unlike
+/// `getrusage(RUSAGE_SELF)`, this tells the ONNX Runtime workers apart from the main thread.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadSnapshot {
+    threads: ThreadTimes,
+}
+
+/// CPU time one thread spent during an operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadCpu {
+    pub tid: u32,
+    pub name: String,
+    pub cpu_time: Duration,
+}
+
+impl ThreadSnapshot {
+    /// `None` where `/proc` is not available.
+    pub fn capture() -> Option<Self> {
+        let tasks = fs::read_dir("/proc/self/task").ok()?;
+        let mut threads: ThreadTimes = HashMap::new();
+        for task in tasks.flatten() {
+            let Some(tid) = task.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+                continue;
+            };
+            // A thread that exited since the listing has no files left
+            let Some(cpu_time) = thread_cpu_time(tid) else {
+                continue;
+            };
+            let name: String = fs::read_to_string(format!("/proc/self/task/{}/comm", tid))
+                .map(|name| name.trim_end().to_string())
+                .unwrap_or_default();
+            threads.insert(tid, (name, cpu_time));
+        }
+        Some(Self { threads })
+    }
+
+    /// CPU time each thread used since `earlier`, busiest first. Threads started in between are
+    /// counted from zero, threads that did not run are left out.
+    pub fn since(&self, earlier: &ThreadSnapshot) -> Vec<ThreadCpu> {
+        let mut threads: Vec<ThreadCpu> = self
+            .threads
+            .iter()
+            .map(|(&tid, (name, cpu_time))| {
+                let before: Duration = earlier
+                    .threads
+                    .get(&tid)
+                    .map(|(_, cpu_time)| *cpu_time)
+                    .unwrap_or_default();
+                ThreadCpu {
+                    tid,
+                    name: name.clone(),
+                    cpu_time: cpu_time.saturating_sub(before),
+                }
+            })
+            .filter(|thread| !thread.cpu_time.is_zero())
+            .collect();
+        threads.sort_by(|a, b| b.cpu_time.cmp(&a.cpu_time).then(a.tid.cmp(&b.tid)));
+        threads
+    }
+}
+
+/// Nanoseconds on CPU from `schedstat`, or the user and system clock ticks of `stat` on kernels
+/// built without scheduler statistics.
+fn thread_cpu_time(tid: u32) -> Option<Duration> {
+    if let Ok(schedstat) = fs::read_to_string(format!("/proc/self/task/{}/schedstat", tid)) {
+        if let Some(nanos) = schedstat
+            .split_whitespace()
+            .next()
+            .and_then(|nanos| nanos.parse::<u64>().ok())
+        {
+            return Some(Duration::from_nanos(nanos));
+        }
+    }
+
+    let stat: String = fs::read_to_string(format!("/proc/self/task/{}/stat", tid)).ok()?;
+    // The command name may contain spaces, the fields after it are plain numbers
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let ticks_per_second: libc::c_long = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks_per_second <= 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(
+        (utime + stime) as f64 / ticks_per_second as f64,
+    ))
+}
+
+/// CPU time of one thread over every run of an operation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadUsage {
+    pub tid: u32,
+    pub name: String,
+    pub cpu_time_ms: f64,
+    /// Share of the CPU time of the operation spent on this thread.
+    pub share: f64,
+}
+
+/// How the CPU time of one operation spread over the threads of the process.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationThreads {
+    pub operation: String,
+    pub count: usize,
+    pub wall_clock_time_ms: f64,
+    pub cpu_time_ms: f64,
+    /// CPU time over wall clock time: how many threads were busy on average.
+    pub parallelism: f64,
+    pub threads: Vec<ThreadUsage>,
+}
+
+/// Per-thread CPU time of every operation, for runs with `--thread-cpu`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadCpuReport {
+    pub operations: Vec<OperationThreads>,
+}
+
+impl ThreadCpuReport {
+    /// Runs of the same operation are merged, in the order the operations first finished. `None`
+    /// when the tracker did not sample threads.
+    pub fn from_tracker(tracker: &BenchmarkTracker) -> Option<Self> {
+        let mut operations: Vec<(String, usize, Duration, ThreadTimes)> = Vec::new();
+        for span in tracker.spans() {
+            let (SpanKind::Operation, Some(threads)) = (span.kind, &span.threads) else {
+                continue;
+            };
+            let index: usize = match operations.iter().position(|(name, ..)| *name == span.name) {
+                Some(index) => index,
+                None => {
+                    operations.push((span.name.clone(), 0, Duration::ZERO, HashMap::new()));
+                    operations.len() - 1
+                }
+            };
+            let (_, count, wall_clock_time, usage) = &mut operations[index];
+            *count += 1;
+            *wall_clock_time += span.metrics.wall_clock_time;
+            for thread in threads {
+                usage
+                    .entry(thread.tid)
+                    .or_insert_with(|| (thread.name.clone(), Duration::ZERO))
+                    .1 += thread.cpu_time;
+            }
+        }
+        if operations.is_empty() {
+            return None;
+        }
+
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.;
+        let operations: Vec<OperationThreads> = operations
+            .into_iter()
+            .map(|(operation, count, wall_clock_time, usage)| {
+                let cpu_time: Duration = usage.values().map(|(_, cpu_time)| *cpu_time).sum();
+                let mut threads: Vec<ThreadUsage> = usage
+                    .into_iter()
+                    .map(|(tid, (name, thread_time))| ThreadUsage {
+                        tid,
+                        name,
+                        cpu_time_ms: millis(thread_time),
+                        share: if cpu_time.is_zero() {
+                            0.
+                        } else {
+                            thread_time.as_secs_f64() / cpu_time.as_secs_f64()
+                        },
+                    })
+                    .collect();
+                threads.sort_by(|a, b| b.cpu_time_ms.total_cmp(&a.cpu_time_ms));
+                OperationThreads {
+                    operation,
+                    count,
+                    wall_clock_time_ms: millis(wall_clock_time),
+                    cpu_time_ms: millis(cpu_time),
+                    parallelism: if wall_clock_time.is_zero() {
+                        0.
+                    } else {
+                        cpu_time.as_secs_f64() / wall_clock_time.as_secs_f64()
+                    },
+                    threads,
+                }
+            })
+            .collect();
+        Some(Self { operations })
+    }
+}
+
+impl std::fmt::Display for ThreadCpuReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Thread CPU Metrics =============")?;
+        for operation in &self.operations {
+            writeln!(
+                f,
+                "{} (x{}): wall {:.3} ms, CPU {:.3} ms, parallelism {:.2}",
+                operation.operation,
+                operation.count,
+                operation.wall_clock_time_ms,
+                operation.cpu_time_ms,
+                operation.parallelism
+            )?;
+            for thread in &operation.threads {
+                writeln!(
+                    f,
+                    "  {:<16} {:>7}: {:>10.3} ms ({:.1}%)",
+                    thread.name,
+                    thread.tid,
+                    thread.cpu_time_ms,
+                    thread.share * 100.
+                )?;
+            }
+        }
+        writeln!(f, "=======================================")
+    }
+}
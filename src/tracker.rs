@@ -0,0 +1,574 @@
+use crate::{
+    error::AppError,
+    metadata::RunMetadata,
+    metrics::{Metrics, MetricsSummary},
+    sink::{MetricsSink, SinkRegistry, StdoutSink},
+    threads::{ThreadCpu, ThreadSnapshot},
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::span::EnteredSpan;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Operation,
+    Phase,
+}
+
+/// A finished operation or phase with its time bounds, for exporters that need a timeline.
+#[derive(Debug, Clone)]
+pub struct SpanRecord {
+    pub kind: SpanKind,
+    /// Unique to this span, so the runs of a repeated phase can be told apart.
+    pub id: u64,
+    pub name: String,
+    /// Innermost phase the operation or phase ran in, `None` outside of any phase.
+    pub parent: Option<String>,
+    /// Id of the run of the parent phase.
+    pub parent_id: Option<u64>,
+    pub start: Instant,
+    pub end: Instant,
+    pub metrics: Metrics,
+    /// CPU time of every thread during the operation, when threads are sampled.
+    pub threads: Option<Vec<ThreadCpu>>,
+}
+
+/// A phase and the phases started while it was running, with times summed over every run of it at
+/// this place of the tree.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseNode {
+    pub name: String,
+    pub count: usize,
+    /// Time of the operations of the phase, those of its sub-phases included.
+    pub inclusive_ms: f64,
+    /// Time of the operations of the phase outside of its sub-phases.
+    pub exclusive_ms: f64,
+    pub children: Vec<PhaseNode>,
+}
+
+/// Told when operations and phases start and finish, e.g. by a profiler covering only some of them.
+/// Operations start after their hooks ran and finish before, so hooks are not part of their time.
+pub trait SpanHook: std::fmt::Debug + Send {
+    fn started(&mut self, kind: SpanKind, name: &str);
+    fn finished(&mut self, kind: SpanKind, name: &str);
+}
+
+/// A hook the tracker shares with its owner, who reads what it collected after the run.
+pub type SharedHook = Arc<Mutex<dyn SpanHook>>;
+
+/// Identifies an open operation, so overlapping operations can finish in any order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperationId(u64);
+
+#[derive(Debug)]
+struct OpenOperation {
+    id: OperationId,
+    start_metrics: Metrics,
+    span: EnteredSpan,
+    /// Started while another operation was open, whose time already counts towards the phases.
+    overlapping: bool,
+    threads: Option<ThreadSnapshot>,
+}
+
+/// A phase that started and did not end yet.
+#[derive(Debug)]
+struct ActivePhase {
+    id: u64,
+    /// Run of the phase this one started in.
+    parent_id: Option<u64>,
+    name: String,
+    /// Names of the enclosing phases from the outermost, the phase itself last.
+    path: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct BenchmarkTracker {
+    start_metrics: Metrics,
+    open_operations: Vec<OpenOperation>,
+    /// Next id of an operation or a phase run.
+    next_span: u64,
+    completed_metrics: Vec<Metrics>,
+    active_phases: HashMap<String, Metrics>,
+    phase_spans: HashMap<String, EnteredSpan>,
+    /// Active phases from the outermost to the innermost.
+    phase_stack: Vec<ActivePhase>,
+    /// Finished phases with their path of ancestors, the phase itself last.
+    phase_metrics: Vec<(Vec<String>, Metrics)>,
+    phase_order: Vec<String>,
+    spans: Vec<SpanRecord>,
+    /// Most recent finished operations and phase runs kept, all of them when None.
+    span_limit: Option<usize>,
+    interrupted: bool,
+    sample_threads: bool,
+    hooks: Vec<SharedHook>,
+    sinks: SinkRegistry,
+}
+
+impl Default for BenchmarkTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BenchmarkTracker {
+    pub fn new() -> Self {
+        Self {
+            start_metrics: Metrics::current("Total".to_string()),
+            open_operations: Vec::new(),
+            next_span: 0,
+            completed_metrics: Vec::new(),
+            active_phases: HashMap::new(),
+            phase_spans: HashMap::new(),
+            phase_stack: Vec::new(),
+            phase_metrics: Vec::new(),
+            phase_order: Vec::new(),
+            spans: Vec::new(),
+            span_limit: None,
+            interrupted: false,
+            sample_threads: false,
+            hooks: Vec::new(),
+            sinks: SinkRegistry::new(),
+        }
+    }
+
+    /// Calls `hook` whenever an operation or a phase starts or finishes from now on.
+    pub fn add_hook(&mut self, hook: SharedHook) {
+        self.hooks.push(hook);
+    }
+
+    /// Hands the metrics of the run to `sink` once it is exported with [`Self::export_sinks`].
+    pub fn register_sink(&mut self, sink: Box<dyn MetricsSink>) {
+        self.sinks.register(sink);
+    }
+
+    /// Hands the metrics to every registered sink, see [`SinkRegistry::export`].
+    pub fn export_sinks(&mut self, metadata: &RunMetadata) -> Result<(), AppError> {
+        let mut sinks: SinkRegistry = std::mem::take(&mut self.sinks);
+        let exported: Result<(), AppError> = sinks.export(self, metadata);
+        self.sinks = sinks;
+        exported
+    }
+
+    fn call_hooks(&self, call: impl Fn(&mut dyn SpanHook)) {
+        for hook in &self.hooks {
+            if let Ok(mut hook) = hook.lock() {
+                call(&mut *hook);
+            }
+        }
+    }
+
+    /// Also records the CPU time of every thread of the process during each operation. Reading
+    /// `/proc` costs some time at both ends of every operation, so this is off by default.
+    pub fn set_thread_sampling(&mut self, enabled: bool) {
+        self.sample_threads = enabled;
+    }
+
+    /// Keeps only the `limit` most recent finished operations and phase runs, for processes that
+    /// run for as long as they are asked to, such as a server. The oldest are dropped in batches,
+    /// so up to twice as many are held between two trims.
+    pub fn set_span_limit(&mut self, limit: usize) {
+        self.span_limit = Some(limit);
+        self.trim_spans();
+    }
+
+    fn trim_spans(&mut self) {
+        let Some(limit) = self.span_limit else {
+            return;
+        };
+        if self.spans.len() > limit.saturating_mul(2) {
+            self.spans.drain(..self.spans.len() - limit);
+        }
+        if self.completed_metrics.len() > limit.saturating_mul(2) {
+            self.completed_metrics
+                .drain(..self.completed_metrics.len() - limit);
+        }
+        if self.phase_metrics.len() > limit.saturating_mul(2) {
+            self.phase_metrics.drain(..self.phase_metrics.len() - limit);
+        }
+    }
+
+    /// Starts an operation, which may overlap the operations still open. Only operations started
+    /// while no other one was open count towards the phases, so overlapping time is not counted
+    /// twice.
+    pub fn start_operation(&mut self, name: &str) -> OperationId {
+        let id: OperationId = OperationId(self.next_span);
+        self.next_span += 1;
+        self.call_hooks(|hook| hook.started(SpanKind::Operation, name));
+        let span: EnteredSpan = tracing::info_span!("operation", name).entered();
+        tracing::trace!("operation started");
+        let threads: Option<ThreadSnapshot> =
+            self.sample_threads.then(ThreadSnapshot::capture).flatten();
+        self.open_operations.push(OpenOperation {
+            id,
+            threads,
+            start_metrics: Metrics::current(name.to_string()),
+            span,
+            overlapping: !self.open_operations.is_empty(),
+        });
+        id
+    }
+
+    /// Finishes the most recently started operation still open.
+    pub fn finish_operation(&mut self) {
+        if let Some(operation) = self.open_operations.pop() {
+            self.finish_operation_internal(operation);
+        }
+    }
+
+    /// Finishes `id`, whether or not operations started after it are still open.
+    pub fn finish_operation_id(&mut self, id: OperationId) {
+        if let Some(position) = self
+            .open_operations
+            .iter()
+            .position(|operation| operation.id == id)
+        {
+            let operation: OpenOperation = self.open_operations.remove(position);
+            self.finish_operation_internal(operation);
+        }
+    }
+
+    /// Starts an operation finished when the returned guard is dropped, even on an early return.
+    /// The guard dereferences to the tracker, so nested operations and phases can be recorded
+    /// while it is alive.
+    #[must_use = "the operation finishes as soon as the guard is dropped"]
+    pub fn scoped(&mut self, name: &str) -> OperationGuard<'_> {
+        let id: OperationId = self.start_operation(name);
+        OperationGuard { tracker: self, id }
+    }
+
+    /// Records an operation timed by someone else, e.g. a step ONNX Runtime's profiler measured
+    /// inside the operation still open. Only its wall-clock time is known, and as it ran inside
+    /// another operation it does not count towards the phases.
+    pub fn record_operation(&mut self, name: &str, start: Instant, end: Instant) {
+        let mut metrics: Metrics = zero_metrics(name, start);
+        metrics.wall_clock_time = end.saturating_duration_since(start);
+        tracing::debug!(
+            name,
+            wall_clock_time = ?metrics.wall_clock_time,
+            "operation recorded"
+        );
+        self.completed_metrics.push(metrics.clone());
+        let id: u64 = self.next_span;
+        self.next_span += 1;
+        self.spans.push(SpanRecord {
+            kind: SpanKind::Operation,
+            id,
+            name: name.to_string(),
+            parent: self.current_phase().map(str::to_string),
+            parent_id: self.current_phase_id(),
+            start,
+            end,
+            metrics,
+            threads: None,
+        });
+        self.trim_spans();
+    }
+
+    fn finish_operation_internal(&mut self, operation: OpenOperation) {
+        let OpenOperation {
+            id,
+            start_metrics,
+            span,
+            overlapping,
+            threads,
+        } = operation;
+        let end_metrics: Metrics = Metrics::current(start_metrics.name.clone());
+        let threads: Option<Vec<ThreadCpu>> =
+            threads.and_then(|start| ThreadSnapshot::capture().map(|end| end.since(&start)));
+        let diff_metrics: Metrics = end_metrics.diff(&start_metrics);
+
+        tracing::debug!(
+            wall_clock_time = ?diff_metrics.wall_clock_time,
+            user_time = ?diff_metrics.user_time,
+            system_time = ?diff_metrics.system_time,
+            rss = diff_metrics.rss_after,
+            peak_rss_growth = diff_metrics.peak_rss_growth,
+            cpu_usage = diff_metrics.cpu_usage,
+            "operation finished"
+        );
+        self.completed_metrics.push(diff_metrics.clone());
+        self.spans.push(SpanRecord {
+            kind: SpanKind::Operation,
+            id: id.0,
+            name: diff_metrics.name.clone(),
+            parent: self.current_phase().map(str::to_string),
+            parent_id: self.current_phase_id(),
+            start: start_metrics.timestamp,
+            end: end_metrics.timestamp,
+            metrics: diff_metrics.clone(),
+            threads,
+        });
+        self.trim_spans();
+
+        if !overlapping {
+            for (_, phase_metrics) in self.active_phases.iter_mut() {
+                *phase_metrics = phase_metrics.combine(&diff_metrics);
+            }
+        }
+        self.call_hooks(|hook| hook.finished(SpanKind::Operation, &diff_metrics.name));
+        drop(span);
+    }
+
+    /// Innermost active phase, which operations and newly started phases belong to.
+    pub fn current_phase(&self) -> Option<&str> {
+        self.phase_stack.last().map(|phase| phase.name.as_str())
+    }
+
+    fn current_phase_id(&self) -> Option<u64> {
+        self.phase_stack.last().map(|phase| phase.id)
+    }
+
+    /// Starts a phase inside the current one, if any. Phases nest to any depth, and an operation
+    /// counts towards every active phase.
+    pub fn start_phase(&mut self, phase_name: &str) {
+        self.active_phases.insert(
+            phase_name.to_string(),
+            zero_metrics(phase_name, Instant::now()),
+        );
+        self.phase_stack.retain(|phase| phase.name != phase_name);
+        let mut path: Vec<String> = self
+            .phase_stack
+            .last()
+            .map(|phase| phase.path.clone())
+            .unwrap_or_default();
+        path.push(phase_name.to_string());
+        self.phase_stack.push(ActivePhase {
+            id: self.next_span,
+            parent_id: self.current_phase_id(),
+            name: phase_name.to_string(),
+            path,
+        });
+        self.next_span += 1;
+        self.phase_spans.insert(
+            phase_name.to_string(),
+            tracing::info_span!("phase", name = phase_name).entered(),
+        );
+        tracing::debug!("phase started");
+        self.call_hooks(|hook| hook.started(SpanKind::Phase, phase_name));
+
+        if !self.phase_order.contains(&phase_name.to_string()) {
+            self.phase_order.push(phase_name.to_string());
+        }
+    }
+
+    pub fn end_phase(&mut self, phase_name: &str) {
+        // A phase is active exactly while it is on the stack
+        let Some(position) = self
+            .phase_stack
+            .iter()
+            .position(|phase| phase.name == phase_name)
+        else {
+            return;
+        };
+        let phase: ActivePhase = self.phase_stack.remove(position);
+        let path: Vec<String> = phase.path;
+        if let Some(metrics) = self.active_phases.remove(phase_name) {
+            tracing::debug!(
+                wall_clock_time = ?metrics.wall_clock_time,
+                rss = metrics.rss_after,
+                peak_rss_growth = metrics.peak_rss_growth,
+                "phase finished"
+            );
+            self.spans.push(SpanRecord {
+                kind: SpanKind::Phase,
+                id: phase.id,
+                name: phase_name.to_string(),
+                parent: (path.len() > 1).then(|| path[path.len() - 2].clone()),
+                parent_id: phase.parent_id,
+                start: metrics.timestamp,
+                end: Instant::now(),
+                metrics: metrics.clone(),
+                threads: None,
+            });
+            self.phase_metrics.push((path, metrics));
+            self.trim_spans();
+            self.call_hooks(|hook| hook.finished(SpanKind::Phase, phase_name));
+        }
+        self.phase_spans.remove(phase_name);
+    }
+
+    /// Closes the operations and the phases an interrupted run left open, innermost first, and
+    /// marks the recorded metrics as partial.
+    pub fn interrupt(&mut self) {
+        while !self.open_operations.is_empty() {
+            self.finish_operation();
+        }
+        while let Some(phase) = self.current_phase().map(str::to_string) {
+            self.end_phase(&phase);
+        }
+        self.interrupted = true;
+    }
+
+    /// Whether the run stopped early on SIGINT or SIGTERM.
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted
+    }
+
+    /// Finished phases as a tree following their nesting. Repeated runs of a phase at the same place
+    /// are merged into one node, in the order they first finished.
+    pub fn phase_tree(&self) -> Vec<PhaseNode> {
+        let mut roots: Vec<PhaseNode> = Vec::new();
+        for (path, metrics) in &self.phase_metrics {
+            let mut level: &mut Vec<PhaseNode> = &mut roots;
+            for (depth, name) in path.iter().enumerate() {
+                let index: usize = match level.iter().position(|node| &node.name == name) {
+                    Some(index) => index,
+                    None => {
+                        level.push(PhaseNode {
+                            name: name.clone(),
+                            count: 0,
+                            inclusive_ms: 0.,
+                            exclusive_ms: 0.,
+                            children: Vec::new(),
+                        });
+                        level.len() - 1
+                    }
+                };
+                let node: &mut PhaseNode = &mut level[index];
+                if depth == path.len() - 1 {
+                    node.count += 1;
+                    node.inclusive_ms += metrics.wall_clock_time.as_secs_f64() * 1000.;
+                }
+                level = &mut node.children;
+            }
+        }
+
+        fn exclusive(nodes: &mut [PhaseNode]) {
+            for node in nodes {
+                exclusive(&mut node.children);
+                let children: f64 = node.children.iter().map(|child| child.inclusive_ms).sum();
+                node.exclusive_ms = (node.inclusive_ms - children).max(0.);
+            }
+        }
+        exclusive(&mut roots);
+        roots
+    }
+
+    pub fn get_total_metrics(&self) -> Metrics {
+        let current: Metrics = Metrics::current("Total".to_string());
+        current.diff(&self.start_metrics)
+    }
+
+    pub fn start_time(&self) -> Instant {
+        self.start_metrics.timestamp
+    }
+
+    /// Finished operations and phases, in the order they finished.
+    pub fn spans(&self) -> &[SpanRecord] {
+        &self.spans
+    }
+
+    /// Metrics of the finished operations or phases, in the order they finished.
+    pub fn summaries(&self, kind: SpanKind) -> Vec<MetricsSummary> {
+        self.spans
+            .iter()
+            .filter(|span| span.kind == kind)
+            .map(|span| MetricsSummary::from(&span.metrics))
+            .collect()
+    }
+
+    /// Wall clock time of every run of the operation `name`, in the order they finished.
+    pub fn operation_times(&self, name: &str) -> Vec<Duration> {
+        self.spans
+            .iter()
+            .filter(|span| span.kind == SpanKind::Operation && span.name == name)
+            .map(|span| span.metrics.wall_clock_time)
+            .collect()
+    }
+
+    pub fn print_all_metrics(&self) {
+        // Printing to stdout cannot fail without panicking first
+        let _ = self.emit_metrics(&mut StdoutSink::default());
+    }
+
+    /// Hands every finished operation, every run of the phases in the order the phases first started
+    /// and the total to `sink`.
+    pub fn emit_metrics(&self, sink: &mut dyn MetricsSink) -> Result<(), AppError> {
+        let total: Metrics = self.get_total_metrics();
+        for metrics in &self.completed_metrics {
+            sink.operation(metrics)?;
+        }
+
+        for phase_name in &self.phase_order {
+            for (_, metrics) in self
+                .phase_metrics
+                .iter()
+                .filter(|(_, metrics)| &metrics.name == phase_name)
+            {
+                sink.phase(metrics)?;
+            }
+        }
+
+        sink.total(&total, &self.phase_tree())
+    }
+}
+
+/// An operation of a [`BenchmarkTracker`], finished when the guard goes out of scope.
+pub struct OperationGuard<'a> {
+    tracker: &'a mut BenchmarkTracker,
+    id: OperationId,
+}
+
+impl OperationGuard<'_> {
+    pub fn id(&self) -> OperationId {
+        self.id
+    }
+}
+
+impl Deref for OperationGuard<'_> {
+    type Target = BenchmarkTracker;
+
+    fn deref(&self) -> &Self::Target {
+        self.tracker
+    }
+}
+
+impl DerefMut for OperationGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.tracker
+    }
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.finish_operation_id(self.id);
+    }
+}
+
+/// Times an expression as an operation of a tracker: `bench_op!(tracker, "Inference", expr)`.
+/// The operation finishes once the expression is evaluated, also when `?` returns early from it.
+#[macro_export]
+macro_rules! bench_op {
+    ($tracker:expr, $name:expr, $body:expr $(,)?) => {{
+        let _operation = $tracker.scoped($name);
+        $body
+    }};
+}
+
+/// Metrics of a span nothing was measured of yet, with the RSS of the process at this point.
+fn zero_metrics(name: &str, timestamp: Instant) -> Metrics {
+    let memory: Metrics = Metrics::current(name.to_string());
+    Metrics {
+        name: name.to_string(),
+        timestamp,
+        wall_clock_time: Duration::default(),
+        user_time: Duration::default(),
+        system_time: Duration::default(),
+        rss_before: memory.rss_after,
+        rss_after: memory.rss_after,
+        peak_rss: memory.peak_rss,
+        peak_rss_growth: 0,
+        cpu_usage: 0.0,
+        voluntary_switches: 0,
+        involuntary_switches: 0,
+        major_faults: 0,
+        allocations: 0,
+        allocated_bytes: 0,
+        process_heap_peak: 0,
+    }
+}
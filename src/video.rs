@@ -0,0 +1,265 @@
+use crate::{
+    error::AppError,
+    prefetch::PrefetchReport,
+    stats::{mean, millis, percentile},
+    tracker::{BenchmarkTracker, SpanKind},
+};
+use image::RgbImage;
+use serde::Serialize;
+use std::{
+    io::{ErrorKind, Read},
+    process::{Child, ChildStdout, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Frames of a video file or V4L2 camera, decoded to RGB by an `ffmpeg` child process. Frames are
+/// decoded as they are stored, without applying the rotation of phone videos, so they have the size
+/// `ffprobe` reports.
+pub struct VideoSource {
+    pub width: u32,
+    pub height: u32,
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl VideoSource {
+    pub fn open(path: &str) -> Result<Self, AppError> {
+        // Cameras are only recognized with an explicit input format
+        let format: &[&str] = if path.starts_with("/dev/video") {
+            &["-f", "v4l2"]
+        } else {
+            &[]
+        };
+
+        let probe = Command::new("ffprobe")
+            .args(["-v", "error"])
+            .args(format)
+            .args([
+                "-select_streams",
+                "v:0",
+                "-show_entries",
+                "stream=width,height",
+                "-of",
+                "csv=p=0",
+                path,
+            ])
+            .output()
+            .map_err(|e| AppError::VideoError(format!("failed to run ffprobe: {}", e)))?;
+        if !probe.status.success() {
+            return Err(AppError::VideoError(format!(
+                "ffprobe failed on {}: {}",
+                path,
+                String::from_utf8_lossy(&probe.stderr).trim()
+            )));
+        }
+        let size: String = String::from_utf8_lossy(&probe.stdout).trim().to_string();
+        let (width, height) = size
+            .lines()
+            .next()
+            .and_then(|line| line.split_once(','))
+            .and_then(|(width, height)| {
+                Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+            })
+            .ok_or_else(|| {
+                AppError::VideoError(format!("{} has no video stream ('{}')", path, size))
+            })?;
+
+        let mut child: Child = Command::new("ffmpeg")
+            .args(["-nostdin", "-v", "error", "-noautorotate"])
+            .args(format)
+            .args(["-i", path, "-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| AppError::VideoError(format!("failed to run ffmpeg: {}", e)))?;
+        let stdout: ChildStdout = child.stdout.take().expect("stdout is piped");
+        tracing::debug!(path, width, height, "video opened");
+
+        Ok(Self {
+            width,
+            height,
+            child,
+            stdout,
+        })
+    }
+
+    /// The next decoded frame, `None` at the end of the stream.
+    pub fn next_frame(&mut self) -> Result<Option<RgbImage>, AppError> {
+        let mut buffer: Vec<u8> = vec![0; self.width as usize * self.height as usize * 3];
+        match self.stdout.read_exact(&mut buffer) {
+            Ok(()) => Ok(RgbImage::from_raw(self.width, self.height, buffer)),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(AppError::VideoError(format!("failed to read frame: {}", e))),
+        }
+    }
+}
+
+impl Drop for VideoSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Paces a stream at `--target-fps`: frames arrive at the target rate, and every frame that arrived
+/// while the previous one was still being processed is dropped. Without a target every frame is
+/// processed as fast as possible.
+pub struct FramePacer {
+    target_fps: Option<f64>,
+    start: Instant,
+    /// Index of the next frame to read from the source.
+    next: u64,
+    dropped: u64,
+    latencies: Vec<Duration>,
+}
+
+impl FramePacer {
+    pub fn new(target_fps: Option<f64>) -> Self {
+        Self {
+            target_fps,
+            start: Instant::now(),
+            next: 0,
+            dropped: 0,
+            latencies: Vec::new(),
+        }
+    }
+
+    /// The frame to process next: the latest one that has arrived, waiting for it when processing
+    /// is ahead of the stream.
+    pub fn next_frame(&mut self, source: &mut VideoSource) -> Result<Option<RgbImage>, AppError> {
+        if let Some(fps) = self.target_fps {
+            let arrival = |index: u64| Duration::from_secs_f64(index as f64 / fps);
+            let elapsed: Duration = self.start.elapsed();
+            let latest: u64 = (elapsed.as_secs_f64() * fps) as u64;
+            if latest < self.next {
+                thread::sleep(arrival(self.next).saturating_sub(elapsed));
+            }
+            while self.next < latest {
+                if source.next_frame()?.is_none() {
+                    return Ok(None);
+                }
+                self.next += 1;
+                self.dropped += 1;
+            }
+        }
+
+        let frame: Option<RgbImage> = source.next_frame()?;
+        self.next += 1;
+        Ok(frame)
+    }
+
+    /// Preprocessing, inference and post-processing time of one processed frame.
+    pub fn record(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    pub fn report(&self) -> VideoReport {
+        let elapsed: f64 = self.start.elapsed().as_secs_f64();
+        let mut latencies: Vec<Duration> = self.latencies.clone();
+        latencies.sort();
+
+        VideoReport {
+            frames: latencies.len(),
+            dropped: self.dropped,
+            target_fps: self.target_fps,
+            fps: if elapsed > 0. {
+                latencies.len() as f64 / elapsed
+            } else {
+                0.
+            },
+            mean_latency_ms: millis(mean(&latencies)),
+            p50_latency_ms: millis(percentile(&latencies, 0.5)),
+            p95_latency_ms: millis(percentile(&latencies, 0.95)),
+            max_latency_ms: millis(percentile(&latencies, 1.)),
+            stages: Vec::new(),
+            prefetch: None,
+        }
+    }
+}
+
+/// Latency of one step of the streamed frames, e.g. their inference, over every frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct FrameStage {
+    pub name: String,
+    pub runs: usize,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+}
+
+impl FrameStage {
+    /// Every operation the frames recorded in `tracker`, in the order they first finished.
+    pub fn from_tracker(tracker: &BenchmarkTracker) -> Vec<Self> {
+        let mut names: Vec<&str> = Vec::new();
+        for span in tracker.spans() {
+            if span.kind == SpanKind::Operation && !names.contains(&span.name.as_str()) {
+                names.push(&span.name);
+            }
+        }
+        names
+            .into_iter()
+            .map(|name| {
+                let mut times: Vec<Duration> = tracker.operation_times(name);
+                times.sort();
+                Self {
+                    name: name.to_string(),
+                    runs: times.len(),
+                    mean_ms: millis(mean(&times)),
+                    p95_ms: millis(percentile(&times, 0.95)),
+                    max_ms: millis(percentile(&times, 1.)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Per-frame latency and throughput of a `--video` run.
+#[derive(Debug, Clone, Serialize)]
+pub struct VideoReport {
+    pub frames: usize,
+    pub dropped: u64,
+    pub target_fps: Option<f64>,
+    pub fps: f64,
+    pub mean_latency_ms: f64,
+    pub p50_latency_ms: f64,
+    pub p95_latency_ms: f64,
+    pub max_latency_ms: f64,
+    /// The steps of the streamed frames, the first frame is measured with the run.
+    pub stages: Vec<FrameStage>,
+    /// Stage utilization when frames were decoded and pre-processed on worker threads.
+    pub prefetch: Option<PrefetchReport>,
+}
+
+impl std::fmt::Display for VideoReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "============= Video Metrics =============")?;
+        writeln!(f, "Frames processed: {}", self.frames)?;
+        match self.target_fps {
+            Some(target_fps) => writeln!(
+                f,
+                "Frames dropped: {} (target {:.1} FPS)",
+                self.dropped, target_fps
+            )?,
+            None => writeln!(f, "Frames dropped: {}", self.dropped)?,
+        }
+        writeln!(f, "Achieved FPS: {:.2}", self.fps)?;
+        writeln!(
+            f,
+            "Frame latency: mean {:.3} ms, p50 {:.3} ms, p95 {:.3} ms, max {:.3} ms",
+            self.mean_latency_ms, self.p50_latency_ms, self.p95_latency_ms, self.max_latency_ms
+        )?;
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "  {}: mean {:.3} ms, p95 {:.3} ms, max {:.3} ms over {} frames",
+                stage.name, stage.mean_ms, stage.p95_ms, stage.max_ms, stage.runs
+            )?;
+        }
+        writeln!(f, "=======================================")?;
+        if let Some(prefetch) = &self.prefetch {
+            write!(f, "{}", prefetch)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{
+    error::AppError,
+    histogram::LatencyHistogram,
+    metrics::MetricsSummary,
+    outliers::OutlierReport,
+    tracker::{BenchmarkTracker, PhaseNode, SpanKind},
+};
+use ndarray::Array4;
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use wasi_nn::{
+    ExecutionTarget, Graph, GraphBuilder, GraphEncoding, GraphExecutionContext, TensorType,
+};
+
+/// Graph format the host runtime loads the model with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Onnx,
+    Openvino,
+    Tensorflow,
+    Pytorch,
+    Tflite,
+    Autodetect,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "onnx" => Ok(Encoding::Onnx),
+            "openvino" => Ok(Encoding::Openvino),
+            "tensorflow" => Ok(Encoding::Tensorflow),
+            "pytorch" => Ok(Encoding::Pytorch),
+            "tflite" => Ok(Encoding::Tflite),
+            "autodetect" => Ok(Encoding::Autodetect),
+            other => Err(format!(
+                "unknown graph encoding '{}', expected onnx, openvino, tensorflow, pytorch, tflite or autodetect",
+                other
+            )),
+        }
+    }
+}
+
+impl From<Encoding> for GraphEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Onnx => GraphEncoding::Onnx,
+            Encoding::Openvino => GraphEncoding::Openvino,
+            Encoding::Tensorflow => GraphEncoding::Tensorflow,
+            Encoding::Pytorch => GraphEncoding::Pytorch,
+            Encoding::Tflite => GraphEncoding::TensorflowLite,
+            Encoding::Autodetect => GraphEncoding::Autodetec,
+        }
+    }
+}
+
+/// Device the host runtime executes the graph on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    Cpu,
+    Gpu,
+    Tpu,
+    Auto,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cpu" => Ok(Target::Cpu),
+            "gpu" => Ok(Target::Gpu),
+            "tpu" => Ok(Target::Tpu),
+            "auto" => Ok(Target::Auto),
+            other => Err(format!(
+                "unknown execution target '{}', expected cpu, gpu, tpu or auto",
+                other
+            )),
+        }
+    }
+}
+
+impl From<Target> for ExecutionTarget {
+    fn from(target: Target) -> Self {
+        match target {
+            Target::Cpu => ExecutionTarget::CPU,
+            Target::Gpu => ExecutionTarget::GPU,
+            Target::Tpu => ExecutionTarget::TPU,
+            Target::Auto => ExecutionTarget::AUTO,
+        }
+    }
+}
+
+/// Loads a graph through the wasi-nn host functions. OpenVINO models come as an `.xml` file with
+/// its weights in the `.bin` file next to it, every other encoding is a single file.
+pub fn load_graph(path: &str, encoding: Encoding, target: Target) -> Result<Graph, AppError> {
+    let model: &Path = Path::new(path);
+    let files: Vec<PathBuf> = match encoding {
+        Encoding::Openvino => vec![model.to_path_buf(), model.with_extension("bin")],
+        _ => vec![model.to_path_buf()],
+    };
+    Ok(GraphBuilder::new(encoding.into(), target.into()).build_from_files(files)?)
+}
+
+/// One inference: the input is copied to the host, computed, and the first output copied back
+/// into `output`. Returns the number of elements the host wrote.
+pub fn infer(
+    context: &mut GraphExecutionContext<'_>,
+    input: &Array4<f32>,
+    output: &mut [f32],
+) -> Result<usize, AppError> {
+    let data: &[f32] = input
+        .as_slice()
+        .expect("preprocessed tensors are contiguous");
+    context.set_input(0, TensorType::F32, input.shape(), data)?;
+    context.compute()?;
+    let written: usize = context.get_output(0, output)?;
+    Ok(written / std::mem::size_of::<f32>())
+}
+
+/// Highest score of the first output, as the classification post-processing of the native build.
+#[derive(Debug, Clone, Serialize)]
+pub struct WasiPrediction {
+    pub output_len: usize,
+    pub predicted_index: usize,
+    pub score: f32,
+}
+
+impl WasiPrediction {
+    pub fn new(output: &[f32]) -> Option<Self> {
+        let (predicted_index, &score) = output
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        Some(Self {
+            output_len: output.len(),
+            predicted_index,
+            score,
+        })
+    }
+}
+
+impl std::fmt::Display for WasiPrediction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Output: [{}]", self.output_len)?;
+        writeln!(f, "Predicted Class Index: {}", self.predicted_index)?;
+        writeln!(f, "Confidence Score: {:.4}", self.score)
+    }
+}
+
+/// Everything a Wasm run measured, with the field names of the native `RunReport` so both can be
+/// compared with the same tooling.
+#[derive(Debug, Clone, Serialize)]
+pub struct WasiReport {
+    pub model: String,
+    pub iterations: u32,
+    pub encoding: Encoding,
+    pub target: Target,
+    pub operations: Vec<MetricsSummary>,
+    pub phases: Vec<MetricsSummary>,
+    pub phase_tree: Vec<PhaseNode>,
+    pub total: MetricsSummary,
+    pub latency: Option<LatencyHistogram>,
+    pub outliers: Option<OutlierReport>,
+    pub output: Option<WasiPrediction>,
+}
+
+impl WasiReport {
+    pub fn new(
+        model: &str,
+        iterations: u32,
+        (encoding, target): (Encoding, Target),
+        tracker: &BenchmarkTracker,
+        output: Option<WasiPrediction>,
+    ) -> Self {
+        Self {
+            model: model.to_string(),
+            iterations,
+            encoding,
+            target,
+            operations: tracker.summaries(SpanKind::Operation),
+            phases: tracker.summaries(SpanKind::Phase),
+            phase_tree: tracker.phase_tree(),
+            total: MetricsSummary::from(&tracker.get_total_metrics()),
+            latency: LatencyHistogram::from_tracker(tracker, "Inference"),
+            outliers: OutlierReport::from_tracker(tracker, "Inference"),
+            output,
+        }
+    }
+}